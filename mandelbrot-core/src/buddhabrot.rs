@@ -0,0 +1,184 @@
+//Buddhabrot rendering, the density of the orbits of escaping points rather
+//than how fast each pixel escapes. Random c are sampled over the whole set,
+//so the image fills in gradually as batches are added
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rayon::prelude::*;
+
+use crate::{CancelToken, MandleParams, RenderMode};
+
+//Red, green and blue iteration limits for the nebulabrot. Red only sees
+//the long orbits close to the set, blue mostly the quick escapes
+pub const NEBULA_ITERATIONS: [u32; 3] = [5000, 500, 50];
+
+//A batch is split into chunks that each have their own generator,
+//the frame is redrawn after each batch
+const BATCH_CHUNKS: u64 = 64;
+const CHUNK_SAMPLES: usize = 4096;
+
+//Sampling stops once there are this many samples per pixel
+const SAMPLES_PER_PIXEL: u64 = 64;
+
+//c is sampled from this square, everything outside escapes immediately
+const SAMPLE_RADIUS: f64 = 2.0;
+
+//One histogram per channel, an orbit is added to every channel whose
+//iteration limit it escaped within. A single channel is drawn in grayscale,
+//three as red, green and blue.
+//Orbits are iterated in f64, the buddhabrot is not useful at zooms where that runs out
+pub struct Buddhabrot {
+    width : usize,
+    height : usize,
+    limits : Vec<u32>,
+    channels : Vec<Vec<AtomicU32>>,
+    samples : u64,
+}
+
+impl Buddhabrot {
+
+    //Channels for the params' mode, the plain buddhabrot uses the iteration limit
+    pub fn new(params : &MandleParams) -> Buddhabrot {
+        let limits = match params.mode {
+            RenderMode::Nebulabrot => params.nebula_iterations.to_vec(),
+            _ => vec![params.iterations],
+        };
+        let (width, height) = (params.width, params.height);
+        Buddhabrot {
+            width,
+            height,
+            channels: limits.iter()
+                .map(|_| (0..width * height).map(|_| AtomicU32::new(0)).collect())
+                .collect(),
+            limits,
+            samples: 0,
+        }
+    }
+
+    pub fn done(&self) -> bool {
+        self.samples >= self.width as u64 * self.height as u64 * SAMPLES_PER_PIXEL
+    }
+
+    //Adds one batch of samples to the histogram. Returns false if it was cancelled part way
+    pub fn sample(&mut self, params : &MandleParams, cancel : &CancelToken) -> bool {
+        let x = params.x.to_f64();
+        let y = params.y.to_f64();
+        let zoom = params.zoom;
+        let half_width = self.width as f64 / 2.0;
+        let half_height = self.height as f64 / 2.0;
+        let bailout2 = params.bailout * params.bailout;
+        let max_iter = self.limits.iter().copied().max().unwrap_or(0) as usize;
+        let batch = self.samples / (BATCH_CHUNKS * CHUNK_SAMPLES as u64);
+        let (width, height) = (self.width, self.height);
+        let limits = &self.limits;
+        let channels = &self.channels;
+
+        (0..BATCH_CHUNKS).into_par_iter().for_each(|chunk| {
+            let mut rng = XorShift::new(batch * BATCH_CHUNKS + chunk);
+            let mut orbit = Vec::with_capacity(max_iter);
+            for _ in 0..CHUNK_SAMPLES {
+                if cancel.is_cancelled() {
+                    return;
+                }
+                let c_a = (rng.next_f64() * 2.0 - 1.0) * SAMPLE_RADIUS;
+                let c_b = (rng.next_f64() * 2.0 - 1.0) * SAMPLE_RADIUS;
+                if in_main_bulbs(c_a, c_b) {
+                    continue;
+                }
+
+                orbit.clear();
+                let (mut a, mut b) = (0.0f64, 0.0f64);
+                let mut escaped = false;
+                for _ in 0..max_iter {
+                    let a_new = a * a - b * b + c_a;
+                    b = 2.0 * a * b + c_b;
+                    a = a_new;
+                    if a * a + b * b > bailout2 {
+                        escaped = true;
+                        break;
+                    }
+                    orbit.push((a, b));
+                }
+                //Points inside the set never escape and don't count
+                if !escaped {
+                    continue;
+                }
+
+                for (&limit, counts) in limits.iter().zip(channels) {
+                    if orbit.len() >= limit as usize {
+                        continue;
+                    }
+                    for &(a, b) in &orbit {
+                        let px = ((a - x) / zoom + half_width).floor();
+                        let py = ((b - y) / zoom + half_height).floor();
+                        if px >= 0.0 && py >= 0.0 && (px as usize) < width && (py as usize) < height {
+                            counts[py as usize * width + px as usize].fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            }
+        });
+        if cancel.is_cancelled() {
+            return false;
+        }
+        self.samples += BATCH_CHUNKS * CHUNK_SAMPLES as u64;
+        true
+    }
+
+    //Each channel is square root tone mapped against its own busiest pixel
+    pub fn render(&self, frame : &mut [u8]) {
+        let maxima : Vec<f64> = self.channels.iter()
+            .map(|counts| {
+                counts.iter()
+                    .map(|count| count.load(Ordering::Relaxed))
+                    .max()
+                    .unwrap_or(0)
+                    .max(1) as f64
+            })
+            .collect();
+        let level = |channel : usize, idx : usize| -> u8 {
+            let count = self.channels[channel][idx].load(Ordering::Relaxed) as f64;
+            ((count / maxima[channel]).sqrt() * 255.0) as u8
+        };
+        for (idx, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let rgb = if self.channels.len() == 3 {
+                [level(0, idx), level(1, idx), level(2, idx)]
+            } else {
+                let value = level(0, idx);
+                [value, value, value]
+            };
+            pixel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 0xff]);
+        }
+    }
+}
+
+//The main cardioid and period 2 bulb never escape, skipping them saves
+//iterating most of the points that would run to max_iter
+fn in_main_bulbs(a : f64, b : f64) -> bool {
+    let q = (a - 0.25) * (a - 0.25) + b * b;
+    let cardioid = q * (q + (a - 0.25)) <= 0.25 * b * b;
+    let bulb = (a + 1.0) * (a + 1.0) + b * b <= 0.0625;
+    cardioid || bulb
+}
+
+//xorshift64*, plenty for scattering samples and seedable per chunk so
+//batches don't need a shared generator. Also jitters the supersamples
+pub struct XorShift(u64);
+
+impl XorShift {
+
+    pub fn new(seed : u64) -> XorShift {
+        //Zero is a fixed point of xorshift. Multiplying by an odd constant is
+        //a bijection so only seed = u64::MAX could land there
+        XorShift(seed.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    //Uniform in 0..1
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,107 @@
+//How samples become colours. Each frame is coloured by a Colorizer, made
+//for it by the first colouring in COLORIZERS that takes its params. They
+//only read the samples, so switching colour mode, palette or histogram
+//colouring colours the grid again without iterating anything
+
+use crate::formula::{self, Divergence};
+use crate::histogram::Equalizer;
+use crate::palette::{self, Palette};
+use crate::{Grid, MandleParams, Sample};
+
+pub trait Colorizer : Sync {
+    //Linear light shown for a sample, the interior included. Averages of
+    //samples are taken of this
+    fn light(&self, sample : Sample) -> [f64; 3];
+
+    //Whether light is dithered on the way to 8 bits rather than rounded
+    fn dithered(&self) -> bool;
+
+    //rgba of a sample drawn at pixel (x, y) of the frame
+    fn color(&self, sample : Sample, pixel : (usize, usize)) -> [u8; 4] {
+        let [r, g, b] = palette::quantize(self.light(sample), pixel, self.dithered());
+        [r, g, b, 0xff]
+    }
+}
+
+//Makes the colouring of a frame drawn from grid at a refinement step, None
+//when params don't ask for it
+type Make = for<'a> fn(&Grid<Sample>, usize, &MandleParams, &'a Palette) -> Option<Box<dyn Colorizer + 'a>>;
+
+//In order of precedence, the palette colouring takes every frame
+static COLORIZERS: &[Make] = &[histogram, palette];
+
+//The colouring of a frame drawn from grid at this refinement step
+pub fn new<'a>(grid : &Grid<Sample>, step : usize, params : &MandleParams, palettes : &'a [Palette]) -> Box<dyn Colorizer + 'a> {
+    let palette = &palettes[params.palette];
+    COLORIZERS
+        .iter()
+        .find_map(|make| make(grid, step, params, palette))
+        .expect("the palette colouring takes every frame")
+}
+
+fn histogram<'a>(grid : &Grid<Sample>, step : usize, params : &MandleParams, palette : &'a Palette) -> Option<Box<dyn Colorizer + 'a>> {
+    let colors = PaletteColorizer::new(params, palette);
+    if !params.histogram || !colors.formula.equalized() {
+        return None;
+    }
+    Some(Box::new(HistogramColorizer::new(colors, Equalizer::new(grid, step, params))))
+}
+
+fn palette<'a>(_grid : &Grid<Sample>, _step : usize, params : &MandleParams, palette : &'a Palette) -> Option<Box<dyn Colorizer + 'a>> {
+    Some(Box::new(PaletteColorizer::new(params, palette)))
+}
+
+//Each sample's value in the colour mode of params, smooth, trap or any of
+//the others, through the formula's colouring of the palette
+pub struct PaletteColorizer<'a> {
+    params : MandleParams,
+    palette : &'a Palette,
+    formula : &'static dyn Divergence,
+}
+
+impl<'a> PaletteColorizer<'a> {
+    pub fn new(params : &MandleParams, palette : &'a Palette) -> PaletteColorizer<'a> {
+        PaletteColorizer {
+            params: params.clone(),
+            palette,
+            formula: formula::get(params.formula),
+        }
+    }
+
+    fn value_light(&self, value : f64) -> [f64; 3] {
+        let linear = self.formula.color(value, self.palette, self.params.palette_offset);
+        palette::tone(linear, self.params.exposure, self.params.gamma)
+    }
+}
+
+impl Colorizer for PaletteColorizer<'_> {
+    fn light(&self, sample : Sample) -> [f64; 3] {
+        self.value_light(sample.value(&self.params))
+    }
+
+    fn dithered(&self) -> bool {
+        self.params.dither
+    }
+}
+
+//The palette colouring with the values equalised over a frame, see histogram
+pub struct HistogramColorizer<'a> {
+    colors : PaletteColorizer<'a>,
+    equalizer : Equalizer,
+}
+
+impl<'a> HistogramColorizer<'a> {
+    pub fn new(colors : PaletteColorizer<'a>, equalizer : Equalizer) -> HistogramColorizer<'a> {
+        HistogramColorizer { colors, equalizer }
+    }
+}
+
+impl Colorizer for HistogramColorizer<'_> {
+    fn light(&self, sample : Sample) -> [f64; 3] {
+        self.colors.value_light(self.equalizer.map(sample.value(&self.colors.params)))
+    }
+
+    fn dithered(&self) -> bool {
+        self.colors.dithered()
+    }
+}
@@ -0,0 +1,174 @@
+//View coordinates. The centre of the view has to stay exact to well within
+//a pixel however far in it is, pixels are placed as f64 offsets from it.
+//128 bit fixed point normally, the rug feature swaps in a much wider fixed
+//point from the deep module
+
+#[cfg(not(feature = "rug"))]
+use std::fmt;
+#[cfg(not(feature = "rug"))]
+use std::str::FromStr;
+
+#[cfg(not(feature = "rug"))]
+use crate::MReal;
+
+#[cfg(feature = "rug")]
+pub use crate::deep::Coord;
+
+//Deepest pixel spacing the view zooms to. Past this neighbouring pixels
+//would round onto the same coordinate
+#[cfg(not(feature = "rug"))]
+pub const MIN_ZOOM: f64 = 1.0e-33;
+#[cfg(feature = "rug")]
+pub const MIN_ZOOM: f64 = 1.0e-300;
+
+#[cfg(not(feature = "rug"))]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Coord(MReal);
+
+#[cfg(not(feature = "rug"))]
+impl Coord {
+
+    pub const ZERO: Coord = Coord(MReal::ZERO);
+
+    pub fn to_fixed(self) -> MReal {
+        self.0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0.to_num::<f64>()
+    }
+
+    //self + offset, saturating at the edge of the range
+    pub fn offset(self, offset : f64) -> Coord {
+        Coord(self.0.saturating_add(MReal::saturating_from_num(offset)))
+    }
+
+    //self - other
+    pub fn minus(self, other : Coord) -> f64 {
+        self.0.saturating_sub(other.0).to_num::<f64>()
+    }
+}
+
+//Decimal strings are parsed exactly, those with an exponent (eg. 1.5e-20)
+//too, by moving the point before parsing rather than going through f64.
+//Digits past the last fraction bit are rounded off
+#[cfg(not(feature = "rug"))]
+impl FromStr for Coord {
+    type Err = String;
+
+    fn from_str(val : &str) -> Result<Coord, String> {
+        let plain = plain_decimal(val.trim()).ok_or_else(|| format!("{} is not a decimal number", val))?;
+        plain.parse::<MReal>()
+            .map(Coord)
+            .map_err(|_| format!("{} is out of range", val))
+    }
+}
+
+//Places the point is moved by at most. Numbers that far out are well past
+//MReal's integer bits one way and below its last fraction bit the other,
+//this just keeps an exponent like 1e-999999 from making a huge string
+#[cfg(not(feature = "rug"))]
+const MAX_EXPONENT: i64 = 200;
+
+//val written as [-]digits.digits with no exponent, None if it isn't a number
+#[cfg(not(feature = "rug"))]
+fn plain_decimal(val : &str) -> Option<String> {
+    let (sign, unsigned) = match val.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", val.strip_prefix('+').unwrap_or(val)),
+    };
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+        None => (unsigned, 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int.is_empty() && frac.is_empty() || !int.bytes().chain(frac.bytes()).all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let digits = format!("{}{}", int, frac);
+    let len = digits.len() as i64;
+    //Where the point ends up among the digits
+    let point = (int.len() as i64).saturating_add(exponent.clamp(-MAX_EXPONENT, MAX_EXPONENT));
+    let (int, frac) = if point <= 0 {
+        ("0".to_string(), "0".repeat(-point as usize) + digits.as_str())
+    } else if point >= len {
+        (digits + "0".repeat((point - len) as usize).as_str(), "0".to_string())
+    } else {
+        let (int, frac) = digits.split_at(point as usize);
+        (int.to_string(), frac.to_string())
+    };
+    Some(format!("{}{}.{}", sign, int, frac))
+}
+
+//Passes the precision through, the hud only shows the digits that matter
+#[cfg(not(feature = "rug"))]
+impl fmt::Display for Coord {
+    fn fmt(&self, fmt : &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, fmt)
+    }
+}
+
+#[cfg(all(test, not(feature = "rug")))]
+mod tests {
+    use super::*;
+
+    fn parse(val : &str) -> Coord {
+        val.parse().unwrap()
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for val in [0.0, 1.0, -0.75, 0.1, -1.768_778_833, 1000.0, -1024.0] {
+            let coord = Coord::ZERO.offset(val);
+            assert_eq!(parse(&coord.to_string()), coord);
+        }
+        //One fraction bit, below anything f64 would keep next to 1
+        let tiny = Coord(MReal::from_bits(1));
+        assert_eq!(parse(&tiny.to_string()), tiny);
+        let near_one = Coord(MReal::ONE + MReal::from_bits(1));
+        assert_eq!(parse(&near_one.to_string()), near_one);
+        assert_eq!(parse(&(-near_one.0).to_string()), Coord(-near_one.0));
+    }
+
+    #[test]
+    fn exact_past_f64() {
+        let coord = parse("-0.743643887037158704752191506114774");
+        assert_ne!(coord, Coord::ZERO.offset(coord.to_f64()));
+        assert_eq!(coord, parse("-743643887037158704752191506114774e-33"));
+    }
+
+    #[test]
+    fn exponents() {
+        assert_eq!(parse("1.5e-20"), parse("0.000000000000000000015"));
+        assert_eq!(parse("1.5E2"), parse("150"));
+        assert_eq!(parse("-25e-1"), parse("-2.5"));
+        assert_eq!(parse("+.5e1"), parse("5"));
+        assert_eq!(parse("5.e-1"), parse("0.5"));
+        assert_eq!(parse(" 0.25 "), Coord::ZERO.offset(0.25));
+    }
+
+    #[test]
+    fn exponent_clamped() {
+        //Anything moved down MAX_EXPONENT places or more is past the last fraction bit
+        assert_eq!(parse(&format!("1e-{}", MAX_EXPONENT)), Coord::ZERO);
+        assert_eq!(parse("1e-999999999999"), Coord::ZERO);
+        assert_eq!(plain_decimal("1e-999999999999"), plain_decimal(&format!("1e-{}", MAX_EXPONENT)));
+        assert!(format!("1e{}", MAX_EXPONENT).parse::<Coord>().is_err());
+        assert!("1e999999999999".parse::<Coord>().is_err());
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(parse("1023.5"), Coord::ZERO.offset(1023.5));
+        assert!("1024".parse::<Coord>().is_err());
+        assert_eq!(parse("-1024"), Coord::ZERO.offset(-1024.0));
+        assert!("-1025".parse::<Coord>().is_err());
+    }
+
+    #[test]
+    fn rejects_non_numbers() {
+        for val in ["", "-", ".", "e5", "1e", "1e+", "abc", "1.2.3", "--1", "1,5", "0x10", "1e5.5", "inf", "NaN"] {
+            assert!(val.parse::<Coord>().is_err(), "{:?} parsed", val);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//Arbitrary precision through MPFR, only built with the rug feature. The view
+//centre is kept in fixed point wide enough for any pixel spacing down to
+//coord::MIN_ZOOM, and perturbation reference orbits past the reach of 128
+//bit fixed point are iterated with as many bits as their zoom needs
+
+use std::fmt;
+use std::str::FromStr;
+
+use rug::integer::Order;
+use rug::{Assign, Float, Integer};
+
+use crate::coord::MIN_ZOOM;
+use crate::{Fractal, MandleParams, MReal};
+
+//64 bit limbs of the view coordinates, with 11 integer bits like MReal
+const LIMBS: usize = 18;
+const TOTAL_BITS: u32 = LIMBS as u32 * 64;
+const FRAC_BITS: u32 = TOTAL_BITS - 11;
+const FIXED_FRAC_BITS: u32 = 117;
+
+//Decimal places that cover every fraction bit, log10(2) is about 0.30103
+const DIGITS: usize = (FRAC_BITS as usize * 30103).div_ceil(100_000);
+
+//Bits kept past what tells neighbouring pixels apart, for the rounding
+//that builds up over a long orbit
+const GUARD_BITS: u32 = 64;
+
+//Two's complement fixed point, least significant limb first. Copied
+//around with the params like MReal, MPFR numbers are only made to compute with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Coord([u64; LIMBS]);
+
+impl Coord {
+
+    pub const ZERO: Coord = Coord([0; LIMBS]);
+
+    const MAX: Coord = {
+        let mut limbs = [u64::MAX; LIMBS];
+        limbs[LIMBS - 1] = u64::MAX >> 1;
+        Coord(limbs)
+    };
+
+    const MIN: Coord = {
+        let mut limbs = [0; LIMBS];
+        limbs[LIMBS - 1] = 1 << 63;
+        Coord(limbs)
+    };
+
+    pub fn from_fixed(value : MReal) -> Coord {
+        Coord::from_integer(Integer::from(value.to_bits()) << (FRAC_BITS - FIXED_FRAC_BITS))
+    }
+
+    //Rounded down to the MReal below
+    pub fn to_fixed(self) -> MReal {
+        MReal::from_bits((self.to_integer() >> (FRAC_BITS - FIXED_FRAC_BITS)).to_i128_wrapping())
+    }
+
+    pub fn to_f64(self) -> f64 {
+        scaled_to_f64(self.to_integer())
+    }
+
+    //self + offset, saturating at the edge of the range
+    pub fn offset(self, offset : f64) -> Coord {
+        let mut scaled = Float::with_val(64, offset);
+        scaled <<= FRAC_BITS;
+        match scaled.to_integer() {
+            Some(offset) => Coord::from_integer(self.to_integer() + offset),
+            None => self,
+        }
+    }
+
+    //self - other
+    pub fn minus(self, other : Coord) -> f64 {
+        scaled_to_f64(self.to_integer() - other.to_integer())
+    }
+
+    pub fn to_float(self, prec : u32) -> Float {
+        let mut float = Float::with_val(prec, self.to_integer());
+        float >>= FRAC_BITS;
+        float
+    }
+
+    //The value scaled up by 2^FRAC_BITS
+    fn to_integer(self) -> Integer {
+        let mut value = Integer::from_digits(&self.0, Order::Lsf);
+        if self.0[LIMBS - 1] >> 63 == 1 {
+            value -= Integer::from(1) << TOTAL_BITS;
+        }
+        value
+    }
+
+    fn from_integer(value : Integer) -> Coord {
+        if value.signed_bits() > TOTAL_BITS {
+            return if value.is_negative() { Coord::MIN } else { Coord::MAX };
+        }
+        let mut limbs = [0; LIMBS];
+        let digits = value.keep_bits(TOTAL_BITS).to_digits::<u64>(Order::Lsf);
+        limbs[..digits.len()].copy_from_slice(&digits);
+        Coord(limbs)
+    }
+}
+
+fn scaled_to_f64(scaled : Integer) -> f64 {
+    let mut float = Float::with_val(64, scaled);
+    float >>= FRAC_BITS;
+    float.to_f64()
+}
+
+//Decimal strings and exponents alike are rounded to the nearest fraction bit
+impl FromStr for Coord {
+    type Err = String;
+
+    fn from_str(val : &str) -> Result<Coord, String> {
+        let parsed = Float::parse(val).map_err(|err| err.to_string())?;
+        let mut scaled = Float::with_val(TOTAL_BITS + GUARD_BITS, parsed);
+        scaled <<= FRAC_BITS;
+        let value = scaled.to_integer().ok_or_else(|| format!("{} is not a number", val))?;
+        if value.signed_bits() > TOTAL_BITS {
+            return Err(format!("{} is out of range", val));
+        }
+        Ok(Coord::from_integer(value))
+    }
+}
+
+//To the precision given if there is one, otherwise every digit the fraction
+//bits can hold with trailing zeros dropped, so it parses back the same
+impl fmt::Display for Coord {
+    fn fmt(&self, fmt : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let places = fmt.precision().unwrap_or(DIGITS);
+        let mut scaled = self.to_integer() * Integer::from(Integer::u_pow_u(10, places as u32));
+        scaled += Integer::from(1) << (FRAC_BITS - 1);
+        scaled >>= FRAC_BITS;
+        let sign = if scaled.is_negative() { "-" } else { "" };
+        let digits = format!("{:0>width$}", scaled.abs().to_string(), width = places + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - places);
+        let fraction = match fmt.precision() {
+            Some(_) => fraction,
+            None => fraction.trim_end_matches('0'),
+        };
+        if fraction.is_empty() {
+            write!(fmt, "{}{}", sign, whole)
+        } else {
+            write!(fmt, "{}{}.{}", sign, whole, fraction)
+        }
+    }
+}
+
+//MPFR bits for a reference orbit at this pixel spacing
+pub fn precision(zoom : f64) -> u32 {
+    (-zoom.max(MIN_ZOOM).log2()).max(0.0).ceil() as u32 + GUARD_BITS
+}
+
+//Orbit of (a, b) for the perturbation backend, iterated at the precision the
+//zoom of params needs and rounded to f64 a step at a time. Z[0] is included,
+//the orbit stops on the first point past the bailout
+pub fn reference_orbit(a : Coord, b : Coord, params : &MandleParams) -> Vec<(f64, f64)> {
+    let prec = precision(params.zoom);
+    let mut z_a = a.to_float(prec);
+    let mut z_b = b.to_float(prec);
+    let (c_a, c_b) = match params.fractal {
+        Fractal::Mandlebrot => (z_a.clone(), z_b.clone()),
+        Fractal::Julia { c_a, c_b } => (
+            Coord::from_fixed(c_a).to_float(prec),
+            Coord::from_fixed(c_b).to_float(prec),
+        ),
+    };
+    let bailout2 = params.bailout * params.bailout;
+    let mut orbit = Vec::with_capacity(params.iterations as usize);
+    let mut cross = Float::new(prec);
+    for _ in 0..params.iterations {
+        let (a, b) = (z_a.to_f64(), z_b.to_f64());
+        orbit.push((a, b));
+        if a * a + b * b > bailout2 {
+            break;
+        }
+        //z^2 + c, 2ab is taken before either part is squared in place
+        cross.assign(&z_a * &z_b);
+        cross <<= 1u32;
+        cross += &c_b;
+        z_a.square_mut();
+        z_b.square_mut();
+        z_a -= &z_b;
+        z_a += &c_a;
+        std::mem::swap(&mut z_b, &mut cross);
+    }
+    orbit
+}
@@ -0,0 +1,140 @@
+//Double-double numbers, an unevaluated sum of two f64 with the low part
+//holding the bits the high part rounded off. About 106 bits of mantissa,
+//as deep as fixed point goes near the origin for a fraction of the cost
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::MReal;
+use crate::real::Real;
+
+//Kept normalised, |lo| is at most half an ulp of hi. Comparing hi first
+//and lo after is then the same as comparing the sums
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct DoubleDouble {
+    hi : f64,
+    lo : f64,
+}
+
+impl DoubleDouble {
+    const fn new(hi : f64, lo : f64) -> DoubleDouble {
+        DoubleDouble { hi, lo }
+    }
+}
+
+//a + b exactly, as the rounded sum and its error
+fn two_sum(a : f64, b : f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_part = sum - a;
+    let error = (a - (sum - b_part)) + (b - b_part);
+    (sum, error)
+}
+
+//The same for |a| >= |b|, which saves the branch two_sum works around
+fn quick_two_sum(a : f64, b : f64) -> (f64, f64) {
+    let sum = a + b;
+    (sum, b - (sum - a))
+}
+
+//Veltkamp's split into two halves of 26 bits that multiply without rounding.
+//mul_add would be exact as well but is a libm call without fma enabled
+fn split(a : f64) -> (f64, f64) {
+    let scaled = a * 134217729.0;
+    let hi = scaled - (scaled - a);
+    (hi, a - hi)
+}
+
+//a * b exactly, as the rounded product and its error
+fn two_prod(a : f64, b : f64) -> (f64, f64) {
+    let product = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    let error = ((a_hi * b_hi - product) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo;
+    (product, error)
+}
+
+//The errors of the low parts are dropped, which loses relative precision
+//when nearly equal numbers cancel. Still within an ulp of the larger of the
+//two, and the fractal only needs the position to be that close
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+
+    fn add(self, other : DoubleDouble) -> DoubleDouble {
+        let (sum, error) = two_sum(self.hi, other.hi);
+        let (hi, lo) = quick_two_sum(sum, error + self.lo + other.lo);
+        DoubleDouble::new(hi, lo)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+
+    fn sub(self, other : DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    //lo * lo is below the precision kept and is left out
+    fn mul(self, other : DoubleDouble) -> DoubleDouble {
+        let (product, error) = two_prod(self.hi, other.hi);
+        let error = error + (self.hi * other.lo + self.lo * other.hi);
+        let (hi, lo) = quick_two_sum(product, error);
+        DoubleDouble::new(hi, lo)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble::new(-self.hi, -self.lo)
+    }
+}
+
+//Overflows to infinity in the high part like f64
+impl Real for DoubleDouble {
+    const MAX: DoubleDouble = DoubleDouble::new(1024.0, 0.0);
+
+
+    fn from_f64(value : f64) -> DoubleDouble {
+        DoubleDouble::new(value, 0.0)
+    }
+
+    //The low part is whatever is left after rounding to f64, exact as
+    //long as the fixed point value has no more than 106 significant bits
+    fn from_fixed(value : MReal) -> DoubleDouble {
+        let hi = value.to_num::<f64>();
+        let lo = value.saturating_sub(MReal::saturating_from_num(hi)).to_num::<f64>();
+        DoubleDouble::new(hi, lo)
+    }
+
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+
+    fn to_f32(self) -> f32 {
+        self.hi as f32
+    }
+
+
+    fn abs(self) -> DoubleDouble {
+        if self.hi < 0.0 { -self } else { self }
+    }
+
+
+    fn saturating_mul(self, other : DoubleDouble) -> DoubleDouble {
+        self * other
+    }
+
+
+    fn saturating_add(self, other : DoubleDouble) -> DoubleDouble {
+        self + other
+    }
+}
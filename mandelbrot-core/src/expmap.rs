@@ -0,0 +1,154 @@
+//Exponential maps for zoom videos into a fixed centre. The plane around the
+//centre is sampled in log-polar coordinates, columns stepping out in log
+//radius and rows around the circle, so every column is as many pixels across
+//at any zoom. A frame zoomed in by some factor only needs the columns a few
+//steps further in, and everything still on screen from the frames before is
+//reused rather than computed again
+
+use std::collections::VecDeque;
+use std::f64::consts::TAU;
+
+use rayon::prelude::*;
+
+use crate::formula::{self, Divergence};
+use crate::palette::{self, Palette};
+use crate::{simd, supersample};
+use crate::MandleParams;
+
+pub struct ExpMap {
+    //Centre and settings of the zoom, sized to the frames
+    params : MandleParams,
+    //Samples around the circle, the rows of the map
+    angles : usize,
+    //Log radius between neighbouring columns, square cells in log-polar space
+    step : f64,
+    //Log radius of column 0, half a pixel of the deepest frame
+    ln_inner : f64,
+    //Where each pixel of a frame falls in the map, its log distance from the
+    //centre and its angle, both in cells. Frames only differ by how far the
+    //columns are shifted
+    pixels : Vec<(f64, f64)>,
+    //Values of the columns around the current frames, the first of them first
+    columns : VecDeque<Vec<f32>>,
+    first : usize,
+}
+
+impl ExpMap {
+
+    //For frames of params' size and centre, zoomed no deeper than deepest.
+    //Enough angles that the corners of a frame get a sample per pixel
+    pub fn new(params : &MandleParams, deepest : f64) -> ExpMap {
+        let corner = half_diagonal(params);
+        let angles = (TAU * corner).ceil().max(8.0) as usize;
+        let step = TAU / angles as f64;
+        let ln_inner = (deepest * 0.5).ln();
+        let (width, height) = (params.width, params.height);
+        let pixels = (0..width * height)
+            .map(|index| {
+                let dx = (index % width) as f64 + 0.5 - width as f64 / 2.0;
+                let dy = (index / width) as f64 + 0.5 - height as f64 / 2.0;
+                (dx.hypot(dy).ln() / step, dy.atan2(dx).rem_euclid(TAU) / step)
+            })
+            .collect();
+        ExpMap {
+            params: params.clone(),
+            angles,
+            step,
+            ln_inner,
+            pixels,
+            columns: VecDeque::new(),
+            first: 0,
+        }
+    }
+
+    //Renders the frame of params' zoom and palette into an rgb image of the
+    //map's size, centred on the map's centre whatever params' is. Columns it
+    //needs are computed and ones it doesn't are dropped, so frames should
+    //come in zoom order
+    pub fn render(&mut self, params : &MandleParams, palette : &Palette) -> Vec<u8> {
+        //Column one pixel out from the centre
+        let shift = (params.zoom.ln() - self.ln_inner) / self.step;
+        let first = (shift + 0.5f64.ln() / self.step).floor().max(0.0) as usize;
+        let last = (shift + half_diagonal(&self.params).ln() / self.step).ceil() as usize + 1;
+        self.cover(first, last);
+
+        let formula = formula::get(self.params.formula);
+        let color = |column : usize, row : usize| {
+            let value = self.columns.get(column - self.first).map_or(0.0, |values| values[row % self.angles]);
+            formula.color(value as f64, palette, params.palette_offset)
+        };
+        let mut image = vec![0u8; self.pixels.len() * 3];
+        let width = self.params.width;
+        image.par_chunks_mut(3).zip(&self.pixels).enumerate().for_each(|(index, (pixel, &(distance, angle)))| {
+            let (u, v) = ((distance + shift).max(first as f64), angle);
+            let (u0, v0) = (u.floor() as usize, v.floor() as usize);
+            let (fu, fv) = (u.fract(), v.fract());
+            //Bilinear between the colours of the four cells around
+            let top = lerp(color(u0, v0), color(u0 + 1, v0), fu);
+            let bottom = lerp(color(u0, v0 + 1), color(u0 + 1, v0 + 1), fu);
+            let light = palette::tone(lerp(top, bottom, fv), params.exposure, params.gamma);
+            pixel.copy_from_slice(&palette::quantize(light, (index % width, index / width), params.dither));
+        });
+        image
+    }
+
+    //Drops the columns outside first..=last and computes the missing ones
+    fn cover(&mut self, first : usize, last : usize) {
+        let held = self.first..self.first + self.columns.len();
+        if held.end <= first || held.start > last {
+            self.columns.clear();
+            self.first = first;
+        }
+        while self.first < first {
+            self.columns.pop_front();
+            self.first += 1;
+        }
+        self.columns.truncate(last + 1 - self.first);
+
+        let inner : Vec<Vec<f32>> = (first..self.first).into_par_iter().map(|column| self.compute_column(column)).collect();
+        let outer : Vec<Vec<f32>> = (self.first + self.columns.len()..=last)
+            .into_par_iter()
+            .map(|column| self.compute_column(column))
+            .collect();
+        for column in inner.into_iter().rev() {
+            self.columns.push_front(column);
+        }
+        self.columns.extend(outer);
+        self.first = first;
+    }
+
+    //Every angle at one radius. Sampled through params zoomed to the size of a
+    //cell there, so each point is iterated at the precision that size needs
+    fn compute_column(&self, column : usize) -> Vec<f32> {
+        let radius = (self.ln_inner + column as f64 * self.step).exp();
+        let params = MandleParams {
+            zoom: radius * self.step,
+            ..self.params.clone()
+        }.resolved();
+        //In cells, which are step of the radius across
+        let offsets = (0..self.angles).map(|row| {
+            let angle = row as f64 * self.step;
+            (angle.cos() / self.step, angle.sin() / self.step)
+        });
+        if simd::applies(&params) {
+            let points : Vec<(f64, f64)> = offsets
+                .map(|(dx, dy)| (
+                    params.x.offset(dx * params.zoom).to_fixed().to_num::<f64>(),
+                    params.y.offset(dy * params.zoom).to_fixed().to_num::<f64>()
+                ))
+                .collect();
+            return simd::samples(&points, &params).iter().map(|sample| sample.value(&params) as f32).collect();
+        }
+        let formula : &dyn Divergence = formula::get(params.formula);
+        offsets.map(|(dx, dy)| supersample::sample(formula, &params, dx, dy).value(&params) as f32).collect()
+    }
+}
+
+//From the centre to a corner, in pixels
+fn half_diagonal(params : &MandleParams) -> f64 {
+    (params.width as f64).hypot(params.height as f64) / 2.0
+}
+
+fn lerp(a : [f64; 3], b : [f64; 3], t : f64) -> [f64; 3] {
+    [0, 1, 2].map(|channel| a[channel] + (b[channel] - a[channel]) * t)
+}
@@ -0,0 +1,458 @@
+//Iteration formulas the renderer can draw. Each formula only knows how to
+//take one step, when to stop and what value a finished point gets, the
+//render loop and mandlebrot/julia handling are shared
+
+use crate::double_double::DoubleDouble;
+use crate::nucleus::{self, Nucleus};
+use crate::palette::Palette;
+use crate::real::Real;
+use crate::{Backend, MandleParams, MReal, Precision, Sample, calc_mandle_divergence, calc_orbit, escaped};
+
+//Multibrot exponents the +/- keys move between
+pub const MIN_EXPONENT: f64 = 2.0;
+pub const MAX_EXPONENT: f64 = 16.0;
+
+//Past this |z^d| no longer fits in MReal, the point escapes on the next check anyway
+const MAX_MODULUS: f64 = 512.0;
+
+//A newton point has converged once |z^n - 1|^2 is below this
+const NEWTON_TOLERANCE: f64 = 1.0e-12;
+
+//Newton points get half as bright every this many iterations
+const NEWTON_FALLOFF: f64 = 8.0;
+
+pub trait FractalFormula : Sync {
+    fn name(&self) -> &'static str;
+
+    //z[n+1] from z[n] and the constant c. The methods generic over Real are
+    //only called from the escape time loop, which knows the formula's type
+    fn step<R : Real>(&self, z : (R, R), c : (R, R), params : &MandleParams) -> (R, R) where Self : Sized;
+
+    //Power of z in the formula, used for smooth colouring
+    fn degree(&self, _params : &MandleParams) -> f64 {
+        2.0
+    }
+
+    //True once z has left the set and iteration can stop
+    fn bailout<R : Real>(&self, z : (R, R), bailout2 : R, _params : &MandleParams) -> bool where Self : Sized {
+        escaped(z.0, z.1, bailout2)
+    }
+
+    //Colour value of a point that stopped after i iterations at z,
+    //points that never stop are 0
+    fn value(&self, i : u32, (a, b) : (f64, f64), params : &MandleParams) -> f64 {
+        params.color_mode.divergence(i, a * a + b * b, params.iterations, self.degree(params))
+    }
+
+    //Linear colour of a value, offset shifts the palette in repeats
+    fn color(&self, value : f64, palette : &Palette, offset : f64) -> [f64; 3] {
+        palette.color(value, offset)
+    }
+
+    //f'(z) * dz, the next derivative of the orbit before adding dc/dc for
+    //the mandlebrot set. None where the formula isn't complex differentiable
+    fn derivative<R : Real>(&self, _z : (R, R), _dz : (f64, f64), _params : &MandleParams) -> Option<(f64, f64)> where Self : Sized {
+        None
+    }
+
+    //True for a constant c whose orbit is known to stay bounded, those
+    //points are 0 without iterating. Only asked about mandlebrot pixels,
+    //a julia set has the same c everywhere
+    fn interior<R : Real>(&self, _c : (R, R), _params : &MandleParams) -> bool where Self : Sized {
+        false
+    }
+
+    //Whether the escape time loop should look for orbits stuck in a cycle
+    //and stop them early. Only worth it where the interior is made of
+    //attracting cycles, it is an extra comparison every iteration otherwise
+    fn periodic(&self) -> bool {
+        false
+    }
+
+    //Whether values are escape times histogram colouring can spread out,
+    //formulas with their own colour scheme keep their values as they are
+    fn equalized(&self) -> bool {
+        true
+    }
+
+    //Whether step goes through f64 whatever type the loop runs in, so
+    //zooms past f64 can't be resolved in any precision
+    fn f64_only(&self, _params : &MandleParams) -> bool {
+        false
+    }
+
+    //Whether the f64 simd kernel draws it instead of the escape time loop,
+    //like the gpu shader it is only written for z^2 + c
+    fn vectorized(&self) -> bool {
+        false
+    }
+
+    //The gpu shader and perturbation are written for one formula only,
+    //anything else is drawn by the cpu
+    fn supports(&self, backend : Backend) -> bool {
+        backend == Backend::Cpu
+    }
+}
+
+//The escape time loop, implemented for every formula so the loop is
+//compiled per formula and only the call per pixel goes through the vtable
+pub trait Divergence : FractalFormula {
+    fn divergence(&self, z : (MReal, MReal), c : (MReal, MReal), params : &MandleParams) -> Sample;
+
+    //See calc_orbit, in the precision divergence would use
+    fn orbit(&self, z : (MReal, MReal), c : (MReal, MReal), params : &MandleParams, limit : usize) -> Vec<(f64, f64)>;
+
+    //See nucleus::find
+    fn nucleus(&self, params : &MandleParams) -> Result<Nucleus, String>;
+}
+
+impl<F : FractalFormula> Divergence for F {
+    fn divergence(&self, z : (MReal, MReal), c : (MReal, MReal), params : &MandleParams) -> Sample {
+        match params.precision() {
+            Precision::Double => {
+                let z = (z.0.to_f64(), z.1.to_f64());
+                let c = (c.0.to_f64(), c.1.to_f64());
+                calc_mandle_divergence(self, z, c, params)
+            }
+            Precision::DoubleDouble => {
+                let z = (DoubleDouble::from_fixed(z.0), DoubleDouble::from_fixed(z.1));
+                let c = (DoubleDouble::from_fixed(c.0), DoubleDouble::from_fixed(c.1));
+                calc_mandle_divergence(self, z, c, params)
+            }
+            //Past fixed point only perturbation has the precision, this is as close as it gets
+            _ => calc_mandle_divergence(self, z, c, params),
+        }
+    }
+
+    fn orbit(&self, z : (MReal, MReal), c : (MReal, MReal), params : &MandleParams, limit : usize) -> Vec<(f64, f64)> {
+        match params.precision() {
+            Precision::Double => {
+                let z = (z.0.to_f64(), z.1.to_f64());
+                let c = (c.0.to_f64(), c.1.to_f64());
+                calc_orbit(self, z, c, params, limit)
+            }
+            Precision::DoubleDouble => {
+                let z = (DoubleDouble::from_fixed(z.0), DoubleDouble::from_fixed(z.1));
+                let c = (DoubleDouble::from_fixed(c.0), DoubleDouble::from_fixed(c.1));
+                calc_orbit(self, z, c, params, limit)
+            }
+            _ => calc_orbit(self, z, c, params, limit),
+        }
+    }
+
+    fn nucleus(&self, params : &MandleParams) -> Result<Nucleus, String> {
+        nucleus::find(self, params)
+    }
+}
+
+//z^2 + c
+pub struct Mandlebrot;
+
+impl FractalFormula for Mandlebrot {
+    fn name(&self) -> &'static str {
+        "Mandlebrot"
+    }
+
+    fn periodic(&self) -> bool {
+        true
+    }
+
+    fn step<R : Real>(&self, (a, b) : (R, R), (c_a, c_b) : (R, R), _params : &MandleParams) -> (R, R) {
+        (
+            a * a - b * b + c_a,
+            R::from_f64(2.0) * a * b + c_b,
+        )
+    }
+
+    //2 * z * dz
+    fn derivative<R : Real>(&self, (a, b) : (R, R), dz : (f64, f64), _params : &MandleParams) -> Option<(f64, f64)> {
+        let z = (2.0 * a.to_f64(), 2.0 * b.to_f64());
+        Some(complex_mul_f64(z, dz))
+    }
+
+    fn interior<R : Real>(&self, c : (R, R), _params : &MandleParams) -> bool {
+        in_main_bulbs(c)
+    }
+
+    fn vectorized(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _backend : Backend) -> bool {
+        true
+    }
+}
+
+//z^2 + c with the absolute value of both parts of z taken before squaring
+pub struct BurningShip;
+
+impl FractalFormula for BurningShip {
+    fn name(&self) -> &'static str {
+        "Burning ship"
+    }
+
+    fn periodic(&self) -> bool {
+        true
+    }
+
+    fn step<R : Real>(&self, (a, b) : (R, R), (c_a, c_b) : (R, R), _params : &MandleParams) -> (R, R) {
+        (
+            a * a - b * b + c_a,
+            R::from_f64(2.0) * (a * b).abs() + c_b,
+        )
+    }
+}
+
+//The mandlebar, z is conjugated before squaring
+pub struct Tricorn;
+
+impl FractalFormula for Tricorn {
+    fn name(&self) -> &'static str {
+        "Tricorn"
+    }
+
+    fn periodic(&self) -> bool {
+        true
+    }
+
+    fn step<R : Real>(&self, (a, b) : (R, R), (c_a, c_b) : (R, R), _params : &MandleParams) -> (R, R) {
+        (
+            a * a - b * b + c_a,
+            R::from_f64(-2.0) * a * b + c_b,
+        )
+    }
+}
+
+//z^d + c for the exponent in the params. Whole exponents are multiplied
+//out in the loop's number type, fractional ones go through polar form in f64
+pub struct Multibrot;
+
+impl FractalFormula for Multibrot {
+    fn name(&self) -> &'static str {
+        "Multibrot"
+    }
+
+    fn periodic(&self) -> bool {
+        true
+    }
+
+    fn step<R : Real>(&self, (a, b) : (R, R), (c_a, c_b) : (R, R), params : &MandleParams) -> (R, R) {
+        let exponent = params.exponent;
+        let a_f = a.to_f64();
+        let b_f = b.to_f64();
+        let modulus = (a_f * a_f + b_f * b_f).sqrt().powf(exponent);
+        if modulus > MAX_MODULUS {
+            return (R::MAX, R::MAX);
+        }
+
+        if exponent.fract() == 0.0 {
+            let (a, b) = complex_pow((a, b), exponent as u32);
+            (a + c_a, b + c_b)
+        } else {
+            let angle = b_f.atan2(a_f) * exponent;
+            (
+                R::from_f64(modulus * angle.cos()) + c_a,
+                R::from_f64(modulus * angle.sin()) + c_b,
+            )
+        }
+    }
+
+    fn degree(&self, params : &MandleParams) -> f64 {
+        params.exponent
+    }
+
+    //Fractional powers are taken in polar form
+    fn f64_only(&self, params : &MandleParams) -> bool {
+        params.exponent.fract() != 0.0
+    }
+
+    //d * z^(d-1) * dz, in polar form for any exponent
+    fn derivative<R : Real>(&self, (a, b) : (R, R), dz : (f64, f64), params : &MandleParams) -> Option<(f64, f64)> {
+        let exponent = params.exponent;
+        let a = a.to_f64();
+        let b = b.to_f64();
+        let modulus = exponent * (a * a + b * b).sqrt().powf(exponent - 1.0);
+        let angle = b.atan2(a) * (exponent - 1.0);
+        Some(complex_mul_f64((modulus * angle.cos(), modulus * angle.sin()), dz))
+    }
+}
+
+//Newton's method on z^n - 1 with n the rounded exponent, z[0] is the
+//pixel. Points are coloured by which of the n roots of unity they end up
+//at and darkened by how long it took. Iterated in f64, the derivative
+//n * z^(n-1) quickly leaves the range of MReal
+pub struct Newton;
+
+impl Newton {
+    fn degree(params : &MandleParams) -> i32 {
+        params.exponent.round() as i32
+    }
+}
+
+impl FractalFormula for Newton {
+    fn name(&self) -> &'static str {
+        "Newton"
+    }
+
+    //z - (z^n - 1) / (n * z^(n-1)), rearranged to z * (n-1)/n + 1 / (n * z^(n-1))
+    fn step<R : Real>(&self, (a, b) : (R, R), _c : (R, R), params : &MandleParams) -> (R, R) {
+        let n = Newton::degree(params);
+        let a = a.to_f64();
+        let b = b.to_f64();
+        let modulus = (a * a + b * b).sqrt();
+        //The derivative is 0 at the origin, the point never converges
+        if modulus == 0.0 {
+            return (R::from_f64(0.0), R::from_f64(0.0));
+        }
+        let angle = b.atan2(a);
+        let inverse_modulus = modulus.powi(1 - n) / n as f64;
+        let inverse_angle = angle * (1 - n) as f64;
+        let scale = (n - 1) as f64 / n as f64;
+        (
+            R::from_f64(a * scale + inverse_modulus * inverse_angle.cos()),
+            R::from_f64(b * scale + inverse_modulus * inverse_angle.sin()),
+        )
+    }
+
+    fn degree(&self, params : &MandleParams) -> f64 {
+        Newton::degree(params) as f64
+    }
+
+    fn f64_only(&self, _params : &MandleParams) -> bool {
+        true
+    }
+
+    //Converged onto a root rather than escaped
+    fn bailout<R : Real>(&self, (a, b) : (R, R), _bailout2 : R, params : &MandleParams) -> bool {
+        let n = Newton::degree(params) as f64;
+        let a = a.to_f64();
+        let b = b.to_f64();
+        let modulus = (a * a + b * b).sqrt().powf(n);
+        let angle = b.atan2(a) * n;
+        let re = modulus * angle.cos() - 1.0;
+        let im = modulus * angle.sin();
+        re * re + im * im < NEWTON_TOLERANCE
+    }
+
+    //Root index k (at angle 2 pi k / n) plus the brightness in 0..1
+    fn value(&self, i : u32, (a, b) : (f64, f64), params : &MandleParams) -> f64 {
+        let n = Newton::degree(params);
+        let angle = b.atan2(a);
+        let root = (angle * n as f64 / std::f64::consts::TAU).round().rem_euclid(n as f64);
+        let shade = 0.5f64.powf(i as f64 / NEWTON_FALLOFF).min(0.999);
+        root + shade
+    }
+
+    fn equalized(&self) -> bool {
+        false
+    }
+
+    //Neighbouring roots are spread around the palette by the golden ratio,
+    //starting away from 0 where most palettes are dark
+    fn color(&self, value : f64, palette : &Palette, offset : f64) -> [f64; 3] {
+        if value <= 0.0 {
+            return [0.0; 3];
+        }
+        let root = value.floor();
+        let shade = value.fract();
+        let color = palette.sample(((root + 1.0) * 0.618_034 + offset).fract());
+        color.map(|channel| channel * shade)
+    }
+}
+
+//A formula from a script file, see script. Without one it is z^2 + c, so
+//params naming it still draw something. Scripts run in f64 complex numbers
+pub struct Scripted;
+
+impl FractalFormula for Scripted {
+    fn name(&self) -> &'static str {
+        "Script"
+    }
+
+    fn step<R : Real>(&self, (a, b) : (R, R), (c_a, c_b) : (R, R), params : &MandleParams) -> (R, R) {
+        let Some(script) = &params.script else {
+            return Mandlebrot.step((a, b), (c_a, c_b), params);
+        };
+        let (a, b) = script.step(
+            (a.to_f64(), b.to_f64()),
+            (c_a.to_f64(), c_b.to_f64()),
+            params.exponent,
+            params.bailout * params.bailout,
+        );
+        (R::from_f64(a), R::from_f64(b))
+    }
+
+    fn degree(&self, params : &MandleParams) -> f64 {
+        params.script.as_ref().map_or(2.0, |script| script.degree())
+    }
+
+    fn bailout<R : Real>(&self, (a, b) : (R, R), bailout2 : R, params : &MandleParams) -> bool {
+        match &params.script {
+            Some(script) => script.escaped((a.to_f64(), b.to_f64()), params.exponent, bailout2.to_f64()),
+            None => escaped(a, b, bailout2),
+        }
+    }
+
+    fn f64_only(&self, _params : &MandleParams) -> bool {
+        true
+    }
+}
+
+//Closed form membership of the main cardioid and the period 2 bulb of
+//z^2 + c, together they cover most of the set when zoomed out
+pub fn in_main_bulbs<R : Real>((a, b) : (R, R)) -> bool {
+    //Both are well inside |c| < 2, ruling out the rest first keeps the squares in range
+    let two = R::from_f64(2.0);
+    if a.abs() > two || b.abs() > two {
+        return false;
+    }
+    let one = R::from_f64(1.0);
+    let quarter = R::from_f64(0.25);
+    let b2 = b * b;
+    let q = (a - quarter) * (a - quarter) + b2;
+    let cardioid = q * (q + (a - quarter)) <= quarter * b2;
+    let bulb = (a + one) * (a + one) + b2 <= R::from_f64(0.0625);
+    cardioid || bulb
+}
+
+fn complex_mul<R : Real>((a, b) : (R, R), (c, d) : (R, R)) -> (R, R) {
+    (a * c - b * d, a * d + b * c)
+}
+
+fn complex_mul_f64((a, b) : (f64, f64), (c, d) : (f64, f64)) -> (f64, f64) {
+    (a * c - b * d, a * d + b * c)
+}
+
+//Square and multiply
+fn complex_pow<R : Real>(mut base : (R, R), mut power : u32) -> (R, R) {
+    let mut result = (R::from_f64(1.0), R::from_f64(0.0));
+    while power > 0 {
+        if power & 1 == 1 {
+            result = complex_mul(result, base);
+        }
+        power >>= 1;
+        if power > 0 {
+            base = complex_mul(base, base);
+        }
+    }
+    result
+}
+
+//Cycled through in this order, params refer to formulas by index
+pub static FORMULAS: &[&dyn Divergence] = &[
+    &Mandlebrot,
+    &BurningShip,
+    &Tricorn,
+    &Multibrot,
+    &Newton,
+    &Scripted,
+];
+
+pub fn get(index : usize) -> &'static dyn Divergence {
+    FORMULAS[index]
+}
+
+//Formula by name ignoring case
+pub fn find(name : &str) -> Option<usize> {
+    FORMULAS.iter().position(|formula| formula.name().eq_ignore_ascii_case(name))
+}
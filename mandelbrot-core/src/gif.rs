@@ -0,0 +1,420 @@
+//Animated GIF encoding for short loops of rendered frames. Every frame
+//shares one 256 colour table, median cut from all of them, so colours
+//don't flicker between frames. The table is small next to the smooth
+//palettes, dithering hides the steps between its colours
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use crate::offline::OfflineError;
+use crate::palette;
+
+const COLORS: usize = 256;
+
+//Pixels looked at to build the colour table, spread over every frame
+const SAMPLES: usize = 1 << 16;
+
+//LZW codes are at most 12 bits, the table starts over once it fills
+const MAX_CODE: u16 = 4095;
+
+//How colours between those in the table are drawn
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum GifDither {
+    //The nearest colour, in bands
+    None,
+    //A fixed pattern, the same from frame to frame so it doesn't crawl
+    Ordered,
+    //Floyd-Steinberg, smoother but moves when the image under it does
+    Diffusion,
+}
+
+//Writes rgb frames of width x height as a GIF looping forever, each shown
+//for delay_ms. GIF times are in hundredths of a second
+pub fn write_gif(
+    path : &Path,
+    frames : &[Vec<u8>],
+    width : usize,
+    height : usize,
+    delay_ms : u32,
+    dither : GifDither
+) -> Result<(), OfflineError> {
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("a GIF can't be {}x{}", width, height)).into());
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    encode_gif(&mut file, frames, width, height, delay_ms, dither)?;
+    file.flush()?;
+    Ok(())
+}
+
+//The bytes of write_gif's file, the size already checked
+fn encode_gif(
+    file : &mut impl Write,
+    frames : &[Vec<u8>],
+    width : usize,
+    height : usize,
+    delay_ms : u32,
+    dither : GifDither
+) -> Result<(), Error> {
+    let colors = color_table(frames);
+    let mut lookup = Lookup::new(&colors);
+    let spread = spread(&colors);
+    let delay = (delay_ms / 10).min(u16::MAX as u32) as u16;
+
+    file.write_all(b"GIF89a")?;
+    file.write_all(&(width as u16).to_le_bytes())?;
+    file.write_all(&(height as u16).to_le_bytes())?;
+    //A global table of 2^(7+1) colours at 8 bits a channel
+    file.write_all(&[0xf7, 0, 0])?;
+    for i in 0..COLORS {
+        file.write_all(&colors.get(i).copied().unwrap_or([0; 3]))?;
+    }
+    //Loops forever
+    file.write_all(&[0x21, 0xff, 11])?;
+    file.write_all(b"NETSCAPE2.0")?;
+    file.write_all(&[3, 1, 0, 0, 0])?;
+
+    for frame in frames {
+        let indices = map_frame(frame, width, height, dither, spread, &mut lookup);
+        //Graphic control, each frame is left in place for the next to cover
+        file.write_all(&[0x21, 0xf9, 4, 0x04])?;
+        file.write_all(&delay.to_le_bytes())?;
+        file.write_all(&[0, 0])?;
+        //Image descriptor covering the whole screen, no local table
+        file.write_all(&[0x2c, 0, 0, 0, 0])?;
+        file.write_all(&(width as u16).to_le_bytes())?;
+        file.write_all(&(height as u16).to_le_bytes())?;
+        file.write_all(&[0, 8])?;
+        for block in lzw(&indices).chunks(255) {
+            file.write_all(&[block.len() as u8])?;
+            file.write_all(block)?;
+        }
+        file.write_all(&[0])?;
+    }
+    file.write_all(&[0x3b])
+}
+
+//Median cut. The box with the widest channel is split at its median until
+//there are COLORS boxes, each gives the average of its pixels
+pub(crate) fn color_table(frames : &[impl AsRef<[u8]>]) -> Vec<[u8; 3]> {
+    let pixels = frames.iter().map(|frame| frame.as_ref().len() / 3).sum::<usize>();
+    let step = (pixels / SAMPLES).max(1);
+    let samples : Vec<[u8; 3]> = frames.iter()
+        .flat_map(|frame| frame.as_ref().chunks_exact(3))
+        .step_by(step)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    if samples.is_empty() {
+        return vec![[0; 3]];
+    }
+
+    //Widest channel of a box and how wide it is
+    fn widest(pixels : &[[u8; 3]]) -> (usize, u8) {
+        (0..3).map(|channel| {
+            let min = pixels.iter().map(|pixel| pixel[channel]).min().unwrap_or(0);
+            let max = pixels.iter().map(|pixel| pixel[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        }).max_by_key(|&(_, range)| range).unwrap_or((0, 0))
+    }
+    let mut boxes = vec![samples];
+    while boxes.len() < COLORS {
+        let Some((index, channel)) = boxes.iter().enumerate()
+            .map(|(index, pixels)| (index, widest(pixels)))
+            .filter(|&(_, (_, range))| range > 0)
+            .max_by_key(|&(_, (_, range))| range)
+            .map(|(index, (channel, _))| (index, channel))
+        else {
+            break;
+        };
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|pixels| {
+        let mut sum = [0u64; 3];
+        for pixel in pixels {
+            for channel in 0..3 {
+                sum[channel] += pixel[channel] as u64;
+            }
+        }
+        sum.map(|channel| ((channel + pixels.len() as u64 / 2) / pixels.len() as u64) as u8)
+    }).collect()
+}
+
+//How far apart the table's colours are in a channel, on average from each
+//colour to its nearest neighbour. Ordered dithering is spread over this
+pub(crate) fn spread(colors : &[[u8; 3]]) -> f64 {
+    if colors.len() < 2 {
+        return 0.0;
+    }
+    let total : f64 = colors.iter().enumerate().map(|(i, color)| {
+        colors.iter().enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, other)| distance(*color, *other))
+            .min()
+            .map_or(0.0, |squared| (squared as f64 / 3.0).sqrt())
+    }).sum();
+    total / colors.len() as f64
+}
+
+fn distance(a : [u8; 3], b : [u8; 3]) -> u32 {
+    (0..3).map(|channel| (a[channel] as i32 - b[channel] as i32).pow(2) as u32).sum()
+}
+
+//Nearest table colour to a pixel, remembered for pixels that round to the
+//same 6 bits a channel
+pub(crate) struct Lookup<'a> {
+    colors : &'a [[u8; 3]],
+    nearest : Vec<u16>,
+}
+
+impl<'a> Lookup<'a> {
+    const UNSET: u16 = u16::MAX;
+
+    pub(crate) fn new(colors : &'a [[u8; 3]]) -> Lookup<'a> {
+        Lookup {
+            colors,
+            nearest: vec![Lookup::UNSET; 1 << 18],
+        }
+    }
+
+    fn index(&mut self, pixel : [u8; 3]) -> u8 {
+        let key = (pixel[0] as usize >> 2) << 12 | (pixel[1] as usize >> 2) << 6 | pixel[2] as usize >> 2;
+        if self.nearest[key] == Lookup::UNSET {
+            //Measured from the middle of the cell so the answer is the same
+            //whichever pixel in it asked first
+            let centre = pixel.map(|channel| (channel & !3) | 2);
+            let index = (0..self.colors.len())
+                .min_by_key(|&index| distance(centre, self.colors[index]))
+                .unwrap_or(0);
+            self.nearest[key] = index as u16;
+        }
+        self.nearest[key] as u8
+    }
+}
+
+//The table index of each pixel of an rgb frame
+pub(crate) fn map_frame(
+    frame : &[u8],
+    width : usize,
+    height : usize,
+    dither : GifDither,
+    spread : f64,
+    lookup : &mut Lookup
+) -> Vec<u8> {
+    match dither {
+        GifDither::None => map_nearest(frame, lookup),
+        GifDither::Ordered => map_ordered(frame, width, spread, lookup),
+        GifDither::Diffusion => map_diffused(frame, width, height, lookup),
+    }
+}
+
+fn map_nearest(frame : &[u8], lookup : &mut Lookup) -> Vec<u8> {
+    frame.chunks_exact(3).map(|pixel| lookup.index([pixel[0], pixel[1], pixel[2]])).collect()
+}
+
+fn map_ordered(frame : &[u8], width : usize, spread : f64, lookup : &mut Lookup) -> Vec<u8> {
+    frame.chunks_exact(3).enumerate().map(|(i, pixel)| {
+        let offset = palette::dither((i % width, i / width)) * spread;
+        lookup.index([0, 1, 2].map(|channel| (pixel[channel] as f64 + offset).round().clamp(0.0, 255.0) as u8))
+    }).collect()
+}
+
+//Floyd-Steinberg, the error of each pixel is passed on to the ones right
+//of and below it
+fn map_diffused(frame : &[u8], width : usize, height : usize, lookup : &mut Lookup) -> Vec<u8> {
+    let mut indices = Vec::with_capacity(width * height);
+    //One pixel of margin either side so the edges need no checks
+    let mut row = vec![[0f32; 3]; width + 2];
+    let mut next = vec![[0f32; 3]; width + 2];
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = &frame[(y * width + x) * 3..][..3];
+            let wanted : [f32; 3] = std::array::from_fn(|channel| pixel[channel] as f32 + row[x + 1][channel]);
+            let index = lookup.index(wanted.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+            let got = lookup.colors[index as usize];
+            for channel in 0..3 {
+                let error = wanted[channel] - got[channel] as f32;
+                row[x + 2][channel] += error * 7.0 / 16.0;
+                next[x][channel] += error * 3.0 / 16.0;
+                next[x + 1][channel] += error * 5.0 / 16.0;
+                next[x + 2][channel] += error / 16.0;
+            }
+            indices.push(index);
+        }
+        std::mem::swap(&mut row, &mut next);
+        next.fill([0.0; 3]);
+    }
+    indices
+}
+
+//The image data of a frame, 8 bit indices LZW compressed with variable
+//width codes packed from the low bit up
+fn lzw(indices : &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+    let mut out = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    let mut emit = |code : u16, size : u32, out : &mut Vec<u8>| {
+        bits |= (code as u32) << count;
+        count += size;
+        while count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+
+    let mut table : HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = 9;
+    let mut next = END + 1;
+    emit(CLEAR, size, &mut out);
+    let Some((&first, rest)) = indices.split_first() else {
+        emit(END, size, &mut out);
+        emit(0, 7, &mut out);
+        return out;
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        emit(prefix, size, &mut out);
+        //The decoder widens its codes once its table reaches the next power
+        //of two. Its table is a code behind, so this is checked before adding
+        if next as u32 >= 1 << size && size < 12 {
+            size += 1;
+        }
+        if next < MAX_CODE {
+            table.insert((prefix, index), next);
+            next += 1;
+        } else {
+            emit(CLEAR, size, &mut out);
+            table.clear();
+            size = 9;
+            next = END + 1;
+        }
+        prefix = index as u16;
+    }
+    emit(prefix, size, &mut out);
+    if next as u32 >= 1 << size && size < 12 {
+        size += 1;
+    }
+    emit(END, size, &mut out);
+    //Whatever is left of the last byte
+    emit(0, 7, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use weezl::BitOrder;
+    use weezl::decode::Decoder;
+
+    fn decode(data : &[u8]) -> Vec<u8> {
+        Decoder::new(BitOrder::Lsb, 8).decode(data).unwrap()
+    }
+
+    //Bytes from a fixed linear congruential sequence
+    fn noise(length : usize) -> Vec<u8> {
+        let mut state = 1u32;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn lzw_decodes_to_its_indices() {
+        for indices in [vec![], vec![7], vec![0, 0], (0..=255).collect(), vec![3; 100_000]] {
+            assert_eq!(decode(&lzw(&indices)), indices, "{} indices", indices.len());
+        }
+    }
+
+    //Clear codes in data after the first, read at the widths a decoder
+    //would with its table a code behind
+    fn clears(data : &[u8]) -> usize {
+        let (mut at, mut size, mut length, mut first, mut clears) = (0, 9, 258, true, 0);
+        while at + size <= data.len() * 8 {
+            let code = (0..size).map(|bit| ((data[(at + bit) / 8] >> ((at + bit) % 8)) as usize & 1) << bit).sum::<usize>();
+            at += size;
+            match code {
+                256 => {
+                    clears += usize::from(at > 9);
+                    (size, length, first) = (9, 258, true);
+                }
+                257 => break,
+                _ if first => first = false,
+                _ => {
+                    length = (length + 1).min(4096);
+                    if length == 1 << size && size < 12 {
+                        size += 1;
+                    }
+                }
+            }
+        }
+        clears
+    }
+
+    #[test]
+    fn lzw_starts_over_when_its_table_fills() {
+        //Noise adds a code for every byte or two, many times what the table
+        //holds before it's cleared
+        let indices = noise(50_000);
+        let data = lzw(&indices);
+        assert!(clears(&data) >= 5);
+        assert_eq!(decode(&data), indices);
+        //Under a table's worth of codes never clears
+        assert_eq!(clears(&lzw(&noise(1000))), 0);
+    }
+
+    #[test]
+    fn gif_blocks_are_laid_out() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let (width, height) = (3, 2);
+        let frames : Vec<Vec<u8>> = (0..2).map(|frame| {
+            (0..width * height).flat_map(|i| colors[(i + frame) % colors.len()]).collect()
+        }).collect();
+        let mut gif = Vec::new();
+        encode_gif(&mut gif, &frames, width, height, 250, GifDither::None).unwrap();
+
+        assert_eq!(&gif[..6], b"GIF89a");
+        //Logical screen, 3x2 with a global table of 256 colours
+        assert_eq!(&gif[6..13], &[3, 0, 2, 0, 0xf7, 0, 0]);
+        let table = &gif[13..13 + 3 * COLORS];
+        let mut at = 13 + 3 * COLORS;
+        assert_eq!(&gif[at..at + 3], &[0x21, 0xff, 11]);
+        assert_eq!(&gif[at + 3..at + 14], b"NETSCAPE2.0");
+        //Sub-block of 3, loop count 0 for forever, terminator
+        assert_eq!(&gif[at + 14..at + 19], &[3, 1, 0, 0, 0]);
+        at += 19;
+
+        for frame in &frames {
+            //Graphic control with 25 hundredths of a second
+            assert_eq!(&gif[at..at + 8], &[0x21, 0xf9, 4, 0x04, 25, 0, 0, 0]);
+            assert_eq!(&gif[at + 8..at + 18], &[0x2c, 0, 0, 0, 0, 3, 0, 2, 0, 0]);
+            assert_eq!(gif[at + 18], 8);
+            at += 19;
+            let mut data = Vec::new();
+            while gif[at] != 0 {
+                let length = gif[at] as usize;
+                data.extend_from_slice(&gif[at + 1..at + 1 + length]);
+                at += 1 + length;
+            }
+            at += 1;
+            //Few enough colours that the table holds each exactly
+            let pixels : Vec<u8> = decode(&data).iter().flat_map(|&index| table[index as usize * 3..][..3].to_vec()).collect();
+            assert_eq!(&pixels, frame);
+        }
+        assert_eq!(&gif[at..], &[0x3b]);
+    }
+}
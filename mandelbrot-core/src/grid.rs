@@ -0,0 +1,255 @@
+//Per pixel values of a frame, stored row by row. Cells are addressed by
+//their (x, y) pixel position, x along the row and y down the rows
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use rayon::prelude::*;
+
+/// Position that lies outside a [`Grid`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct OutOfBounds {
+    pub x : usize,
+    pub y : usize,
+    pub cols : usize,
+    pub rows : usize,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "x:{} y:{} out of bounds for a {}x{} grid", self.x, self.y, self.cols, self.rows)
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
+/// A `cols` x `rows` grid of cells in row-major order, cell (x, y) is
+/// stored at `y * cols + x`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid<T> {
+    rows : usize,
+    cols : usize,
+    cells : Box<[T]>,
+}
+
+impl<T : Clone> Grid<T> {
+
+    /// A grid `width` cells across and `height` down, every cell set to `default`.
+    pub fn new(width : usize, height : usize, default : T) -> Grid<T> {
+        Grid {
+            rows: height,
+            cols: width,
+            cells: vec![default; width * height].into_boxed_slice(),
+        }
+    }
+}
+
+impl<T : Clone> Grid<T> {
+
+    /// Moves every cell by (-dx, -dy), so the cell that was at (x + dx, y + dy)
+    /// ends up at (x, y). Cells with nothing to move in are set to `fill`.
+    pub fn shift(&mut self, dx : isize, dy : isize, fill : T) {
+        let moved = (0..self.rows)
+            .flat_map(|y| (0..self.cols).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let from_x = x.checked_add_signed(dx).filter(|&from_x| from_x < self.cols);
+                let from_y = y.checked_add_signed(dy).filter(|&from_y| from_y < self.rows);
+                match (from_x, from_y) {
+                    (Some(from_x), Some(from_y)) => self.cells[from_y * self.cols + from_x].clone(),
+                    _ => fill.clone(),
+                }
+            })
+            .collect();
+        self.cells = moved;
+    }
+}
+
+impl<T> Grid<T> {
+
+    /// Number of rows, the height.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of cells in a row, the width.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    fn offset(&self, x : usize, y : usize) -> Result<usize, OutOfBounds> {
+        if x < self.cols && y < self.rows {
+            Ok(y * self.cols + x)
+        } else {
+            Err(OutOfBounds { x, y, cols: self.cols, rows: self.rows })
+        }
+    }
+
+    /// The cell at (x, y), or where it falls outside the grid.
+    pub fn try_get(&self, x : usize, y : usize) -> Result<&T, OutOfBounds> {
+        self.offset(x, y).map(|offset| &self.cells[offset])
+    }
+
+    /// Mutable [`Grid::try_get`].
+    pub fn try_get_mut(&mut self, x : usize, y : usize) -> Result<&mut T, OutOfBounds> {
+        self.offset(x, y).map(|offset| &mut self.cells[offset])
+    }
+
+    /// The cells of row y.
+    pub fn row(&self, y : usize) -> &[T] {
+        &self.cells[y * self.cols..(y + 1) * self.cols]
+    }
+
+    /// Every cell with its (x, y) position, row by row from the top left.
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let cols = self.cols;
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(offset, cell)| ((offset % cols, offset / cols), cell))
+    }
+
+    /// Mutable [`Grid::iter`].
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = ((usize, usize), &mut T)> {
+        let cols = self.cols;
+        self.cells
+            .iter_mut()
+            .enumerate()
+            .map(move |(offset, cell)| ((offset % cols, offset / cols), cell))
+    }
+}
+
+impl<T : Send> Grid<T> {
+
+    /// The rows with their y, to be filled in on the rayon pool.
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = (usize, &mut [T])> {
+        //Chunks of 0 panic, an empty grid has no rows either way
+        self.cells.par_chunks_mut(self.cols.max(1)).enumerate()
+    }
+}
+
+//Out of bounds positions panic like slice indexing, try_get doesn't
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y) : (usize, usize)) -> &T {
+        match self.try_get(x, y) {
+            Ok(cell) => cell,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y) : (usize, usize)) -> &mut T {
+        match self.offset(x, y) {
+            Ok(offset) => &mut self.cells[offset],
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Wider than it is high, so mixing up rows and columns shows
+    fn numbered() -> Grid<usize> {
+        let mut grid = Grid::new(3, 2, 0);
+        for ((x, y), cell) in grid.iter_mut() {
+            *cell = y * 10 + x;
+        }
+        grid
+    }
+
+    #[test]
+    fn dimensions() {
+        let grid = numbered();
+        assert_eq!(grid.cols(), 3);
+        assert_eq!(grid.rows(), 2);
+    }
+
+    #[test]
+    fn row_major() {
+        let grid = numbered();
+        assert_eq!(grid.row(0), &[0, 1, 2]);
+        assert_eq!(grid.row(1), &[10, 11, 12]);
+        assert_eq!(grid[(2, 1)], 12);
+        assert_eq!(grid[(0, 1)], 10);
+    }
+
+    #[test]
+    fn index_mut() {
+        let mut grid = numbered();
+        grid[(1, 1)] = 99;
+        assert_eq!(grid.row(1), &[10, 99, 12]);
+        *grid.try_get_mut(2, 0).unwrap() = 42;
+        assert_eq!(grid.row(0), &[0, 1, 42]);
+    }
+
+    #[test]
+    fn try_get_in_bounds() {
+        let grid = numbered();
+        assert_eq!(grid.try_get(2, 1), Ok(&12));
+    }
+
+    #[test]
+    fn try_get_out_of_bounds() {
+        let grid = numbered();
+        assert_eq!(grid.try_get(3, 0), Err(OutOfBounds { x: 3, y: 0, cols: 3, rows: 2 }));
+        //In range of the width, which the old layout mixed up with the height
+        assert!(grid.try_get(0, 2).is_err());
+        assert!(grid.try_get(2, 2).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "x:0 y:2 out of bounds for a 3x2 grid")]
+    fn index_out_of_bounds() {
+        let _ = numbered()[(0, 2)];
+    }
+
+    #[test]
+    fn iter_positions() {
+        let positions : Vec<(usize, usize)> = numbered().iter().map(|(pos, _)| pos).collect();
+        assert_eq!(positions, [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+        assert!(numbered().iter().all(|((x, y), &cell)| cell == y * 10 + x));
+    }
+
+    #[test]
+    fn par_rows() {
+        let mut grid = Grid::new(3, 2, 0);
+        grid.par_rows_mut().for_each(|(y, row)| {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = y * 10 + x;
+            }
+        });
+        assert_eq!(grid, numbered());
+    }
+
+    #[test]
+    fn shift() {
+        let mut grid = numbered();
+        grid.shift(1, 0, 0);
+        assert_eq!(grid.row(0), &[1, 2, 0]);
+        assert_eq!(grid.row(1), &[11, 12, 0]);
+
+        let mut grid = numbered();
+        grid.shift(-1, 1, 99);
+        assert_eq!(grid.row(0), &[99, 10, 11]);
+        assert_eq!(grid.row(1), &[99, 99, 99]);
+    }
+
+    #[test]
+    fn shift_past_the_edge() {
+        let mut grid = numbered();
+        grid.shift(0, -2, 7);
+        assert!(grid.iter().all(|(_, &cell)| cell == 7));
+    }
+
+    #[test]
+    fn empty() {
+        let mut grid = Grid::new(0, 0, 0);
+        assert_eq!(grid.iter().count(), 0);
+        assert_eq!(grid.par_rows_mut().count(), 0);
+        assert!(grid.try_get(0, 0).is_err());
+    }
+}
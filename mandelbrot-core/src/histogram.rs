@@ -0,0 +1,59 @@
+//Histogram colouring. Each value is replaced by the fraction of the
+//frame's escaped pixels at or below it, so every part of the palette
+//covers the same number of pixels whatever the zoom and iteration limit
+
+use crate::{Grid, MandleParams, Sample};
+
+#[derive(Clone)]
+pub struct Equalizer {
+    //Bins are one iteration wide, values are scaled back up by this
+    iterations : f64,
+    //cumulative[i] is the fraction of escaped pixels below bin i
+    cumulative : Vec<f64>,
+}
+
+impl Equalizer {
+
+    //From the pixels a frame at this refinement step was drawn from
+    pub fn new(grid : &Grid<Sample>, step : usize, params : &MandleParams) -> Equalizer {
+        let iterations = params.iterations.max(1) as f64;
+        let bins = params.iterations as usize + 2;
+        let mut counts = vec![0u64; bins];
+        for y in (0..grid.rows()).step_by(step) {
+            for x in (0..grid.cols()).step_by(step) {
+                let value = grid[(x, y)].value(params);
+                if value > 0.0 {
+                    counts[bin(value, iterations, bins)] += 1;
+                }
+            }
+        }
+
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        let mut cumulative = Vec::with_capacity(counts.len() + 1);
+        let mut below = 0;
+        cumulative.push(0.0);
+        for count in counts {
+            below += count;
+            cumulative.push(below as f64 / total);
+        }
+        Equalizer { iterations, cumulative }
+    }
+
+    //Interpolated within the bin so smooth colouring stays smooth
+    pub fn map(&self, value : f64) -> f64 {
+        if value <= 0.0 {
+            return value;
+        }
+        let bins = self.cumulative.len() - 1;
+        let scaled = value * self.iterations;
+        let i = bin(value, self.iterations, bins);
+        let (low, high) = (self.cumulative[i], self.cumulative[i + 1]);
+        let fraction = (scaled - i as f64).clamp(0.0, 1.0);
+        //0 is the interior, the first escaped pixel still needs a colour
+        (low + (high - low) * fraction).max(f64::MIN_POSITIVE)
+    }
+}
+
+fn bin(value : f64, iterations : f64, bins : usize) -> usize {
+    ((value * iterations) as usize).min(bins - 1)
+}
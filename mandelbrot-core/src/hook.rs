@@ -0,0 +1,62 @@
+//What colour modes track along the orbit besides where it stopped. The
+//escape time loops are generic over the hook, so each mode gets a loop of
+//its own and the plain one, with (), has nothing extra left in it
+
+use crate::real::Real;
+use crate::{Fractal, MandleParams};
+
+pub trait OrbitHook {
+    //Whether the interior is coloured by the period of the cycle its orbit
+    //settles into, so it has to be iterated until the cycle check catches it
+    const PERIOD: bool = false;
+
+    //Sees z[i] before it is checked for escape, z[0] being the start
+    fn visit<R : Real>(&mut self, i : u32, z : (R, R));
+
+    //What the sample keeps as its distance for an orbit that escaped with
+    //|z|^2 = mod2, degree the power z is raised to each iteration
+    fn escaped(&self, mod2 : f64, degree : f64, params : &MandleParams) -> f32;
+}
+
+impl OrbitHook for () {
+    #[inline(always)]
+    fn visit<R : Real>(&mut self, _i : u32, _z : (R, R)) {}
+
+    fn escaped(&self, _mod2 : f64, _degree : f64, _params : &MandleParams) -> f32 {
+        f32::NAN
+    }
+}
+
+//Iteration the orbit came closest to 0 on, for atom colouring. z[0] is the
+//pixel for julia sets and doesn't count, the mandlebrot set's is 0 and
+//iteration i reaches z[i + 1]
+pub struct AtomDomain {
+    first : u32,
+    closest : (f64, f32),
+}
+
+impl AtomDomain {
+    pub fn new(params : &MandleParams) -> AtomDomain {
+        AtomDomain {
+            first: if params.fractal == Fractal::Mandlebrot { 1 } else { 0 },
+            closest: (f64::MAX, f32::NAN),
+        }
+    }
+}
+
+impl OrbitHook for AtomDomain {
+    const PERIOD: bool = true;
+
+    fn visit<R : Real>(&mut self, i : u32, z : (R, R)) {
+        if i + self.first > 0 {
+            let (a, b) = (z.0.to_f64(), z.1.to_f64());
+            if a * a + b * b < self.closest.0 {
+                self.closest = (a * a + b * b, (i + self.first) as f32);
+            }
+        }
+    }
+
+    fn escaped(&self, _mod2 : f64, _degree : f64, _params : &MandleParams) -> f32 {
+        self.closest.1
+    }
+}
@@ -0,0 +1,1035 @@
+//! The mandlebrot renderer without a window: the formulas, the cpu and
+//! perturbation backends, colouring and the render pipeline they feed.
+//! [`Renderer`] draws a view described by [`MandleParams`] into an rgba
+//! buffer, which is all an embedding program needs. The modules are public
+//! for programs that want to drive the pipeline a step at a time, as the
+//! rust_manlebrot window does.
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rayon::prelude::*;
+
+use fixed::FixedI128;
+use fixed::types::extra::U117;
+
+pub mod buddhabrot;
+pub mod colorizer;
+pub mod coord;
+pub mod double_double;
+#[cfg(feature = "rug")]
+pub mod deep;
+pub mod expmap;
+pub mod formula;
+pub mod gif;
+mod grid;
+pub mod histogram;
+pub mod hook;
+pub mod mesh;
+pub mod nucleus;
+pub mod offline;
+pub mod palette;
+pub mod perturbation;
+pub mod raw;
+pub mod real;
+mod renderer;
+pub mod script;
+pub mod shading;
+pub mod simd;
+pub mod sixel;
+pub mod stripe;
+pub mod subdivide;
+pub mod supersample;
+pub mod temporal;
+pub mod tia;
+pub mod tiles;
+pub mod trap;
+
+pub use colorizer::Colorizer;
+pub use coord::{Coord, MIN_ZOOM};
+pub use grid::{Grid, OutOfBounds};
+pub use renderer::{RenderError, Renderer};
+use hook::{AtomDomain, OrbitHook};
+use real::Real;
+use stripe::Stripe;
+use tia::Tia;
+use trap::Trapped;
+
+pub type MReal = FixedI128<U117>;
+//This type allows a max of 1024/-1024.
+//Width or heigh will be the value that decdes this range
+
+
+//Escape radius limits. Z is squared in fixed point before the escape test,
+//so bailout^2 has to stay well inside the 1024 range of MReal
+pub const MIN_BAILOUT: f64 = 2.0;
+pub const MAX_BAILOUT: f64 = 16.0;
+
+//Below this zoom level f32 can no longer tell neighbouring pixels apart,
+//so the gpu backend hands the view to the fixed point cpu path instead
+pub const GPU_MIN_ZOOM: f64 = 1.0e-6;
+
+//Most pixels a render is given, a 16384 x 16384 poster. Past that the
+//iteration data alone runs to several gigabytes
+pub const MAX_RENDER_PIXELS: usize = 1 << 28;
+
+//Whether a render size is small enough to allocate
+pub fn check_size((width, height) : (usize, usize)) -> Result<(), String> {
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= MAX_RENDER_PIXELS => Ok(()),
+        _ => Err(format!("{}x{} is more than the {} pixels a render can be", width, height, MAX_RENDER_PIXELS)),
+    }
+}
+
+//Auto iterations never go below this, shallow views still need some detail
+const MIN_AUTO_ITERATIONS: u32 = 100;
+
+//An orbit that comes back within this fraction of a pixel of an earlier
+//point is taken to be caught in a cycle
+const PERIOD_TOLERANCE: f64 = 1.0e-6;
+
+//A pan this close to whole pixels lines the new pixels up with the old ones
+//well enough to keep their values
+const PAN_TOLERANCE: f64 = 1.0e-3;
+
+//Past this zoom f64 still resolves neighbouring pixels with plenty to spare,
+//below it the cpu backends iterate in double-double
+const F64_MIN_ZOOM: f64 = 1.0e-13;
+
+//The same for double-double, below it they iterate in fixed point
+const DOUBLE_DOUBLE_MIN_ZOOM: f64 = 1.0e-29;
+
+//And the same for 128 bit fixed point, below it perturbation reference
+//orbits are iterated in MPFR
+#[cfg(feature = "rug")]
+const FIXED_MIN_ZOOM: f64 = 1.0e-30;
+
+//An orbit near the set passes |z| of about 2, floating point types can't
+//place it closer to anything than their epsilon at that size
+const ORBIT_MAGNITUDE: f64 = 2.0;
+
+//ln of the distance in pixels that maps to the end of the 0..1 value range
+const DISTANCE_RANGE: f64 = 12.0;
+
+//Gaps an orbit caught in a cycle is iterated on before its period is measured
+const PERIOD_SETTLE: u32 = 4;
+
+//A cycle none of whose steps come back within tolerance has the period of
+//the first to come back within this much of the closest, in squared distance
+const PERIOD_RETURN: f64 = 4.0;
+
+//Fractional part of the golden ratio, how far round the palette each atom
+//domain's colour is from the one before
+const ATOM_SPREAD: f64 = 0.618_033_988_749_895;
+
+//Where the divergence values are computed.
+//Gpu is f32 only, deep zooms always fall back to the fixed point cpu path.
+//Perturbation iterates one fixed point reference orbit and f64 deltas per pixel
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Backend {
+    Cpu,
+    Gpu,
+    Perturbation,
+}
+
+impl Backend {
+    pub fn next(self) -> Backend {
+        match self {
+            Backend::Cpu => Backend::Gpu,
+            Backend::Gpu => Backend::Perturbation,
+            Backend::Perturbation => Backend::Cpu,
+        }
+    }
+}
+
+//Number type the cpu escape time loop runs in, picked from the zoom.
+//Arbitrary is only reached by perturbation, other formulas stay in fixed point
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Precision {
+    Double,
+    DoubleDouble,
+    Fixed,
+    #[cfg(feature = "rug")]
+    Arbitrary,
+}
+
+impl Precision {
+    pub fn name(self) -> &'static str {
+        match self {
+            Precision::Double => "f64",
+            Precision::DoubleDouble => "double-double",
+            Precision::Fixed => "fixed 128",
+            #[cfg(feature = "rug")]
+            Precision::Arbitrary => "mpfr",
+        }
+    }
+
+    //Gap between neighbouring numbers along an orbit, pixels closer together
+    //than this come out as blocks. MPFR gets more bits as the zoom goes in
+    pub fn resolution(self) -> f64 {
+        match self {
+            Precision::Double => f64::EPSILON * ORBIT_MAGNITUDE,
+            Precision::DoubleDouble => f64::EPSILON * f64::EPSILON * ORBIT_MAGNITUDE,
+            Precision::Fixed => MReal::DELTA.to_num::<f64>(),
+            #[cfg(feature = "rug")]
+            Precision::Arbitrary => 0.0,
+        }
+    }
+}
+
+//What is drawn to the window. Escape time colours each pixel by how fast
+//it escapes through the formula and backend, the buddhabrot plots the
+//density of escaping orbits instead and the nebulabrot does that for three
+//iteration limits at once
+#[derive(Clone, Copy, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum RenderMode {
+    #[default]
+    EscapeTime,
+    Buddhabrot,
+    Nebulabrot,
+}
+
+impl RenderMode {
+    pub fn next(self) -> RenderMode {
+        match self {
+            RenderMode::EscapeTime => RenderMode::Buddhabrot,
+            RenderMode::Buddhabrot => RenderMode::Nebulabrot,
+            RenderMode::Nebulabrot => RenderMode::EscapeTime,
+        }
+    }
+}
+
+//How the escape iteration is turned into the divergence value stored in the grid.
+//Discrete is the plain i / max_iter which bands, Smooth is the normalized iteration count.
+//Distance is the estimated distance to the set, formulas without a
+//derivative are drawn smooth instead. Trap is the closest the orbit came to
+//the orbit trap in params. Atom colours escaped points by the iteration
+//their orbit came closest to 0 on, its atom domain, and the interior by the
+//period its orbit settles into. Binary and Angle colour by the argument of z
+//where it escaped, binary decomposition shifts the smooth colours half a
+//palette repeat below the real axis and Angle draws the field lines. Stripe
+//is the average of a sine of that argument over the orbit, and Tia the
+//average of where each step landed between the bounds the triangle
+//inequality puts on it
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub enum ColorMode {
+    Discrete,
+    Smooth,
+    Distance,
+    Trap,
+    Atom,
+    Binary,
+    Angle,
+    Stripe,
+    Tia,
+}
+
+impl ColorMode {
+
+    pub fn next(self) -> ColorMode {
+        match self {
+            ColorMode::Discrete => ColorMode::Smooth,
+            ColorMode::Smooth => ColorMode::Distance,
+            ColorMode::Distance => ColorMode::Trap,
+            ColorMode::Trap => ColorMode::Atom,
+            ColorMode::Atom => ColorMode::Binary,
+            ColorMode::Binary => ColorMode::Angle,
+            ColorMode::Angle => ColorMode::Stripe,
+            ColorMode::Stripe => ColorMode::Tia,
+            ColorMode::Tia => ColorMode::Discrete,
+        }
+    }
+
+    //Whether a grid computed in this mode can be recoloured in other. Distance,
+    //trap, atom, stripe and triangle inequality colouring need what they tracked while iterating, and subdivided
+    //rectangles were filled wherever this mode's values matched. The rest only
+    //read where each point stopped
+    fn recolors_as(self, other : ColorMode, subdivide : bool) -> bool {
+        self == other
+            || (!subdivide && matches!(other, ColorMode::Discrete | ColorMode::Smooth | ColorMode::Binary | ColorMode::Angle))
+    }
+
+    //Divergence of a point that escaped after i iterations with |z|^2 = mod2,
+    //degree is the power z is raised to each iteration
+    fn divergence(self, i : u32, mod2 : f64, max_iter : u32, degree : f64) -> f64 {
+        match self {
+            ColorMode::Discrete => i as f64 / max_iter as f64,
+            ColorMode::Smooth
+            | ColorMode::Distance
+            | ColorMode::Trap
+            | ColorMode::Atom
+            | ColorMode::Binary
+            | ColorMode::Angle
+            | ColorMode::Stripe
+            | ColorMode::Tia => {
+                //i + 1 - log_d(ln|z|), ln|z| is half of ln|z|^2
+                let nu = i as f64 + 1.0 - (0.5 * mod2.ln()).ln() / degree.ln();
+                (nu / max_iter as f64).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+//Which set is rendered. The mandlebrot set iterates z^2 + c with c at the
+//pixel, a julia set has z start at the pixel and c fixed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fractal {
+    Mandlebrot,
+    Julia { c_a : MReal, c_b : MReal },
+}
+
+impl Fractal {
+
+    //The constant c used for the pixel at (a, b)
+    fn constant(self, a : MReal, b : MReal) -> (MReal, MReal) {
+        match self {
+            Fractal::Mandlebrot => (a, b),
+            Fractal::Julia { c_a, c_b } => (c_a, c_b),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct MandleParams {
+    pub x : Coord,
+    pub y : Coord,
+    //Distance between neighbouring pixels
+    pub zoom : f64,
+    pub iterations : u32,
+    //When set the iteration limit follows the zoom instead, see max_iterations
+    pub auto_iterations : bool,
+    pub iteration_scale : f64,
+    //Index into formula::FORMULAS
+    pub formula : usize,
+    //Power for the multibrot formula, z^exponent + c
+    pub exponent : f64,
+    //Step and bailout for the Script formula, see script
+    pub script : Option<std::sync::Arc<script::Script>>,
+    pub fractal : Fractal,
+    pub mode : RenderMode,
+    //Red, green and blue iteration limits for the nebulabrot
+    pub nebula_iterations : [u32; 3],
+    pub backend : Backend,
+    pub color_mode : ColorMode,
+    //Index into the palettes loaded at startup
+    pub palette : usize,
+    //Shift of the palette in 0..1 repeats, animated while cycling at
+    //cycle_speed repeats per second
+    pub palette_offset : f64,
+    pub cycling : bool,
+    pub cycle_speed : f64,
+    //Stops the linear colours are brightened by and the gamma they are
+    //raised to on the way to sRGB, see palette::encode
+    pub exposure : f64,
+    pub gamma : f64,
+    //Dither the colours down to 8 bits so gradients don't band
+    pub dither : bool,
+    pub bailout : f64,
+    //Grid size in pixels
+    pub width : usize,
+    pub height : usize,
+    //Overlay toggled with tab, only needs the frame redrawn
+    pub hud : bool,
+    //Worker threads for the cpu backends, 0 for one per core
+    pub threads : usize,
+    //Fill rectangles with a uniform border instead of computing them, see subdivide
+    pub subdivide : bool,
+    //Iterate in double-double between the f64 and fixed point zooms, see precision
+    pub double_double : bool,
+    //Samples per axis for antialiasing, 1 for none. Only pixels on an edge
+    //are supersampled unless supersample_all is set
+    pub supersample : u32,
+    pub supersample_all : bool,
+    //Keep antialiasing with a new jittered sample per pixel while the view is still
+    pub temporal : bool,
+    //Draw a quick gpu sketch of the view while it isn't computed in full, see
+    //the viewer's sketch module
+    pub sketch : bool,
+    //Slope shading and the direction of its light in degrees, 0 is from the right
+    pub shading : bool,
+    pub light_angle : f64,
+    pub light_elevation : f64,
+    //Spread the palette evenly over the escaped pixels of each frame, see histogram
+    pub histogram : bool,
+    //Orbit trap for trap colouring, centred on (trap_x, trap_y)
+    pub trap : trap::Trap,
+    pub trap_x : f64,
+    pub trap_y : f64,
+    pub trap_radius : f64,
+}
+
+impl std::fmt::Display for MandleParams{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>{
+        write!(
+            fmt,"MandleParams[X: {}, Y: {}, Zoom:{:e}, Iterations:{}{}, Formula:{}, Exponent:{}, Fractal:{:?}, Mode:{:?}, Nebula:{:?}, Backend:{:?}, Subdivide:{}, Supersample:{}x{}{}, Temporal:{}, Sketch:{}, Shading:{}{}, Color:{:?}{}{}, Palette:{}{}, Exposure:{}, Gamma:{}, Bailout:{}]",
+            self.x,
+            self.y,
+            self.zoom,
+            self.max_iterations(),
+            if self.auto_iterations { " (auto)" } else { "" },
+            formula::get(self.formula).name(),
+            self.exponent,
+            self.fractal,
+            self.mode,
+            self.nebula_iterations,
+            self.backend,
+            self.subdivide,
+            self.supersample,
+            self.supersample,
+            if self.supersample_all { " (all)" } else { "" },
+            self.temporal,
+            self.sketch,
+            self.shading,
+            if self.shading { format!(" (light {} at {})", self.light_angle, self.light_elevation) } else { String::new() },
+            self.color_mode,
+            if self.histogram { " (histogram)" } else { "" },
+            if self.color_mode == ColorMode::Trap {
+                format!(" ({:?} at {}, {} r {})", self.trap, self.trap_x, self.trap_y, self.trap_radius)
+            } else {
+                String::new()
+            },
+            self.palette,
+            if self.cycling { format!(" (cycling {}/s)", self.cycle_speed) } else { String::new() },
+            self.exposure,
+            self.gamma,
+            self.bailout
+        )
+    }
+}
+
+impl MandleParams {
+
+    //The whole mandlebrot set fitted into width x height, with everything
+    //else at the defaults of the rust_manlebrot command line. Change the
+    //fields from there, see Renderer
+    pub fn new(width : usize, height : usize) -> MandleParams {
+        MandleParams {
+            x: Coord::ZERO.offset(-0.5),
+            y: Coord::ZERO,
+            zoom: (3.0 / width as f64).max(2.5 / height as f64),
+            iterations: 300,
+            auto_iterations: false,
+            iteration_scale: 100.0,
+            formula: 0,
+            exponent: 3.0,
+            script: None,
+            fractal: Fractal::Mandlebrot,
+            mode: RenderMode::EscapeTime,
+            nebula_iterations: buddhabrot::NEBULA_ITERATIONS,
+            backend: Backend::Cpu,
+            color_mode: ColorMode::Discrete,
+            palette: 0,
+            palette_offset: 0.0,
+            cycling: false,
+            cycle_speed: 0.1,
+            exposure: 0.0,
+            gamma: 1.0,
+            dither: true,
+            bailout: MIN_BAILOUT,
+            width,
+            height,
+            hud: false,
+            threads: 0,
+            subdivide: false,
+            double_double: false,
+            supersample: 1,
+            supersample_all: false,
+            temporal: false,
+            sketch: false,
+            shading: false,
+            light_angle: 135.0,
+            light_elevation: 45.0,
+            histogram: false,
+            trap: trap::Trap::Point,
+            trap_x: 0.0,
+            trap_y: 0.0,
+            trap_radius: 1.0,
+        }
+    }
+
+    //Back to the whole mandlebrot set at the default iteration limit, like
+    //new. The formula, colouring and render settings are kept
+    pub fn reset_view(&mut self) {
+        let home = MandleParams::new(self.width, self.height);
+        self.x = home.x;
+        self.y = home.y;
+        self.zoom = home.zoom;
+        self.iterations = home.iterations;
+        self.fractal = home.fractal;
+    }
+
+    //Iteration limit to render with. In auto mode deeper zooms need more
+    //iterations before the boundary resolves, so it grows with log(1/zoom)
+    pub fn max_iterations(&self) -> u32 {
+        if !self.auto_iterations {
+            return self.iterations;
+        }
+        let depth = (1.0 / self.zoom).ln().max(0.0);
+        ((self.iteration_scale * depth) as u32).max(MIN_AUTO_ITERATIONS)
+    }
+
+    //Copy with the iteration limit worked out, the render paths only read iterations.
+    //The auto flag stays set for display, for the same zoom it resolves to the same limit
+    pub fn resolved(&self) -> MandleParams {
+        MandleParams {
+            iterations: self.max_iterations(),
+            ..self.clone()
+        }
+    }
+
+    //f64 until it can no longer tell neighbouring pixels apart, the same for
+    //double-double and fixed point. Double-double only when asked for, one
+    //orbit at a time it is slower than 128 bit fixed point on x86-64
+    pub fn precision(&self) -> Precision {
+        #[cfg(feature = "rug")]
+        if self.zoom < FIXED_MIN_ZOOM {
+            return Precision::Arbitrary;
+        }
+        if self.zoom >= F64_MIN_ZOOM {
+            Precision::Double
+        } else if self.double_double && self.zoom >= DOUBLE_DOUBLE_MIN_ZOOM {
+            Precision::DoubleDouble
+        } else {
+            Precision::Fixed
+        }
+    }
+
+    //Name and resolution of the narrowest number type the frame goes through.
+    //The backends hand over to a wider type before running out where there is
+    //one, so only formulas and modes stuck in one type get here
+    pub fn limiting_type(&self) -> (&'static str, f64) {
+        if self.mode != RenderMode::EscapeTime || formula::get(self.formula).f64_only(self) {
+            return (Precision::Double.name(), Precision::Double.resolution());
+        }
+        let precision = match (self.render_backend(), self.precision()) {
+            (Backend::Gpu, _) if self.zoom >= GPU_MIN_ZOOM => {
+                return ("f32", f32::EPSILON as f64 * ORBIT_MAGNITUDE);
+            }
+            //The deltas are relative to the reference, which is the limit
+            (Backend::Perturbation, Precision::Double) => Precision::Fixed,
+            (Backend::Perturbation, precision) => precision,
+            //Past fixed point everything but perturbation carries on in it
+            #[cfg(feature = "rug")]
+            (_, Precision::Arbitrary) => Precision::Fixed,
+            (_, precision) => precision,
+        };
+        (precision.name(), precision.resolution())
+    }
+
+    //The number type that has run out if neighbouring pixels are closer than it resolves
+    pub fn exhausted_precision(&self) -> Option<&'static str> {
+        let (name, resolution) = self.limiting_type();
+        (self.zoom < resolution).then_some(name)
+    }
+
+    //Whether a grid computed for last can be shown for these params by recolouring it.
+    //A new palette, the exposure or dithering, the hud, the lighting, histogram colouring, the sketch or a colour mode
+    //the grid has the data for don't need it recomputed
+    pub fn recolors(&self, last : &MandleParams) -> bool {
+        let recolored = MandleParams {
+            color_mode: if last.color_mode.recolors_as(self.color_mode, last.subdivide) {
+                self.color_mode
+            } else {
+                last.color_mode
+            },
+            palette: self.palette,
+            palette_offset: self.palette_offset,
+            cycling: self.cycling,
+            cycle_speed: self.cycle_speed,
+            exposure: self.exposure,
+            gamma: self.gamma,
+            dither: self.dither,
+            hud: self.hud,
+            shading: self.shading,
+            light_angle: self.light_angle,
+            light_elevation: self.light_elevation,
+            histogram: self.histogram,
+            sketch: self.sketch,
+            ..last.clone()
+        };
+        recolored == *self
+    }
+
+    //Whole pixels the view has moved by since last, when nothing else changed.
+    //A grid computed for last holds all of these params' view but the strips
+    //along the edges, shifted by that much
+    pub fn pan_from(&self, last : &MandleParams) -> Option<(isize, isize)> {
+        if *self != (MandleParams { x: self.x, y: self.y, ..last.clone() }) {
+            return None;
+        }
+        let dx = self.x.minus(last.x) / self.zoom;
+        let dy = self.y.minus(last.y) / self.zoom;
+        let whole = |moved : f64| (moved - moved.round()).abs() < PAN_TOLERANCE;
+        if !whole(dx) || !whole(dy) {
+            return None;
+        }
+        let (dx, dy) = (dx.round(), dy.round());
+        if dx.abs() >= self.width as f64 || dy.abs() >= self.height as f64 {
+            return None;
+        }
+        Some((dx as isize, dy as isize))
+    }
+
+    //Whether the iteration limit went up since last and nothing else changed.
+    //Pixels still going at the old limit are all a grid computed for last
+    //needs carried on
+    pub fn raises_iterations(&self, last : &MandleParams) -> bool {
+        self.iterations > last.iterations && *self == MandleParams { iterations: self.iterations, ..last.clone() }
+    }
+
+    //Scales the escape radius, clamped to what the fixed point path can square
+    pub fn scale_bailout(&mut self, factor : f64) {
+        self.bailout = (self.bailout * factor).clamp(MIN_BAILOUT, MAX_BAILOUT);
+    }
+
+    //The selected backend if the formula has it, the cpu otherwise
+    pub fn render_backend(&self) -> Backend {
+        //The shader has no derivative to estimate distance with and doesn't keep the orbit
+        let cpu_only = matches!(self.color_mode, ColorMode::Distance | ColorMode::Trap | ColorMode::Atom | ColorMode::Stripe | ColorMode::Tia);
+        let cpu_only_on_gpu = self.backend == Backend::Gpu && cpu_only;
+        //Nothing else reaches past fixed point
+        #[cfg(feature = "rug")]
+        if self.precision() == Precision::Arbitrary && formula::get(self.formula).supports(Backend::Perturbation) {
+            return Backend::Perturbation;
+        }
+        if formula::get(self.formula).supports(self.backend) && !cpu_only_on_gpu {
+            self.backend
+        } else {
+            Backend::Cpu
+        }
+    }
+
+    //Complex coordinate at grid position (px, py), same mapping as calc_mandlebrot_set
+    pub fn pixel_to_complex(&self, px : f64, py : f64) -> (Coord, Coord) {
+        (
+            self.x.offset((px - self.width as f64 / 2.0) * self.zoom),
+            self.y.offset((py - self.height as f64 / 2.0) * self.zoom),
+        )
+    }
+
+    //Scales the zoom by factor while keeping the complex coordinate under
+    //grid position (px, py) fixed, no deeper than MIN_ZOOM
+    pub fn zoom_about(&mut self, px : f64, py : f64, factor : f64) {
+        let zoom = (self.zoom * factor).max(MIN_ZOOM);
+        //(px, py) is this many pixels from the centre before and after
+        let dx = px - self.width as f64 / 2.0;
+        let dy = py - self.height as f64 / 2.0;
+        self.x = self.x.offset(dx * (self.zoom - zoom));
+        self.y = self.y.offset(dy * (self.zoom - zoom));
+        self.zoom = zoom;
+    }
+
+    //Zooms so the rectangle between two corners, in grid pixels, fills the
+    //view. The longer side relative to the view decides the zoom, so the
+    //aspect ratio is kept and the whole rectangle stays in sight
+    pub fn zoom_to_box(&mut self, (x0, y0) : (f64, f64), (x1, y1) : (f64, f64)) {
+        let factor = ((x1 - x0).abs() / self.width as f64).max((y1 - y0).abs() / self.height as f64);
+        let dx = (x0 + x1) / 2.0 - self.width as f64 / 2.0;
+        let dy = (y0 + y1) / 2.0 - self.height as f64 / 2.0;
+        self.x = self.x.offset(dx * self.zoom);
+        self.y = self.y.offset(dy * self.zoom);
+        self.zoom = (self.zoom * factor).max(MIN_ZOOM);
+    }
+}
+
+//Checked while rendering so a frame for stale params stops immediately
+pub struct CancelToken<'a> {
+    latest : &'a AtomicU64,
+    generation : u64,
+}
+
+//Generation that is never bumped, for renders that can't be cancelled
+static NEVER_CANCELLED: AtomicU64 = AtomicU64::new(0);
+
+impl CancelToken<'_> {
+
+    //Cancelled once latest is bumped past generation
+    pub fn new(latest : &AtomicU64, generation : u64) -> CancelToken<'_> {
+        CancelToken { latest, generation }
+    }
+
+    pub fn never() -> CancelToken<'static> {
+        CancelToken {
+            latest: &NEVER_CANCELLED,
+            generation: 0,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.latest.load(Ordering::Relaxed) > self.generation
+    }
+}
+
+
+//True once |z| is past the bailout radius. Saturating so a point that has
+//just left the range of MReal still counts as escaped instead of overflowing
+pub fn escaped<R : Real>(a : R, b : R, bailout2 : R) -> bool {
+    a.saturating_mul(a).saturating_add(b.saturating_mul(b)) > bailout2
+}
+
+//What iterating one point found. The grid keeps these rather than colour
+//values, so a new palette or colour mode only needs the grid recoloured
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sample {
+    //Iteration the orbit stopped on, None for points that never stop
+    pub stopped : Option<u32>,
+    //z when it stopped, f32 is plenty within the bailout radius
+    pub z : (f32, f32),
+    //Distance to the set in pixels in distance colouring, the closest the
+    //orbit came to the trap in trap colouring, or in atom colouring the
+    //iteration it came closest to 0 on, or for the interior its period, or
+    //the average in stripe and triangle inequality colouring. NaN when none
+    //of them was tracked
+    pub distance : f32,
+}
+
+impl Sample {
+
+    pub const INTERIOR: Sample = Sample {
+        stopped: None,
+        z: (0.0, 0.0),
+        distance: f32::NAN,
+    };
+
+    //Value in the colour mode of params, 0 for the interior but where atom
+    //colouring found its period. Distance colouring falls back to the
+    //formula's value where there is no estimate
+    pub fn value(&self, params : &MandleParams) -> f64 {
+        let Some(i) = self.stopped else {
+            if params.color_mode == ColorMode::Atom && !self.distance.is_nan() {
+                return atom_value(self.distance);
+            }
+            return 0.0;
+        };
+        let z = (self.z.0 as f64, self.z.1 as f64);
+        match params.color_mode {
+            ColorMode::Distance if !self.distance.is_nan() => distance_value(self.distance),
+            ColorMode::Trap if !self.distance.is_nan() => trap::value(self.distance as f64),
+            ColorMode::Atom if !self.distance.is_nan() => atom_value(self.distance),
+            ColorMode::Stripe | ColorMode::Tia if !self.distance.is_nan() => stripe::value(self.distance),
+            ColorMode::Binary => {
+                let smooth = MandleParams { color_mode: ColorMode::Smooth, ..params.clone() };
+                binary_value(formula::get(params.formula).value(i, z, &smooth), z)
+            }
+            ColorMode::Angle => angle_value(z),
+            _ => formula::get(params.formula).value(i, z, params),
+        }
+    }
+}
+
+//Estimated distance |z| ln|z| / |dz| from the set in pixels, for a point
+//that escaped with |z|^2 = mod2 and |dz|^2 = dz_mod2
+pub fn distance_estimate(mod2 : f64, dz_mod2 : f64, zoom : f64) -> f32 {
+    let distance = mod2.sqrt() * 0.5 * mod2.ln() / dz_mod2.sqrt();
+    let pixels = distance / zoom;
+    //A derivative that overflowed puts the point right on the set
+    if pixels.is_nan() {
+        return 0.0;
+    }
+    pixels as f32
+}
+
+//Value of a point an estimated distance in pixels from the set. On a log
+//scale, so the filaments within a pixel of the set sit at the dark start of
+//the palette and it brightens away from them
+pub fn distance_value(pixels : f32) -> f64 {
+    //Never 0, that is the interior
+    ((1.0 + pixels as f64).ln() / DISTANCE_RANGE).clamp(f64::MIN_POSITIVE, 1.0)
+}
+
+//Period of the cycle z has been caught in. The gap the cycle check found
+//can be any multiple of it while the orbit is still closing in, so the
+//orbit is left to settle for a few more gaps and this is the first step
+//within that many to come back within tolerance. If none do, about as close
+//as any does, once z has stopped moving rounding is all that tells them apart
+fn period<F : formula::FractalFormula, R : Real>(
+    formula : &F,
+    mut z : (R, R),
+    c : (R, R),
+    gap : u32,
+    tolerance : R,
+    params : &MandleParams
+) -> u32 {
+    for _ in 0..gap * PERIOD_SETTLE {
+        z = formula.step(z, c, params);
+    }
+    let mut next = z;
+    let returns : Vec<(f64, bool)> = (0..gap).map(|_| {
+        next = formula.step(next, c, params);
+        let (a, b) = (next.0 - z.0, next.1 - z.1);
+        (a.to_f64() * a.to_f64() + b.to_f64() * b.to_f64(), a.abs() <= tolerance && b.abs() <= tolerance)
+    }).collect();
+    let closest = returns.iter().map(|&(distance, _)| distance).fold(f64::MAX, f64::min);
+    returns
+        .iter()
+        .position(|&(distance, within)| within || distance <= closest * PERIOD_RETURN)
+        .map_or(gap, |k| k as u32 + 1)
+}
+
+//Value of an atom domain or a component's period. Consecutive ones go round
+//the palette by the golden ratio, so neighbours never come out alike
+pub fn atom_value(index : f32) -> f64 {
+    //Never 0, that is the interior
+    (index as f64 * ATOM_SPREAD).fract().max(f64::MIN_POSITIVE) / palette::PALETTE_REPEATS
+}
+
+//Value of a point that escaped at z in binary decomposition, its smooth
+//value moved on half a palette repeat where z escaped below the real axis
+fn binary_value(smooth : f64, (_, b) : (f64, f64)) -> f64 {
+    if b >= 0.0 {
+        return smooth;
+    }
+    (smooth + 0.5 / palette::PALETTE_REPEATS).fract().max(f64::MIN_POSITIVE)
+}
+
+//Value of a point that escaped at z in angle colouring, how far round a turn
+//the argument of z is, over one palette repeat. It jumps where the bands of
+//escape time meet and the field lines run across them
+fn angle_value((a, b) : (f64, f64)) -> f64 {
+    (b.atan2(a) / std::f64::consts::TAU).rem_euclid(1.0).max(f64::MIN_POSITIVE) / palette::PALETTE_REPEATS
+}
+
+//Iterates the formula from z, with the constant c
+pub fn calc_mandle_divergence<F : formula::FractalFormula, R : Real>(
+    formula : &F,
+    z : (R, R),
+    c : (R, R),
+    params : &MandleParams
+) -> Sample {
+    match params.color_mode {
+        ColorMode::Trap => calc_hooked_divergence(formula, z, c, params, Trapped::new(params)),
+        ColorMode::Atom => calc_hooked_divergence(formula, z, c, params, AtomDomain::new(params)),
+        ColorMode::Stripe => calc_hooked_divergence(formula, z, c, params, Stripe::default()),
+        ColorMode::Tia => {
+            let tia = Tia::new((c.0.to_f64(), c.1.to_f64()), formula.degree(params));
+            calc_hooked_divergence(formula, z, c, params, tia)
+        }
+        _ => calc_hooked_divergence(formula, z, c, params, ()),
+    }
+}
+
+//calc_mandle_divergence with hook shown the orbit, and what it made of it
+//kept as the distance when the distance isn't being estimated
+fn calc_hooked_divergence<F : formula::FractalFormula, R : Real, H : OrbitHook>(
+    formula : &F,
+    mut z : (R, R),
+    c : (R, R),
+    params : &MandleParams,
+    mut hook : H
+) -> Sample {
+
+    //The interior would run all the way to max_iter. The period, when it is
+    //wanted, is found by the cycle check below
+    if params.fractal == Fractal::Mandlebrot && !H::PERIOD && formula.interior(c, params) {
+        return Sample::INTERIOR;
+    }
+
+    let max_iter = params.iterations;
+    let bailout2 = R::from_f64(params.bailout * params.bailout);
+    //Brent's cycle detection. z is saved after 1, 2, 4, 8... iterations and
+    //checked against in between, so any period is caught once the gap between
+    //saves is longer than it. Fixed point can round onto an exact cycle, so
+    //the tolerance never drops below the smallest step
+    let periodic = formula.periodic();
+    let tolerance = R::from_fixed(MReal::saturating_from_num(params.zoom * PERIOD_TOLERANCE).max(MReal::DELTA));
+    let mut saved = z;
+    //dz/dc for the mandlebrot set, dz/dz[0] for julia sets, both start at 1.
+    //Dropped for formulas that have no derivative
+    let mut derivative = (params.color_mode == ColorMode::Distance).then_some((1.0f64, 0.0f64));
+    let plus_one = if params.fractal == Fractal::Mandlebrot { 1.0 } else { 0.0 };
+    //The point and derivative one step back. A formula that overflows
+    //returns Real::MAX, the estimate is then taken from there instead
+    let mut last = (z, (1.0f64, 0.0f64));
+    let mut saved_at = 0;
+    for i in 0..max_iter{
+        hook.visit(i, z);
+        if formula.bailout(z, bailout2, params) {
+            let distance = if let Some(dz) = derivative {
+                let ((a, b), (da, db)) = if z == (R::MAX, R::MAX) { last } else { (z, dz) };
+                let a = a.to_f64();
+                let b = b.to_f64();
+                distance_estimate(a * a + b * b, da * da + db * db, params.zoom)
+            } else {
+                let (a, b) = (z.0.to_f64(), z.1.to_f64());
+                hook.escaped(a * a + b * b, formula.degree(params), params)
+            };
+            return Sample {
+                stopped: Some(i),
+                z: (z.0.to_f32(), z.1.to_f32()),
+                distance,
+            };
+        }
+        if let Some(dz) = derivative {
+            last = (z, dz);
+            derivative = formula.derivative(z, dz, params).map(|(da, db)| (da + plus_one, db));
+        }
+        if periodic {
+            if i.is_power_of_two() {
+                saved = z;
+                saved_at = i;
+            } else if i > 1 && (z.0 - saved.0).abs() <= tolerance && (z.1 - saved.1).abs() <= tolerance {
+                return Sample {
+                    distance: if H::PERIOD { period(formula, z, c, i - saved_at, tolerance, params) as f32 } else { f32::NAN },
+                    ..Sample::INTERIOR
+                };
+            }
+        }
+        z = formula.step(z, c, params);
+    }
+    Sample::INTERIOR
+}
+
+//The points the orbit from z goes through, in pixels from the centre of
+//params' view, up to limit of them or the iteration the escape time loop
+//would stop it on. For drawing the orbit over the view
+pub fn calc_orbit<F : formula::FractalFormula, R : Real>(
+    formula : &F,
+    mut z : (R, R),
+    c : (R, R),
+    params : &MandleParams,
+    limit : usize
+) -> Vec<(f64, f64)> {
+    let bailout2 = R::from_f64(params.bailout * params.bailout);
+    //Taken off in R, at deep zooms the orbit is only pixels from the centre
+    let centre = (-R::from_fixed(params.x.to_fixed()), -R::from_fixed(params.y.to_fixed()));
+    let mut points = Vec::new();
+    for _ in 0..limit.min(params.iterations as usize + 1) {
+        points.push((
+            z.0.saturating_add(centre.0).to_f64() / params.zoom,
+            z.1.saturating_add(centre.1).to_f64() / params.zoom,
+        ));
+        if formula.bailout(z, bailout2, params) {
+            break;
+        }
+        z = formula.step(z, c, params);
+    }
+    points
+}
+
+
+//Orbit of the point at grid position (px, py) of params' view, see calc_orbit
+pub fn orbit(params : &MandleParams, px : f64, py : f64, limit : usize) -> Vec<(f64, f64)> {
+    let (a, b) = params.pixel_to_complex(px, py);
+    let (a, b) = (a.to_fixed(), b.to_fixed());
+    formula::get(params.formula).orbit((a, b), params.fractal.constant(a, b), params, limit)
+}
+
+//One pass of the coarse to fine refinement. Only pixels on the step grid
+//that the previous (coarser) pass didn't already compute are calculated,
+//the rest of each step x step block is filled in when rendering
+#[derive(Clone, Copy)]
+pub struct RefinePass {
+    pub step : usize,
+    previous : Option<usize>,
+}
+
+impl RefinePass {
+
+    pub const FULL: RefinePass = RefinePass { step: 1, previous: None };
+
+    //Every 8th pixel first, then halving down to full resolution
+    pub fn progressive() -> [RefinePass; 4] {
+        [
+            RefinePass { step: 8, previous: None },
+            RefinePass { step: 4, previous: Some(8) },
+            RefinePass { step: 2, previous: Some(4) },
+            RefinePass { step: 1, previous: Some(2) },
+        ]
+    }
+
+    //Every pixel pass and the finer passes after it would compute, for
+    //finishing parts of the grid at full resolution in one go
+    pub fn rest_after(pass : RefinePass) -> RefinePass {
+        RefinePass { step: 1, previous: Some(pass.step) }
+    }
+
+    fn row_included(&self, y : usize) -> bool {
+        y.is_multiple_of(self.step)
+    }
+
+    fn includes(&self, x : usize, y : usize) -> bool {
+        let on_step = x.is_multiple_of(self.step) && y.is_multiple_of(self.step);
+        let done = match self.previous {
+            Some(previous) => x.is_multiple_of(previous) && y.is_multiple_of(previous),
+            None => false,
+        };
+        on_step && !done
+    }
+}
+
+//Returns false if the render was cancelled before every pixel was computed
+pub fn calc_mandlebrot_set(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken
+    ) -> bool {
+    if params.subdivide {
+        return subdivide::calc_mandlebrot_set(grid, params, pass, cancel);
+    }
+    if simd::applies(params) {
+        return simd::calc_mandlebrot_set(grid, params, pass, cancel);
+    }
+    //A is the real part of the complex number
+    //B is the coefficent to I
+    let a = params.x.to_fixed();
+    let b = params.y.to_fixed();
+    let zoom_level = params.zoom;
+    let formula = formula::get(params.formula);
+
+    //Rows can be computed independently on the rayon pool
+    let half_width = grid.cols() as f64 / 2.0;
+    let half_height = grid.rows() as f64 / 2.0;
+    grid.par_rows_mut()
+        .filter(|(y, _)| pass.row_included(*y))
+        .for_each(|(y, row)| {
+            let y_offset = b + MReal::from_num((y as f64 - half_height) * zoom_level);
+            for (x, cell) in row.iter_mut().enumerate(){
+                if cancel.is_cancelled() {
+                    return;
+                }
+                if !pass.includes(x, y) {
+                    continue;
+                }
+                let x_offset = a + MReal::from_num((x as f64 - half_width) * zoom_level);
+                *cell = formula.divergence(
+                    (x_offset, y_offset),
+                    params.fractal.constant(x_offset, y_offset),
+                    params
+                );
+            }
+        });
+    !cancel.is_cancelled()
+}
+
+//Each pixel takes the value computed at the top left of its step x step block
+pub fn render_mandlebrot(
+    grid : & Grid<Sample>,
+    frame : & mut [u8],
+    step : usize,
+    coloring : &dyn Colorizer
+    ){
+    render_mandlebrot_region(grid, frame, step, coloring, 0..grid.cols(), 0..grid.rows());
+}
+
+//render_mandlebrot for the pixels in columns x rows only
+pub fn render_mandlebrot_region(
+    grid : & Grid<Sample>,
+    frame : & mut [u8],
+    step : usize,
+    coloring : &dyn Colorizer,
+    columns : Range<usize>,
+    rows : Range<usize>
+    ){
+    
+    let width = grid.cols();
+    for x in columns{
+        for y in rows.clone(){
+            let col = coloring.color(grid[(x - x % step, y - y % step)], (x, y)); 
+            let idx = (x + (y * width)) * 4;
+            frame[idx..idx + 4].copy_from_slice(&col);
+        }
+    }
+    
+}
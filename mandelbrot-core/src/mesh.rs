@@ -0,0 +1,286 @@
+//Heightmap meshes of a view for 3D printing or Blender. Each pixel is a
+//vertex raised by its smooth colouring value, the interior is a flat top
+//as high as the highest point outside it. The surface stands on a base
+//with walls down the sides and a bottom, so the mesh is closed and prints
+//as a solid. Written as OBJ, or binary STL for a .stl path
+
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use crate::offline::OfflineError;
+use crate::raw::{self, PixelData};
+use crate::MandleParams;
+
+//Length of the longer side of the mesh, STL has no units but slicers take
+//them as millimetres
+pub const MESH_SIZE: f64 = 100.0;
+
+//Thickness of the base under the lowest point
+pub const BASE: f64 = 2.0;
+
+//How pixel values become heights
+#[derive(Clone, Copy, Debug)]
+pub struct Relief {
+    //Height of the highest point above the base
+    pub height : f64,
+    //Heights from the log of the value, so the slopes near the set don't
+    //tower over everything else
+    pub log : bool,
+}
+
+pub struct Mesh {
+    pub vertices : Vec<[f32; 3]>,
+    //Counterclockwise seen from outside
+    pub triangles : Vec<[u32; 3]>,
+}
+
+//The surface of data, with x to the right and y up the image. data is at
+//least 2x2
+pub fn heightmap(data : &PixelData, relief : Relief) -> Mesh {
+    let (width, height) = (data.width, data.height);
+    let [_, iterations, _, smooth] = &data.channels;
+    let value = |i : usize| if relief.log { smooth[i].max(0.0).ln_1p() } else { smooth[i].max(0.0) };
+    let highest = (0..width * height)
+        .filter(|&i| iterations[i] >= 0.0)
+        .map(value)
+        .fold(0.0, f32::max);
+    let scale = if highest > 0.0 { relief.height as f32 / highest } else { 0.0 };
+    let spacing = (MESH_SIZE / (width.max(height) - 1) as f64) as f32;
+
+    let mut vertices = Vec::with_capacity(width * height + 2 * (width + height));
+    for row in 0..height {
+        for col in 0..width {
+            let i = row * width + col;
+            let z = if iterations[i] >= 0.0 { value(i) * scale } else { relief.height as f32 };
+            vertices.push([col as f32 * spacing, (height - 1 - row) as f32 * spacing, z]);
+        }
+    }
+    let top = |col : usize, row : usize| (row * width + col) as u32;
+
+    let mut triangles = Vec::new();
+    for row in 0..height - 1 {
+        for col in 0..width - 1 {
+            //Rows run down the image, so row + 1 is the lower edge of the quad
+            let (a, b) = (top(col, row + 1), top(col + 1, row + 1));
+            let (c, d) = (top(col + 1, row), top(col, row));
+            triangles.push([a, b, c]);
+            triangles.push([a, c, d]);
+        }
+    }
+
+    //The edge of the surface counterclockwise from the bottom left corner,
+    //each point once
+    let mut edge = Vec::new();
+    edge.extend((0..width).map(|col| top(col, height - 1)));
+    edge.extend((0..height - 1).rev().map(|row| top(width - 1, row)));
+    edge.extend((0..width - 1).rev().map(|col| top(col, 0)));
+    edge.extend((1..height - 1).map(|row| top(0, row)));
+
+    let floor = -BASE as f32;
+    let first_bottom = vertices.len() as u32;
+    for &point in &edge {
+        let [x, y, _] = vertices[point as usize];
+        vertices.push([x, y, floor]);
+    }
+    let middle = vertices.len() as u32;
+    vertices.push([
+        (width - 1) as f32 * spacing / 2.0,
+        (height - 1) as f32 * spacing / 2.0,
+        floor,
+    ]);
+    for i in 0..edge.len() {
+        let next = (i + 1) % edge.len();
+        let (top_here, top_next) = (edge[i], edge[next]);
+        let (bottom_here, bottom_next) = (first_bottom + i as u32, first_bottom + next as u32);
+        triangles.push([top_here, bottom_here, bottom_next]);
+        triangles.push([top_here, bottom_next, top_next]);
+        triangles.push([middle, bottom_next, bottom_here]);
+    }
+    Mesh { vertices, triangles }
+}
+
+//Whether path is for a mesh rather than an image, by its extension
+pub fn is_mesh_path(path : &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("obj") || ext.eq_ignore_ascii_case("stl"))
+}
+
+//Renders the view at width x height, a vertex a pixel, and saves its mesh,
+//printing progress to stdout
+pub fn render_to_file(
+    params : &MandleParams,
+    width : usize,
+    height : usize,
+    relief : Relief,
+    path : &Path
+) -> Result<(), OfflineError> {
+    println!("Rendering a {}x{} mesh to {}", width, height, path.display());
+    let triangles = save_mesh(params, width, height, relief, path, |done| {
+        print!("\rRendering {:.0}%", done * 100.0);
+        let _ = std::io::stdout().flush();
+    })?;
+    println!();
+    println!("Saved {} with {} triangles", path.display(), triangles);
+    Ok(())
+}
+
+//Renders and saves the mesh, calling progress with the fraction done.
+//How many triangles it has
+pub fn save_mesh(
+    params : &MandleParams,
+    width : usize,
+    height : usize,
+    relief : Relief,
+    path : &Path,
+    progress : impl FnMut(f64)
+) -> Result<usize, OfflineError> {
+    if width < 2 || height < 2 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("a mesh can't be {}x{}", width, height)).into());
+    }
+    let mesh = heightmap(&raw::render_data(params, width, height, progress), relief);
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("stl")) {
+        write_stl(path, &mesh)?;
+    } else {
+        write_obj(path, &mesh, params)?;
+    }
+    Ok(mesh.triangles.len())
+}
+
+pub fn write_obj(path : &Path, mesh : &Mesh, params : &MandleParams) -> Result<(), OfflineError> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "# {}", params)?;
+    for [x, y, z] in &mesh.vertices {
+        writeln!(file, "v {} {} {}", x, y, z)?;
+    }
+    //OBJ counts vertices from 1
+    for [a, b, c] in &mesh.triangles {
+        writeln!(file, "f {} {} {}", a + 1, b + 1, c + 1)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+pub fn write_stl(path : &Path, mesh : &Mesh) -> Result<(), OfflineError> {
+    let mut file = BufWriter::new(File::create(path)?);
+    encode_stl(&mut file, mesh)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn encode_stl(file : &mut impl Write, mesh : &Mesh) -> Result<(), Error> {
+    let mut header = [0u8; 80];
+    let title = b"mandlebrot heightmap";
+    header[..title.len()].copy_from_slice(title);
+    file.write_all(&header)?;
+    file.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+    for triangle in &mesh.triangles {
+        let [a, b, c] = triangle.map(|index| mesh.vertices[index as usize]);
+        let normal = normal(a, b, c);
+        for value in normal.iter().chain(&a).chain(&b).chain(&c) {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        //No attributes
+        file.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+fn normal(a : [f32; 3], b : [f32; 3], c : [f32; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 { n.map(|value| value / length) } else { [0.0; 3] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    //width x height with smooth values from value, None for the interior
+    fn data(width : usize, height : usize, value : impl Fn(usize) -> Option<f32>) -> PixelData {
+        let values : Vec<Option<f32>> = (0..width * height).map(value).collect();
+        PixelData {
+            width,
+            height,
+            channels: [
+                vec![f32::NAN; width * height],
+                values.iter().map(|value| if value.is_some() { 10.0 } else { -1.0 }).collect(),
+                vec![0.0; width * height],
+                values.iter().map(|value| value.unwrap_or(0.0)).collect(),
+            ],
+        }
+    }
+
+    //Six times the volume inside, positive when every triangle faces out
+    fn volume(mesh : &Mesh) -> f64 {
+        mesh.triangles.iter().map(|triangle| {
+            let [a, b, c] = triangle.map(|index| mesh.vertices[index as usize].map(f64::from));
+            a[0] * (b[1] * c[2] - b[2] * c[1]) + a[1] * (b[2] * c[0] - b[0] * c[2]) + a[2] * (b[0] * c[1] - b[1] * c[0])
+        }).sum()
+    }
+
+    //Every edge is in two triangles, once in each direction, so the mesh
+    //is closed and its windings agree
+    fn assert_closed(mesh : &Mesh) {
+        let mut edges : HashMap<(u32, u32), usize> = HashMap::new();
+        for &[a, b, c] in &mesh.triangles {
+            assert!(a != b && b != c && c != a);
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+        for (&(from, to), &count) in &edges {
+            assert_eq!(count, 1, "edge {}-{} is in {} triangles one way", from, to, count);
+            assert_eq!(edges.get(&(to, from)), Some(&1), "edge {}-{} has no triangle the other way", from, to);
+        }
+        assert!(mesh.triangles.iter().flatten().all(|&index| (index as usize) < mesh.vertices.len()));
+    }
+
+    #[test]
+    fn small_heightmaps_are_closed() {
+        let relief = Relief { height: 5.0, log: false };
+        for (width, height) in [(2, 2), (3, 2), (2, 3), (4, 3)] {
+            let mesh = heightmap(&data(width, height, |i| (i % 2 == 0).then_some(i as f32)), relief);
+            assert_closed(&mesh);
+            assert!(volume(&mesh) > 0.0, "{}x{} faces in", width, height);
+        }
+    }
+
+    #[test]
+    fn flat_heightmaps_are_the_base() {
+        let mesh = heightmap(&data(3, 2, |_| Some(0.0)), Relief { height: 5.0, log: false });
+        assert_closed(&mesh);
+        //100 by 50, BASE thick
+        assert!((volume(&mesh) / 6.0 - 100.0 * 50.0 * BASE).abs() < 1e-6);
+        //Two triangles a quad, and round the edge a wall quad and a bottom one
+        assert_eq!(mesh.triangles.len(), 2 * 2 + 3 * 6);
+        //The top faces up
+        for triangle in &mesh.triangles[..4] {
+            let [a, b, c] = triangle.map(|index| mesh.vertices[index as usize]);
+            assert_eq!(normal(a, b, c), [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn interior_is_as_high_as_the_highest_point() {
+        let mesh = heightmap(&data(2, 2, |i| (i != 3).then_some(i as f32)), Relief { height: 5.0, log: false });
+        let heights : Vec<f32> = mesh.vertices[..4].iter().map(|vertex| vertex[2]).collect();
+        assert_eq!(heights, [0.0, 2.5, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn stl_is_fixed_size_records() {
+        let mesh = heightmap(&data(3, 2, |i| Some(i as f32)), Relief { height: 5.0, log: true });
+        let mut stl = Vec::new();
+        encode_stl(&mut stl, &mesh).unwrap();
+        assert_eq!(stl.len(), 84 + 50 * mesh.triangles.len());
+        assert_eq!(u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize, mesh.triangles.len());
+        assert!(stl[..80].starts_with(b"mandlebrot heightmap"));
+        //The first vertex of the first triangle after its normal
+        let first : Vec<f32> = (0..3).map(|i| f32::from_le_bytes(stl[96 + i * 4..][..4].try_into().unwrap())).collect();
+        assert_eq!(first, mesh.vertices[mesh.triangles[0][0] as usize]);
+    }
+}
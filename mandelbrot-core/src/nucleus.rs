@@ -0,0 +1,157 @@
+//Finding the minibrot nearest the view. Box-period checking gives the
+//period of the lowest period component around: the corners of the view are
+//iterated together, and the first iteration their polygon goes round the
+//origin is the period of a component with its nucleus inside. Newton's
+//method on z_p(c) = 0 then homes in on that nucleus from the view's centre
+//and the size of the component sets the zoom
+
+use crate::double_double::DoubleDouble;
+use crate::formula::FractalFormula;
+use crate::real::Real;
+use crate::{Coord, Fractal, MandleParams, MIN_ZOOM};
+
+//Newton steps before giving up, it only takes a handful once close
+const NEWTON_STEPS: usize = 64;
+
+//Converged once a step moves c by less than this much of the component's size
+const NEWTON_TOLERANCE: f64 = 1.0e-9;
+
+//Sizes of the component across the height of the view zoomed in on it
+const VIEW_SIZES: f64 = 3.0;
+
+pub struct Nucleus {
+    pub x : Coord,
+    pub y : Coord,
+    pub period : u32,
+    //Roughly how far across the component is
+    pub size : f64,
+}
+
+impl Nucleus {
+
+    //params centred on the nucleus and zoomed so its minibrot fills the view
+    pub fn view(&self, params : &MandleParams) -> MandleParams {
+        MandleParams {
+            x: self.x,
+            y: self.y,
+            zoom: (self.size * VIEW_SIZES / params.height as f64).max(MIN_ZOOM),
+            ..params.clone()
+        }
+    }
+}
+
+//In double double whatever the zoom, the iterations are few enough next to a
+//frame's that the extra precision is free, and everything is relative to the
+//view's centre so offsets from it only need f64
+pub fn find<F : FractalFormula>(formula : &F, params : &MandleParams) -> Result<Nucleus, String> {
+    if params.fractal != Fractal::Mandlebrot {
+        return Err("minibrots are only found in the Mandelbrot set".to_string());
+    }
+    let centre = (DoubleDouble::from_fixed(params.x.to_fixed()), DoubleDouble::from_fixed(params.y.to_fixed()));
+    let at = |(dx, dy) : (f64, f64)| (centre.0 + DoubleDouble::from_f64(dx), centre.1 + DoubleDouble::from_f64(dy));
+    let period = period(formula, params, at).ok_or_else(|| "no component found around the view".to_string())?;
+
+    let mut offset = (0.0, 0.0);
+    for _ in 0..NEWTON_STEPS {
+        let Orbit { z, dz, size } = iterate(formula, at(offset), period, params)
+            .ok_or_else(|| format!("{} has no derivative to follow", formula.name()))?;
+        let step = complex_div(z, dz);
+        if !step.0.is_finite() || !step.1.is_finite() {
+            break;
+        }
+        let next = (offset.0 - step.0, offset.1 - step.1);
+        //A step too small to move the offset is as close as f64 gets
+        let converged = step.0.hypot(step.1) <= size * NEWTON_TOLERANCE || next == offset;
+        offset = next;
+        if converged {
+            return Ok(Nucleus {
+                x: params.x.offset(offset.0),
+                y: params.y.offset(offset.1),
+                period,
+                size,
+            });
+        }
+    }
+    Err(format!("newton's method didn't converge on the nucleus of period {}", period))
+}
+
+//First iteration the corners of the view surround the origin, None if one
+//escapes first or it takes more than the view's iterations
+fn period<F : FractalFormula, R : Real>(formula : &F, params : &MandleParams, at : impl Fn((f64, f64)) -> (R, R)) -> Option<u32> {
+    let half_width = params.width as f64 / 2.0 * params.zoom;
+    let half_height = params.height as f64 / 2.0 * params.zoom;
+    //Going round the box, so the polygon of their orbits doesn't cross itself
+    let corners = [
+        (-half_width, -half_height),
+        (half_width, -half_height),
+        (half_width, half_height),
+        (-half_width, half_height),
+    ].map(&at);
+    let bailout2 = R::from_f64(params.bailout * params.bailout);
+    let mut z = [(R::from_f64(0.0), R::from_f64(0.0)); 4];
+    for period in 1..=params.iterations {
+        for (z, &c) in z.iter_mut().zip(&corners) {
+            *z = formula.step(*z, c, params);
+        }
+        if z.iter().any(|&z| formula.bailout(z, bailout2, params)) {
+            return None;
+        }
+        if surrounds_origin(z.map(|(a, b)| (a.to_f64(), b.to_f64()))) {
+            return Some(period);
+        }
+    }
+    None
+}
+
+//Whether the polygon through the points goes round the origin, by counting
+//the edges crossing the positive real axis
+fn surrounds_origin(points : [(f64, f64); 4]) -> bool {
+    let mut inside = false;
+    for (index, &(a, b)) in points.iter().enumerate() {
+        let (c, d) = points[(index + 1) % points.len()];
+        if (b > 0.0) != (d > 0.0) && a - b * (c - a) / (d - b) > 0.0 {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+//z_p(c) from z_0 = 0 and its derivative dz_p/dc, with the size of the
+//component of period p at c
+struct Orbit {
+    z : (f64, f64),
+    dz : (f64, f64),
+    size : f64,
+}
+
+//The size is 1 / |b l^2|, l the product of f'(z) along the orbit and b the
+//sum of each partial product's reciprocal. None for formulas without a
+//derivative
+fn iterate<F : FractalFormula, R : Real>(formula : &F, c : (R, R), period : u32, params : &MandleParams) -> Option<Orbit> {
+    let mut z = (R::from_f64(0.0), R::from_f64(0.0));
+    let mut dz = (0.0, 0.0);
+    let mut l = (1.0, 0.0);
+    let mut b = (1.0, 0.0);
+    for i in 0..period {
+        let (da, db) = formula.derivative(z, dz, params)?;
+        dz = (da + 1.0, db);
+        if i > 0 {
+            l = formula.derivative(z, l, params)?;
+            let inverse = complex_div((1.0, 0.0), l);
+            b = (b.0 + inverse.0, b.1 + inverse.1);
+        }
+        z = formula.step(z, c, params);
+    }
+    let (bl, bm) = (b.0 * l.0 - b.1 * l.1, b.0 * l.1 + b.1 * l.0);
+    let bl2 = (bl * l.0 - bm * l.1, bl * l.1 + bm * l.0);
+    Some(Orbit {
+        z: (z.0.to_f64(), z.1.to_f64()),
+        dz,
+        size: 1.0 / bl2.0.hypot(bl2.1),
+    })
+}
+
+fn complex_div((a, b) : (f64, f64), (c, d) : (f64, f64)) -> (f64, f64) {
+    let denominator = c * c + d * d;
+    ((a * c + b * d) / denominator, (b * c - a * d) / denominator)
+}
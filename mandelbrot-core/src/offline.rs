@@ -0,0 +1,553 @@
+//Offline renders at any resolution, independent of the window.
+//The image is computed in tiles so only one tile grid is alive at a time
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::colorizer::{HistogramColorizer, PaletteColorizer};
+use crate::formula;
+use crate::palette::{self, Palette};
+use crate::histogram;
+use crate::{Backend, CancelToken, Colorizer, Grid, MandleParams, RefinePass, Sample, calc_mandlebrot_set, perturbation};
+
+//Edge length of a square tile in pixels
+const TILE_SIZE: usize = 512;
+
+//Bits a channel of the PNGs offline renders write. 16 bits keeps the slow
+//gradients of a large print from banding
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ImageDepth {
+    #[cfg_attr(feature = "clap", value(name = "8"))]
+    Eight,
+    #[cfg_attr(feature = "clap", value(name = "16"))]
+    Sixteen,
+}
+
+#[derive(Debug)]
+pub enum OfflineError {
+    Io(std::io::Error),
+    Encoding(png::EncodingError),
+}
+
+impl fmt::Display for OfflineError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OfflineError::Io(err) => write!(fmt, "{}", err),
+            OfflineError::Encoding(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for OfflineError {
+    fn from(err : std::io::Error) -> OfflineError {
+        OfflineError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for OfflineError {
+    fn from(err : png::EncodingError) -> OfflineError {
+        OfflineError::Encoding(err)
+    }
+}
+
+//Params for the image as a whole, zoomed so the view of params
+//(sized to the window) fits inside width x height
+pub(crate) fn scaled_params(params : &MandleParams, width : usize, height : usize) -> MandleParams {
+    let scale = (params.width as f64 / width as f64).max(params.height as f64 / height as f64);
+    MandleParams {
+        zoom: params.zoom * scale,
+        width,
+        height,
+        ..params.clone()
+    }
+}
+
+//Where the grids of an offline render's tiles are computed. Tiles are
+//coloured as they arrive, so they may finish in any order
+pub trait TileSource {
+    //Computes the view of each of tiles, handing done the index and grid of
+    //each as it finishes
+    fn compute_tiles(&self, tiles : &[MandleParams], cancel : &CancelToken, done : &mut dyn FnMut(usize, Grid<Sample>));
+}
+
+//This machine's cores, one tile after another
+pub struct Local;
+
+impl TileSource for Local {
+    fn compute_tiles(&self, tiles : &[MandleParams], cancel : &CancelToken, done : &mut dyn FnMut(usize, Grid<Sample>)) {
+        for (index, tile) in tiles.iter().enumerate() {
+            let mut grid = Grid::new(tile.width, tile.height, Sample::INTERIOR);
+            compute(&mut grid, tile, cancel);
+            done(index, grid);
+        }
+    }
+}
+
+//The whole grid in one pass. The gpu renderer belongs to the window,
+//offline renders and Renderer use the cpu
+pub fn compute(grid : &mut Grid<Sample>, params : &MandleParams, cancel : &CancelToken) {
+    match params.render_backend() {
+        Backend::Perturbation => perturbation::calc_mandlebrot_set(grid, params, RefinePass::FULL, cancel),
+        _ => calc_mandlebrot_set(grid, params, RefinePass::FULL, cancel),
+    };
+}
+
+//Computes the view in params at width x height into an rgb buffer,
+//calling progress with the fraction done after every tile
+pub fn render_image(
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    progress : impl FnMut(f64)
+) -> Vec<u8> {
+    render_image_from(&Local, params, palette, width, height, progress)
+}
+
+//render_image with the tiles computed by tiles
+pub fn render_image_from(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    progress : impl FnMut(f64)
+) -> Vec<u8> {
+    let mut images = render_recolored(tiles, params, palette, width, height, &[params.palette_offset], progress);
+    images.remove(0)
+}
+
+//The view coloured at each of the palette offsets, one rgb buffer each.
+//The set is only computed once, for palette cycling loops
+pub fn render_recolored(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    offsets : &[f64],
+    progress : impl FnMut(f64)
+) -> Vec<Vec<u8>> {
+    //Resolved after scaling, auto iterations follow the zoom of the image
+    let image_params = scaled_params(params, width, height).resolved();
+    let cancel = CancelToken::never();
+    let mut images = vec![vec![0u8; width * height * 3]; offsets.len()];
+    let equalizer = equalizer(&image_params, &cancel);
+    let colorings : Vec<Box<dyn Colorizer>> = offsets.iter()
+        .map(|&palette_offset| coloring(&MandleParams { palette_offset, ..image_params.clone() }, palette, &equalizer))
+        .collect();
+
+    for_each_tile(&image_params, tiles, &cancel, progress, |grid, left, top| {
+        for (image, coloring) in images.iter_mut().zip(&colorings) {
+            for ty in 0..grid.rows() {
+                for tx in 0..grid.cols() {
+                    let col = coloring.color(grid[(tx, ty)], (left + tx, top + ty));
+                    let idx = ((top + ty) * width + left + tx) * 3;
+                    image[idx..idx + 3].copy_from_slice(&col[..3]);
+                }
+            }
+        }
+    });
+    images
+}
+
+//The image at 16 bits a channel, rgb in sRGB like render_image
+pub fn render_image16(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    progress : impl FnMut(f64)
+) -> Vec<u16> {
+    let mut image = vec![0u16; width * height * 3];
+    render_light(tiles, params, palette, width, height, progress, |idx, light| {
+        for (value, channel) in image[idx * 3..idx * 3 + 3].iter_mut().zip(light) {
+            *value = palette::to_srgb16(channel);
+        }
+    });
+    image
+}
+
+//The image as the linear light of each pixel, rgb at full range. Exposure
+//can take it above 1, where render_image clips
+pub fn render_hdr(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    progress : impl FnMut(f64)
+) -> Vec<[f32; 3]> {
+    let mut image = vec![[0.0; 3]; width * height];
+    render_light(tiles, params, palette, width, height, progress, |idx, light| {
+        image[idx] = light.map(|channel| channel as f32);
+    });
+    image
+}
+
+//Hands store the light of each pixel of the image with its index
+fn render_light(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    progress : impl FnMut(f64),
+    mut store : impl FnMut(usize, [f64; 3])
+) {
+    let image_params = scaled_params(params, width, height).resolved();
+    let cancel = CancelToken::never();
+    let coloring = coloring(&image_params, palette, &equalizer(&image_params, &cancel));
+    for_each_tile(&image_params, tiles, &cancel, progress, |grid, left, top| {
+        for ty in 0..grid.rows() {
+            for tx in 0..grid.cols() {
+                store((top + ty) * width + left + tx, coloring.light(grid[(tx, ty)]));
+            }
+        }
+    });
+}
+
+//Tiles are coloured as they finish, so histogram colouring takes its
+//histogram from one tile sized render of the whole view
+fn equalizer(image_params : &MandleParams, cancel : &CancelToken) -> Option<histogram::Equalizer> {
+    let (width, height) = (image_params.width, image_params.height);
+    (image_params.histogram && formula::get(image_params.formula).equalized()).then(|| {
+        let sample_params = scaled_params(image_params, TILE_SIZE, (TILE_SIZE * height / width).max(1));
+        let mut grid = Grid::new(sample_params.width, sample_params.height, Sample::INTERIOR);
+        compute(&mut grid, &sample_params, cancel);
+        histogram::Equalizer::new(&grid, 1, &sample_params)
+    })
+}
+
+fn coloring<'a>(image_params : &MandleParams, palette : &'a Palette, equalizer : &Option<histogram::Equalizer>) -> Box<dyn Colorizer + 'a> {
+    let colors = PaletteColorizer::new(image_params, palette);
+    match equalizer {
+        Some(equalizer) => Box::new(HistogramColorizer::new(colors, equalizer.clone())),
+        None => Box::new(colors),
+    }
+}
+
+//Computes image_params a tile at a time with tiles, handing visit each
+//tile's grid with the pixel of the image its top left corner is at
+pub(crate) fn for_each_tile(
+    image_params : &MandleParams,
+    tiles : &dyn TileSource,
+    cancel : &CancelToken,
+    mut progress : impl FnMut(f64),
+    mut visit : impl FnMut(&Grid<Sample>, usize, usize)
+) {
+    let (width, height) = (image_params.width, image_params.height);
+    let mut corners = Vec::new();
+    let mut views = Vec::new();
+    for top in (0..height).step_by(TILE_SIZE) {
+        for left in (0..width).step_by(TILE_SIZE) {
+            let tile_width = TILE_SIZE.min(width - left);
+            let tile_height = TILE_SIZE.min(height - top);
+
+            //Each tile is its own view centered on the middle of the tile
+            let (x, y) = image_params.pixel_to_complex(
+                left as f64 + tile_width as f64 / 2.0,
+                top as f64 + tile_height as f64 / 2.0
+            );
+            corners.push((left, top));
+            views.push(MandleParams {
+                x,
+                y,
+                width: tile_width,
+                height: tile_height,
+                ..image_params.clone()
+            });
+        }
+    }
+
+    let mut done = 0;
+    tiles.compute_tiles(&views, cancel, &mut |index, grid| {
+        let (left, top) = corners[index];
+        visit(&grid, left, top);
+        done += 1;
+        progress(done as f64 / views.len() as f64);
+    });
+}
+
+pub fn write_png(path : &Path, image : &[u8], width : usize, height : usize) -> Result<(), OfflineError> {
+    encode_png(BufWriter::new(File::create(path)?), image, width, height)
+}
+
+//An 8 bit rgb PNG into out, a file or memory
+pub fn encode_png(out : impl Write, image : &[u8], width : usize, height : usize) -> Result<(), OfflineError> {
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image)?;
+    Ok(())
+}
+
+pub fn write_png16(path : &Path, image : &[u16], width : usize, height : usize) -> Result<(), OfflineError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header()?;
+    //PNG samples are big endian
+    let bytes : Vec<u8> = image.iter().flat_map(|value| value.to_be_bytes()).collect();
+    writer.write_image_data(&bytes)?;
+    Ok(())
+}
+
+//Radiance RGBE, each scanline run length encoded a channel at a time
+pub fn write_hdr(path : &Path, image : &[[f32; 3]], width : usize, height : usize) -> Result<(), OfflineError> {
+    let mut file = BufWriter::new(File::create(path)?);
+    encode_hdr(&mut file, image, width, height)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn encode_hdr(file : &mut impl Write, image : &[[f32; 3]], width : usize, height : usize) -> Result<(), OfflineError> {
+    write!(file, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+    //Scanlines this narrow or wide can't be run length encoded
+    let encoded = (8..=0x7fff).contains(&width);
+    for row in image.chunks_exact(width) {
+        let pixels : Vec<[u8; 4]> = row.iter().map(|&light| rgbe(light)).collect();
+        if !encoded {
+            file.write_all(pixels.as_flattened())?;
+            continue;
+        }
+        file.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        for channel in 0..4 {
+            let values : Vec<u8> = pixels.iter().map(|pixel| pixel[channel]).collect();
+            write_runs(file, &values)?;
+        }
+    }
+    Ok(())
+}
+
+//Linear light as a shared exponent and three 8 bit mantissas
+fn rgbe(light : [f32; 3]) -> [u8; 4] {
+    let light = light.map(|channel| if channel.is_finite() { channel.max(0.0) } else { 0.0 });
+    let brightest = light[0].max(light[1]).max(light[2]);
+    if brightest < 1e-32 {
+        return [0; 4];
+    }
+    //brightest is mantissa * 2^exponent with the mantissa in 0.5..1
+    let exponent = brightest.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(exponent);
+    let [r, g, b] = light.map(|channel| (channel * scale).min(255.0) as u8);
+    [r, g, b, (exponent + 128).clamp(0, 255) as u8]
+}
+
+//Runs of 4 or more of a byte as a count above 128 and the byte, the rest
+//as a count up to 128 and the bytes
+fn write_runs(file : &mut impl Write, values : &[u8]) -> Result<(), OfflineError> {
+    const MIN_RUN: usize = 4;
+    let mut literal = 0;
+    let mut i = 0;
+    while i < values.len() {
+        let run = values[i..].iter().take(127).take_while(|&&value| value == values[i]).count();
+        if run >= MIN_RUN {
+            for chunk in values[literal..i].chunks(128) {
+                file.write_all(&[chunk.len() as u8])?;
+                file.write_all(chunk)?;
+            }
+            file.write_all(&[128 + run as u8, values[i]])?;
+            i += run;
+            literal = i;
+        } else {
+            i += 1;
+        }
+    }
+    for chunk in values[literal..].chunks(128) {
+        file.write_all(&[chunk.len() as u8])?;
+        file.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+//Whether path is for Radiance HDR rather than PNG, by its extension
+pub fn is_hdr_path(path : &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"))
+}
+
+//Renders the view into path, Radiance HDR for a .hdr path and otherwise a
+//PNG of depth bits a channel
+pub fn save_image(
+    params : &MandleParams,
+    palette : &Palette,
+    (width, height) : (usize, usize),
+    path : &Path,
+    depth : ImageDepth,
+    progress : impl FnMut(f64)
+) -> Result<(), OfflineError> {
+    save_image_from(&Local, params, palette, (width, height), path, depth, progress)
+}
+
+//save_image with the tiles computed by tiles
+pub fn save_image_from(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    (width, height) : (usize, usize),
+    path : &Path,
+    depth : ImageDepth,
+    progress : impl FnMut(f64)
+) -> Result<(), OfflineError> {
+    if is_hdr_path(path) {
+        write_hdr(path, &render_hdr(tiles, params, palette, width, height, progress), width, height)
+    } else if depth == ImageDepth::Sixteen {
+        write_png16(path, &render_image16(tiles, params, palette, width, height, progress), width, height)
+    } else {
+        write_png(path, &render_image_from(tiles, params, palette, width, height, progress), width, height)
+    }
+}
+
+//Renders and saves the image, printing progress to stdout
+pub fn render_to_file(
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    path : &Path,
+    depth : ImageDepth
+) -> Result<(), OfflineError> {
+    render_to_file_from(&Local, params, palette, width, height, path, depth)
+}
+
+//render_to_file with the tiles computed by tiles
+pub fn render_to_file_from(
+    tiles : &dyn TileSource,
+    params : &MandleParams,
+    palette : &Palette,
+    width : usize,
+    height : usize,
+    path : &Path,
+    depth : ImageDepth
+) -> Result<(), OfflineError> {
+    println!("Rendering {}x{} to {}", width, height, path.display());
+    if let Some(name) = scaled_params(params, width, height).exhausted_precision() {
+        println!("Warning {} has run out of precision at this zoom", name);
+    }
+    save_image_from(tiles, params, palette, (width, height), path, depth, |done| {
+        print!("\rRendering {:.0}%", done * 100.0);
+        let _ = std::io::stdout().flush();
+    })?;
+    println!();
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Light back from rgbe the way Radiance readers decode it
+    fn unrgbe([r, g, b, e] : [u8; 4]) -> [f32; 3] {
+        if e == 0 {
+            return [0.0; 3];
+        }
+        [r, g, b].map(|mantissa| (mantissa as f32 + 0.5) * 2f32.powi(e as i32 - 136))
+    }
+
+    //Undoes write_runs for length values, and how many bytes it read
+    fn read_runs(bytes : &[u8], length : usize) -> (Vec<u8>, usize) {
+        let mut values = Vec::new();
+        let mut at = 0;
+        while values.len() < length {
+            let count = bytes[at] as usize;
+            if count > 128 {
+                values.extend(std::iter::repeat_n(bytes[at + 1], count - 128));
+                at += 2;
+            } else {
+                assert!(count > 0);
+                values.extend_from_slice(&bytes[at + 1..at + 1 + count]);
+                at += 1 + count;
+            }
+        }
+        assert_eq!(values.len(), length);
+        (values, at)
+    }
+
+    #[test]
+    fn rgbe_keeps_light_to_its_mantissa() {
+        for power in -30..=30 {
+            let edge = 2f32.powi(power);
+            for light in [edge, edge * (1.0 - f32::EPSILON), edge * (1.0 + f32::EPSILON), edge * 1.5] {
+                let pixel = [light, light / 3.0, 0.0];
+                let back = unrgbe(rgbe(pixel));
+                //Every channel is within a step of the brightest's mantissa
+                for channel in 0..3 {
+                    assert!((back[channel] - pixel[channel]).abs() <= light / 128.0, "{:?} came back {:?}", pixel, back);
+                }
+                assert!(rgbe(pixel)[0] >= 127);
+            }
+        }
+        assert_eq!(rgbe([0.0; 3]), [0; 4]);
+        assert_eq!(rgbe([1e-40, 0.0, 0.0]), [0; 4]);
+        //Negative and non finite channels are black, the others are kept
+        assert_eq!(rgbe([-1.0, -0.0, f32::NAN]), [0; 4]);
+        assert_eq!(rgbe([f32::INFINITY, 0.5, f32::NEG_INFINITY]), rgbe([0.0, 0.5, 0.0]));
+        assert_eq!(rgbe([0.5, 0.0, 0.0]), [128, 0, 0, 128]);
+    }
+
+    #[test]
+    fn runs_decode_to_their_values() {
+        for run in [1, 3, 4, 127, 128, 200, 300] {
+            //The run between bytes that differ from it and each other
+            let mut values = vec![1, 2];
+            values.extend(std::iter::repeat_n(9, run));
+            values.extend([3, 4, 5]);
+            let mut bytes = Vec::new();
+            write_runs(&mut bytes, &values).unwrap();
+            assert_eq!(read_runs(&bytes, values.len()), (values.clone(), bytes.len()), "run of {}", run);
+            //Runs over 127 go on in a second, or in literals if short
+            let encoded = bytes.windows(2).any(|pair| pair == [128 + run.min(127) as u8, 9]);
+            assert_eq!(encoded, run >= 4, "run of {}", run);
+        }
+        //Literals are split at 128
+        let values : Vec<u8> = (0..300).map(|i| (i % 2) as u8).collect();
+        let mut bytes = Vec::new();
+        write_runs(&mut bytes, &values).unwrap();
+        assert_eq!((bytes[0], bytes[129], bytes[258]), (128, 128, 44));
+        assert_eq!(read_runs(&bytes, values.len()).0, values);
+    }
+
+    #[test]
+    fn hdr_scanlines_read_back() {
+        let (width, height) = (20, 2);
+        let image : Vec<[f32; 3]> = (0..width * height).map(|i| if i % width < 10 { [1.0, 0.5, 0.0] } else { [i as f32, 2.0, 0.25] }).collect();
+        let mut hdr = Vec::new();
+        encode_hdr(&mut hdr, &image, width, height).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 20\n";
+        assert_eq!(&hdr[..header.len()], header);
+        let mut at = header.len();
+        for row in image.chunks_exact(width) {
+            assert_eq!(&hdr[at..at + 4], &[2, 2, 0, 20]);
+            at += 4;
+            let mut channels = Vec::new();
+            for _ in 0..4 {
+                let (values, read) = read_runs(&hdr[at..], width);
+                channels.push(values);
+                at += read;
+            }
+            let pixels : Vec<[u8; 4]> = (0..width).map(|x| std::array::from_fn(|channel| channels[channel][x])).collect();
+            assert_eq!(pixels, row.iter().map(|&light| rgbe(light)).collect::<Vec<_>>());
+        }
+        assert_eq!(at, hdr.len());
+    }
+
+    #[test]
+    fn narrow_hdr_scanlines_are_flat() {
+        for width in 1..8 {
+            let image : Vec<[f32; 3]> = (0..width * 3).map(|i| [i as f32, 1.0, 0.0]).collect();
+            let mut hdr = Vec::new();
+            encode_hdr(&mut hdr, &image, width, 3).unwrap();
+            let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 3 +X {}\n", width);
+            assert_eq!(&hdr[..header.len()], header.as_bytes());
+            let pixels : Vec<u8> = image.iter().flat_map(|&light| rgbe(light)).collect();
+            assert_eq!(&hdr[header.len()..], &pixels);
+        }
+    }
+}
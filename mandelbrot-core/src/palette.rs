@@ -0,0 +1,564 @@
+//Colour palettes, gradients through (position, r/g/b) control points
+//that map a divergence value onto r/g/b. The control points are sRGB like
+//any colour picker's, the gradient between them runs in linear light so
+//it doesn't go muddy halfway, and colours only go back to sRGB at output
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+
+//How many times the palette repeats over the 0..1 divergence range.
+//Most of the view escapes within a small fraction of max_iter, so
+//stretching one gradient over the whole range leaves it a single colour
+pub const PALETTE_REPEATS: f64 = 8.0;
+
+//Palette file loaded at startup if it exists, unless another is given with --palettes
+pub const PALETTE_FILE: &str = "palettes.toml";
+
+//Directory scanned at startup for Fractint .map and Ultra Fractal .ugr
+//palettes, unless another is given with --palette-dir
+pub const PALETTE_DIR: &str = "palettes";
+
+//Positions of an Ultra Fractal gradient run 0..400 round the whole palette
+const UGR_POSITIONS: f64 = 400.0;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ControlPoint {
+    pub position : f64,
+    pub color : [u8; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct Palette {
+    pub name : String,
+    //Sorted by position, first at 0 and last at 1
+    points : Vec<ControlPoint>,
+    //The points' colours in linear light
+    linear : Vec<[f64; 3]>,
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(fmt, "{}", err),
+            PaletteError::Parse(err) => write!(fmt, "{}", err),
+            PaletteError::Invalid(msg) => write!(fmt, "{}", msg),
+        }
+    }
+}
+
+impl From<std::io::Error> for PaletteError {
+    fn from(err : std::io::Error) -> PaletteError {
+        PaletteError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for PaletteError {
+    fn from(err : toml::de::Error) -> PaletteError {
+        PaletteError::Parse(err)
+    }
+}
+
+//Layout of a palette file:
+//
+//  [[palette]]
+//  name = "Sunset"
+//  points = [
+//      { position = 0.0, color = [20, 0, 40] },
+//      { position = 0.5, color = [255, 120, 0] },
+//      { position = 1.0, color = [20, 0, 40] },
+//  ]
+#[derive(Deserialize)]
+struct PaletteFile {
+    palette : Vec<PaletteDef>,
+}
+
+#[derive(Deserialize)]
+struct PaletteDef {
+    name : String,
+    points : Vec<ControlPoint>,
+}
+
+impl Palette {
+
+    pub fn new(name : &str, mut points : Vec<ControlPoint>) -> Result<Palette, PaletteError> {
+        if points.len() < 2 {
+            return Err(PaletteError::Invalid(
+                format!("palette {} needs at least 2 points", name)
+            ));
+        }
+        if let Some(point) = points.iter().find(|p| !(0.0..=1.0).contains(&p.position)) {
+            return Err(PaletteError::Invalid(
+                format!("palette {} has point at {} outside 0..1", name, point.position)
+            ));
+        }
+        points.sort_by(|a, b| a.position.total_cmp(&b.position));
+        let linear = points.iter().map(|point| point.color.map(to_linear)).collect();
+        Ok(Palette {
+            name: name.to_string(),
+            points,
+            linear,
+        })
+    }
+
+    //map divergence value (x) to a linear r/g/b, moved along the palette by
+    //offset repeats. 0 is used for points that never escaped, those are drawn black
+    pub fn color(&self, x : f64, offset : f64) -> [f64; 3] {
+        if x <= 0.0 {
+            return [0.0; 3];
+        }
+        self.sample((x * PALETTE_REPEATS + offset).fract())
+    }
+
+    //Catmull-Rom interpolation through the control points in linear light,
+    //t in 0..1
+    pub fn sample(&self, t : f64) -> [f64; 3] {
+        let points = &self.points;
+        let last = points.len() - 1;
+        if t <= points[0].position {
+            return self.linear[0];
+        }
+        if t >= points[last].position {
+            return self.linear[last];
+        }
+
+        let i = points.windows(2)
+            .position(|pair| t <= pair[1].position)
+            .unwrap_or(last - 1);
+        let p0 = self.linear[i.saturating_sub(1)];
+        let p1 = self.linear[i];
+        let p2 = self.linear[i + 1];
+        let p3 = self.linear[(i + 2).min(last)];
+
+        let span = points[i + 1].position - points[i].position;
+        let u = if span > 0.0 { (t - points[i].position) / span } else { 0.0 };
+        let u2 = u * u;
+        let u3 = u2 * u;
+
+        let mut col = [0.0; 3];
+        for c in 0..3 {
+            let (p0, p1, p2, p3) = (p0[c], p1[c], p2[c], p3[c]);
+            let v = 0.5 * (
+                2.0 * p1
+                + (p2 - p0) * u
+                + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+                + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3
+            );
+            col[c] = v.clamp(0.0, 1.0);
+        }
+        col
+    }
+}
+
+//An sRGB channel in linear light, 0..1
+pub fn to_linear(channel : u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+//A linear channel back in sRGB, clipped to 0..1 first. threshold, within
+//half a step either way, moves where it rounds, see dither
+pub fn to_srgb(linear : f64, threshold : f64) -> u8 {
+    (encode_srgb(linear) * 255.0 + threshold).round().clamp(0.0, 255.0) as u8
+}
+
+//The same at 16 bits, fine enough steps not to need dithering
+pub fn to_srgb16(linear : f64) -> u16 {
+    (encode_srgb(linear) * 65535.0).round().clamp(0.0, 65535.0) as u16
+}
+
+fn encode_srgb(linear : f64) -> f64 {
+    let l = linear.clamp(0.0, 1.0);
+    if l <= 0.003_130_8 {
+        l * 12.92
+    } else {
+        1.055 * l.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+//A linear colour as the light it is shown as, brightened by exposure stops
+//and raised to 1 / gamma. A gamma above 1 lifts the dark parts of the
+//palette, below 1 deepens them
+pub fn tone(linear : [f64; 3], exposure : f64, gamma : f64) -> [f64; 3] {
+    let scale = exposure.exp2();
+    linear.map(|channel| (channel * scale).max(0.0).powf(1.0 / gamma))
+}
+
+//Bayer matrix for ordered dithering, each threshold as far as it can be
+//from its neighbours
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+//Threshold of pixel (x, y) of the frame, within half an 8 bit step either
+//way. Rounding by it turns the steps of a slow gradient into a fine even
+//pattern instead of bands
+pub fn dither((x, y) : (usize, usize)) -> f64 {
+    (BAYER[y % 8][x % 8] as f64 + 0.5) / 64.0 - 0.5
+}
+
+//Light as the sRGB pixel at (x, y), dithered or just rounded
+pub fn quantize(light : [f64; 3], pixel : (usize, usize), dithered : bool) -> [u8; 3] {
+    let threshold = if dithered { dither(pixel) } else { 0.0 };
+    light.map(|channel| to_srgb(channel, threshold))
+}
+
+fn builtin(name : &str, points : &[(f64, [u8; 3])]) -> Palette {
+    let points = points.iter()
+        .map(|&(position, color)| ControlPoint { position, color })
+        .collect();
+    Palette::new(name, points).unwrap()
+}
+
+//Built in palettes, all start and end on the same colour so the repeats are seamless
+pub fn builtin_palettes() -> Vec<Palette> {
+    vec![
+        builtin("Blue gold", &[
+            (0.0, [0, 20, 80]),
+            (0.45, [40, 110, 220]),
+            (0.7, [255, 200, 60]),
+            (1.0, [0, 20, 80]),
+        ]),
+        builtin("Fire", &[
+            (0.0, [0, 0, 0]),
+            (0.3, [180, 20, 0]),
+            (0.6, [255, 160, 0]),
+            (0.85, [255, 255, 200]),
+            (1.0, [0, 0, 0]),
+        ]),
+        builtin("Grayscale", &[
+            (0.0, [0, 0, 0]),
+            (0.5, [255, 255, 255]),
+            (1.0, [0, 0, 0]),
+        ]),
+        builtin("Rainbow", &[
+            (0.0, [255, 0, 0]),
+            (1.0 / 6.0, [255, 255, 0]),
+            (2.0 / 6.0, [0, 255, 0]),
+            (3.0 / 6.0, [0, 255, 255]),
+            (4.0 / 6.0, [0, 0, 255]),
+            (5.0 / 6.0, [255, 0, 255]),
+            (1.0, [255, 0, 0]),
+        ]),
+        //The default gradient from Ultra Fractal
+        builtin("Ultra Fractal", &[
+            (0.0, [0, 7, 100]),
+            (0.16, [32, 107, 203]),
+            (0.42, [237, 255, 255]),
+            (0.6425, [255, 170, 0]),
+            (0.8575, [0, 2, 0]),
+            (1.0, [0, 7, 100]),
+        ]),
+    ]
+}
+
+pub fn load_palettes(path : &Path) -> Result<Vec<Palette>, PaletteError> {
+    let file : PaletteFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+    file.palette
+        .into_iter()
+        .map(|def| Palette::new(&def.name, def.points))
+        .collect()
+}
+
+//Fractint .map palette, a line of "r g b" for each colour, usually 256 of
+//them, with anything after the three numbers a comment. The colours are
+//spread evenly and the first comes round again at the end, as the map
+//wraps when its colours cycle
+pub fn load_map(path : &Path) -> Result<Palette, PaletteError> {
+    parse_map(&file_name(path), &std::fs::read_to_string(path)?)
+}
+
+fn parse_map(name : &str, text : &str) -> Result<Palette, PaletteError> {
+    let mut points = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut color = [0u8; 3];
+        let mut numbers = line.split_whitespace().map(str::parse::<u8>);
+        for channel in color.iter_mut() {
+            *channel = match numbers.next() {
+                Some(Ok(value)) => value,
+                _ => return Err(PaletteError::Invalid(
+                    format!("palette {} line {} isn't r g b", name, line_no + 1)
+                )),
+            };
+        }
+        points.push(ControlPoint { position: 0.0, color });
+    }
+    let count = points.len() as f64;
+    for (i, point) in points.iter_mut().enumerate() {
+        point.position = i as f64 / count;
+    }
+    wrap(&mut points);
+    Palette::new(name, points)
+}
+
+//Ultra Fractal .ugr gradient file, any number of entries like
+//
+//  Sunset {
+//  gradient:
+//    title="Sunset" smooth=yes
+//    index=0 color=2621460
+//    index=200 color=3942655
+//  opacity:
+//    ...
+//  }
+//
+//Indexes run round 0..400 and colours are integers with red in the low
+//byte. Only the gradient's colours are read, and they wrap round from the
+//last index to the first like they do in Ultra Fractal
+pub fn load_ugr(path : &Path) -> Result<Vec<Palette>, PaletteError> {
+    parse_ugr(&path.display().to_string(), &std::fs::read_to_string(path)?)
+}
+
+//The gradients in text, errors naming it source
+fn parse_ugr(source : &str, text : &str) -> Result<Vec<Palette>, PaletteError> {
+    let mut palettes = Vec::new();
+    let mut entry : Option<(String, Vec<ControlPoint>)> = None;
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let invalid = |what : &str| PaletteError::Invalid(
+            format!("{} line {} has {}", source, line_no + 1, what)
+        );
+        if let Some(name) = line.strip_suffix('{') {
+            entry = Some((name.trim().to_string(), Vec::new()));
+        } else if line == "}" {
+            let (name, mut points) = entry.take().ok_or_else(|| invalid("a } outside an entry"))?;
+            points.sort_by(|a, b| a.position.total_cmp(&b.position));
+            wrap(&mut points);
+            palettes.push(Palette::new(&name, points)?);
+        } else if let Some((name, points)) = entry.as_mut() {
+            let fields = ugr_fields(line);
+            let field = |key : &str| fields.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+            if let Some(title) = field("title") {
+                *name = title.to_string();
+            }
+            if let (Some(index), Some(color)) = (field("index"), field("color")) {
+                let index : i64 = index.parse().map_err(|_| invalid("a bad index"))?;
+                let color : u32 = color.parse().map_err(|_| invalid("a bad color"))?;
+                points.push(ControlPoint {
+                    position: index.rem_euclid(UGR_POSITIONS as i64) as f64 / UGR_POSITIONS,
+                    color: [color as u8, (color >> 8) as u8, (color >> 16) as u8],
+                });
+            }
+        }
+    }
+    if let Some((name, _)) = entry {
+        return Err(PaletteError::Invalid(format!("{} ends inside entry {}", source, name)));
+    }
+    Ok(palettes)
+}
+
+//The key=value pairs of a line of a .ugr file, values in quotes may have spaces
+fn ugr_fields(line : &str) -> Vec<(&str, &str)> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].trim();
+        let after = &rest[equals + 1..];
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        fields.push((key, value));
+        rest = next.trim_start();
+    }
+    fields
+}
+
+//Closes a gradient sorted by position whose colours go round in a loop,
+//with the colour it has where it comes back round at both ends
+fn wrap(points : &mut Vec<ControlPoint>) {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return;
+    };
+    let t = (1.0 - last.position) / (1.0 - last.position + first.position);
+    let color = std::array::from_fn(|c| {
+        (last.color[c] as f64 + (first.color[c] as f64 - last.color[c] as f64) * t).round() as u8
+    });
+    if first.position > 0.0 {
+        points.insert(0, ControlPoint { position: 0.0, color });
+    }
+    points.push(ControlPoint { position: 1.0, color });
+}
+
+fn file_name(path : &Path) -> String {
+    path.file_stem().map_or_else(|| path.display().to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+//Palettes from the .map and .ugr files in dir, in order of file name.
+//Files that don't load are reported and skipped
+pub fn load_palette_dir(dir : &Path) -> Vec<Palette> {
+    let mut paths = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect::<Vec<_>>(),
+        Err(err) => {
+            println!("Error reading palette directory {} {}", dir.display(), err);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+    let mut palettes = Vec::new();
+    for path in paths {
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        let loaded = match extension.as_deref() {
+            Some("map") => load_map(&path).map(|palette| vec![palette]),
+            Some("ugr") => load_ugr(&path),
+            _ => continue,
+        };
+        match loaded {
+            Ok(loaded) => palettes.extend(loaded),
+            Err(err) => println!("Error loading palettes from {} {}", path.display(), err),
+        }
+    }
+    palettes
+}
+
+//Built in palettes followed by any from the palette file and then the
+//palette directory
+pub fn all_palettes(path : &Path, dir : &Path) -> Vec<Palette> {
+    let mut palettes = builtin_palettes();
+    if path.exists() {
+        match load_palettes(path) {
+            Ok(loaded) => palettes.extend(loaded),
+            Err(err) => println!("Error loading palettes from {} {}", path.display(), err),
+        }
+    }
+    if dir.is_dir() {
+        palettes.extend(load_palette_dir(dir));
+    }
+    palettes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(palette : &Palette) -> Vec<(f64, [u8; 3])> {
+        palette.points.iter().map(|point| (point.position, point.color)).collect()
+    }
+
+    #[test]
+    fn map_lines_are_spread_evenly() {
+        let palette = parse_map("four", "0 0 0 black\n255 0 0   ; red\n\n  0 255 0\n0 0 255 blue, then more\n").unwrap();
+        assert_eq!(palette.name, "four");
+        assert_eq!(stops(&palette), [
+            (0.0, [0, 0, 0]),
+            (0.25, [255, 0, 0]),
+            (0.5, [0, 255, 0]),
+            (0.75, [0, 0, 255]),
+            (1.0, [0, 0, 0]),
+        ]);
+    }
+
+    #[test]
+    fn maps_of_any_length_wrap_to_their_first_colour() {
+        for count in [1, 16, 256, 300] {
+            let text : String = (0..count).map(|i| format!("{} {} 7\n", i % 256, 255 - i % 256)).collect();
+            let palette = parse_map("ramp", &text).unwrap();
+            let stops = stops(&palette);
+            assert_eq!(stops.len(), count + 1);
+            for (i, &(position, color)) in stops[..count].iter().enumerate() {
+                assert_eq!(position, i as f64 / count as f64);
+                assert_eq!(color, [(i % 256) as u8, (255 - i % 256) as u8, 7]);
+            }
+            assert_eq!(stops[count], (1.0, [0, 255, 7]));
+        }
+    }
+
+    #[test]
+    fn bad_maps_are_errors() {
+        for text in ["", "\n\n", "0 0 0\n255 0\n", "0 0 0\n256 0 0\n", "0 0 0\nred green blue\n", "0 0 -1\n"] {
+            assert!(matches!(parse_map("bad", text), Err(PaletteError::Invalid(_))), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn ugr_entries_are_each_a_palette() {
+        let text = r#"
+Sunset {
+gradient:
+  title="Sunset over the sea" smooth=yes
+  index=300 color=16711680
+  index=100 color=255
+opacity:
+  smooth=no index=0 opacity=255
+}
+
+Second {
+gradient:
+  smooth=no
+  index=0 color=65280
+  index=-200 color=0
+  index=600 color=16777215
+}
+"#;
+        let palettes = parse_ugr("test.ugr", text).unwrap();
+        assert_eq!(palettes.len(), 2);
+        assert_eq!(palettes[0].name, "Sunset over the sea");
+        //Red at 100 and blue at 300 meet halfway round through 0
+        assert_eq!(stops(&palettes[0]), [
+            (0.0, [128, 0, 128]),
+            (0.25, [255, 0, 0]),
+            (0.75, [0, 0, 255]),
+            (1.0, [128, 0, 128]),
+        ]);
+        //Without a title the entry's name, indexes outside 0..400 come round
+        assert_eq!(palettes[1].name, "Second");
+        assert_eq!(stops(&palettes[1]), [
+            (0.0, [0, 255, 0]),
+            (0.5, [0, 0, 0]),
+            (0.5, [255, 255, 255]),
+            (1.0, [0, 255, 0]),
+        ]);
+    }
+
+    #[test]
+    fn ugr_fields_keep_quoted_spaces() {
+        assert_eq!(ugr_fields(r#"title="A b  c" smooth=yes"#), [("title", "A b  c"), ("smooth", "yes")]);
+        assert_eq!(ugr_fields(r#"  index=4   color=12 "#), [("index", "4"), ("color", "12")]);
+        assert_eq!(ugr_fields(r#"title="never closed"#), [("title", "never closed")]);
+        assert_eq!(ugr_fields(r#"title="" x=1"#), [("title", ""), ("x", "1")]);
+        assert_eq!(ugr_fields("gradient:"), []);
+    }
+
+    #[test]
+    fn bad_ugrs_are_errors() {
+        for text in [
+            "}\n",
+            "A {\nindex=x color=0\nindex=1 color=0\n}\n",
+            "A {\nindex=0 color=-1\nindex=1 color=0\n}\n",
+            "A {\nindex=0 color=99999999999\nindex=1 color=0\n}\n",
+            "A {\nindex=0 color=0\nindex=1 color=0\n",
+            //No colours, so no gradient
+            "A {\n}\n",
+        ] {
+            assert!(matches!(parse_ugr("bad.ugr", text), Err(PaletteError::Invalid(_))), "{:?}", text);
+        }
+    }
+}
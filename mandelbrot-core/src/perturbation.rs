@@ -0,0 +1,340 @@
+use rayon::prelude::*;
+
+use crate::double_double::DoubleDouble;
+use crate::formula::{Mandlebrot, in_main_bulbs};
+use crate::real::Real;
+use crate::hook::{AtomDomain, OrbitHook};
+use crate::stripe::Stripe;
+use crate::tia::Tia;
+use crate::trap::Trapped;
+use crate::{CancelToken, ColorMode, Coord, Fractal, Grid, MandleParams, MReal, Precision, RefinePass, Sample, calc_mandle_divergence, distance_estimate, escaped};
+
+//How many times glitched pixels are re-rendered against a new reference
+//before falling back to full precision iteration for whatever is left
+const MAX_REFERENCE_PASSES: usize = 8;
+
+//Pauldelbrot's glitch criterion, a pixel is glitched once |Z + dz| drops
+//below this fraction of |Z| as the delta no longer carries enough precision
+const GLITCH_TOLERANCE: f64 = 1.0e-6;
+
+//Orbit of the reference point Z[0..n], stored in f64 since the orbit
+//itself is bounded by the bailout and only the deltas need to be tiny.
+//For julia sets c stays fixed instead of following Z[0]
+struct ReferenceOrbit {
+    orbit : Vec<(f64, f64)>,
+    //c of the reference, for colouring that needs each pixel's
+    c : (f64, f64),
+}
+
+impl ReferenceOrbit {
+
+    //Iterated in double-double when the pixels would be, fixed point otherwise
+    //and MPFR once the zoom is past that
+    fn new(a : Coord, b : Coord, params : &MandleParams) -> ReferenceOrbit {
+        let reference_c = match params.fractal {
+            Fractal::Mandlebrot => (a.to_f64(), b.to_f64()),
+            Fractal::Julia { c_a, c_b } => (c_a.to_num(), c_b.to_num()),
+        };
+        #[cfg(feature = "rug")]
+        if params.precision() == Precision::Arbitrary {
+            return ReferenceOrbit { orbit: crate::deep::reference_orbit(a, b, params), c: reference_c };
+        }
+        let (a, b) = (a.to_fixed(), b.to_fixed());
+        let c = params.fractal.constant(a, b);
+        let orbit = match params.precision() {
+            Precision::DoubleDouble => {
+                let real = |value : MReal| DoubleDouble::from_fixed(value);
+                iterate((real(a), real(b)), (real(c.0), real(c.1)), params)
+            }
+            _ => iterate((a, b), c, params),
+        };
+        ReferenceOrbit { orbit, c: reference_c }
+    }
+
+    //Z[m] of the orbit with a zero before it, for rebasing. The mandlebrot
+    //set's orbit really starts from zero, Z[0] = c is the step after
+    fn reference(&self, m : usize) -> Option<(f64, f64)> {
+        match m {
+            0 => Some((0.0, 0.0)),
+            m => self.orbit.get(m - 1).copied(),
+        }
+    }
+
+    //Iterates dz[n+1] = 2 * Z[n] * dz[n] + dz[n]^2 + dc in f64 from dz[0] = dz0.
+    //dc is the same as dz0 for the mandlebrot set and zero for julia sets.
+    //Returns the iteration the pixel got to when it outlived the reference or
+    //glitched, and needs another reference
+    fn divergence(&self, dz0 : (f64, f64), dc : (f64, f64), params : &MandleParams) -> Result<Sample, u32> {
+        //Same hooks as calc_mandle_divergence's. There is no cycle check here,
+        //so the interior has no period in atom colouring
+        match params.color_mode {
+            ColorMode::Trap => self.hooked_divergence(dz0, dc, params, Trapped::new(params)),
+            ColorMode::Atom => self.hooked_divergence(dz0, dc, params, AtomDomain::new(params)),
+            ColorMode::Stripe => self.hooked_divergence(dz0, dc, params, Stripe::default()),
+            ColorMode::Tia => {
+                let tia = Tia::new((self.c.0 + dc.0, self.c.1 + dc.1), 2.0);
+                self.hooked_divergence(dz0, dc, params, tia)
+            }
+            _ => self.hooked_divergence(dz0, dc, params, ()),
+        }
+    }
+
+    fn hooked_divergence<H : OrbitHook>(
+        &self,
+        (mut dz_a, mut dz_b) : (f64, f64),
+        (dc_a, dc_b) : (f64, f64),
+        params : &MandleParams,
+        mut hook : H
+    ) -> Result<Sample, u32> {
+        let max_iter = params.iterations;
+        let bailout2 = params.bailout * params.bailout;
+        //Derivative of the full orbit for distance estimation, only the
+        //delta needs the reference so Z + dz in f64 is precise enough here
+        let distance = params.color_mode == ColorMode::Distance;
+        let plus_one = if params.fractal == Fractal::Mandlebrot { 1.0 } else { 0.0 };
+        let (mut der_a, mut der_b) = (1.0f64, 0.0f64);
+        //Zhuoran's rebasing, see reference(). Julia orbits don't start from
+        //zero so those only have the glitch test
+        let rebase = params.fractal == Fractal::Mandlebrot;
+        let mut m = 1;
+        for i in 0..max_iter {
+            //The reference escaped before this pixel did
+            let Some((mut z_a, mut z_b)) = self.reference(m) else {
+                return Err(i);
+            };
+
+            let a = z_a + dz_a;
+            let b = z_b + dz_b;
+            let mod2 = a * a + b * b;
+            hook.visit(i, (a, b));
+            if mod2 > bailout2 {
+                let distance = if distance {
+                    distance_estimate(mod2, der_a * der_a + der_b * der_b, params.zoom)
+                } else {
+                    hook.escaped(mod2, 2.0, params)
+                };
+                return Ok(Sample {
+                    stopped: Some(i),
+                    z: (a as f32, b as f32),
+                    distance,
+                });
+            }
+            if rebase {
+                //Once the pixel is nearer the start of the orbit than Z[m]
+                //the delta from there is the smaller and more precise one
+                if mod2 < dz_a * dz_a + dz_b * dz_b {
+                    (dz_a, dz_b) = (a, b);
+                    (z_a, z_b) = (0.0, 0.0);
+                    m = 0;
+                }
+            } else if mod2 < GLITCH_TOLERANCE * (z_a * z_a + z_b * z_b) {
+                return Err(i);
+            }
+            if distance {
+                let der_a_new = 2.0 * (a * der_a - b * der_b) + plus_one;
+                der_b = 2.0 * (a * der_b + b * der_a);
+                der_a = der_a_new;
+            }
+
+            let dz_a_new = 2.0 * (z_a * dz_a - z_b * dz_b) + dz_a * dz_a - dz_b * dz_b + dc_a;
+            let dz_b_new = 2.0 * (z_a * dz_b + z_b * dz_a) + 2.0 * dz_a * dz_b + dc_b;
+            dz_a = dz_a_new;
+            dz_b = dz_b_new;
+            m += 1;
+        }
+        Ok(Sample::INTERIOR)
+    }
+}
+
+//Z[0..n] from z, rounded to f64 a step at a time
+fn iterate<R : Real>((mut z_a, mut z_b) : (R, R), (c_a, c_b) : (R, R), params : &MandleParams) -> Vec<(f64, f64)> {
+    let mut orbit = Vec::with_capacity(params.iterations as usize);
+    let bailout2 = R::from_f64(params.bailout * params.bailout);
+    for _ in 0..params.iterations {
+        orbit.push((z_a.to_f64(), z_b.to_f64()));
+        if escaped(z_a, z_b, bailout2) {
+            break;
+        }
+        let a_new = z_a * z_a - z_b * z_b;
+        let b_new = R::from_f64(2.0) * z_a * z_b;
+        z_a = a_new + c_a;
+        z_b = b_new + c_b;
+    }
+    orbit
+}
+
+//Same contract as calc_mandlebrot_set but only one orbit per pass is
+//iterated in fixed point, every pixel is a f64 delta from that orbit.
+//The deltas are derived for z^2 + c so this is only used for that formula
+pub fn calc_mandlebrot_set(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken
+) -> bool {
+    perturb(grid, params, pass, cancel).is_some()
+}
+
+//calc_mandlebrot_set, with how many pixels no reference got through and
+//were iterated on their own. None when cancelled
+fn perturb(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken
+) -> Option<usize> {
+    let zoom_level = params.zoom;
+    let width = grid.cols();
+    let height = grid.rows();
+    //Past fixed point the bulb test can't tell the pixels apart, they are all iterated
+    #[cfg(feature = "rug")]
+    let bulbs = params.precision() != Precision::Arbitrary;
+    #[cfg(not(feature = "rug"))]
+    let bulbs = true;
+
+    let mut pending : Vec<(usize, usize)> = grid
+        .iter()
+        .map(|(pos, _)| pos)
+        .filter(|&(x, y)| pass.includes(x, y))
+        .collect();
+    //Grid position of the reference, the deltas are from there
+    let mut reference_pos = (width as f64 / 2.0, height as f64 / 2.0);
+    let mut reference = ReferenceOrbit::new(params.x, params.y, params);
+
+    for _ in 0..MAX_REFERENCE_PASSES {
+        let results : Vec<((usize, usize), Result<Sample, u32>)> = pending
+            .par_iter()
+            .map(|&pos| {
+                if cancel.is_cancelled() {
+                    return (pos, Err(0));
+                }
+                let (x, y) = pixel_pos(pos);
+                if params.fractal == Fractal::Mandlebrot && bulbs && in_main_bulbs(fixed_pos(params, x, y)) {
+                    return (pos, Ok(Sample::INTERIOR));
+                }
+                let dz0 = (
+                    (x - reference_pos.0) * zoom_level,
+                    (y - reference_pos.1) * zoom_level,
+                );
+                let dc = match params.fractal {
+                    Fractal::Mandlebrot => dz0,
+                    Fractal::Julia { .. } => (0.0, 0.0),
+                };
+                (pos, reference.divergence(dz0, dc, params))
+            })
+            .collect();
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        pending.clear();
+        //The pixel that got furthest before needing a new reference. Its
+        //orbit outlasts the others', which mostly just outlived the reference
+        let mut next = ((0, 0), 0);
+        for (pos, value) in results {
+            match value {
+                Ok(sample) => grid[pos] = sample,
+                Err(reached) => {
+                    if pending.is_empty() || reached > next.1 {
+                        next = (pos, reached);
+                    }
+                    pending.push(pos);
+                }
+            }
+        }
+        if pending.is_empty() {
+            return Some(0);
+        }
+
+        reference_pos = pixel_pos(next.0);
+        let (ref_a, ref_b) = params.pixel_to_complex(reference_pos.0, reference_pos.1);
+        reference = ReferenceOrbit::new(ref_a, ref_b, params);
+    }
+
+    //Anything still glitched is computed the slow way
+    let results : Vec<((usize, usize), Sample)> = pending
+        .par_iter()
+        .map(|&pos| {
+            if cancel.is_cancelled() {
+                return (pos, Sample::INTERIOR);
+            }
+            let (x, y) = pixel_pos(pos);
+            #[cfg(feature = "rug")]
+            if params.precision() == Precision::Arbitrary {
+                let (a, b) = params.pixel_to_complex(x, y);
+                return (pos, divergence_at(a, b, params));
+            }
+            let (z_a, z_b) = fixed_pos(params, x, y);
+            let c = params.fractal.constant(z_a, z_b);
+            (pos, calc_mandle_divergence(&Mandlebrot, (z_a, z_b), c, params))
+        })
+        .collect();
+    if cancel.is_cancelled() {
+        return None;
+    }
+    for (pos, sample) in results {
+        grid[pos] = sample;
+    }
+    Some(pending.len())
+}
+
+//Grid position as the f64 pixel offsets are taken from
+fn pixel_pos((x, y) : (usize, usize)) -> (f64, f64) {
+    (x as f64, y as f64)
+}
+
+//Fixed point coordinate of grid position (x, y)
+fn fixed_pos(params : &MandleParams, x : f64, y : f64) -> (MReal, MReal) {
+    let (a, b) = params.pixel_to_complex(x, y);
+    (a.to_fixed(), b.to_fixed())
+}
+
+//A single point iterated as its own reference, which can't glitch. For
+//points past the reach of fixed point that glitched against every other
+#[cfg(feature = "rug")]
+pub fn divergence_at(a : Coord, b : Coord, params : &MandleParams) -> Sample {
+    ReferenceOrbit::new(a, b, params)
+        .divergence((0.0, 0.0), (0.0, 0.0), params)
+        .unwrap_or(Sample::INTERIOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    //Seahorse valley at 1e-24, where the centre escapes long before a few
+    //thousand of the pixels around it do
+    fn deep_view(width : usize, height : usize) -> MandleParams {
+        let mut params = MandleParams::new(width, height);
+        params.x = "-0.743643887037158704752191506114774".parse().unwrap();
+        params.y = "0.131825904205311970493132056385139".parse().unwrap();
+        params.zoom = 1e-24;
+        params.iterations = 20000;
+        params
+    }
+
+    #[test]
+    fn deep_view_barely_falls_back() {
+        let params = deep_view(320, 180);
+        let latest = AtomicU64::new(0);
+        let mut grid = Grid::new(320, 180, Sample::INTERIOR);
+        let fallback = perturb(&mut grid, &params, RefinePass::FULL, &CancelToken::new(&latest, 0)).unwrap();
+        assert!(fallback * 100 < 320 * 180, "{} pixels fell back to fixed point", fallback);
+    }
+
+    #[test]
+    fn escapes_like_fixed_point() {
+        let mut params = deep_view(16, 9);
+        params.iterations = 5000;
+        let latest = AtomicU64::new(0);
+        let mut grid = Grid::new(16, 9, Sample::INTERIOR);
+        perturb(&mut grid, &params, RefinePass::FULL, &CancelToken::new(&latest, 0)).unwrap();
+        for ((x, y), sample) in grid.iter() {
+            let z = fixed_pos(&params, x as f64, y as f64);
+            let c = params.fractal.constant(z.0, z.1);
+            let fixed = calc_mandle_divergence(&Mandlebrot, z, c, &params);
+            assert_eq!(sample.stopped, fixed.stopped, "pixel ({}, {})", x, y);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//What was computed for each pixel rather than its colour, for other
+//programs to recolour or post-process without iterating again. Written as
+//OpenEXR with a float channel each, or as plain little endian floats:
+//
+//  MANDRAW\0, then width and height as u32, then for each pixel its
+//  distance, iterations, modulus and smooth, the EXR channels in order
+//
+//iterations is the iteration the orbit stopped on, -1 for points that never
+//stop. smooth is the smooth colouring value, 0 for those. modulus is |z|
+//when it stopped. distance is whatever the colour mode tracked alongside,
+//see Sample, NaN otherwise
+
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::offline::{self, OfflineError};
+use crate::{CancelToken, ColorMode, MandleParams};
+
+pub const MAGIC: &[u8; 8] = b"MANDRAW\0";
+
+//Sorted by name, as EXR lists them
+pub const CHANNELS: [&str; 4] = ["distance", "iterations", "modulus", "smooth"];
+
+pub struct PixelData {
+    pub width : usize,
+    pub height : usize,
+    //One row after another for each channel, in the order of CHANNELS
+    pub channels : [Vec<f32>; 4],
+}
+
+impl PixelData {
+
+    fn pixel(&self, x : usize, y : usize) -> impl Iterator<Item = f32> + '_ {
+        self.channels.iter().map(move |channel| channel[y * self.width + x])
+    }
+}
+
+//Computes the view in params at width x height like render_image does,
+//calling progress with the fraction done after every tile
+pub fn render_data(params : &MandleParams, width : usize, height : usize, progress : impl FnMut(f64)) -> PixelData {
+    let image_params = offline::scaled_params(params, width, height).resolved();
+    let smooth = MandleParams { color_mode: ColorMode::Smooth, ..image_params.clone() };
+    let mut channels : [Vec<f32>; 4] = std::array::from_fn(|_| vec![0.0; width * height]);
+    offline::for_each_tile(&image_params, &offline::Local, &CancelToken::never(), progress, |grid, left, top| {
+        for ty in 0..grid.rows() {
+            for tx in 0..grid.cols() {
+                let sample = grid[(tx, ty)];
+                let idx = (top + ty) * width + left + tx;
+                channels[0][idx] = sample.distance;
+                channels[1][idx] = sample.stopped.map_or(-1.0, |i| i as f32);
+                channels[2][idx] = sample.z.0.hypot(sample.z.1);
+                channels[3][idx] = sample.value(&smooth) as f32;
+            }
+        }
+    });
+    PixelData { width, height, channels }
+}
+
+//Whether path is for the data rather than an image, by its extension
+pub fn is_data_path(path : &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr") || ext.eq_ignore_ascii_case("raw"))
+}
+
+//Renders and saves the data, printing progress to stdout
+pub fn render_to_file(params : &MandleParams, width : usize, height : usize, path : &Path) -> Result<(), OfflineError> {
+    println!("Rendering {}x{} data to {}", width, height, path.display());
+    save_data(params, width, height, path, |done| {
+        print!("\rRendering {:.0}%", done * 100.0);
+        let _ = std::io::stdout().flush();
+    })?;
+    println!();
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+//Renders and saves the data, calling progress with the fraction done
+pub fn save_data(
+    params : &MandleParams,
+    width : usize,
+    height : usize,
+    path : &Path,
+    progress : impl FnMut(f64)
+) -> Result<(), OfflineError> {
+    let data = render_data(params, width, height, progress);
+    write_data(path, &data, params)
+}
+
+//OpenEXR for a .exr path, the plain format otherwise
+pub fn write_data(path : &Path, data : &PixelData, params : &MandleParams) -> Result<(), OfflineError> {
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr")) {
+        write_exr(path, data, params)
+    } else {
+        write_raw(path, data)
+    }
+}
+
+pub fn write_raw(path : &Path, data : &PixelData) -> Result<(), OfflineError> {
+    let mut file = BufWriter::new(File::create(path)?);
+    encode_raw(&mut file, data)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn encode_raw(file : &mut impl Write, data : &PixelData) -> Result<(), Error> {
+    file.write_all(MAGIC)?;
+    file.write_all(&(data.width as u32).to_le_bytes())?;
+    file.write_all(&(data.height as u32).to_le_bytes())?;
+    for y in 0..data.height {
+        for x in 0..data.width {
+            for value in data.pixel(x, y) {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+//Uncompressed scanlines, one to a chunk. The params are printed into the
+//comments attribute so the file says what view it holds
+pub fn write_exr(path : &Path, data : &PixelData, params : &MandleParams) -> Result<(), OfflineError> {
+    let mut file = BufWriter::new(File::create(path)?);
+    encode_exr(&mut file, data, &params.to_string())?;
+    file.flush()?;
+    Ok(())
+}
+
+fn encode_exr(file : &mut impl Write, data : &PixelData, comments : &str) -> Result<(), Error> {
+    //EXR's float type and no compression
+    const FLOAT: i32 = 2;
+    const NO_COMPRESSION: u8 = 0;
+    let (width, height) = (data.width as i32, data.height as i32);
+
+    let mut header = Vec::new();
+    let mut attribute = |name : &str, kind : &str, value : &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    let mut channels = Vec::new();
+    for name in CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&FLOAT.to_le_bytes());
+        //Not perceptually linear, then three reserved bytes, then no subsampling
+        channels.extend_from_slice(&[0; 4]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    let window : Vec<u8> = [0, 0, width - 1, height - 1].iter().flat_map(|value : &i32| value.to_le_bytes()).collect();
+    attribute("channels", "chlist", &channels);
+    attribute("compression", "compression", &[NO_COMPRESSION]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    attribute("comments", "string", comments.as_bytes());
+    header.push(0);
+
+    //Magic number, then version 2 with no flags for a single part scanline file
+    file.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+    file.write_all(&header)?;
+    //Where each scanline's chunk starts, its y and size come first
+    let line_size = CHANNELS.len() * data.width * 4;
+    let first = 8 + header.len() + data.height * 8;
+    for y in 0..data.height {
+        file.write_all(&((first + y * (8 + line_size)) as u64).to_le_bytes())?;
+    }
+    for y in 0..data.height {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&(line_size as i32).to_le_bytes())?;
+        for channel in &data.channels {
+            for value in &channel[y * data.width..(y + 1) * data.width] {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    //3x2, every value telling its channel and pixel apart
+    fn data() -> PixelData {
+        PixelData {
+            width: 3,
+            height: 2,
+            channels: std::array::from_fn(|channel| (0..6).map(|i| (channel * 100 + i) as f32).collect()),
+        }
+    }
+
+    fn u32_at(bytes : &[u8], at : usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn i32_at(bytes : &[u8], at : usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes : &[u8], at : usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    //The text up to the next nul from at, and where it ends
+    fn name_at(bytes : &[u8], at : usize) -> (String, usize) {
+        let end = at + bytes[at..].iter().position(|&byte| byte == 0).unwrap();
+        (String::from_utf8(bytes[at..end].to_vec()).unwrap(), end + 1)
+    }
+
+    #[test]
+    fn raw_pixels_are_interleaved() {
+        let mut raw = Vec::new();
+        encode_raw(&mut raw, &data()).unwrap();
+        assert_eq!(&raw[..8], MAGIC);
+        assert_eq!((u32_at(&raw, 8), u32_at(&raw, 12)), (3, 2));
+        assert_eq!(raw.len(), 16 + 6 * 4 * 4);
+        //Pixel (1, 1), its four channels together
+        let at = 16 + 4 * 4 * 4;
+        let pixel : Vec<f32> = (0..4).map(|channel| f32_at(&raw, at + channel * 4)).collect();
+        assert_eq!(pixel, [4.0, 104.0, 204.0, 304.0]);
+    }
+
+    #[test]
+    fn exr_header_and_scanlines_read_back() {
+        let mut exr = Vec::new();
+        encode_exr(&mut exr, &data(), "x = -0.5").unwrap();
+        assert_eq!(&exr[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(u32_at(&exr, 4), 2);
+
+        let mut attributes = HashMap::new();
+        let mut at = 8;
+        loop {
+            let (name, after) = name_at(&exr, at);
+            if name.is_empty() {
+                at = after;
+                break;
+            }
+            let (kind, after) = name_at(&exr, after);
+            let size = i32_at(&exr, after) as usize;
+            attributes.insert(name, (kind, exr[after + 4..after + 4 + size].to_vec()));
+            at = after + 4 + size;
+        }
+        for (name, kind) in [
+            ("channels", "chlist"),
+            ("compression", "compression"),
+            ("dataWindow", "box2i"),
+            ("displayWindow", "box2i"),
+            ("lineOrder", "lineOrder"),
+            ("pixelAspectRatio", "float"),
+            ("screenWindowCenter", "v2f"),
+            ("screenWindowWidth", "float"),
+        ] {
+            assert_eq!(attributes.get(name).map(|(kind, _)| kind.as_str()), Some(kind), "{}", name);
+        }
+        assert_eq!(attributes["compression"].1, [0]);
+        assert_eq!(attributes["comments"].1, b"x = -0.5");
+        let window = &attributes["dataWindow"].1;
+        assert_eq!((0..4).map(|i| i32_at(window, i * 4)).collect::<Vec<_>>(), [0, 0, 2, 1]);
+
+        //Each channel is its name, float type, 4 flag bytes and 1x1 sampling
+        let list = &attributes["channels"].1;
+        let mut names = Vec::new();
+        let mut entry = 0;
+        while list[entry] != 0 {
+            let (name, after) = name_at(list, entry);
+            assert_eq!([i32_at(list, after), i32_at(list, after + 8), i32_at(list, after + 12)], [2, 1, 1]);
+            names.push(name);
+            entry = after + 16;
+        }
+        assert_eq!(names, CHANNELS);
+
+        //The offset table, then a chunk for each line
+        let line_size = 4 * 3 * 4;
+        let offsets : Vec<usize> = (0..2).map(|y| u64::from_le_bytes(exr[at + y * 8..][..8].try_into().unwrap()) as usize).collect();
+        assert_eq!(offsets[0], at + 2 * 8);
+        assert_eq!(offsets[1], offsets[0] + 8 + line_size);
+        assert_eq!(exr.len(), offsets[1] + 8 + line_size);
+        let line = offsets[1];
+        assert_eq!((i32_at(&exr, line), i32_at(&exr, line + 4)), (1, line_size as i32));
+        //Each channel's row in turn
+        let values : Vec<f32> = (0..12).map(|i| f32_at(&exr, line + 8 + i * 4)).collect();
+        assert_eq!(values, [3.0, 4.0, 5.0, 103.0, 104.0, 105.0, 203.0, 204.0, 205.0, 303.0, 304.0, 305.0]);
+    }
+}
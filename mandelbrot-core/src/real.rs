@@ -0,0 +1,96 @@
+//Number types the escape time loop can run in. MReal stays exact to far
+//deeper zooms, f64 is several times faster while the zoom is shallow enough
+//for it to tell neighbouring pixels apart, see MandleParams::precision
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::MReal;
+
+pub trait Real :
+    Copy
+    + PartialOrd
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+{
+    //Returned for both parts of z by a formula that would overflow. The same
+    //for every type so those points are coloured the same whichever runs
+    const MAX: Self;
+
+    //Saturating at MAX for types with a limited range
+    fn from_f64(value : f64) -> Self;
+    fn from_fixed(value : MReal) -> Self;
+    fn to_f64(self) -> f64;
+    fn to_f32(self) -> f32;
+    fn abs(self) -> Self;
+    fn saturating_mul(self, other : Self) -> Self;
+    fn saturating_add(self, other : Self) -> Self;
+}
+
+impl Real for MReal {
+    const MAX: MReal = MReal::MAX;
+
+    fn from_f64(value : f64) -> MReal {
+        MReal::saturating_from_num(value)
+    }
+
+    fn from_fixed(value : MReal) -> MReal {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self.to_num::<f64>()
+    }
+
+    fn to_f32(self) -> f32 {
+        self.to_num::<f32>()
+    }
+
+    fn abs(self) -> MReal {
+        MReal::abs(self)
+    }
+
+    fn saturating_mul(self, other : MReal) -> MReal {
+        MReal::saturating_mul(self, other)
+    }
+
+    fn saturating_add(self, other : MReal) -> MReal {
+        MReal::saturating_add(self, other)
+    }
+}
+
+//f64 overflows to infinity, which still compares as escaped
+impl Real for f64 {
+    const MAX: f64 = 1024.0;
+
+    fn from_f64(value : f64) -> f64 {
+        value
+    }
+
+    fn from_fixed(value : MReal) -> f64 {
+        value.to_num::<f64>()
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+
+    fn saturating_mul(self, other : f64) -> f64 {
+        self * other
+    }
+
+    fn saturating_add(self, other : f64) -> f64 {
+        self + other
+    }
+}
@@ -0,0 +1,117 @@
+//One call from params to a finished rgba image, for programs embedding the
+//renderer. Computed the way the window finishes a still frame, minus the
+//progressive passes, the gpu and temporal antialiasing
+
+use std::fmt;
+
+use crate::buddhabrot::Buddhabrot;
+use crate::colorizer;
+use crate::offline;
+use crate::palette::{self, Palette};
+use crate::shading;
+use crate::supersample::Supersamples;
+use crate::{CancelToken, Grid, MandleParams, RenderMode, Sample, render_mandlebrot};
+
+/// Why [`Renderer::render`] refused a view.
+#[derive(Debug)]
+pub enum RenderError {
+    /// The buffer isn't `width * height * 4` bytes.
+    BufferSize { expected : usize, actual : usize },
+    /// `params.palette` is past the end of the renderer's palettes.
+    Palette(usize),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::BufferSize { expected, actual } => {
+                write!(fmt, "buffer holds {} bytes, the view needs {}", actual, expected)
+            }
+            RenderError::Palette(index) => write!(fmt, "no palette {}", index),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Renders views into caller owned rgba buffers, colouring them with one of
+/// its palettes.
+pub struct Renderer {
+    palettes : Vec<Palette>,
+}
+
+impl Renderer {
+
+    /// A renderer with the built in palettes.
+    pub fn new() -> Renderer {
+        Renderer::with_palettes(palette::builtin_palettes())
+    }
+
+    /// A renderer with the given palettes, for example those from
+    /// [`palette::all_palettes`].
+    pub fn with_palettes(palettes : Vec<Palette>) -> Renderer {
+        Renderer { palettes }
+    }
+
+    /// The palettes [`MandleParams::palette`] indexes into.
+    pub fn palettes(&self) -> &[Palette] {
+        &self.palettes
+    }
+
+    /// Renders the view in `params` into `buffer`, `params.width *
+    /// params.height` rgba pixels row by row from the top left.
+    ///
+    /// Supersampling and slope shading are applied when `params` asks for
+    /// them. The gpu backend is computed on the cpu, and the work runs on the
+    /// current rayon pool rather than one sized by `params.threads`. Blocks
+    /// until the image is done.
+    ///
+    /// ```
+    /// use mandelbrot_core::{MandleParams, Renderer};
+    ///
+    /// let params = MandleParams::new(160, 90);
+    /// let mut buffer = vec![0; params.width * params.height * 4];
+    /// Renderer::new().render(&params, &mut buffer).unwrap();
+    /// ```
+    pub fn render(&self, params : &MandleParams, buffer : &mut [u8]) -> Result<(), RenderError> {
+        let expected = params.width * params.height * 4;
+        if buffer.len() != expected {
+            return Err(RenderError::BufferSize { expected, actual: buffer.len() });
+        }
+        if params.palette >= self.palettes.len() {
+            return Err(RenderError::Palette(params.palette));
+        }
+        if expected == 0 {
+            return Ok(());
+        }
+
+        let params = params.resolved();
+        let cancel = CancelToken::never();
+        if params.mode != RenderMode::EscapeTime {
+            let mut buddhabrot = Buddhabrot::new(&params);
+            while !buddhabrot.done() {
+                buddhabrot.sample(&params, &cancel);
+            }
+            buddhabrot.render(buffer);
+            return Ok(());
+        }
+
+        let mut grid = Grid::new(params.width, params.height, Sample::INTERIOR);
+        offline::compute(&mut grid, &params, &cancel);
+        let coloring = colorizer::new(&grid, 1, &params, &self.palettes);
+        render_mandlebrot(&grid, buffer, 1, &*coloring);
+        if params.supersample > 1 {
+            if let Some(samples) = Supersamples::compute(&grid, &params, &cancel) {
+                samples.render(buffer, &*coloring);
+            }
+        }
+        shading::apply(&grid, buffer, 1, &params);
+        Ok(())
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::new()
+    }
+}
@@ -0,0 +1,529 @@
+//Formulas written in Rhai instead of Rust, loaded from a file. The script
+//defines a step function giving the next z from z and c, run every
+//iteration, and may define a bailout function deciding when a point has
+//left:
+//
+//  // the burning ship, bent
+//  fn step(z, c, p) {
+//      let w = complex(abs(z.re), abs(z.im));
+//      w.pow(p) + c * exp(I() * arg(c))
+//  }
+//
+//  fn bailout(z) {
+//      abs(z) > 2.0
+//  }
+//
+//step takes z, c, p (the multibrot exponent, moved with Ctrl +/-) and r
+//(the bailout radius), bailout z, p and r, each only as many as it names.
+//Without a bailout points leave once |z| is past the view's bailout
+//radius. A top level degree variable is the constant power of z used for
+//smooth colouring, 2 otherwise.
+//
+//Complex numbers are made with complex(re, im) and I(), and have .re and
+//.im, + - * / with each other and with plain numbers, pow and the
+//functions abs arg norm conj exp log sqrt sin cos tan sinh cosh tanh.
+//
+//Scripts are compiled once, and the engine has no modules and a limit on
+//operations so a script from a file or a coordinator can't read files or
+//loop forever. A step that fails or gives something other than a number
+//is a point that has left
+
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, ParseError, Position, Scope, AST};
+
+//Operations one call may take before it is stopped, far more than any
+//formula needs
+const MAX_OPERATIONS: u64 = 100_000;
+
+//Most arguments step and bailout can take
+const STEP_ARGUMENTS: usize = 4;
+const BAILOUT_ARGUMENTS: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Complex {
+    re : f64,
+    im : f64,
+}
+
+impl Complex {
+    const ZERO: Complex = Complex::real(0.0);
+    const ONE: Complex = Complex::real(1.0);
+    const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    const fn real(re : f64) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+
+    fn neg(self) -> Complex {
+        Complex { re: -self.re, im: -self.im }
+    }
+
+    fn conj(self) -> Complex {
+        Complex { re: self.re, im: -self.im }
+    }
+
+    fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    fn norm(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn add(self, other : Complex) -> Complex {
+        Complex { re: self.re + other.re, im: self.im + other.im }
+    }
+
+    fn sub(self, other : Complex) -> Complex {
+        Complex { re: self.re - other.re, im: self.im - other.im }
+    }
+
+    fn mul(self, other : Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    fn div(self, other : Complex) -> Complex {
+        let norm = other.norm();
+        Complex {
+            re: (self.re * other.re + self.im * other.im) / norm,
+            im: (self.im * other.re - self.re * other.im) / norm,
+        }
+    }
+
+    fn exp(self) -> Complex {
+        let scale = self.re.exp();
+        Complex { re: scale * self.im.cos(), im: scale * self.im.sin() }
+    }
+
+    fn log(self) -> Complex {
+        Complex { re: self.abs().ln(), im: self.im.atan2(self.re) }
+    }
+
+    //Whole powers by squaring, they are the common case and exact where
+    //going through log and exp isn't
+    fn pow(self, power : Complex) -> Complex {
+        if power.im == 0.0 && power.re.fract() == 0.0 && power.re.abs() <= 64.0 {
+            let mut n = power.re.abs() as u32;
+            let mut base = self;
+            let mut result = Complex::ONE;
+            while n > 0 {
+                if n & 1 == 1 {
+                    result = result.mul(base);
+                }
+                base = base.mul(base);
+                n >>= 1;
+            }
+            return if power.re < 0.0 { Complex::ONE.div(result) } else { result };
+        }
+        if self == Complex::ZERO {
+            return Complex::ZERO;
+        }
+        power.mul(self.log()).exp()
+    }
+
+    fn sqrt(self) -> Complex {
+        let modulus = self.abs();
+        let re = ((modulus + self.re) / 2.0).sqrt();
+        let im = ((modulus - self.re) / 2.0).sqrt();
+        Complex { re, im: if self.im < 0.0 { -im } else { im } }
+    }
+
+    fn sin(self) -> Complex {
+        Complex { re: self.re.sin() * self.im.cosh(), im: self.re.cos() * self.im.sinh() }
+    }
+
+    fn cos(self) -> Complex {
+        Complex { re: self.re.cos() * self.im.cosh(), im: -self.re.sin() * self.im.sinh() }
+    }
+
+    fn sinh(self) -> Complex {
+        Complex { re: self.re.sinh() * self.im.cos(), im: self.re.cosh() * self.im.sin() }
+    }
+
+    fn cosh(self) -> Complex {
+        Complex { re: self.re.cosh() * self.im.cos(), im: self.re.sinh() * self.im.sin() }
+    }
+}
+
+
+//The arithmetic of complex numbers, with each other and with plain numbers
+//on either side
+fn register_complex(engine : &mut Engine) {
+    engine.register_type_with_name::<Complex>("Complex")
+        .register_fn("complex", |re : f64, im : f64| Complex { re, im })
+        .register_fn("complex", |re : i64, im : i64| Complex { re: re as f64, im: im as f64 })
+        .register_fn("I", || Complex::I)
+        .register_get("re", |z : &mut Complex| z.re)
+        .register_get("im", |z : &mut Complex| z.im)
+        .register_fn("-", |z : Complex| z.neg())
+        .register_fn("to_string", |z : &mut Complex| format!("{} + {}i", z.re, z.im))
+        .register_fn("to_debug", |z : &mut Complex| format!("complex({}, {})", z.re, z.im));
+
+    macro_rules! operator {
+        ($name:literal, $method:ident) => {
+            engine.register_fn($name, |x : Complex, y : Complex| x.$method(y))
+                .register_fn($name, |x : Complex, y : f64| x.$method(Complex::real(y)))
+                .register_fn($name, |x : f64, y : Complex| Complex::real(x).$method(y))
+                .register_fn($name, |x : Complex, y : i64| x.$method(Complex::real(y as f64)))
+                .register_fn($name, |x : i64, y : Complex| Complex::real(x as f64).$method(y));
+        };
+    }
+    operator!("+", add);
+    operator!("-", sub);
+    operator!("*", mul);
+    operator!("/", div);
+    operator!("pow", pow);
+
+    engine.register_fn("conj", Complex::conj)
+        .register_fn("exp", Complex::exp)
+        .register_fn("log", Complex::log)
+        .register_fn("sqrt", Complex::sqrt)
+        .register_fn("sin", Complex::sin)
+        .register_fn("cos", Complex::cos)
+        .register_fn("tan", |z : Complex| z.sin().div(z.cos()))
+        .register_fn("sinh", Complex::sinh)
+        .register_fn("cosh", Complex::cosh)
+        .register_fn("tanh", |z : Complex| z.sinh().div(z.cosh()))
+        .register_fn("abs", Complex::abs)
+        .register_fn("arg", Complex::arg)
+        .register_fn("norm", Complex::norm);
+}
+
+//A step's result as a complex number, plain numbers are real ones
+fn to_complex(value : Dynamic) -> Option<Complex> {
+    if value.is::<Complex>() {
+        value.try_cast::<Complex>()
+    } else if let Ok(re) = value.as_float() {
+        Some(Complex::real(re))
+    } else {
+        value.as_int().ok().map(|re| Complex::real(re as f64))
+    }
+}
+
+#[derive(Debug)]
+pub struct ScriptError {
+    pub line : usize,
+    pub message : String,
+}
+
+impl ScriptError {
+
+    fn new(message : String) -> ScriptError {
+        ScriptError { line: 0, message }
+    }
+
+    fn at(position : Position, message : String) -> ScriptError {
+        ScriptError { line: position.line().unwrap_or(0), message }
+    }
+}
+
+impl From<ParseError> for ScriptError {
+    fn from(err : ParseError) -> ScriptError {
+        ScriptError::at(err.1, err.0.to_string())
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(mut err : Box<EvalAltResult>) -> ScriptError {
+        let position = err.take_position();
+        ScriptError::at(position, err.to_string())
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {} {}", self.line, self.message)
+        }
+    }
+}
+
+//A compiled script. Scripts are the same when their text is
+pub struct Script {
+    source : String,
+    engine : Engine,
+    ast : AST,
+    //How many of z, c, p and r step takes
+    step_arguments : usize,
+    //How many of z, p and r bailout takes, if it's defined
+    bailout_arguments : Option<usize>,
+    degree : f64,
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Script").field("source", &self.source).finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Script {
+    fn eq(&self, other : &Script) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Script {
+
+    pub fn load(path : &Path) -> Result<Script, ScriptError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| ScriptError::new(format!("reading {} {}", path.display(), err)))?;
+        Script::parse(&source)
+    }
+
+    pub fn parse(source : &str) -> Result<Script, ScriptError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_complex(&mut engine);
+        let ast = engine.compile(source)?;
+
+        //The arities of the functions named, erroring on ones taking too many
+        //so a typo'd argument isn't silently never passed
+        let arguments = |name : &str, most : usize| -> Result<Option<usize>, ScriptError> {
+            let mut found = None;
+            for function in ast.iter_functions().filter(|function| function.name == name) {
+                if function.params.is_empty() || function.params.len() > most {
+                    return Err(ScriptError::new(format!("{} takes 1 to {} arguments, not {}", name, most, function.params.len())));
+                }
+                found = found.max(Some(function.params.len()));
+            }
+            Ok(found)
+        };
+        let step_arguments = arguments("step", STEP_ARGUMENTS)?
+            .ok_or_else(|| ScriptError::new("the script doesn't define fn step(z, c)".to_string()))?;
+        let bailout_arguments = arguments("bailout", BAILOUT_ARGUMENTS)?;
+
+        //The top level runs once, for degree
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+        let degree = match scope.get("degree") {
+            Some(degree) => {
+                let degree = degree.as_float().or_else(|_| degree.as_int().map(|degree| degree as f64))
+                    .map_err(|kind| ScriptError::new(format!("degree is a {}, not a number", kind)))?;
+                if !(degree.is_finite() && degree > 0.0) {
+                    return Err(ScriptError::new(format!("degree {} isn't a positive number", degree)));
+                }
+                degree
+            }
+            None => 2.0,
+        };
+
+        let script = Script {
+            source: source.to_string(),
+            engine,
+            ast,
+            step_arguments,
+            bailout_arguments,
+            degree,
+        };
+
+        //Tried once at a point inside the set, so scripts that can't run at
+        //all say why instead of drawing nothing
+        let z = Dynamic::from(Complex::ZERO);
+        let step = script.call("step", &[z.clone(), z.clone(), Dynamic::from_float(2.0), Dynamic::from_float(2.0)][..step_arguments])?;
+        if to_complex(step.clone()).is_none() {
+            return Err(ScriptError::new(format!("step gave a {}, not a number", step.type_name())));
+        }
+        if let Some(bailout_arguments) = bailout_arguments {
+            let escaped = script.call("bailout", &[z, Dynamic::from_float(2.0), Dynamic::from_float(2.0)][..bailout_arguments])?;
+            if !escaped.is_bool() {
+                return Err(ScriptError::new(format!("bailout gave a {}, not true or false", escaped.type_name())));
+            }
+        }
+        Ok(script)
+    }
+
+    //Calls a function of the script without running its top level again
+    fn call(&self, name : &str, arguments : &[Dynamic]) -> Result<Dynamic, Box<EvalAltResult>> {
+        let options = CallFnOptions::new().eval_ast(false).rewind_scope(true);
+        self.engine.call_fn_with_options(options, &mut Scope::new(), &self.ast, name, arguments.to_vec())
+    }
+
+    //Tells scripts apart in tile cache keys
+    pub fn id(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.source.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    //The text it was compiled from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn degree(&self) -> f64 {
+        self.degree
+    }
+
+    //The next z, with p the exponent and bailout2 the squared radius. NaN
+    //when the step fails, which bailout takes as escaped
+    pub fn step(&self, z : (f64, f64), c : (f64, f64), p : f64, bailout2 : f64) -> (f64, f64) {
+        let arguments = [
+            Dynamic::from(Complex { re: z.0, im: z.1 }),
+            Dynamic::from(Complex { re: c.0, im: c.1 }),
+            Dynamic::from_float(p),
+            Dynamic::from_float(bailout2.sqrt()),
+        ];
+        match self.call("step", &arguments[..self.step_arguments]).ok().and_then(to_complex) {
+            Some(z) => (z.re, z.im),
+            None => (f64::NAN, f64::NAN),
+        }
+    }
+
+    //Whether z has left. Points that overflowed or went NaN have too,
+    //whatever the script says, as have ones its bailout fails on
+    pub fn escaped(&self, z : (f64, f64), p : f64, bailout2 : f64) -> bool {
+        let z = Complex { re: z.0, im: z.1 };
+        if !z.norm().is_finite() {
+            return true;
+        }
+        match self.bailout_arguments {
+            Some(bailout_arguments) => {
+                let arguments = [Dynamic::from(z), Dynamic::from_float(p), Dynamic::from_float(bailout2.sqrt())];
+                self.call("bailout", &arguments[..bailout_arguments])
+                    .map_or(true, |escaped| escaped.as_bool().unwrap_or(true))
+            }
+            None => z.norm() > bailout2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANDLEBROT: &str = "fn step(z, c) { z * z + c }";
+
+    fn close((a, b) : (f64, f64), (x, y) : (f64, f64)) -> bool {
+        (a - x).abs() < 1e-12 && (b - y).abs() < 1e-12
+    }
+
+    #[test]
+    fn steps_like_the_mandlebrot_set() {
+        let script = Script::parse(MANDLEBROT).unwrap();
+        let z = script.step((0.5, -0.25), (0.1, 0.2), 2.0, 4.0);
+        //(0.5 - 0.25i)^2 + 0.1 + 0.2i
+        assert!(close(z, (0.5 * 0.5 - 0.25 * 0.25 + 0.1, -0.25 + 0.2)), "{:?}", z);
+        assert_eq!(script.degree(), 2.0);
+    }
+
+    #[test]
+    fn runs_the_example() {
+        let script = Script::parse("
+            let degree = 2;
+
+            fn step(z, c, p) {
+                let w = complex(abs(z.re), abs(z.im));
+                w.pow(p) + c * exp(I() * arg(c))
+            }
+
+            fn bailout(z) {
+                abs(z) > 2.0
+            }
+        ").unwrap();
+        assert!(close(script.step((-1.0, -1.0), (0.0, 0.0), 2.0, 4.0), (0.0, 2.0)));
+        assert!(!script.escaped((0.0, 2.0), 2.0, 4.0));
+        assert!(script.escaped((0.0, 2.5), 2.0, 4.0));
+    }
+
+    #[test]
+    fn passes_as_many_arguments_as_step_names() {
+        let script = Script::parse("fn step(z, c, p, r) { complex(p, r) }").unwrap();
+        assert!(close(script.step((1.0, 1.0), (0.0, 0.0), 3.0, 16.0), (3.0, 4.0)));
+        let script = Script::parse("fn step(z, c, p) { z.pow(p) + c }").unwrap();
+        assert!(close(script.step((0.0, 1.0), (0.5, 0.0), 3.0, 4.0), (0.5, -1.0)));
+    }
+
+    #[test]
+    fn mixes_plain_numbers_and_complex_ones() {
+        let script = Script::parse("fn step(z, c) { 2 * z - 1.5 + I() * z.re / 2 + conj(c) }").unwrap();
+        assert!(close(script.step((1.0, 1.0), (0.0, 1.0), 2.0, 4.0), (0.5, 1.5)));
+        //A plain number is a real one
+        let script = Script::parse("fn step(z, c) { abs(z) }").unwrap();
+        assert!(close(script.step((3.0, 4.0), (0.0, 0.0), 2.0, 4.0), (5.0, 0.0)));
+    }
+
+    #[test]
+    fn complex_functions() {
+        let script = Script::parse("fn step(z, c) { exp(I() * z.re) + sqrt(c) }").unwrap();
+        let pi = std::f64::consts::PI;
+        assert!(close(script.step((pi, 0.0), (-4.0, 0.0), 2.0, 4.0), (-1.0, 2.0)));
+    }
+
+    #[test]
+    fn bails_out_at_the_radius_without_a_bailout() {
+        let script = Script::parse(MANDLEBROT).unwrap();
+        assert!(!script.escaped((1.0, 1.0), 2.0, 4.0));
+        assert!(script.escaped((2.0, 1.0), 2.0, 4.0));
+        assert!(script.escaped((f64::NAN, 0.0), 2.0, 4.0));
+    }
+
+    #[test]
+    fn bails_out_where_the_script_says() {
+        let script = Script::parse(&format!("{}\nfn bailout(z, p, r) {{ z.re > r }}", MANDLEBROT)).unwrap();
+        assert!(!script.escaped((1.5, 100.0), 2.0, 4.0));
+        assert!(script.escaped((2.5, 0.0), 2.0, 4.0));
+        assert!(script.escaped((f64::INFINITY, 0.0), 2.0, 4.0));
+    }
+
+    #[test]
+    fn reads_degree_from_the_top_level() {
+        let script = Script::parse(&format!("let degree = 3;\n{}", MANDLEBROT)).unwrap();
+        assert_eq!(script.degree(), 3.0);
+        let err = Script::parse(&format!("let degree = -1.0;\n{}", MANDLEBROT)).unwrap_err();
+        assert!(err.message.contains("positive"), "{}", err);
+    }
+
+    #[test]
+    fn reports_the_line_of_syntax_errors() {
+        let err = Script::parse("fn step(z, c) {\n    z * z +\n}").unwrap_err();
+        assert_eq!(err.line, 3, "{}", err);
+    }
+
+    #[test]
+    fn rejects_scripts_that_cant_step() {
+        assert!(Script::parse("let degree = 2;").unwrap_err().message.contains("fn step"));
+        assert!(Script::parse("fn step(z, c, p, r, q) { z }").is_err());
+        //Wrong types show up in the trial step rather than as a blank view
+        assert!(Script::parse("fn step(z, c) { \"z\" }").is_err());
+        assert!(Script::parse("fn step(z, c) { z + undefined_name }").is_err());
+        assert!(Script::parse(&format!("{}\nfn bailout(z) {{ 1 }}", MANDLEBROT)).is_err());
+    }
+
+    #[test]
+    fn failing_steps_escape() {
+        //Fine at 0, dividing by a string past 1
+        let script = Script::parse("fn step(z, c) { if z.re > 1.0 { z / \"x\" } else { z } }").unwrap();
+        let z = script.step((2.0, 0.0), (0.0, 0.0), 2.0, 1e6);
+        assert!(z.0.is_nan());
+        assert!(script.escaped(z, 2.0, 1e6));
+    }
+
+    #[test]
+    fn endless_loops_are_stopped() {
+        let script = Script::parse("fn step(z, c) { if z.re > 1.0 { loop {} } z }").unwrap();
+        assert!(script.step((2.0, 0.0), (0.0, 0.0), 2.0, 4.0).0.is_nan());
+        assert!(Script::parse(&format!("loop {{}}\n{}", MANDLEBROT)).is_err());
+    }
+
+    #[test]
+    fn scripts_are_the_same_when_their_text_is() {
+        let script = Script::parse(MANDLEBROT).unwrap();
+        assert!(script == Script::parse(MANDLEBROT).unwrap());
+        assert_eq!(script.id(), Script::parse(MANDLEBROT).unwrap().id());
+        let other = Script::parse("fn step(z, c) { z * z * z + c }").unwrap();
+        assert!(script != other);
+        assert_ne!(script.id(), other.id());
+    }
+}
@@ -0,0 +1,86 @@
+//Slope shading. The grid is turned into a height field and lit from one
+//direction, so the set looks embossed. With distance colouring the height
+//is the distance estimate, the set is a ridge and the ground falls away
+//from it at the same slope everywhere. Otherwise the iteration count is
+//used, which is steeper close to the set
+
+use std::ops::Range;
+
+use crate::{ColorMode, DISTANCE_RANGE, Grid, MandleParams, Sample};
+
+//Degrees the [ and ] keys turn the light by
+pub const LIGHT_STEP: f64 = 15.0;
+
+//Height of the relief, the gradient is scaled by this before lighting
+const RELIEF: f64 = 1.0;
+
+//Brightness of a face turned away from the light
+const AMBIENT: f64 = 0.25;
+
+//Darkens the escaped pixels of a frame drawn from the grid, step is the
+//refinement step the frame was drawn at. Does nothing with shading off
+pub fn apply(grid : &Grid<Sample>, frame : &mut [u8], step : usize, params : &MandleParams) {
+    apply_region(grid, frame, step, params, 0..grid.cols(), 0..grid.rows());
+}
+
+//apply for the pixels in columns x rows only, their neighbours outside are
+//still read from the grid
+pub fn apply_region(
+    grid : &Grid<Sample>,
+    frame : &mut [u8],
+    step : usize,
+    params : &MandleParams,
+    columns : Range<usize>,
+    rows : Range<usize>
+) {
+    if !params.shading {
+        return;
+    }
+    let azimuth = params.light_angle.to_radians();
+    let elevation = params.light_elevation.to_radians();
+    //Screen y points down, so an angle of 90 is light from the top
+    let light = (
+        azimuth.cos() * elevation.cos(),
+        -azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+    );
+
+    let width = grid.cols();
+    let height = grid.rows();
+    //Interior neighbours would make a cliff at the edge of the set, the
+    //pixel itself stands in for them
+    let height_at = |x : usize, y : usize, fallback : f64| {
+        let value = grid[(x - x % step, y - y % step)].value(params);
+        if value <= 0.0 {
+            return fallback;
+        }
+        match params.color_mode {
+            //Back to pixels from the log scale of distance_value
+            ColorMode::Distance => 1.0 - (value * DISTANCE_RANGE).exp(),
+            _ => value * params.iterations as f64,
+        }
+    };
+    for y in rows {
+        for x in columns.clone() {
+            if grid[(x - x % step, y - y % step)].value(params) <= 0.0 {
+                continue;
+            }
+            let value = height_at(x, y, 0.0);
+            let left = height_at(x.saturating_sub(step), y, value);
+            let right = height_at((x + step).min(width - 1), y, value);
+            let up = height_at(x, y.saturating_sub(step), value);
+            let down = height_at(x, (y + step).min(height - 1), value);
+            let gx = (right - left) * RELIEF / (2 * step) as f64;
+            let gy = (down - up) * RELIEF / (2 * step) as f64;
+
+            let length = (gx * gx + gy * gy + 1.0).sqrt();
+            let lit = (-gx * light.0 - gy * light.1 + light.2) / length;
+            let brightness = AMBIENT + (1.0 - AMBIENT) * lit.max(0.0);
+
+            let idx = (y * width + x) * 4;
+            for channel in &mut frame[idx..idx + 3] {
+                *channel = (*channel as f64 * brightness) as u8;
+            }
+        }
+    }
+}
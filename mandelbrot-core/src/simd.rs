@@ -0,0 +1,306 @@
+//Four pixels at a time for z^2 + c in f64, which is where the cpu spends
+//its time at shallow zooms. Lanes that escape or are caught in a cycle
+//are masked off until the whole group is done, so groups are neighbouring
+//pixels of a row, which tend to finish together. Computes the same samples
+//as the scalar loop in calc_mandle_divergence, in the same order
+
+use rayon::prelude::*;
+use wide::{CmpGt, CmpLe, CmpLt, f64x4};
+
+use crate::formula::{self, in_main_bulbs};
+use crate::{CancelToken, ColorMode, Fractal, Grid, MandleParams, MReal, PERIOD_TOLERANCE, Precision, RefinePass, Sample, distance_estimate};
+
+const LANES: usize = 4;
+
+//Orbits resumed together on the rayon pool
+const RESUME_CHUNK: usize = 256;
+
+//Where an orbit still going at the iteration limit had got to, everything
+//the loop needs to carry on from there as if it had never stopped
+#[derive(Clone, Copy, Debug)]
+pub struct Orbit {
+    z : (f64, f64),
+    c : (f64, f64),
+    //The point cycle detection compares against, and dz
+    saved : (f64, f64),
+    derivative : (f64, f64),
+}
+
+impl Orbit {
+    fn start(z : (f64, f64), c : (f64, f64)) -> Orbit {
+        Orbit { z, c, saved: z, derivative: (1.0, 0.0) }
+    }
+}
+
+//The orbits of a grid's pixels that reached its iteration limit without
+//escaping or settling into a cycle. Raising the limit only needs these
+//carried on, every other pixel comes out the same
+pub struct Orbits {
+    iterations : u32,
+    orbits : Vec<((usize, usize), Orbit)>,
+}
+
+impl Orbits {
+
+    //None kept yet, for a grid being computed with params
+    pub fn new(params : &MandleParams) -> Orbits {
+        Orbits { iterations: params.iterations, orbits: Vec::new() }
+    }
+
+    //Carries the orbits on from the limit they were kept at to params' higher
+    //one. Pixels that escape on the way are written into grid, the rest are
+    //kept for the next raise. Nothing changes when cancelled, false then
+    pub fn resume(&mut self, grid : &mut Grid<Sample>, params : &MandleParams, cancel : &CancelToken) -> bool {
+        let from = self.iterations;
+        let resumed : Option<Vec<(Sample, Option<Orbit>)>> = self.orbits
+            .par_chunks(RESUME_CHUNK)
+            .map(|chunk| {
+                let pending : Vec<Pending> = chunk.iter().enumerate().map(|(index, &(_, orbit))| (index, orbit)).collect();
+                let mut results = Vec::with_capacity(chunk.len());
+                for group in pending.chunks(LANES) {
+                    if cancel.is_cancelled() {
+                        return None;
+                    }
+                    let (samples, orbits) = divergence(group, from, params);
+                    results.extend(samples.into_iter().zip(orbits).take(group.len()));
+                }
+                Some(results)
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|chunks| chunks.into_iter().flatten().collect());
+        let Some(resumed) = resumed else {
+            return false;
+        };
+        let mut still_going = Vec::new();
+        for (&(pos, _), (sample, orbit)) in self.orbits.iter().zip(resumed) {
+            grid[pos] = sample;
+            if let Some(orbit) = orbit {
+                still_going.push((pos, orbit));
+            }
+        }
+        self.orbits = still_going;
+        self.iterations = params.iterations;
+        true
+    }
+
+    //Follows a grid shifted by (dx, dy) with Grid::shift, the orbits of
+    //pixels shifted off the edge are dropped
+    pub fn shift(&mut self, dx : isize, dy : isize, width : usize, height : usize) {
+        self.orbits.retain_mut(|((x, y), _)| {
+            match (x.checked_add_signed(-dx), y.checked_add_signed(-dy)) {
+                (Some(to_x), Some(to_y)) if to_x < width && to_y < height => {
+                    (*x, *y) = (to_x, to_y);
+                    true
+                }
+                _ => false,
+            }
+        });
+    }
+
+    //Adds the orbits kept for a part of the grid, computed as a view of its
+    //own with its top left corner at (left, top)
+    pub fn insert(&mut self, part : Orbits, left : usize, top : usize) {
+        self.orbits.extend(part.orbits.into_iter().map(|((x, y), orbit)| ((left + x, top + y), orbit)));
+    }
+}
+
+//A pixel waiting for a lane, its column or index and where its orbit is
+type Pending = (usize, Orbit);
+
+//Whether the kernel draws params. Colour modes with an orbit hook track
+//more of the orbit than it does, other formulas and precisions and
+//subdivision go through the scalar loop
+pub fn applies(params : &MandleParams) -> bool {
+    params.precision() == Precision::Double
+        && formula::get(params.formula).vectorized()
+        && !matches!(params.color_mode, ColorMode::Trap | ColorMode::Atom | ColorMode::Stripe | ColorMode::Tia)
+        && !params.subdivide
+}
+
+//Same contract as calc_mandlebrot_set
+pub fn calc_mandlebrot_set(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken
+) -> bool {
+    compute(grid, params, pass, cancel, None)
+}
+
+//calc_mandlebrot_set that also keeps the orbits of pixels still going at
+//the iteration limit in orbits, kept for params
+pub fn calc_mandlebrot_set_keeping(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken,
+    orbits : &mut Orbits
+) -> bool {
+    compute(grid, params, pass, cancel, Some(orbits))
+}
+
+fn compute(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken,
+    orbits : Option<&mut Orbits>
+) -> bool {
+    let a = params.x.to_fixed();
+    let b = params.y.to_fixed();
+    let zoom_level = params.zoom;
+    let row_len = grid.cols();
+    let half_width = grid.cols() as f64 / 2.0;
+    let half_height = grid.rows() as f64 / 2.0;
+    let keep = orbits.is_some();
+    let kept : Vec<((usize, usize), Orbit)> = grid.par_rows_mut()
+        .filter(|(y, _)| pass.row_included(*y))
+        .flat_map_iter(|(y, row)| {
+            let y_offset = (b + MReal::from_num((y as f64 - half_height) * zoom_level)).to_num::<f64>();
+            let mut pending : Vec<Pending> = Vec::with_capacity(row_len);
+            for (x, cell) in row.iter_mut().enumerate() {
+                if !pass.includes(x, y) {
+                    continue;
+                }
+                let x_offset = (a + MReal::from_num((x as f64 - half_width) * zoom_level)).to_num::<f64>();
+                match waiting(x, (x_offset, y_offset), params) {
+                    Some(pixel) => pending.push(pixel),
+                    None => *cell = Sample::INTERIOR,
+                }
+            }
+            let mut kept = Vec::new();
+            for group in pending.chunks(LANES) {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                let (samples, orbits) = divergence(group, 0, params);
+                for ((&(x, _), sample), orbit) in group.iter().zip(samples).zip(orbits) {
+                    row[x] = sample;
+                    if let Some(orbit) = orbit.filter(|_| keep) {
+                        kept.push(((x, y), orbit));
+                    }
+                }
+            }
+            kept
+        })
+        .collect();
+    if let Some(orbits) = orbits {
+        orbits.orbits.extend(kept);
+    }
+    !cancel.is_cancelled()
+}
+
+//Samples of points given by z[0] rather than laid out in a grid, for
+//sampling along other shapes. Neighbours in the list share groups
+pub fn samples(points : &[(f64, f64)], params : &MandleParams) -> Vec<Sample> {
+    let mut samples = vec![Sample::INTERIOR; points.len()];
+    let pending : Vec<Pending> = points.iter()
+        .enumerate()
+        .filter_map(|(index, &z)| waiting(index, z, params))
+        .collect();
+    for group in pending.chunks(LANES) {
+        for (&(index, _), sample) in group.iter().zip(divergence(group, 0, params).0) {
+            samples[index] = sample;
+        }
+    }
+    samples
+}
+
+//The point waiting for a lane, None when it is known to be interior
+fn waiting(index : usize, z : (f64, f64), params : &MandleParams) -> Option<Pending> {
+    let c = match params.fractal {
+        Fractal::Mandlebrot => z,
+        Fractal::Julia { c_a, c_b } => (c_a.to_num::<f64>(), c_b.to_num::<f64>()),
+    };
+    //The interior would hold the whole group up to max_iter
+    if params.fractal == Fractal::Mandlebrot && in_main_bulbs(c) {
+        return None;
+    }
+    Some((index, Orbit::start(z, c)))
+}
+
+//Samples of up to LANES pixels from iteration from on, the lanes past the
+//end of group are unused. Orbits of the lanes that reach the iteration
+//limit without stopping are handed back
+fn divergence(group : &[Pending], from : u32, params : &MandleParams) -> ([Sample; LANES], [Option<Orbit>; LANES]) {
+    let lanes = |part : fn(&Orbit) -> f64| {
+        f64x4::from(std::array::from_fn::<f64, LANES, _>(|lane| group.get(lane).map_or(0.0, |(_, orbit)| part(orbit))))
+    };
+    let (mut z_a, mut z_b) = (lanes(|orbit| orbit.z.0), lanes(|orbit| orbit.z.1));
+    let (c_a, c_b) = (lanes(|orbit| orbit.c.0), lanes(|orbit| orbit.c.1));
+    let mut active = f64x4::from([0.0, 1.0, 2.0, 3.0]).cmp_lt(f64x4::splat(group.len() as f64));
+    let mut samples = [Sample::INTERIOR; LANES];
+
+    let bailout2 = f64x4::splat(params.bailout * params.bailout);
+    let tolerance = MReal::saturating_from_num(params.zoom * PERIOD_TOLERANCE).max(MReal::DELTA);
+    let tolerance = f64x4::splat(tolerance.to_num::<f64>());
+    let two = f64x4::splat(2.0);
+    let (mut saved_a, mut saved_b) = (lanes(|orbit| orbit.saved.0), lanes(|orbit| orbit.saved.1));
+    let distance = params.color_mode == ColorMode::Distance;
+    let plus_one = f64x4::splat(if params.fractal == Fractal::Mandlebrot { 1.0 } else { 0.0 });
+    let (mut der_a, mut der_b) = (lanes(|orbit| orbit.derivative.0), lanes(|orbit| orbit.derivative.1));
+
+    for i in from..params.iterations {
+        let mod2 = z_a * z_a + z_b * z_b;
+        let escaped = mod2.cmp_gt(bailout2) & active;
+        if escaped.any() {
+            let (a, b, mod2) = (z_a.to_array(), z_b.to_array(), mod2.to_array());
+            let der_mod2 = (der_a * der_a + der_b * der_b).to_array();
+            let mask = escaped.move_mask();
+            for (lane, sample) in samples.iter_mut().enumerate() {
+                if mask & (1 << lane) == 0 {
+                    continue;
+                }
+                *sample = Sample {
+                    stopped: Some(i),
+                    z: (a[lane] as f32, b[lane] as f32),
+                    distance: if distance {
+                        distance_estimate(mod2[lane], der_mod2[lane], params.zoom)
+                    } else {
+                        f32::NAN
+                    },
+                };
+            }
+            active = escaped.blend(f64x4::ZERO, active);
+            if active.none() {
+                break;
+            }
+        }
+        //2 * z * dz + 1
+        if distance {
+            let (twice_a, twice_b) = (two * z_a, two * z_b);
+            let der_a_new = twice_a * der_a - twice_b * der_b + plus_one;
+            der_b = twice_a * der_b + twice_b * der_a;
+            der_a = der_a_new;
+        }
+        //Brent's cycle detection as in calc_mandle_divergence, caught lanes stay interior
+        if i.is_power_of_two() {
+            saved_a = z_a;
+            saved_b = z_b;
+        } else if i > 1 {
+            let caught = (z_a - saved_a).abs().cmp_le(tolerance) & (z_b - saved_b).abs().cmp_le(tolerance) & active;
+            if caught.any() {
+                active = caught.blend(f64x4::ZERO, active);
+                if active.none() {
+                    break;
+                }
+            }
+        }
+        let a_new = z_a * z_a - z_b * z_b + c_a;
+        z_b = two * z_a * z_b + c_b;
+        z_a = a_new;
+    }
+
+    //Whatever is still active ran out of iterations
+    let mask = active.move_mask();
+    let (z_a, z_b) = (z_a.to_array(), z_b.to_array());
+    let (saved_a, saved_b) = (saved_a.to_array(), saved_b.to_array());
+    let (der_a, der_b) = (der_a.to_array(), der_b.to_array());
+    let orbits = std::array::from_fn(|lane| (mask & (1 << lane) != 0).then(|| Orbit {
+        z: (z_a[lane], z_b[lane]),
+        c: group[lane].1.c,
+        saved: (saved_a[lane], saved_b[lane]),
+        derivative: (der_a[lane], der_b[lane]),
+    }));
+    (samples, orbits)
+}
@@ -0,0 +1,193 @@
+//Sixel images, for terminals that draw pixels inline. The colours are cut
+//down to 256 registers like a GIF's table. Each band of six rows is sent
+//a colour at a time, a character per column with a bit for each row the
+//colour covers, in runs where it repeats
+
+use std::fmt::Write as _;
+
+use crate::gif::{self, GifDither, Lookup};
+
+//Encodes rgb pixels of width x height, from the escape that starts the
+//image to the one that ends it
+pub fn encode(image : &[u8], width : usize, height : usize, dither : GifDither) -> String {
+    let colors = gif::color_table(&[image]);
+    let mut lookup = Lookup::new(&colors);
+    let indices = gif::map_frame(image, width, height, dither, gif::spread(&colors), &mut lookup);
+
+    //Square pixels with the background filled in, then the size
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    //Registers take percentages
+    for (index, color) in colors.iter().enumerate() {
+        let [r, g, b] = color.map(|channel| (channel as u32 * 100 + 127) / 255);
+        let _ = write!(out, "#{};2;{};{};{}", index, r, g, b);
+    }
+
+    //The bits of each colour in the band, a byte per column, and which
+    //colours the band uses in the order they first appear
+    let mut bits = vec![0u8; colors.len() * width];
+    let mut used = Vec::new();
+    let mut in_band = vec![false; colors.len()];
+    for band in (0..height).step_by(6) {
+        for row in band..(band + 6).min(height) {
+            for col in 0..width {
+                let color = indices[row * width + col] as usize;
+                if !in_band[color] {
+                    in_band[color] = true;
+                    used.push(color);
+                }
+                bits[color * width + col] |= 1 << (row - band);
+            }
+        }
+        for (i, &color) in used.iter().enumerate() {
+            //Back to the start of the band for every colour after the first
+            if i > 0 {
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", color);
+            let columns = &mut bits[color * width..(color + 1) * width];
+            let mut col = 0;
+            while col < width {
+                let value = columns[col];
+                let run = columns[col..].iter().take_while(|&&other| other == value).count();
+                push_run(&mut out, (b'?' + value) as char, run);
+                col += run;
+            }
+            columns.fill(0);
+            in_band[color] = false;
+        }
+        used.clear();
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+//A sixel repeated run times, with the repeat introducer once it's shorter
+fn push_run(out : &mut String, sixel : char, run : usize) {
+    if run > 3 {
+        let _ = write!(out, "!{}{}", run, sixel);
+    } else {
+        out.extend(std::iter::repeat_n(sixel, run));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    //The registers set and the register each pixel was drawn in, checking
+    //every pixel is drawn once and nothing past the image is
+    fn decode(sixel : &str, width : usize, height : usize) -> (HashMap<usize, [u32; 3]>, Vec<usize>) {
+        let header = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+        let body = sixel.strip_prefix(&header).unwrap().strip_suffix("\x1b\\").unwrap();
+        let mut chars = body.chars().peekable();
+        let number = |chars : &mut std::iter::Peekable<std::str::Chars>| {
+            let mut digits = String::new();
+            while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(digit);
+                chars.next();
+            }
+            digits.parse::<usize>().unwrap()
+        };
+        let mut registers = HashMap::new();
+        let mut pixels = vec![None; width * height];
+        let (mut color, mut col, mut band) = (None, 0, 0);
+        while let Some(c) = chars.next() {
+            let mut run = 1;
+            let sixel = match c {
+                '#' => {
+                    let index = number(&mut chars);
+                    if chars.peek() == Some(&';') {
+                        chars.next();
+                        assert_eq!(number(&mut chars), 2);
+                        let mut channel = || {
+                            assert_eq!(chars.next(), Some(';'));
+                            number(&mut chars) as u32
+                        };
+                        registers.insert(index, [channel(), channel(), channel()]);
+                    } else {
+                        color = Some(index);
+                    }
+                    continue;
+                }
+                '$' => {
+                    col = 0;
+                    continue;
+                }
+                '-' => {
+                    (col, band) = (0, band + 6);
+                    continue;
+                }
+                '!' => {
+                    run = number(&mut chars);
+                    chars.next().unwrap()
+                }
+                sixel => sixel,
+            };
+            let bits = sixel as u8 - b'?';
+            assert!(bits < 64, "{:?} isn't a sixel", sixel);
+            for _ in 0..run {
+                for row in (0..6).filter(|row| bits & 1 << row != 0) {
+                    assert!(col < width && band + row < height, "drawn at {}, {}", col, band + row);
+                    let pixel = &mut pixels[(band + row) * width + col];
+                    assert_eq!(*pixel, None, "drawn twice at {}, {}", col, band + row);
+                    *pixel = color;
+                }
+                col += 1;
+            }
+        }
+        (registers, pixels.into_iter().map(|pixel| pixel.expect("every pixel is drawn")).collect())
+    }
+
+    //Pixels of colours picked by pick from each pixel's position
+    fn image(width : usize, height : usize, pick : impl Fn(usize, usize) -> [u8; 3]) -> Vec<u8> {
+        (0..width * height).flat_map(|i| pick(i % width, i / width)).collect()
+    }
+
+    #[test]
+    fn pixels_come_back_in_their_registers() {
+        let colors = [[255, 0, 0], [0, 128, 255], [255, 255, 255]];
+        let (width, height) = (9, 7);
+        let pixels = image(width, height, |x, y| colors[(x / 3 + y) % 3]);
+        let sixel = encode(&pixels, width, height, GifDither::None);
+        let (registers, drawn) = decode(&sixel, width, height);
+        //As percentages, rounded to the nearest. Median cut can leave a
+        //colour in more than one register
+        let mut set : Vec<[u32; 3]> = registers.values().copied().collect();
+        set.sort();
+        set.dedup();
+        assert_eq!(set, [[0, 50, 100], [100, 0, 0], [100, 100, 100]]);
+        for (i, register) in drawn.iter().enumerate() {
+            let color = colors[(i % width / 3 + i / width) % 3];
+            assert_eq!(registers[register], color.map(|channel| (channel as u32 * 100 + 127) / 255));
+        }
+    }
+
+    #[test]
+    fn bands_are_six_rows() {
+        //Three colours in the first band, one in the last row's
+        let (width, height) = (4, 7);
+        let sixel = encode(&image(width, height, |x, y| if y == 6 { [0; 3] } else { [x as u8 * 80, 0, 0] }), width, height, GifDither::None);
+        let data = &sixel[sixel.rfind(';').unwrap()..];
+        assert_eq!(data.matches('-').count(), 2);
+        let bands : Vec<&str> = data.trim_end_matches("-\x1b\\").split('-').collect();
+        assert_eq!(bands[0].matches('$').count(), 3);
+        assert_eq!(bands[1].matches('$').count(), 0);
+        //Only the band's top row is filled
+        assert!(bands[1].ends_with("!4@"), "{}", bands[1]);
+    }
+
+    #[test]
+    fn repeats_are_runs_over_three() {
+        let mut out = String::new();
+        for run in 1..=5 {
+            push_run(&mut out, '~', run);
+            out.push('|');
+        }
+        assert_eq!(out, "~|~~|~~~|!4~|!5~|");
+        let sixel = encode(&[7; 3 * 200 * 6], 200, 6, GifDither::None);
+        assert!(sixel.ends_with("#0!200~-\x1b\\"), "{}", sixel);
+    }
+}
@@ -0,0 +1,56 @@
+//Stripe average colouring. A point is coloured by the average of a sine of
+//the argument of z over its orbit, which runs in bands along the filaments
+//where the escape time alone is the same all the way across
+
+use crate::MandleParams;
+use crate::hook::OrbitHook;
+use crate::palette::PALETTE_REPEATS;
+use crate::real::Real;
+
+//Stripes per turn of the argument
+const STRIPE_DENSITY: f64 = 5.0;
+
+//Running sum of the stripes the orbit has passed through
+#[derive(Default)]
+pub struct Stripe {
+    sum : f64,
+    //What the last point added, the one the orbit escaped at once it has
+    last : f64,
+    count : u32,
+}
+
+//Starts from the first step, like the trap
+impl OrbitHook for Stripe {
+    fn visit<R : Real>(&mut self, i : u32, (a, b) : (R, R)) {
+        if i > 0 {
+            self.last = 0.5 * (STRIPE_DENSITY * b.to_f64().atan2(a.to_f64())).sin() + 0.5;
+            self.sum += self.last;
+            self.count += 1;
+        }
+    }
+
+    fn escaped(&self, mod2 : f64, degree : f64, params : &MandleParams) -> f32 {
+        average(self.sum, self.last, self.count, mod2, degree, params.bailout)
+    }
+}
+
+//Average of count terms adding up to sum for an orbit that escaped with
+//|z|^2 = mod2, last the term of the point it escaped at and degree the power
+//z is raised to. The averages with and without that point are blended by
+//the fraction of the smooth iteration count, otherwise the average would
+//jump at every band of escape time
+pub fn average(sum : f64, last : f64, count : u32, mod2 : f64, degree : f64, bailout : f64) -> f32 {
+    if count < 2 {
+        return sum as f32;
+    }
+    let with = sum / count as f64;
+    let without = (sum - last) / (count - 1) as f64;
+    let fraction = (1.0 + (bailout.ln() / (0.5 * mod2.ln())).ln() / degree.ln()).clamp(0.0, 1.0);
+    (without + (with - without) * fraction) as f32
+}
+
+//Grid value of a point whose orbit averaged this much, over one palette
+//repeat. Never 0, that is the interior
+pub fn value(average : f32) -> f64 {
+    (average as f64).clamp(f64::MIN_POSITIVE, 1.0) / PALETTE_REPEATS
+}
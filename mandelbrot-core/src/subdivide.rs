@@ -0,0 +1,179 @@
+//Mariani-Silver rectangle subdivision. A rectangle whose border is all one
+//value is filled with its corner sample rather than computed, the set is
+//connected so nothing can be hiding inside. Rectangles with a mixed border are split
+//into quarters until they are small enough to compute outright
+
+use rayon::prelude::*;
+
+use crate::formula::{self, Divergence};
+use crate::{CancelToken, Grid, MandleParams, RefinePass, Sample};
+
+//Pass lattice points across a starting tile, tiles are spread over the rayon pool
+const TILE_SIZE: usize = 32;
+
+//Rectangles this narrow are computed point by point, the border is most of them
+const MIN_SIZE: usize = 4;
+
+//Inclusive bounds in lattice points
+#[derive(Clone, Copy)]
+struct Rect {
+    left : usize,
+    top : usize,
+    right : usize,
+    bottom : usize,
+}
+
+impl Rect {
+    fn width(&self) -> usize {
+        self.right - self.left + 1
+    }
+
+    fn height(&self) -> usize {
+        self.bottom - self.top + 1
+    }
+}
+
+//One starting tile. Samples are cached so the edges shared by quarters are
+//only computed once
+struct Tile<'a> {
+    grid : &'a Grid<Sample>,
+    params : &'a MandleParams,
+    pass : RefinePass,
+    cancel : &'a CancelToken<'a>,
+    formula : &'static dyn Divergence,
+    bounds : Rect,
+    samples : Vec<Option<Sample>>,
+}
+
+impl Tile<'_> {
+
+    fn sample(&mut self, x : usize, y : usize) -> Sample {
+        let idx = (y - self.bounds.top) * self.bounds.width() + x - self.bounds.left;
+        if let Some(sample) = self.samples[idx] {
+            return sample;
+        }
+        let sample = self.compute(x * self.pass.step, y * self.pass.step);
+        self.samples[idx] = Some(sample);
+        sample
+    }
+
+    fn value(&mut self, x : usize, y : usize) -> f64 {
+        self.sample(x, y).value(self.params)
+    }
+
+    //Pixels a coarser pass already did are taken from the grid
+    fn compute(&self, px : usize, py : usize) -> Sample {
+        if !self.pass.includes(px, py) {
+            return self.grid[(px, py)];
+        }
+        if self.cancel.is_cancelled() {
+            return Sample::INTERIOR;
+        }
+        let params = self.params;
+        let (a, b) = params.pixel_to_complex(px as f64, py as f64);
+        let (a, b) = (a.to_fixed(), b.to_fixed());
+        self.formula.divergence((a, b), params.fractal.constant(a, b), params)
+    }
+
+    fn subdivide(&mut self, rect : Rect) {
+        if rect.width() <= MIN_SIZE || rect.height() <= MIN_SIZE {
+            for y in rect.top..=rect.bottom {
+                for x in rect.left..=rect.right {
+                    self.sample(x, y);
+                }
+            }
+            return;
+        }
+
+        let corner = self.sample(rect.left, rect.top);
+        let first = corner.value(self.params);
+        let mut uniform = true;
+        for x in rect.left..=rect.right {
+            uniform &= self.value(x, rect.top) == first;
+            uniform &= self.value(x, rect.bottom) == first;
+        }
+        for y in rect.top..=rect.bottom {
+            uniform &= self.value(rect.left, y) == first;
+            uniform &= self.value(rect.right, y) == first;
+        }
+
+        if uniform {
+            for y in rect.top + 1..rect.bottom {
+                for x in rect.left + 1..rect.right {
+                    let idx = (y - self.bounds.top) * self.bounds.width() + x - self.bounds.left;
+                    self.samples[idx] = Some(corner);
+                }
+            }
+            return;
+        }
+
+        //Quarters share their middle row and column
+        let mid_x = (rect.left + rect.right) / 2;
+        let mid_y = (rect.top + rect.bottom) / 2;
+        for (left, right) in [(rect.left, mid_x), (mid_x, rect.right)] {
+            for (top, bottom) in [(rect.top, mid_y), (mid_y, rect.bottom)] {
+                self.subdivide(Rect { left, top, right, bottom });
+            }
+        }
+    }
+}
+
+//Same contract as calc_mandlebrot_set. The pass's step grid is subdivided
+//as if it were the whole image
+pub fn calc_mandlebrot_set(
+    grid : &mut Grid<Sample>,
+    params : &MandleParams,
+    pass : RefinePass,
+    cancel : &CancelToken
+) -> bool {
+    let step = pass.step;
+    let lattice_width = grid.cols().div_ceil(step);
+    let lattice_height = grid.rows().div_ceil(step);
+
+    let mut tiles = Vec::new();
+    for top in (0..lattice_height).step_by(TILE_SIZE) {
+        for left in (0..lattice_width).step_by(TILE_SIZE) {
+            tiles.push(Rect {
+                left,
+                top,
+                right: (left + TILE_SIZE).min(lattice_width) - 1,
+                bottom: (top + TILE_SIZE).min(lattice_height) - 1,
+            });
+        }
+    }
+
+    let formula = formula::get(params.formula);
+    let filled : Vec<(Rect, Vec<Option<Sample>>)> = {
+        let grid = &*grid;
+        tiles
+            .into_par_iter()
+            .map(|bounds| {
+                let mut tile = Tile {
+                    grid,
+                    params,
+                    pass,
+                    cancel,
+                    formula,
+                    bounds,
+                    samples: vec![None; bounds.width() * bounds.height()],
+                };
+                tile.subdivide(bounds);
+                (bounds, tile.samples)
+            })
+            .collect()
+    };
+    if cancel.is_cancelled() {
+        return false;
+    }
+
+    for (bounds, samples) in filled {
+        for (idx, sample) in samples.into_iter().enumerate() {
+            let x = (bounds.left + idx % bounds.width()) * step;
+            let y = (bounds.top + idx / bounds.width()) * step;
+            if let Some(sample) = sample {
+                grid[(x, y)] = sample;
+            }
+        }
+    }
+    true
+}
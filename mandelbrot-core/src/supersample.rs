@@ -0,0 +1,142 @@
+//Supersampling antialiasing. Once the grid is complete, pixels get an n x n
+//set of jittered samples which are coloured and averaged when drawing. In
+//adaptive mode only pixels that differ from a neighbour are sampled, flat
+//areas look the same with one sample.
+//Extra samples always go through the cpu formula, whichever backend drew the grid
+
+use rayon::prelude::*;
+
+use crate::buddhabrot::XorShift;
+use crate::formula::{self, Divergence};
+use crate::palette;
+use crate::{CancelToken, Colorizer, Grid, MandleParams, Sample};
+#[cfg(feature = "rug")]
+use crate::{Backend, Precision, perturbation};
+
+//Samples per axis Q cycles through, 1 is off
+pub const FACTORS: [u32; 3] = [1, 2, 4];
+
+//Neighbouring values further apart than this get supersampled, about a
+//twentieth of one of the palette's repeats
+const THRESHOLD: f64 = 0.006;
+
+pub struct Supersamples {
+    width : usize,
+    //samples[starts[i]..starts[i + 1]] are the samples of pixel i,
+    //pixels without any keep their grid sample
+    starts : Vec<usize>,
+    samples : Vec<Sample>,
+}
+
+impl Supersamples {
+
+    //None if the render was cancelled part way
+    pub fn compute(grid : &Grid<Sample>, params : &MandleParams, cancel : &CancelToken) -> Option<Supersamples> {
+        let n = params.supersample as usize;
+        let width = grid.cols();
+        let height = grid.rows();
+        let formula = formula::get(params.formula);
+        let half_width = width as f64 / 2.0;
+        let half_height = height as f64 / 2.0;
+
+        let rows : Vec<(Vec<usize>, Vec<Sample>)> = (0..height)
+            .into_par_iter()
+            .map(|y| {
+                let mut counts = vec![0; width];
+                let mut samples = Vec::new();
+                for (x, count) in counts.iter_mut().enumerate() {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    if !params.supersample_all && !differs(grid, params, x, y) {
+                        continue;
+                    }
+                    //One sample in each of the n x n cells of the pixel, at a random spot in the cell
+                    let mut rng = XorShift::new((y * width + x) as u64);
+                    for sy in 0..n {
+                        for sx in 0..n {
+                            let px = x as f64 + (sx as f64 + rng.next_f64()) / n as f64 - 0.5;
+                            let py = y as f64 + (sy as f64 + rng.next_f64()) / n as f64 - 0.5;
+                            samples.push(sample(formula, params, px - half_width, py - half_height));
+                        }
+                    }
+                    *count = n * n;
+                }
+                (counts, samples)
+            })
+            .collect();
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        let mut starts = Vec::with_capacity(width * height + 1);
+        let mut samples = Vec::new();
+        starts.push(0);
+        for (counts, row_samples) in rows {
+            for count in counts {
+                starts.push(starts[starts.len() - 1] + count);
+            }
+            samples.extend(row_samples);
+        }
+        Some(Supersamples { width, starts, samples })
+    }
+
+    //Average over the grid, pixels without extra samples count as one
+    pub fn per_pixel(&self) -> f64 {
+        let pixels = self.starts.len() - 1;
+        let sampled = (0..pixels).filter(|&i| self.starts[i + 1] > self.starts[i]).count();
+        (self.samples.len() + pixels - sampled) as f64 / pixels as f64
+    }
+
+    //Redraws the supersampled pixels of a frame already drawn from the grid
+    pub fn render(&self, frame : &mut [u8], coloring : &dyn Colorizer) {
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let samples = &self.samples[self.starts[i]..self.starts[i + 1]];
+            if samples.is_empty() {
+                continue;
+            }
+            //Averaged in linear light, sRGB averages come out too dark
+            let mut sum = [0.0f64; 3];
+            for &sample in samples {
+                for (total, channel) in sum.iter_mut().zip(coloring.light(sample)) {
+                    *total += channel;
+                }
+            }
+            let average = sum.map(|total| total / samples.len() as f64);
+            let position = (i % self.width, i / self.width);
+            let col = palette::quantize(average, position, coloring.dithered());
+            pixel[..3].copy_from_slice(&col);
+        }
+    }
+}
+
+//Whether any of the 4 neighbours is a different colour band
+fn differs(grid : &Grid<Sample>, params : &MandleParams, x : usize, y : usize) -> bool {
+    let value = grid[(x, y)].value(params);
+    let neighbours = [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ];
+    neighbours.iter()
+        .filter(|&&(nx, ny)| nx < grid.cols() && ny < grid.rows())
+        .any(|&(nx, ny)| {
+            let other = grid[(nx, ny)].value(params);
+            //In or out of the set is always a hard edge
+            (value <= 0.0) != (other <= 0.0) || (value - other).abs() > THRESHOLD
+        })
+}
+
+//Sample at an offset in pixels from the middle of the view
+pub fn sample(formula : &dyn Divergence, params : &MandleParams, dx : f64, dy : f64) -> Sample {
+    let a = params.x.offset(dx * params.zoom);
+    let b = params.y.offset(dy * params.zoom);
+    //Past fixed point each sample is iterated as its own perturbation reference
+    #[cfg(feature = "rug")]
+    if params.render_backend() == Backend::Perturbation && params.precision() == Precision::Arbitrary {
+        return perturbation::divergence_at(a, b, params);
+    }
+    let (a, b) = (a.to_fixed(), b.to_fixed());
+    formula.divergence((a, b), params.fractal.constant(a, b), params)
+}
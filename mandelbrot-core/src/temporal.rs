@@ -0,0 +1,83 @@
+//Temporal antialiasing. While the view sits still every pixel keeps
+//getting one more sample at a random spot inside it, and the frame shows
+//the average colour of everything sampled so far
+
+use rayon::prelude::*;
+
+use crate::buddhabrot::XorShift;
+use crate::formula;
+use crate::palette;
+use crate::supersample;
+use crate::{CancelToken, Colorizer, MandleParams};
+
+//Frames accumulated before stopping, past this the image hardly changes
+pub const MAX_FRAMES: u32 = 64;
+
+pub struct Accumulator {
+    width : usize,
+    //Sums of the light shown at each pixel, divided by frames when drawing
+    sums : Vec<[f32; 3]>,
+    frames : u32,
+}
+
+impl Accumulator {
+
+    //Starts from a frame drawn without the hud, counted as the first sample
+    pub fn new(frame : &[u8], width : usize) -> Accumulator {
+        Accumulator {
+            width,
+            sums: frame.chunks_exact(4)
+                .map(|pixel| [0, 1, 2].map(|channel| palette::to_linear(pixel[channel]) as f32))
+                .collect(),
+            frames: 1,
+        }
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    //Adds one jittered sample to every pixel. Returns false if it was cancelled part way
+    pub fn add_frame(&mut self, params : &MandleParams, coloring : &dyn Colorizer, cancel : &CancelToken) -> bool {
+        let formula = formula::get(params.formula);
+        let width = self.width;
+        let half_width = width as f64 / 2.0;
+        let half_height = (self.sums.len() / width) as f64 / 2.0;
+        //Each frame needs different offsets, so the frame is part of the seed
+        let seed = self.frames as u64 * self.sums.len() as u64;
+
+        let added : Vec<[f32; 3]> = self.sums
+            .par_chunks(width)
+            .enumerate()
+            .flat_map_iter(|(y, row)| {
+                (0..row.len()).map(move |x| {
+                    if cancel.is_cancelled() {
+                        return [0.0; 3];
+                    }
+                    let mut rng = XorShift::new(seed + (y * width + x) as u64);
+                    let px = x as f64 + rng.next_f64() - 0.5 - half_width;
+                    let py = y as f64 + rng.next_f64() - 0.5 - half_height;
+                    coloring.light(supersample::sample(formula, params, px, py)).map(|channel| channel as f32)
+                })
+            })
+            .collect();
+        if cancel.is_cancelled() {
+            return false;
+        }
+
+        for (sum, col) in self.sums.iter_mut().zip(added) {
+            for (total, channel) in sum.iter_mut().zip(col) {
+                *total += channel;
+            }
+        }
+        self.frames += 1;
+        true
+    }
+
+    pub fn render(&self, frame : &mut [u8], dithered : bool) {
+        for (i, (pixel, sum)) in frame.chunks_exact_mut(4).zip(&self.sums).enumerate() {
+            let average = sum.map(|total| (total / self.frames as f32) as f64);
+            pixel[..3].copy_from_slice(&palette::quantize(average, (i % self.width, i / self.width), dithered));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//Triangle inequality average colouring. A step z -> z^d + c lands somewhere
+//between ||z^d| - |c|| and |z^d| + |c| from 0, and a point is coloured by
+//the average of how far along that range each step of its orbit landed
+
+use crate::MandleParams;
+use crate::hook::OrbitHook;
+use crate::real::Real;
+use crate::stripe::average;
+
+pub struct Tia {
+    //|c| and the degree of the formula
+    c : f64,
+    degree : f64,
+    //|z|^d of the point before
+    power : f64,
+    sum : f64,
+    //The term of the point the orbit escaped at once it has
+    last : f64,
+    count : u32,
+}
+
+impl Tia {
+    pub fn new((a, b) : (f64, f64), degree : f64) -> Tia {
+        Tia {
+            c: a.hypot(b),
+            degree,
+            power: 0.0,
+            sum: 0.0,
+            last: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl OrbitHook for Tia {
+    fn visit<R : Real>(&mut self, i : u32, (a, b) : (R, R)) {
+        let modulus = a.to_f64().hypot(b.to_f64());
+        //A step from 0 has nowhere to land but |c|, it doesn't count
+        let low = (self.power - self.c).abs();
+        let high = self.power + self.c;
+        if i > 0 && high > low {
+            self.last = ((modulus - low) / (high - low)).clamp(0.0, 1.0);
+            self.sum += self.last;
+            self.count += 1;
+        }
+        self.power = modulus.powf(self.degree);
+    }
+
+    //Blended between the escape times like the stripe average
+    fn escaped(&self, mod2 : f64, degree : f64, params : &MandleParams) -> f32 {
+        average(self.sum, self.last, self.count, mod2, degree, params.bailout)
+    }
+}
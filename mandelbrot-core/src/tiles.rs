@@ -0,0 +1,70 @@
+//Tiles of the window's grid, rendered one by one from the centre of the view
+//outwards so the part being looked at fills in first. Each tile is computed
+//as a view of its own, lined up with the coarse refinement passes so those
+//only ever need the whole grid
+
+use std::f64::consts::TAU;
+use std::ops::Range;
+
+use crate::MandleParams;
+
+//A multiple of the coarsest refinement step, so every tile's pixels fall on
+//the steps the same way the whole grid's do
+pub const TILE_SIZE: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tile {
+    pub left : usize,
+    pub top : usize,
+    pub width : usize,
+    pub height : usize,
+}
+
+impl Tile {
+
+    pub fn columns(&self) -> Range<usize> {
+        self.left..self.left + self.width
+    }
+
+    pub fn rows(&self) -> Range<usize> {
+        self.top..self.top + self.height
+    }
+
+    //The tile as a view centred on its middle, pixel for pixel the same
+    //points as the tile of params' view
+    pub fn view(&self, params : &MandleParams) -> MandleParams {
+        let (x, y) = params.pixel_to_complex(
+            self.left as f64 + self.width as f64 / 2.0,
+            self.top as f64 + self.height as f64 / 2.0
+        );
+        MandleParams { x, y, width: self.width, height: self.height, ..params.clone() }
+    }
+}
+
+//Tiles covering a width x height grid in a spiral from the centre, ring by
+//ring with each ring going round clockwise from the left
+pub fn spiral(width : usize, height : usize) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for top in (0..height).step_by(TILE_SIZE) {
+        for left in (0..width).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                left,
+                top,
+                width: TILE_SIZE.min(width - left),
+                height: TILE_SIZE.min(height - top),
+            });
+        }
+    }
+    let order = |tile : &Tile| {
+        let dx = (tile.left as f64 + tile.width as f64 / 2.0 - width as f64 / 2.0) / TILE_SIZE as f64;
+        let dy = (tile.top as f64 + tile.height as f64 / 2.0 - height as f64 / 2.0) / TILE_SIZE as f64;
+        let ring = dx.abs().max(dy.abs()).round();
+        //Screen y points down, so this goes clockwise
+        (ring, (dy.atan2(dx) + TAU / 2.0).rem_euclid(TAU))
+    };
+    tiles.sort_by(|a, b| {
+        let ((a_ring, a_angle), (b_ring, b_angle)) = (order(a), order(b));
+        a_ring.total_cmp(&b_ring).then(a_angle.total_cmp(&b_angle))
+    });
+    tiles
+}
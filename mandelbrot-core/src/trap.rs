@@ -0,0 +1,76 @@
+//Orbit traps. In trap colouring a point is coloured by how close its orbit
+//came to a shape in the plane rather than by how long it took to escape
+
+use serde::{Deserialize, Serialize};
+
+use crate::MandleParams;
+use crate::hook::OrbitHook;
+use crate::real::Real;
+
+//Distances are taken on a log scale relative to this, the palette runs
+//through its repeats between about this close and the bailout radius
+const TRAP_WIDTH: f64 = 0.01;
+const TRAP_RANGE: f64 = 6.0;
+
+//Shape the orbit is measured against, centred on the trap centre in params
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Trap {
+    Point,
+    //The horizontal and vertical lines through the centre
+    Cross,
+    //A circle of trap_radius
+    Ring,
+}
+
+impl Trap {
+    pub fn next(self) -> Trap {
+        match self {
+            Trap::Point => Trap::Cross,
+            Trap::Cross => Trap::Ring,
+            Trap::Ring => Trap::Point,
+        }
+    }
+
+    //Distance from z to the shape
+    pub fn distance(self, (a, b) : (f64, f64), params : &MandleParams) -> f64 {
+        let dx = a - params.trap_x;
+        let dy = b - params.trap_y;
+        match self {
+            Trap::Point => (dx * dx + dy * dy).sqrt(),
+            Trap::Cross => dx.abs().min(dy.abs()),
+            Trap::Ring => ((dx * dx + dy * dy).sqrt() - params.trap_radius).abs(),
+        }
+    }
+}
+
+//Closest the orbit has come to the trap in params, the starting point
+//isn't counted
+pub struct Trapped<'a> {
+    params : &'a MandleParams,
+    closest : f64,
+}
+
+impl Trapped<'_> {
+    pub fn new(params : &MandleParams) -> Trapped<'_> {
+        Trapped { params, closest: f64::MAX }
+    }
+}
+
+impl OrbitHook for Trapped<'_> {
+    fn visit<R : Real>(&mut self, i : u32, z : (R, R)) {
+        if i > 0 {
+            self.closest = self.closest.min(self.params.trap.distance((z.0.to_f64(), z.1.to_f64()), self.params));
+        }
+    }
+
+    fn escaped(&self, _mod2 : f64, _degree : f64, _params : &MandleParams) -> f32 {
+        self.closest as f32
+    }
+}
+
+//Grid value of a point whose orbit came within distance of the trap.
+//Never 0, that is the interior
+pub fn value(distance : f64) -> f64 {
+    ((distance / TRAP_WIDTH).ln_1p() / TRAP_RANGE).clamp(f64::MIN_POSITIVE, 1.0)
+}
@@ -0,0 +1,18 @@
+//Writes include/mandelbrot.h from the exports in src/lib.rs, so the header
+//can't drift from them. It's checked in for C builds that never run cargo,
+//a change to it showing up in git is the API changing
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR"));
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    match cbindgen::generate(&crate_dir) {
+        Ok(bindings) => {
+            bindings.write_to_file(crate_dir.join("include/mandelbrot.h"));
+        }
+        //Rust's own errors for the same source say more, leave it to them
+        Err(err) => println!("cargo:warning=mandelbrot.h not regenerated, {}", err),
+    }
+}
@@ -0,0 +1,124 @@
+/*
+ * The mandlebrot renderer for C and C++ programs, from mandelbrot-ffi.
+ * Link against libmandelbrot_ffi (shared or static, the static library also
+ * needs -lpthread -ldl -lm on linux).
+ *
+ *     MandelbrotRenderer *renderer = mandelbrot_renderer_new();
+ *     MandelbrotParams params = {
+ *         .x = "-0.743643887", .y = "0.131825904", .zoom = 1e-9,
+ *         .iterations = 2000, .width = 1920, .height = 1080,
+ *     };
+ *     uint8_t *pixels = malloc(1920 * 1080 * 4);
+ *     if (mandelbrot_set_params(renderer, &params) != 0
+ *             || mandelbrot_render(renderer, pixels, 1920 * 1080 * 4) != 0) {
+ *         fprintf(stderr, "%s\n", mandelbrot_last_error(renderer));
+ *     }
+ *     mandelbrot_renderer_free(renderer);
+ *
+ * A renderer is used from one thread at a time, renders spread over every
+ * core themselves.
+ */
+
+#ifndef MANDELBROT_H
+#define MANDELBROT_H
+
+/* Generated from src/lib.rs by cbindgen when mandelbrot-ffi builds, edit that instead */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * A view to render and the palettes to colour it with
+ */
+typedef struct MandelbrotRenderer MandelbrotRenderer;
+
+typedef struct MandelbrotParams {
+  /**
+   * Centre of the view as decimal strings, so deep zooms keep every
+   * digit. NULL keeps the renderer's
+   */
+  const char *x;
+  const char *y;
+  /**
+   * Distance between pixels in the plane, at least 1e-33
+   */
+  double zoom;
+  /**
+   * Iteration limit, at least 1
+   */
+  uint32_t iterations;
+  /**
+   * Image size in pixels, both at least 1 and at most 268435456 pixels
+   * in all
+   */
+  uint32_t width;
+  uint32_t height;
+  /**
+   * Formula and palette by name ignoring case, eg. "burning ship" and
+   * "ultra fractal". NULL keeps the renderer's
+   */
+  const char *formula;
+  const char *palette;
+} MandelbrotParams;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A renderer showing the whole set at 640x480 with 300 iterations and the
+ * built in palettes, to free with mandelbrot_renderer_free. NULL if it
+ * couldn't be made
+ */
+struct MandelbrotRenderer *mandelbrot_renderer_new(void);
+
+/**
+ * Frees a renderer from mandelbrot_renderer_new, NULL is ignored
+ *
+ * # Safety
+ *
+ * `renderer` is null or from `mandelbrot_renderer_new` and not freed
+ * already.
+ */
+void mandelbrot_renderer_free(struct MandelbrotRenderer *renderer);
+
+/**
+ * Changes the view. 0 on success, -1 with the renderer unchanged if any of
+ * params is invalid, see mandelbrot_last_error
+ *
+ * # Safety
+ *
+ * `renderer` is from `mandelbrot_renderer_new`, `params` points to a
+ * `MandelbrotParams` whose strings are null or nul terminated.
+ */
+int mandelbrot_set_params(struct MandelbrotRenderer *renderer,
+                          const struct MandelbrotParams *params);
+
+/**
+ * Renders the view into buffer as width * height rgba pixels, row by row
+ * from the top left. length is the size of buffer in bytes and has to be
+ * exactly width * height * 4. Blocks until done. 0 on success, -1 if the
+ * render failed, see mandelbrot_last_error
+ *
+ * # Safety
+ *
+ * `renderer` is from `mandelbrot_renderer_new` and `buffer` points to
+ * `length` writable bytes.
+ */
+int mandelbrot_render(struct MandelbrotRenderer *renderer, uint8_t *buffer, size_t length);
+
+/**
+ * Why the last call on renderer failed, or NULL if it succeeded. Valid
+ * until the next call on renderer
+ *
+ * # Safety
+ *
+ * `renderer` is from `mandelbrot_renderer_new`.
+ */
+const char *mandelbrot_last_error(const struct MandelbrotRenderer *renderer);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MANDELBROT_H */
@@ -0,0 +1,210 @@
+//! The renderer behind a C API for embedding it in C and C++ programs and
+//! plugins. `include/mandelbrot.h` declares what is exported here, cbindgen
+//! generates it from this file whenever the crate builds (see build.rs and
+//! cbindgen.toml), so the doc comments below are also the header's:
+//!
+//! ```c
+//! MandelbrotRenderer *renderer = mandelbrot_renderer_new();
+//! MandelbrotParams params = { .x = "-0.75", .y = "0.1", .zoom = 1e-5,
+//!     .iterations = 1000, .width = 640, .height = 480 };
+//! mandelbrot_set_params(renderer, &params);
+//! mandelbrot_render(renderer, pixels, 640 * 480 * 4);
+//! mandelbrot_renderer_free(renderer);
+//! ```
+//!
+//! Calls return 0 on success and -1 on failure, leaving the reason for
+//! `mandelbrot_last_error`. Panics are caught before reaching C and reported
+//! the same way.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{check_size, formula, Coord, MandleParams, Renderer, MIN_ZOOM};
+
+//Size of the view a new renderer shows
+const DEFAULT_WIDTH: usize = 640;
+const DEFAULT_HEIGHT: usize = 480;
+
+/// A view to render and the palettes to colour it with
+pub struct MandelbrotRenderer {
+    renderer : Renderer,
+    params : MandleParams,
+    error : Option<CString>,
+}
+
+#[repr(C)]
+pub struct MandelbrotParams {
+    /// Centre of the view as decimal strings, so deep zooms keep every
+    /// digit. NULL keeps the renderer's
+    pub x : *const c_char,
+    pub y : *const c_char,
+    /// Distance between pixels in the plane, at least 1e-33
+    pub zoom : f64,
+    /// Iteration limit, at least 1
+    pub iterations : u32,
+    /// Image size in pixels, both at least 1 and at most 268435456 pixels
+    /// in all
+    pub width : u32,
+    pub height : u32,
+    /// Formula and palette by name ignoring case, eg. "burning ship" and
+    /// "ultra fractal". NULL keeps the renderer's
+    pub formula : *const c_char,
+    pub palette : *const c_char,
+}
+
+impl MandelbrotRenderer {
+
+    //0 for Ok, -1 for Err with the message kept for mandelbrot_last_error
+    fn finish(&mut self, result : Result<(), String>) -> c_int {
+        match result {
+            Ok(()) => {
+                self.error = None;
+                0
+            }
+            Err(message) => {
+                //A message can't hold a nul, cut it off there if one does
+                let message = message.split('\0').next().unwrap_or("").to_string();
+                self.error = CString::new(message).ok();
+                -1
+            }
+        }
+    }
+}
+
+//Runs f, turning a panic into an error instead of unwinding into C
+fn guarded(f : impl FnOnce() -> Result<(), String>) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err("the renderer panicked, see stderr".to_string()))
+}
+
+/// A renderer showing the whole set at 640x480 with 300 iterations and the
+/// built in palettes, to free with mandelbrot_renderer_free. NULL if it
+/// couldn't be made
+#[no_mangle]
+pub extern "C" fn mandelbrot_renderer_new() -> *mut MandelbrotRenderer {
+    let created = panic::catch_unwind(|| {
+        let renderer = MandelbrotRenderer {
+            renderer: Renderer::new(),
+            params: MandleParams::new(DEFAULT_WIDTH, DEFAULT_HEIGHT),
+            error: None,
+        };
+        Box::into_raw(Box::new(renderer))
+    });
+    created.unwrap_or(ptr::null_mut())
+}
+
+/// Frees a renderer from mandelbrot_renderer_new, NULL is ignored
+///
+/// # Safety
+///
+/// `renderer` is null or from `mandelbrot_renderer_new` and not freed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_renderer_free(renderer : *mut MandelbrotRenderer) {
+    if !renderer.is_null() {
+        drop(Box::from_raw(renderer));
+    }
+}
+
+/// Changes the view. 0 on success, -1 with the renderer unchanged if any of
+/// params is invalid, see mandelbrot_last_error
+///
+/// # Safety
+///
+/// `renderer` is from `mandelbrot_renderer_new`, `params` points to a
+/// `MandelbrotParams` whose strings are null or nul terminated.
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_set_params(renderer : *mut MandelbrotRenderer, params : *const MandelbrotParams) -> c_int {
+    let Some(renderer) = renderer.as_mut() else {
+        return -1;
+    };
+    let result = match params.as_ref() {
+        Some(params) => guarded(|| {
+            renderer.params = apply(params, &renderer.params, renderer.renderer.palettes())?;
+            Ok(())
+        }),
+        None => Err("params is null".to_string()),
+    };
+    renderer.finish(result)
+}
+
+/// Renders the view into buffer as width * height rgba pixels, row by row
+/// from the top left. length is the size of buffer in bytes and has to be
+/// exactly width * height * 4. Blocks until done. 0 on success, -1 if the
+/// render failed, see mandelbrot_last_error
+///
+/// # Safety
+///
+/// `renderer` is from `mandelbrot_renderer_new` and `buffer` points to
+/// `length` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_render(renderer : *mut MandelbrotRenderer, buffer : *mut u8, length : usize) -> c_int {
+    let Some(renderer) = renderer.as_mut() else {
+        return -1;
+    };
+    let result = if buffer.is_null() {
+        Err("buffer is null".to_string())
+    } else {
+        let buffer = std::slice::from_raw_parts_mut(buffer, length);
+        let MandelbrotRenderer { renderer: inner, params, .. } = &*renderer;
+        guarded(|| inner.render(params, buffer).map_err(|err| err.to_string()))
+    };
+    renderer.finish(result)
+}
+
+/// Why the last call on renderer failed, or NULL if it succeeded. Valid
+/// until the next call on renderer
+///
+/// # Safety
+///
+/// `renderer` is from `mandelbrot_renderer_new`.
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_last_error(renderer : *const MandelbrotRenderer) -> *const c_char {
+    match renderer.as_ref().and_then(|renderer| renderer.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+//current with params applied over it, or why params can't be
+unsafe fn apply(params : &MandelbrotParams, current : &MandleParams, palettes : &[Palette]) -> Result<MandleParams, String> {
+    let mut view = current.clone();
+    if let Some(x) = text(params.x, "x")? {
+        view.x = x.trim().parse::<Coord>().map_err(|err| format!("x {}", err))?;
+    }
+    if let Some(y) = text(params.y, "y")? {
+        view.y = y.trim().parse::<Coord>().map_err(|err| format!("y {}", err))?;
+    }
+    if !(params.zoom.is_finite() && params.zoom >= MIN_ZOOM) {
+        return Err(format!("zoom {} isn't a number of at least {:e}", params.zoom, MIN_ZOOM));
+    }
+    view.zoom = params.zoom;
+    if params.iterations == 0 {
+        return Err("iterations must be at least 1".to_string());
+    }
+    view.iterations = params.iterations;
+    if params.width == 0 || params.height == 0 {
+        return Err(format!("{}x{} isn't a size to render", params.width, params.height));
+    }
+    check_size((params.width as usize, params.height as usize))?;
+    view.width = params.width as usize;
+    view.height = params.height as usize;
+    if let Some(name) = text(params.formula, "formula")? {
+        view.formula = formula::find(name).ok_or_else(|| format!("no formula named {}", name))?;
+    }
+    if let Some(name) = text(params.palette, "palette")? {
+        view.palette = palettes.iter()
+            .position(|palette| palette.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no palette named {}", name))?;
+    }
+    Ok(view)
+}
+
+//The string at text, None for null
+unsafe fn text<'a>(text : *const c_char, name : &str) -> Result<Option<&'a str>, String> {
+    if text.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(text).to_str().map(Some).map_err(|_| format!("{} isn't utf-8", name))
+}
@@ -0,0 +1,5 @@
+//The interpreter loading the module provides the python symbols, macOS's
+//linker has to be told to leave them for it
+fn main() {
+    pyo3_build_config::add_extension_module_link_args();
+}
@@ -0,0 +1,160 @@
+//! The core renderer as a python module, for scripting renders and looking
+//! at the iteration data with numpy:
+//!
+//! ```python
+//! import mandelbrot
+//! data = mandelbrot.render(-0.75, 0.1, 1e-5, 1000, 640, 480)
+//! print(data.shape, data.max())
+//! ```
+//!
+//! `render(x, y, zoom, iterations, width, height, *, channel="iterations")`
+//! computes width x height pixels around (x, y), zoom apart in the plane,
+//! and returns one of the channels of [`raw::render_data`] as a float32
+//! array of shape (height, width). x and y may be strings to keep more
+//! digits than a float holds.
+//!
+//! Built with pyo3 against the stable ABI, so one build imports into any
+//! python 3 from 3.8 on. The interpreter lock is released while rendering.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyByteArray;
+
+use mandelbrot_core::{check_size, raw, Coord, MandleParams, MIN_ZOOM};
+
+//What render was asked for, checked
+#[derive(Debug)]
+struct Request {
+    x : Coord,
+    y : Coord,
+    zoom : f64,
+    iterations : u32,
+    width : usize,
+    height : usize,
+    channel : usize,
+}
+
+impl Request {
+
+    //Python's arguments, out of range values as ValueError messages
+    fn new(x : &str, y : &str, zoom : f64, iterations : i64, width : i64, height : i64, channel : &str) -> Result<Request, String> {
+        let x = x.trim().parse::<Coord>().map_err(|err| format!("x {}", err))?;
+        let y = y.trim().parse::<Coord>().map_err(|err| format!("y {}", err))?;
+        if !(zoom.is_finite() && zoom >= MIN_ZOOM) {
+            return Err(format!("zoom {} isn't a number of at least {:e}", zoom, MIN_ZOOM));
+        }
+        let iterations = u32::try_from(iterations).ok().filter(|iterations| *iterations > 0)
+            .ok_or_else(|| format!("iterations {} isn't between 1 and {}", iterations, u32::MAX))?;
+        let (width, height) = match (usize::try_from(width), usize::try_from(height)) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err(format!("{}x{} isn't a size to render", width, height)),
+        };
+        check_size((width, height))?;
+        let Some(channel) = raw::CHANNELS.iter().position(|name| *name == channel) else {
+            return Err(format!("no channel named {}, there are {}", channel, raw::CHANNELS.join(", ")));
+        };
+        Ok(Request { x, y, zoom, iterations, width, height, channel })
+    }
+
+    fn render(&self) -> Vec<f32> {
+        let params = MandleParams {
+            x: self.x,
+            y: self.y,
+            zoom: self.zoom,
+            iterations: self.iterations,
+            ..MandleParams::new(self.width, self.height)
+        };
+        let mut data = raw::render_data(&params, self.width, self.height, |_| {});
+        std::mem::take(&mut data.channels[self.channel])
+    }
+}
+
+/// A float32 array of shape (height, width) holding a channel of the view
+/// centred on (x, y) with pixels zoom apart: iterations (-1 for points that
+/// never escape), smooth, modulus or distance. x and y may be strings.
+#[pyfunction]
+#[pyo3(signature = (x, y, zoom, iterations, width, height, *, channel = "iterations"))]
+//The arguments are python's, after the interpreter
+#[allow(clippy::too_many_arguments)]
+fn render<'py>(
+    py : Python<'py>,
+    x : &Bound<'py, PyAny>,
+    y : &Bound<'py, PyAny>,
+    zoom : f64,
+    iterations : i64,
+    width : i64,
+    height : i64,
+    channel : &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    //Anything whose str() parses as a coordinate, floats and strings of
+    //digits alike
+    let x = x.str()?.to_cow()?.into_owned();
+    let y = y.str()?.to_cow()?.into_owned();
+    let request = Request::new(&x, &y, zoom, iterations, width, height, channel).map_err(PyValueError::new_err)?;
+
+    let values = py.detach(|| request.render());
+
+    //numpy.frombuffer over a copy of the values, so the array owns its own
+    //writable memory, shaped into rows
+    let bytes : Vec<u8> = values.iter().flat_map(|value| value.to_ne_bytes()).collect();
+    let buffer = PyByteArray::new(py, &bytes);
+    py.import("numpy")?
+        .call_method1("frombuffer", (buffer, "float32"))?
+        .call_method1("reshape", (request.height, request.width))
+}
+
+/// The mandlebrot renderer, see render
+#[pymodule]
+fn mandelbrot(module : &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(render, module)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(iterations : i64, width : i64, height : i64) -> Result<Request, String> {
+        Request::new("-0.75", "0.1", 1e-2, iterations, width, height, "iterations")
+    }
+
+    #[test]
+    fn accepts_a_view() {
+        let request = request(1000, 64, 48).unwrap();
+        assert_eq!((request.iterations, request.width, request.height), (1000, 64, 48));
+        assert_eq!(raw::CHANNELS[request.channel], "iterations");
+        assert_eq!(request.x, "-0.75".parse::<Coord>().unwrap());
+    }
+
+    #[test]
+    fn rejects_iterations_outside_u32() {
+        assert!(request(0, 64, 64).is_err());
+        //Would have wrapped to 4294967295
+        assert!(request(-1, 64, 64).is_err());
+        assert!(request(i64::from(u32::MAX) + 1, 64, 64).is_err());
+        assert_eq!(request(i64::from(u32::MAX), 64, 64).unwrap().iterations, u32::MAX);
+    }
+
+    #[test]
+    fn rejects_sizes_too_small_or_large() {
+        assert!(request(100, 0, 64).is_err());
+        assert!(request(100, 64, -1).is_err());
+        assert!(request(100, 1 << 20, 1 << 20).is_err());
+        assert!(request(100, i64::MAX, i64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_views() {
+        assert!(Request::new("x", "0", 1e-2, 100, 8, 8, "iterations").is_err());
+        assert!(Request::new("0", "0", f64::NAN, 100, 8, 8, "iterations").is_err());
+        assert!(Request::new("0", "0", 0.0, 100, 8, 8, "iterations").is_err());
+        assert!(Request::new("0", "0", 1e-2, 100, 8, 8, "colour").is_err());
+        let request = Request::new("0", "0", 1e-2, 100, 8, 8, "distance").unwrap();
+        assert_eq!(raw::CHANNELS[request.channel], "distance");
+    }
+
+    #[test]
+    fn renders_a_channel_a_pixel_each() {
+        let values = request(50, 8, 6).unwrap().render();
+        assert_eq!(values.len(), 8 * 6);
+    }
+}
@@ -0,0 +1,63 @@
+//Renders a view a few tiles at a time, for a loop that can't wait on a
+//render thread. Tiles go from the centre outwards like the window's, each
+//step computes them until its time is up and colours them into the frame
+
+use instant::{Duration, Instant};
+
+use mandelbrot_core::colorizer::PaletteColorizer;
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::tiles::{self, Tile};
+use mandelbrot_core::{offline, CancelToken, Colorizer, Grid, MandleParams, Sample};
+
+pub struct ChunkedRender {
+    params : MandleParams,
+    tiles : Vec<Tile>,
+    //Tiles before this one are in the frame already
+    next : usize,
+}
+
+impl ChunkedRender {
+
+    pub fn new(params : &MandleParams) -> ChunkedRender {
+        ChunkedRender {
+            params: params.resolved(),
+            tiles: tiles::spiral(params.width, params.height),
+            next: 0,
+        }
+    }
+
+    //Starts over on a new view. Whatever is in the frame stays until the
+    //tiles over it are drawn
+    pub fn restart(&mut self, params : &MandleParams) {
+        *self = ChunkedRender::new(params);
+    }
+
+    pub fn done(&self) -> bool {
+        self.next == self.tiles.len()
+    }
+
+    //Draws tiles into frame, width * height rgba pixels, until budget has
+    //gone by. At least one tile is drawn so every step gets somewhere.
+    //Returns whether the view is finished
+    pub fn step(&mut self, frame : &mut [u8], palette : &Palette, budget : Duration) -> bool {
+        let start = Instant::now();
+        let coloring = PaletteColorizer::new(&self.params, palette);
+        let cancel = CancelToken::never();
+        while !self.done() {
+            let tile = self.tiles[self.next];
+            let mut grid = Grid::new(tile.width, tile.height, Sample::INTERIOR);
+            offline::compute(&mut grid, &tile.view(&self.params), &cancel);
+            for (ty, y) in tile.rows().enumerate() {
+                for (tx, x) in tile.columns().enumerate() {
+                    let idx = (y * self.params.width + x) * 4;
+                    frame[idx..idx + 4].copy_from_slice(&coloring.color(grid[(tx, ty)], (x, y)));
+                }
+            }
+            self.next += 1;
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        self.done()
+    }
+}
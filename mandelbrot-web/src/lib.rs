@@ -0,0 +1,9 @@
+//! The mandlebrot viewer in a browser. A build for wasm32-unknown-unknown
+//! opens a canvas through winit's web backend and draws into it with pixels.
+//! The browser gives it one thread, so rather than a background render
+//! thread the view is computed a few tiles at a time in each animation
+//! frame by [`chunked::ChunkedRender`], which builds on any target.
+
+pub mod chunked;
+#[cfg(target_arch = "wasm32")]
+mod web;
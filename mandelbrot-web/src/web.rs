@@ -0,0 +1,107 @@
+//The browser side, a canvas appended to the page's body with the basic
+//controls of the window: drag to pan, the wheel to zoom, P for the next
+//palette and +/- for iterations. The event loop asks for a redraw while
+//the view is unfinished and every redraw renders another slice of it
+
+use instant::Duration;
+use pixels::{PixelsBuilder, SurfaceTexture};
+use wasm_bindgen::prelude::*;
+use winit::dpi::LogicalSize;
+use winit::event::{Event, VirtualKeyCode};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::platform::web::WindowExtWebSys;
+use winit::window::WindowBuilder;
+use winit_input_helper::WinitInputHelper;
+
+use mandelbrot_core::palette;
+use mandelbrot_core::MandleParams;
+
+use crate::chunked::ChunkedRender;
+
+const TITLE: &str = "Mandlebrot set";
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 360;
+
+//Time each animation frame spends rendering, the rest of a 60Hz frame is
+//left to the browser
+const FRAME_BUDGET: Duration = Duration::from_millis(12);
+
+//Iterations added or removed by +/-
+const ITERATION_STEP: u32 = 50;
+
+#[wasm_bindgen(start)]
+pub fn start() {
+    std::panic::set_hook(Box::new(|info| web_sys::console::error_1(&info.to_string().into())));
+    wasm_bindgen_futures::spawn_local(run());
+}
+
+async fn run() {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(TITLE)
+        .with_inner_size(LogicalSize::new(WIDTH, HEIGHT))
+        .build(&event_loop)
+        .expect("creating the window");
+    web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.body())
+        .and_then(|body| body.append_child(&web_sys::Element::from(window.canvas())).ok())
+        .expect("adding the canvas to the page");
+
+    let size = window.inner_size();
+    let surface = SurfaceTexture::new(size.width, size.height, &window);
+    let mut pixels = PixelsBuilder::new(WIDTH, HEIGHT, surface)
+        .build_async()
+        .await
+        .expect("creating the pixel buffer");
+
+    let palettes = palette::builtin_palettes();
+    let mut params = MandleParams::new(WIDTH as usize, HEIGHT as usize);
+    let mut render = ChunkedRender::new(&params);
+    let mut input = WinitInputHelper::new();
+
+    event_loop.run(move |event, _, control_flow| {
+        if let Event::RedrawRequested(_) = event {
+            render.step(pixels.frame_mut(), &palettes[params.palette], FRAME_BUDGET);
+            if let Err(err) = pixels.render() {
+                web_sys::console::error_1(&err.to_string().into());
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+        }
+        if !input.update(&event) {
+            return;
+        }
+        let last = params;
+        if input.mouse_held(0) {
+            let (dx, dy) = input.mouse_diff();
+            let scale = window.scale_factor();
+            params.x = params.x.offset(-dx as f64 / scale * params.zoom);
+            params.y = params.y.offset(-dy as f64 / scale * params.zoom);
+        }
+        let scroll = input.scroll_diff();
+        if scroll != 0.0 {
+            if let Some((px, py)) = input.mouse().and_then(|mouse| pixels.window_pos_to_pixel(mouse).ok()) {
+                params.zoom_about(px as f64, py as f64, 0.9f64.powf(scroll as f64));
+            }
+        }
+        if input.key_pressed(VirtualKeyCode::P) {
+            params.palette = (params.palette + 1) % palettes.len();
+        }
+        if input.key_pressed(VirtualKeyCode::Equals) || input.key_pressed(VirtualKeyCode::NumpadAdd) {
+            params.iterations += ITERATION_STEP;
+            params.auto_iterations = false;
+        }
+        if input.key_pressed(VirtualKeyCode::Minus) || input.key_pressed(VirtualKeyCode::NumpadSubtract) {
+            params.iterations = params.iterations.saturating_sub(ITERATION_STEP).max(1);
+            params.auto_iterations = false;
+        }
+        if params != last {
+            render.restart(&params);
+        }
+        if !render.done() {
+            window.request_redraw();
+        }
+    });
+}
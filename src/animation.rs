@@ -0,0 +1,125 @@
+//Zoom videos rendered from keyframes. Every stretch between two keyframes
+//takes the same number of frames, with the zoom changing at a steady rate
+//through it and the palette offset sliding across
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use mandelbrot_core::expmap::ExpMap;
+use mandelbrot_core::offline::{self, OfflineError, TileSource};
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::MandleParams;
+
+use crate::transition;
+
+//Where the frames go
+pub enum Output {
+    //Numbered PNGs in a directory
+    Frames(PathBuf),
+    //Piped into ffmpeg to encode the named video file
+    Ffmpeg(PathBuf),
+}
+
+//Params of every frame, from the first keyframe to the last. The palette
+//switches at each keyframe, the one a stretch starts at is used throughout
+pub fn frames(keyframes : &[MandleParams], frames_per_keyframe : usize) -> Vec<MandleParams> {
+    let mut frames = Vec::new();
+    for pair in keyframes.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        for frame in 0..frames_per_keyframe {
+            let t = frame as f64 / frames_per_keyframe as f64;
+            let mut params = transition::between(from, to, t);
+            params.palette_offset = (from.palette_offset + (to.palette_offset - from.palette_offset) * t).rem_euclid(1.0);
+            frames.push(params);
+        }
+    }
+    frames.extend(keyframes.last().cloned());
+    frames
+}
+
+//Renders the frames one after another at their own size, printing progress
+//to stdout. With expmap they all zoom straight into the centre of the last
+//one through an exponential map, the others only give the zoom and palette.
+//Otherwise their tiles come from tiles
+pub fn render(
+    tiles : &dyn TileSource,
+    frames : &[MandleParams],
+    palettes : &[Palette],
+    output : &Output,
+    fps : u32,
+    expmap : bool
+) -> Result<(), OfflineError> {
+    let Some(first) = frames.first() else {
+        return Ok(());
+    };
+    let (width, height) = (first.width, first.height);
+    if let Some(name) = frames.iter().find_map(MandleParams::exhausted_precision) {
+        println!("Warning {} runs out of precision on the way", name);
+    }
+
+    let mut expmap = expmap.then(|| {
+        let deepest = frames.iter().map(|params| params.zoom).fold(f64::INFINITY, f64::min);
+        ExpMap::new(&frames[frames.len() - 1], deepest)
+    });
+
+    let mut sink = match output {
+        Output::Frames(dir) => {
+            std::fs::create_dir_all(dir)?;
+            println!("Rendering {} {}x{} frames to {}", frames.len(), width, height, dir.display());
+            Sink::Frames(dir)
+        }
+        Output::Ffmpeg(video) => {
+            println!("Rendering {} {}x{} frames to {} with ffmpeg", frames.len(), width, height, video.display());
+            let size = format!("{}x{}", width, height);
+            let fps = fps.to_string();
+            let mut ffmpeg = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pixel_format", "rgb24"])
+                .args(["-video_size", &size, "-framerate", &fps, "-i", "-", "-pix_fmt", "yuv420p"])
+                .arg(video)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|err| std::io::Error::new(err.kind(), format!("starting ffmpeg {}", err)))?;
+            let stdin = ffmpeg.stdin.take().ok_or_else(|| std::io::Error::other("no pipe to ffmpeg"))?;
+            Sink::Ffmpeg { video, ffmpeg, stdin }
+        }
+    };
+
+    for (index, params) in frames.iter().enumerate() {
+        print!("\rRendering frame {} of {}", index + 1, frames.len());
+        let _ = std::io::stdout().flush();
+        crate::recovery::record(params);
+        let palette = &palettes[params.palette];
+        let image = match &mut expmap {
+            Some(expmap) => expmap.render(params, palette),
+            None => offline::render_image_from(tiles, params, palette, width, height, |_| {}),
+        };
+        match &mut sink {
+            Sink::Frames(dir) => {
+                offline::write_png(&dir.join(format!("frame_{:05}.png", index)), &image, width, height)?;
+            }
+            Sink::Ffmpeg { stdin, .. } => stdin.write_all(&image)?,
+        }
+    }
+    println!();
+
+    match sink {
+        Sink::Frames(dir) => println!("Saved {} frames in {}", frames.len(), dir.display()),
+        Sink::Ffmpeg { video, mut ffmpeg, stdin } => {
+            //Closing its input tells ffmpeg the video is over
+            drop(stdin);
+            let status = ffmpeg.wait()?;
+            if !status.success() {
+                return Err(OfflineError::Io(std::io::Error::other(format!("ffmpeg exited with {}", status))));
+            }
+            println!("Saved {}", video.display());
+        }
+    }
+    Ok(())
+}
+
+//Where each rendered frame is written
+enum Sink<'a> {
+    Frames(&'a Path),
+    Ffmpeg { video : &'a Path, ffmpeg : Child, stdin : ChildStdin },
+}
@@ -0,0 +1,146 @@
+//Screensaver dives. Each leg looks over the finished view for where the
+//escape times vary the most, the boundary with the most going on, and flies
+//there zoomed in a step. The next leg waits for the view it arrived at to
+//finish rendering, and a dive that runs out of detail or precision starts
+//again from the whole set
+
+use std::ops::Range;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mandelbrot_core::buddhabrot::XorShift;
+use mandelbrot_core::{Coord, MandleParams, MIN_ZOOM};
+
+use crate::requests::Finished;
+use crate::transition::Transition;
+
+//Zoom factor of each leg
+const ZOOM_STEP: f64 = 4.0;
+
+//Blocks across the shorter side of the view that are scored against each other
+const BLOCKS: usize = 8;
+
+//Blocks scoring at least this much of the best are picked between at random,
+//so dives don't all end up in the same place
+const PICK_FRACTION: f64 = 0.5;
+
+pub struct Autopilot {
+    leg : Option<Transition>,
+    //Centre and zoom the autopilot left the view at, anything else means
+    //someone took over
+    at : (Coord, Coord, f64),
+    rng : XorShift,
+}
+
+impl Autopilot {
+
+    pub fn new(params : &MandleParams) -> Autopilot {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        Autopilot {
+            leg: None,
+            at: (params.x, params.y, params.zoom),
+            rng: XorShift::new(seed),
+        }
+    }
+
+    //Moves params on, a step of the current leg once the frame of the last
+    //step has arrived, or a new leg once the view is finished. False once the
+    //view was moved from elsewhere
+    pub fn step(
+        &mut self,
+        params : &mut MandleParams,
+        finished : Option<&Finished>,
+        frame_arrived : bool,
+        duration : Duration
+    ) -> bool {
+        if (params.x, params.y, params.zoom) != self.at {
+            return false;
+        }
+        match &mut self.leg {
+            Some(leg) => {
+                if frame_arrived && !leg.step(params) {
+                    self.leg = None;
+                }
+            }
+            None => {
+                if let Some(finished) = finished.filter(|finished| params.resolved().recolors(&finished.params)) {
+                    let (x, y, zoom) = self.next_view(finished);
+                    let target = MandleParams { x, y, zoom, ..params.clone() };
+                    //Nothing changed since the last frame, so the first step
+                    //is taken straight away rather than waiting for another
+                    self.leg = Transition::new(params, &target, duration);
+                    match &mut self.leg {
+                        Some(leg) => {
+                            if !leg.step(params) {
+                                self.leg = None;
+                            }
+                        }
+                        None => *params = target,
+                    }
+                }
+            }
+        }
+        self.at = (params.x, params.y, params.zoom);
+        true
+    }
+
+    //Centre and zoom of a busy block of the finished view zoomed in, or of
+    //the whole set again when every block is flat or the zoom can't go deeper
+    fn next_view(&mut self, finished : &Finished) -> (Coord, Coord, f64) {
+        let params = &finished.params;
+        let whole = MandleParams::new(params.width, params.height);
+        let home = (whole.x, whole.y, whole.zoom);
+        let zoom = params.zoom / ZOOM_STEP;
+        if zoom < MIN_ZOOM {
+            return home;
+        }
+        let size = (params.width.min(params.height) / BLOCKS).max(1);
+        let mut blocks = Vec::new();
+        for top in (0..params.height).step_by(size) {
+            for left in (0..params.width).step_by(size) {
+                let right = (left + size).min(params.width);
+                let bottom = (top + size).min(params.height);
+                let centre = ((left + right) as f64 / 2.0, (top + bottom) as f64 / 2.0);
+                blocks.push((centre, score(finished, left..right, top..bottom)));
+            }
+        }
+        let best = blocks.iter().map(|&(_, score)| score).fold(0.0, f64::max);
+        if best <= 0.0 {
+            return home;
+        }
+        let picks : Vec<(f64, f64)> = blocks
+            .iter()
+            .filter(|&&(_, score)| score >= best * PICK_FRACTION)
+            .map(|&(centre, _)| centre)
+            .collect();
+        let (px, py) = picks[((self.rng.next_f64() * picks.len() as f64) as usize).min(picks.len() - 1)];
+        let (x, y) = params.pixel_to_complex(px, py);
+        (x, y, zoom)
+    }
+}
+
+//Variance of the log escape times over the block's escaped pixels, scaled
+//by how many escaped so blocks mostly inside the set count for less.
+//Weighted towards the centre, so a dive doesn't lurch from side to side
+fn score(finished : &Finished, columns : Range<usize>, rows : Range<usize>) -> f64 {
+    let params = &finished.params;
+    let (mut count, mut sum, mut squares) = (0.0, 0.0, 0.0);
+    let total = (columns.len() * rows.len()) as f64;
+    for y in rows.clone() {
+        for sample in &finished.grid.row(y)[columns.clone()] {
+            if let Some(stopped) = sample.stopped {
+                let value = (stopped as f64 + 1.0).ln();
+                count += 1.0;
+                sum += value;
+                squares += value * value;
+            }
+        }
+    }
+    if count < 2.0 {
+        return 0.0;
+    }
+    let mean = sum / count;
+    let variance = (squares / count - mean * mean).max(0.0);
+    let dx = (columns.start + columns.end) as f64 / 2.0 / params.width as f64 - 0.5;
+    let dy = (rows.start + rows.end) as f64 / 2.0 / params.height as f64 - 0.5;
+    variance * count / total / (1.0 + 4.0 * (dx * dx + dy * dy))
+}
@@ -0,0 +1,288 @@
+//Renders a list of locations to images without opening a window, for
+//galleries or calendars. The list is TOML:
+//
+//  [[location]]
+//  name = "seahorse"
+//  x = "-0.743643887"
+//  y = "0.131825904"
+//  zoom = "1e-7"
+//  iterations = 1000
+//  palette = "Grayscale"
+//
+//or CSV with the field names on the first line, fields quoted as in any
+//CSV when they hold commas:
+//
+//  name,x,y,zoom,iterations
+//  "seahorse, east",-0.743643887,0.131825904,1e-7,1000
+//
+//Only x and y are needed, everything else starts from the command line.
+//span, the height of the view like keyframes have, can be given instead of
+//zoom so the location frames the same area at any resolution
+
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::offline::{self, ImageDepth};
+use mandelbrot_core::{MandleParams, MIN_ZOOM};
+
+use crate::view::{ViewError, parse_real, parse_zoom};
+
+#[derive(Deserialize)]
+struct BatchFile {
+    location : Vec<Location>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    pub name : Option<String>,
+    pub x : String,
+    pub y : String,
+    pub zoom : Option<String>,
+    pub span : Option<String>,
+    pub iterations : Option<u32>,
+    pub palette : Option<String>,
+}
+
+impl Location {
+
+    //Moves params to the location. Nothing is changed if any field is invalid
+    fn apply(&self, params : &mut MandleParams, palettes : &[Palette]) -> Result<(), ViewError> {
+        let x = parse_real("x", &self.x)?;
+        let y = parse_real("y", &self.y)?;
+        let zoom = match (&self.zoom, &self.span) {
+            (Some(_), Some(_)) => return Err(ViewError::Invalid("zoom and span can't both be given".to_string())),
+            (Some(zoom), None) => parse_zoom(zoom)?,
+            (None, Some(span)) => {
+                let span : f64 = parse_real("span", span)?;
+                if !(span.is_finite() && span > 0.0) {
+                    return Err(ViewError::Invalid(format!("span {} is not positive", span)));
+                }
+                (span / params.height as f64).max(MIN_ZOOM)
+            }
+            (None, None) => params.zoom,
+        };
+        if self.iterations == Some(0) {
+            return Err(ViewError::Invalid("iterations must be positive".to_string()));
+        }
+        let palette = match &self.palette {
+            Some(name) => crate::find_palette(palettes, name)
+                .ok_or_else(|| ViewError::Invalid(format!("no palette named {}", name)))?,
+            None => params.palette,
+        };
+        params.x = x;
+        params.y = y;
+        params.zoom = zoom;
+        if let Some(iterations) = self.iterations {
+            params.iterations = iterations;
+            params.auto_iterations = false;
+        }
+        params.palette = palette;
+        Ok(())
+    }
+}
+
+//The locations in a .csv file, or a TOML one otherwise
+pub fn load(path : &Path) -> Result<Vec<Location>, ViewError> {
+    let text = std::fs::read_to_string(path)?;
+    parse(&text, path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")))
+}
+
+fn parse(text : &str, csv : bool) -> Result<Vec<Location>, ViewError> {
+    if csv {
+        parse_csv(text)
+    } else {
+        Ok(toml::from_str::<BatchFile>(text)?.location)
+    }
+}
+
+//Column names are matched in any case. Empty fields are left out like a
+//missing column, and so are blank lines
+fn parse_csv(text : &str) -> Result<Vec<Location>, ViewError> {
+    let invalid = |err : csv::Error| ViewError::Invalid(err.to_string());
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns : Vec<String> = reader.headers().map_err(invalid)?.iter().map(str::to_ascii_lowercase).collect();
+    const COLUMNS: [&str; 7] = ["name", "x", "y", "zoom", "span", "iterations", "palette"];
+    if let Some(column) = columns.iter().find(|column| !COLUMNS.contains(&column.as_str())) {
+        return Err(ViewError::Invalid(format!("line 1 has an unknown column {}", column)));
+    }
+    let mut locations = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        let line_no = record.position().map_or(0, |position| position.line());
+        let invalid = |what : String| ViewError::Invalid(format!("line {} {}", line_no, what));
+        if record.len() > columns.len() {
+            return Err(invalid(format!("has {} fields for {} columns", record.len(), columns.len())));
+        }
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let mut location = Location::default();
+        let (mut x, mut y) = (None, None);
+        for (column, field) in columns.iter().zip(&record).filter(|(_, field)| !field.is_empty()) {
+            let field = field.to_string();
+            match column.as_str() {
+                "name" => location.name = Some(field),
+                "x" => x = Some(field),
+                "y" => y = Some(field),
+                "zoom" => location.zoom = Some(field),
+                "span" => location.span = Some(field),
+                "iterations" => location.iterations = Some(field.parse()
+                    .map_err(|_| invalid(format!("iterations {} is not a number", field)))?),
+                //The last of COLUMNS, the header was checked for others
+                _ => location.palette = Some(field),
+            }
+        }
+        location.x = x.ok_or_else(|| invalid("has no x".to_string()))?;
+        location.y = y.ok_or_else(|| invalid("has no y".to_string()))?;
+        locations.push(location);
+    }
+    Ok(locations)
+}
+
+//Renders every location at the size of params into output as name.png,
+//or its number in the list without a name, jobs at a time (0 is one per
+//core). Returns how many failed, those are reported as they go
+pub fn render(
+    locations : &[Location],
+    params : &MandleParams,
+    palettes : &[Palette],
+    output : &Path,
+    jobs : usize,
+    depth : ImageDepth
+) -> usize {
+    if let Err(err) = std::fs::create_dir_all(output) {
+        println!("Error creating {} {}", output.display(), err);
+        return locations.len();
+    }
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(jobs).build() {
+        Ok(pool) => pool,
+        Err(err) => {
+            println!("Error starting batch threads {}", err);
+            return locations.len();
+        }
+    };
+    let digits = locations.len().to_string().len();
+    println!("Rendering {} images at {}x{} to {}", locations.len(), params.width, params.height, output.display());
+    pool.install(|| {
+        locations.par_iter().enumerate().filter(|(index, location)| {
+            let name = match &location.name {
+                //No slashes, every image stays in output
+                Some(name) => name.replace(['/', '\\'], "_"),
+                None => format!("{:0digits$}", index + 1, digits = digits),
+            };
+            let path = output.join(format!("{}.png", name));
+            let mut view = params.clone();
+            if let Err(err) = location.apply(&mut view, palettes) {
+                println!("Error in location {} {}", name, err);
+                return true;
+            }
+            crate::recovery::record(&view);
+            match offline::save_image(&view, &palettes[view.palette], (view.width, view.height), &path, depth, |_| {}) {
+                Ok(()) => {
+                    println!("Saved {}", path.display());
+                    false
+                }
+                Err(err) => {
+                    println!("Error saving {} {}", path.display(), err);
+                    true
+                }
+            }
+        }).count()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text : &str, csv : bool) -> String {
+        match parse(text, csv) {
+            Ok(_) => panic!("{:?} parsed", text),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn csv_columns_match_in_any_case() {
+        let text = "Name, X ,y,ZOOM,Iterations,palette,span\n\
+            \"seahorse, east\",-0.743643887,0.131825904,1e-7,1000,Fire,\n\
+            \n\
+            \x20 , \n\
+            ,0.25,\"-1\",,,,2\n";
+        let locations = parse(text, true).unwrap();
+        assert_eq!(locations.len(), 2);
+        let first = &locations[0];
+        assert_eq!(first.name.as_deref(), Some("seahorse, east"));
+        assert_eq!((first.x.as_str(), first.y.as_str()), ("-0.743643887", "0.131825904"));
+        assert_eq!((first.zoom.as_deref(), first.span.as_deref()), (Some("1e-7"), None));
+        assert_eq!((first.iterations, first.palette.as_deref()), (Some(1000), Some("Fire")));
+        let second = &locations[1];
+        assert_eq!((second.name.as_deref(), second.x.as_str(), second.y.as_str()), (None, "0.25", "-1"));
+        assert_eq!((second.zoom.as_deref(), second.span.as_deref(), second.iterations), (None, Some("2"), None));
+    }
+
+    #[test]
+    fn short_csv_rows_leave_out_columns() {
+        let locations = parse("x,y,zoom,name\n1,2\n3,4,1e-3\n", true).unwrap();
+        assert_eq!((locations[0].zoom.as_deref(), locations[0].name.as_deref()), (None, None));
+        assert_eq!(locations[1].zoom.as_deref(), Some("1e-3"));
+        assert!(parse("", true).unwrap().is_empty());
+        assert!(parse("x,y\n", true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_csv_rows_are_errors() {
+        assert_eq!(error("x,y,colour\n1,2,red\n", true), "line 1 has an unknown column colour");
+        assert_eq!(error("x,y\n1,2\n1,2,3\n", true), "line 3 has 3 fields for 2 columns");
+        assert_eq!(error("x,y,name\n,2,a\n", true), "line 2 has no x");
+        assert_eq!(error("x,name\n1,a\n", true), "line 2 has no y");
+        assert_eq!(error("x,y,iterations\n1,2,many\n", true), "line 2 iterations many is not a number");
+        //A quote left open takes the rest of the file
+        assert_eq!(error("x,y\n\"1,2\n3,4\n", true), "line 2 has no y");
+    }
+
+    #[test]
+    fn toml_lists_locations() {
+        let text = r#"
+[[location]]
+name = "seahorse"
+x = "-0.743643887"
+y = "0.131825904"
+zoom = "1e-7"
+iterations = 1000
+palette = "Grayscale"
+
+[[location]]
+x = "0"
+y = "1"
+span = "0.5"
+"#;
+        let locations = parse(text, false).unwrap();
+        assert_eq!(locations.len(), 2);
+        assert_eq!((locations[0].name.as_deref(), locations[0].iterations), (Some("seahorse"), Some(1000)));
+        assert_eq!((locations[1].x.as_str(), locations[1].span.as_deref()), ("0", Some("0.5")));
+        //The CSV columns are all TOML allows
+        assert!(matches!(parse("[[location]]\nx = \"0\"\ny = \"0\"\ncolour = \"red\"\n", false), Err(ViewError::Parse(_))));
+        assert!(matches!(parse("[[location]]\nx = \"0\"\n", false), Err(ViewError::Parse(_))));
+    }
+
+    #[test]
+    fn locations_move_the_view() {
+        let palettes = mandelbrot_core::palette::builtin_palettes();
+        let mut params = MandleParams::new(200, 100);
+        let location = &parse("x,y,span,iterations,palette\n0.25,-0.5,0.01,500,grayscale\n", true).unwrap()[0];
+        location.apply(&mut params, &palettes).unwrap();
+        assert_eq!((params.x.to_string(), params.y.to_string()), ("0.25".to_string(), "-0.5".to_string()));
+        assert_eq!(params.zoom, 0.01 / 100.0);
+        assert_eq!(params.iterations, 500);
+        assert!(!params.auto_iterations);
+        assert_eq!(palettes[params.palette].name, "Grayscale");
+    }
+}
@@ -0,0 +1,204 @@
+//Bookmark slots bound to the number keys, persisted between sessions
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use mandelbrot_core::MandleParams;
+
+use crate::view::{ViewError, parse_real, parse_zoom};
+
+pub const SLOTS: usize = 9;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bookmark {
+    pub x : String,
+    pub y : String,
+    pub zoom : String,
+    pub iterations : u32,
+}
+
+impl Bookmark {
+
+    pub fn from_params(params : &MandleParams) -> Bookmark {
+        Bookmark {
+            x: params.x.to_string(),
+            y: params.y.to_string(),
+            zoom: format!("{:e}", params.zoom),
+            //What the view renders with, auto or not
+            iterations: params.max_iterations(),
+        }
+    }
+
+    //Nothing is changed if any field is invalid. The bookmark's iterations
+    //are kept rather than worked out from its zoom
+    pub fn apply(&self, params : &mut MandleParams) -> Result<(), ViewError> {
+        let x = parse_real("x", &self.x)?;
+        let y = parse_real("y", &self.y)?;
+        let zoom = parse_zoom(&self.zoom)?;
+        if self.iterations == 0 {
+            return Err(ViewError::Invalid("iterations must be positive".to_string()));
+        }
+        params.x = x;
+        params.y = y;
+        params.zoom = zoom;
+        params.iterations = self.iterations;
+        params.auto_iterations = false;
+        Ok(())
+    }
+}
+
+//The text a view is copied to the clipboard as, one line to paste in a chat:
+//
+//  x=-0.743643887 y=0.131825904 zoom=1e-7 iterations=1000
+impl fmt::Display for Bookmark {
+    fn fmt(&self, fmt : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "x={} y={} zoom={} iterations={}", self.x, self.y, self.zoom, self.iterations)
+    }
+}
+
+//The fields in any order, split by spaces, commas or new lines, so a copy
+//that got wrapped or picked up a trailing full stop still pastes
+impl FromStr for Bookmark {
+    type Err = ViewError;
+
+    fn from_str(text : &str) -> Result<Bookmark, ViewError> {
+        let fields : Vec<(&str, &str)> = text
+            .split(|c : char| c.is_whitespace() || c == ',')
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let field = |key : &str| {
+            fields.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.trim_end_matches('.').to_string())
+                .ok_or_else(|| ViewError::Invalid(format!("no {}= in {:?}", key, text.trim())))
+        };
+        let iterations = field("iterations")?;
+        Ok(Bookmark {
+            x: field("x")?,
+            y: field("y")?,
+            zoom: field("zoom")?,
+            iterations: iterations.parse()
+                .map_err(|_| ViewError::Invalid(format!("iterations {} is not a number", iterations)))?,
+        })
+    }
+}
+
+//Slots are numbered 1..=9 like the keys. Stored as a table keyed by the
+//slot number since TOML keys have to be strings
+pub struct Bookmarks {
+    path : PathBuf,
+    slots : BTreeMap<String, Bookmark>,
+}
+
+impl Bookmarks {
+
+    //Starts empty when the file doesn't exist yet
+    pub fn load(path : &Path) -> Result<Bookmarks, ViewError> {
+        let slots = if path.exists() {
+            toml::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Bookmarks {
+            path: path.to_path_buf(),
+            slots,
+        })
+    }
+
+    pub fn empty(path : &Path) -> Bookmarks {
+        Bookmarks {
+            path: path.to_path_buf(),
+            slots: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, slot : usize) -> Option<&Bookmark> {
+        self.slots.get(&slot.to_string())
+    }
+
+    //Stores the bookmark and writes every slot back to the file
+    pub fn set(&mut self, slot : usize, bookmark : Bookmark) -> Result<(), ViewError> {
+        self.slots.insert(slot.to_string(), bookmark);
+        std::fs::write(&self.path, toml::to_string(&self.slots)?)?;
+        Ok(())
+    }
+}
+
+pub fn slot_key(slot : usize) -> winit::event::VirtualKeyCode {
+    use winit::event::VirtualKeyCode::*;
+    [Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9][slot - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark() -> Bookmark {
+        Bookmark {
+            x: "-0.743643887".to_string(),
+            y: "0.131825904".to_string(),
+            zoom: "1e-7".to_string(),
+            iterations: 1000,
+        }
+    }
+
+    fn fields(bookmark : &Bookmark) -> (&str, &str, &str, u32) {
+        (&bookmark.x, &bookmark.y, &bookmark.zoom, bookmark.iterations)
+    }
+
+    #[test]
+    fn clipboard_text_reads_back() {
+        let text = bookmark().to_string();
+        assert_eq!(text, "x=-0.743643887 y=0.131825904 zoom=1e-7 iterations=1000");
+        assert_eq!(fields(&text.parse().unwrap()), fields(&bookmark()));
+    }
+
+    #[test]
+    fn pasted_text_may_be_wrapped_or_punctuated() {
+        for text in [
+            "x=-0.743643887, y=0.131825904, zoom=1e-7, iterations=1000",
+            "x=-0.743643887\ny=0.131825904\r\nzoom=1e-7\niterations=1000\n",
+            "Look at x=-0.743643887 y=0.131825904 zoom=1e-7 iterations=1000.",
+            "ITERATIONS=1000 zoom=1e-7,,Y=0.131825904   x=-0.743643887",
+        ] {
+            let pasted : Bookmark = text.parse().unwrap();
+            assert_eq!(fields(&pasted), fields(&bookmark()), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn pasted_text_needs_every_field() {
+        for key in ["x", "y", "zoom", "iterations"] {
+            let text : Vec<String> = bookmark().to_string()
+                .split(' ')
+                .filter(|field| !field.starts_with(&format!("{}=", key)))
+                .map(str::to_string)
+                .collect();
+            let err = text.join(" ").parse::<Bookmark>().err().map(|err| err.to_string()).unwrap_or_default();
+            assert!(err.starts_with(&format!("no {}= in", key)), "{}", err);
+        }
+        assert!("x=0 y=0 zoom=1 iterations=lots".parse::<Bookmark>().is_err());
+        assert!("".parse::<Bookmark>().is_err());
+    }
+
+    #[test]
+    fn bookmarks_keep_the_iterations_rendered() {
+        let mut params = MandleParams::new(100, 100);
+        params.zoom = 1e-12;
+        params.auto_iterations = true;
+        let saved = Bookmark::from_params(&params);
+        assert_eq!(saved.iterations, params.max_iterations());
+        assert_ne!(saved.iterations, params.iterations);
+
+        let mut shown = MandleParams::new(100, 100);
+        shown.auto_iterations = true;
+        saved.apply(&mut shown).unwrap();
+        assert!(!shown.auto_iterations);
+        assert_eq!(shown.max_iterations(), saved.iterations);
+        assert_eq!(shown.zoom, 1e-12);
+    }
+}
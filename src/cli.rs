@@ -0,0 +1,487 @@
+//Command line arguments, the view and render settings to start with
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use mandelbrot_core::gif::GifDither;
+use mandelbrot_core::offline::ImageDepth;
+use mandelbrot_core::trap::Trap;
+use mandelbrot_core::{check_size, Backend, Coord, MIN_ZOOM};
+
+use crate::link::Link;
+
+//Without a subcommand the interactive viewer is opened
+#[derive(Parser, Debug)]
+#[command(version, about = "A mandlebrot set generator/renderer")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command : Option<Command>,
+
+    /// Real part of the view center
+    #[arg(long, global = true, default_value = "-0.20710786709396773", value_parser = parse_real, allow_hyphen_values = true)]
+    pub x : Coord,
+
+    /// Imaginary part of the view center
+    #[arg(long, global = true, default_value = "1.1227570636325975", value_parser = parse_real, allow_hyphen_values = true)]
+    pub y : Coord,
+
+    /// Distance between neighbouring pixels in the complex plane
+    #[arg(long, global = true, default_value = "0.01", value_parser = parse_zoom)]
+    pub zoom : f64,
+
+    /// Maximum iterations per pixel
+    #[arg(long, global = true, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations : u32,
+
+    /// Scale the iteration limit with the zoom instead of using --iterations
+    #[arg(long, global = true)]
+    pub auto_iterations : bool,
+
+    /// Auto iterations per unit of ln(1 / zoom)
+    #[arg(long, global = true, default_value_t = 100.0, value_parser = parse_iteration_scale)]
+    pub iteration_scale : f64,
+
+    /// Grid and window (or rendered image) width in pixels, the window starts maximized if not given
+    #[arg(long, global = true, requires = "height", value_parser = clap::value_parser!(u32).range(1..))]
+    pub width : Option<u32>,
+
+    /// Grid and window (or rendered image) height in pixels
+    #[arg(long, global = true, requires = "width", value_parser = clap::value_parser!(u32).range(1..))]
+    pub height : Option<u32>,
+
+    /// Fractal formula by name, eg. "burning ship"
+    #[arg(long, global = true, default_value = "Mandlebrot", value_parser = parse_formula)]
+    pub formula : usize,
+
+    /// Script file defining the step and bailout of the Script formula, which it selects. Reloaded when saved
+    #[arg(long, global = true)]
+    pub formula_script : Option<PathBuf>,
+
+    /// Power of z for the multibrot formula, fractional powers are allowed
+    #[arg(long, global = true, default_value_t = 3.0, value_parser = parse_exponent)]
+    pub exponent : f64,
+
+    /// Red, green and blue iteration limits of the nebulabrot, R,G,B
+    #[arg(long, global = true, default_value = "5000,500,50", value_parser = parse_nebula_iterations)]
+    pub nebula_iterations : [u32; 3],
+
+    /// Palette name or index
+    #[arg(long, global = true, default_value = "Ultra Fractal")]
+    pub palette : String,
+
+    /// Config file with defaults for the other arguments, config.toml in the platform's config directory if not given
+    #[arg(long, global = true, env = "MANDLE_CONFIG")]
+    pub config : Option<PathBuf>,
+
+    /// File with extra palettes
+    #[arg(long, global = true, env = "MANDLE_PALETTES", default_value = mandelbrot_core::palette::PALETTE_FILE)]
+    pub palettes : PathBuf,
+
+    /// Directory of Fractint .map and Ultra Fractal .ugr palettes
+    #[arg(long, global = true, env = "MANDLE_PALETTE_DIR", default_value = mandelbrot_core::palette::PALETTE_DIR)]
+    pub palette_dir : PathBuf,
+
+    /// Kalles Fraktaler .kfr location to start at, in place of --x, --y, --zoom and --iterations
+    #[arg(long, global = true)]
+    pub kfr : Option<PathBuf>,
+
+    /// mandel:// link to start at, as copied with Ctrl+Shift+C
+    #[arg(long, global = true, value_parser = parse_link)]
+    pub link : Option<Link>,
+
+    /// File the view is saved to with F5 and loaded from with F9
+    #[arg(long, global = true, default_value = "view.toml")]
+    pub view_file : PathBuf,
+
+    /// File the session is saved to while the viewer runs and on quitting, and restored from on the next launch
+    #[arg(long, global = true, default_value = "session.toml")]
+    pub session_file : PathBuf,
+
+    /// Start from the arguments instead of restoring the last session
+    #[arg(long, global = true)]
+    pub fresh : bool,
+
+    /// File the view is written to if the program panics
+    #[arg(long, global = true, default_value = "recovery.toml")]
+    pub recovery_file : PathBuf,
+
+    /// File the Ctrl+1..9 bookmarks are kept in
+    #[arg(long, global = true, default_value = "bookmarks.toml")]
+    pub bookmarks_file : PathBuf,
+
+    /// File keyframes captured with Insert are kept in, and animate renders
+    #[arg(long, global = true, default_value = "keyframes.toml")]
+    pub keyframes_file : PathBuf,
+
+    /// Where divergence values are computed
+    #[arg(long, global = true, env = "MANDLE_BACKEND", value_enum, ignore_case = true, default_value_t = Backend::Cpu)]
+    pub backend : Backend,
+
+    /// Worker threads for the cpu backends, 0 for one per core
+    #[arg(long, global = true, env = "MANDLE_THREADS", default_value_t = 0)]
+    pub threads : usize,
+
+    /// Skip computing rectangles whose border is all one value (Mariani-Silver)
+    #[arg(long, global = true)]
+    pub subdivide : bool,
+
+    /// Iterate in double-double rather than fixed point down to a zoom of 1e-29
+    #[arg(long, global = true)]
+    pub double_double : bool,
+
+    /// Antialiasing samples per axis (1, 2 or 4), taken only on edges unless --supersample-all
+    #[arg(long, global = true, default_value_t = 1, value_parser = parse_supersample)]
+    pub supersample : u32,
+
+    /// Supersample every pixel instead of only where neighbours differ
+    #[arg(long, global = true)]
+    pub supersample_all : bool,
+
+    /// Keep adding jittered samples while the view is still, up to 64 per pixel
+    #[arg(long, global = true)]
+    pub temporal : bool,
+
+    /// Draw a quick f32 gpu sketch of the view while it moves, replaced by the cpu render as that finishes
+    #[arg(long, global = true)]
+    pub sketch : bool,
+
+    /// Light the view as a relief, best with distance colouring
+    #[arg(long, global = true)]
+    pub shading : bool,
+
+    /// Direction the shading light comes from in degrees, 0 is the right and 90 the top
+    #[arg(long, global = true, default_value_t = 135.0)]
+    pub light_angle : f64,
+
+    /// Height of the shading light above the view in degrees
+    #[arg(long, global = true, default_value_t = 45.0, value_parser = parse_elevation)]
+    pub light_elevation : f64,
+
+    /// Start with the palette cycling
+    #[arg(long, global = true)]
+    pub cycle : bool,
+
+    /// Palette repeats per second to cycle by, negative runs backwards
+    #[arg(long, global = true, default_value_t = 0.1, allow_hyphen_values = true, value_parser = parse_cycle_speed)]
+    pub cycle_speed : f64,
+
+    /// Stops to brighten the colours by, negative darkens them
+    #[arg(long, global = true, default_value_t = 0.0, allow_hyphen_values = true, value_parser = parse_exposure)]
+    pub exposure : f64,
+
+    /// Gamma of the colours on the way out, above 1 lifts the dark parts of the palette
+    #[arg(long, global = true, default_value_t = 1.0, value_parser = parse_gamma)]
+    pub gamma : f64,
+
+    /// Round the colours to 8 bits instead of dithering them
+    #[arg(long, global = true)]
+    pub no_dither : bool,
+
+    /// Spread the palette evenly over the escaped pixels of the view
+    #[arg(long, global = true)]
+    pub histogram : bool,
+
+    /// Orbit trap shape for trap colouring
+    #[arg(long, global = true, value_enum, ignore_case = true, default_value_t = Trap::Point)]
+    pub trap : Trap,
+
+    /// Real part of the orbit trap centre
+    #[arg(long, global = true, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub trap_x : f64,
+
+    /// Imaginary part of the orbit trap centre
+    #[arg(long, global = true, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub trap_y : f64,
+
+    /// Radius of the ring trap
+    #[arg(long, global = true, default_value_t = 1.0, value_parser = parse_trap_radius)]
+    pub trap_radius : f64,
+
+    /// Seconds jumps to bookmarks and loaded views take, 0 jumps straight there
+    #[arg(long, global = true, default_value_t = 1.0, value_parser = parse_transition)]
+    pub transition : f64,
+
+    /// Keep refining the view while the window is in the background, it only pauses when minimized
+    #[arg(long, global = true)]
+    pub render_unfocused : bool,
+
+    /// Accept WebSocket remote control clients on this address, eg. 127.0.0.1:9001
+    #[arg(long, global = true)]
+    pub remote : Option<String>,
+
+    /// Web pages allowed to connect to --remote, eg. http://localhost:8080, separated by commas.
+    /// Browsers from any other page are refused, programs that send no origin are always let in
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub remote_origin : Vec<String>,
+
+    /// Directory --remote render commands write into, paths leading out of it are refused
+    #[arg(long, global = true, default_value = ".")]
+    pub remote_dir : PathBuf,
+
+    /// Finished tiles of recent views kept in memory to redraw them when revisited, about 80KB each, 0 keeps none
+    #[arg(long, global = true, default_value_t = 1024)]
+    pub tile_cache : usize,
+
+    /// Directory finished tiles are also kept in between sessions, never cleared out
+    #[arg(long, global = true)]
+    pub tile_cache_dir : Option<PathBuf>,
+
+    /// Resolution of F12 poster renders, WIDTHxHEIGHT
+    #[arg(long, global = true, env = "MANDLE_POSTER_SIZE", default_value = "8000x4500", value_parser = parse_size)]
+    pub poster_size : (usize, usize),
+
+    /// Bits a channel of rendered PNGs, posters and batch images included
+    #[arg(long, global = true, value_enum, default_value_t = ImageDepth::Eight)]
+    pub bit_depth : ImageDepth,
+
+    /// Worker processes to compute render and animate tiles on, HOST:PORT separated by commas, see the worker subcommand
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub workers : Vec<String>,
+
+    /// Height of the highest point of .obj and .stl meshes, on a mesh 100 across
+    #[arg(long, global = true, default_value_t = 10.0, value_parser = parse_mesh_height)]
+    pub mesh_height : f64,
+
+    /// Raise mesh vertices by the log of their value, flattening the steep slopes near the set
+    #[arg(long, global = true)]
+    pub mesh_log : bool,
+
+    /// Resolution of F11 GIF loops, WIDTHxHEIGHT
+    #[arg(long, global = true, default_value = "480x270", value_parser = parse_size)]
+    pub gif_size : (usize, usize),
+
+    /// Frames in an F11 GIF loop
+    #[arg(long, global = true, default_value_t = 30, value_parser = parse_gif_frames)]
+    pub gif_frames : usize,
+
+    /// Milliseconds each GIF frame is shown, GIFs keep it in hundredths of a second
+    #[arg(long, global = true, default_value_t = 50)]
+    pub gif_delay : u32,
+
+    /// How GIF frames are dithered down to 256 colours
+    #[arg(long, global = true, value_enum, ignore_case = true, default_value_t = GifDither::Ordered)]
+    pub gif_dither : GifDither,
+
+    /// How far the Shift+F11 zoom GIF zooms in from its first frame to its last
+    #[arg(long, global = true, default_value_t = 2.0, value_parser = parse_gif_zoom)]
+    pub gif_zoom : f64,
+}
+
+pub fn parse_real(val : &str) -> Result<Coord, String> {
+    val.parse::<Coord>()
+}
+
+fn parse_link(val : &str) -> Result<Link, String> {
+    val.parse::<Link>().map_err(|err| err.to_string())
+}
+
+pub fn parse_zoom(val : &str) -> Result<f64, String> {
+    let zoom = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(zoom.is_finite() && zoom > 0.0) {
+        return Err(format!("{} is not a positive zoom", val));
+    }
+    if zoom < MIN_ZOOM {
+        return Err(format!("{} is deeper than the {:e} this build zooms to", val, MIN_ZOOM));
+    }
+    Ok(zoom)
+}
+
+//Index into formula::FORMULAS
+fn parse_formula(val : &str) -> Result<usize, String> {
+    mandelbrot_core::formula::find(val).ok_or_else(|| {
+        let names : Vec<&str> = mandelbrot_core::formula::FORMULAS.iter().map(|formula| formula.name()).collect();
+        format!("{} is not one of {}", val, names.join(", "))
+    })
+}
+
+fn parse_iteration_scale(val : &str) -> Result<f64, String> {
+    let scale = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(format!("{} is not a positive scale", val));
+    }
+    Ok(scale)
+}
+
+fn parse_supersample(val : &str) -> Result<u32, String> {
+    use mandelbrot_core::supersample::FACTORS;
+    let factor = val.parse::<u32>().map_err(|err| err.to_string())?;
+    if !FACTORS.contains(&factor) {
+        return Err(format!("{} is not one of {:?}", val, FACTORS));
+    }
+    Ok(factor)
+}
+
+fn parse_elevation(val : &str) -> Result<f64, String> {
+    let elevation = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(0.0..=90.0).contains(&elevation) {
+        return Err(format!("{} is not between 0 and 90", val));
+    }
+    Ok(elevation)
+}
+
+fn parse_transition(val : &str) -> Result<f64, String> {
+    let seconds = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(seconds.is_finite() && seconds >= 0.0) {
+        return Err(format!("{} is not a number of seconds", val));
+    }
+    Ok(seconds)
+}
+
+fn parse_keyframe_seconds(val : &str) -> Result<f64, String> {
+    let seconds = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(seconds.is_finite() && seconds > 0.0) {
+        return Err(format!("{} is not a positive number of seconds", val));
+    }
+    Ok(seconds)
+}
+
+fn parse_mesh_height(val : &str) -> Result<f64, String> {
+    let height = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(height.is_finite() && height > 0.0) {
+        return Err(format!("{} is not a positive height", val));
+    }
+    Ok(height)
+}
+
+fn parse_gif_frames(val : &str) -> Result<usize, String> {
+    let frames = val.parse::<usize>().map_err(|err| err.to_string())?;
+    if frames == 0 {
+        return Err(format!("{} is not a positive number of frames", val));
+    }
+    Ok(frames)
+}
+
+fn parse_gif_zoom(val : &str) -> Result<f64, String> {
+    let zoom = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(zoom.is_finite() && zoom > 0.0) {
+        return Err(format!("{} is not a positive factor", val));
+    }
+    Ok(zoom)
+}
+
+fn parse_cycle_speed(val : &str) -> Result<f64, String> {
+    let speed = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !speed.is_finite() {
+        return Err(format!("{} is not a number", val));
+    }
+    Ok(speed)
+}
+
+fn parse_exposure(val : &str) -> Result<f64, String> {
+    let exposure = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !exposure.is_finite() {
+        return Err(format!("{} is not a number", val));
+    }
+    Ok(exposure)
+}
+
+fn parse_gamma(val : &str) -> Result<f64, String> {
+    let gamma = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(gamma.is_finite() && gamma > 0.0) {
+        return Err(format!("{} is not positive", val));
+    }
+    Ok(gamma)
+}
+
+fn parse_trap_radius(val : &str) -> Result<f64, String> {
+    let radius = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(radius.is_finite() && radius > 0.0) {
+        return Err(format!("{} is not positive", val));
+    }
+    Ok(radius)
+}
+
+fn parse_exponent(val : &str) -> Result<f64, String> {
+    use mandelbrot_core::formula::{MAX_EXPONENT, MIN_EXPONENT};
+    let exponent = val.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(MIN_EXPONENT..=MAX_EXPONENT).contains(&exponent) {
+        return Err(format!("{} is not between {} and {}", val, MIN_EXPONENT, MAX_EXPONENT));
+    }
+    Ok(exponent)
+}
+
+fn parse_nebula_iterations(val : &str) -> Result<[u32; 3], String> {
+    let limits : Vec<u32> = val.split(',')
+        .map(|limit| limit.trim().parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|err| format!("{} {}", val, err))?;
+    match limits[..] {
+        [r, g, b] if r > 0 && g > 0 && b > 0 => Ok([r, g, b]),
+        _ => Err(format!("{} is not three positive iteration limits R,G,B", val)),
+    }
+}
+
+fn parse_size(val : &str) -> Result<(usize, usize), String> {
+    let size = val.split_once('x').and_then(|(width, height)| {
+        Some((width.parse().ok()?, height.parse().ok()?))
+    });
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => check_size((width, height)).map(|()| (width, height)),
+        _ => Err(format!("{} is not a WIDTHxHEIGHT size", val)),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Render a single image to a file without opening a window
+    Render {
+        /// PNG file to write, Radiance HDR for .hdr, the iteration data of each pixel for .exr and .raw,
+        /// or a heightmap mesh for .obj and .stl
+        #[arg(short, long, default_value = "mandlebrot.png")]
+        output : PathBuf,
+    },
+    /// Render each location in a TOML or CSV list to a PNG at --width x --height
+    Batch {
+        /// Locations to render, see the batch section of the README
+        file : PathBuf,
+        /// Directory the images are written to
+        #[arg(short, long, default_value = "batch")]
+        output : PathBuf,
+        /// Images rendered at once, 0 for one per core
+        #[arg(long, default_value_t = 0)]
+        jobs : usize,
+    },
+    /// Read newline delimited JSON commands from stdin and answer on stdout, for scripts driving renders
+    Pipe,
+    /// Explore in the terminal in 24 bit colour, for SSH sessions without a display
+    Terminal {
+        /// Draw the view as a full resolution Sixel image instead of half blocks, for terminals that support it
+        #[arg(long)]
+        sixel : bool,
+    },
+    /// Serve /z/x/y.png map tiles over HTTP for Leaflet or OpenLayers, computed when first asked for
+    Serve {
+        /// Address and port to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address : String,
+        /// Encoded tiles kept in memory, the least recently asked for go first, 0 keeps none
+        #[arg(long, default_value_t = 4096)]
+        cache : usize,
+    },
+    /// Compute tiles for renders run elsewhere with --workers
+    Worker {
+        /// Address and port to listen on
+        #[arg(long, default_value = "127.0.0.1:7879")]
+        address : String,
+    },
+    /// Render a zoom video through the keyframes in --keyframes-file
+    Animate {
+        /// Directory the numbered PNG frames are written to
+        #[arg(short, long, default_value = "frames")]
+        output : PathBuf,
+        /// Encode the video into this file with ffmpeg instead of writing PNGs
+        #[arg(long)]
+        ffmpeg : Option<PathBuf>,
+        /// Frames per second
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        fps : u32,
+        /// Seconds from one keyframe to the next
+        #[arg(long, default_value_t = 4.0, value_parser = parse_keyframe_seconds)]
+        seconds : f64,
+        /// Zoom straight into the last keyframe through an exponential map,
+        /// reusing each computed point in every frame it shows up in
+        #[arg(long)]
+        expmap : bool,
+    },
+}
@@ -0,0 +1,32 @@
+//The system clipboard, through arboard. One handle is kept for the whole
+//run, on X11 copied text is only on the clipboard while the handle that
+//copied it is still around to hand it out
+
+use std::cell::RefCell;
+use std::io::Error;
+
+use arboard::Clipboard;
+
+thread_local! {
+    static CLIPBOARD: RefCell<Option<Clipboard>> = const { RefCell::new(None) };
+}
+
+pub fn copy(text : &str) -> Result<(), Error> {
+    with_clipboard(|clipboard| clipboard.set_text(text))
+}
+
+pub fn paste() -> Result<String, Error> {
+    with_clipboard(|clipboard| clipboard.get_text())
+}
+
+//Opens the clipboard the first time it is used, so a session without one
+//only fails when copying or pasting
+fn with_clipboard<T>(action : impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>) -> Result<T, Error> {
+    CLIPBOARD.with(|clipboard| {
+        let mut clipboard = clipboard.borrow_mut();
+        if clipboard.is_none() {
+            *clipboard = Some(Clipboard::new().map_err(Error::other)?);
+        }
+        action(clipboard.as_mut().unwrap()).map_err(Error::other)
+    })
+}
@@ -0,0 +1,273 @@
+//Defaults from config.toml in the platform's config directory, used for
+//whatever isn't given on the command line:
+//
+//  width = 1280
+//  height = 720
+//  x = "-0.743643887"
+//  y = "0.131825904"
+//  zoom = 1e-7
+//  iterations = 1000
+//  palette = "Grayscale"
+//  threads = 4
+//
+//  #Moves the action on a key, named by its default key, to another key
+//  [keys]
+//  C = "Z"
+//
+//The viewer watches the file while it runs, and whatever was edited
+//applies straight away
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use serde::Deserialize;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use winit::event::VirtualKeyCode;
+use winit::event_loop::EventLoopProxy;
+use winit_input_helper::WinitInputHelper;
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{Coord, MandleParams};
+
+use crate::cli::Cli;
+use crate::view::{ViewError, parse_real, parse_zoom};
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    width : Option<u32>,
+    height : Option<u32>,
+    //Decimal strings like the view file, a TOML float would round them
+    x : Option<String>,
+    y : Option<String>,
+    zoom : Option<f64>,
+    iterations : Option<u32>,
+    palette : Option<String>,
+    threads : Option<usize>,
+    keys : HashMap<String, String>,
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Config {
+    pub size : Option<(u32, u32)>,
+    pub x : Option<Coord>,
+    pub y : Option<Coord>,
+    pub zoom : Option<f64>,
+    pub iterations : Option<u32>,
+    pub palette : Option<String>,
+    pub threads : Option<usize>,
+    pub keys : Keys,
+}
+
+//config.toml in the config directory of this platform, None if it has no
+//home directory to find that from
+pub fn default_path() -> Option<PathBuf> {
+    let env = |name : &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let dir = if cfg!(windows) {
+        env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|home| home.join(".config")))
+    };
+    dir.map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(CONFIG_FILE))
+}
+
+impl Config {
+
+    //Empty when the file doesn't exist. Nothing is used if any field is invalid
+    pub fn load(path : &Path) -> Result<Config, ViewError> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let file : ConfigFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+        let size = match (file.width, file.height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => Some((width, height)),
+            (None, None) => None,
+            _ => return Err(ViewError::Invalid("width and height have to be given together and positive".to_string())),
+        };
+        if file.iterations == Some(0) {
+            return Err(ViewError::Invalid("iterations must be positive".to_string()));
+        }
+        Ok(Config {
+            size,
+            x: file.x.map(|x| parse_real("x", &x)).transpose()?,
+            y: file.y.map(|y| parse_real("y", &y)).transpose()?,
+            zoom: file.zoom.map(|zoom| parse_zoom(&zoom.to_string())).transpose()?,
+            iterations: file.iterations,
+            palette: file.palette,
+            threads: file.threads,
+            keys: Keys::from_names(&file.keys)?,
+        })
+    }
+
+    //Fills in the arguments of cli that weren't given on the command line
+    //or through the environment
+    pub fn apply(&self, cli : &mut Cli, matches : &ArgMatches) {
+        let unset = |id : &str| !matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable));
+        if let (Some((width, height)), true) = (self.size, unset("width") && unset("height")) {
+            cli.width = Some(width);
+            cli.height = Some(height);
+        }
+        if let (Some(x), true) = (self.x, unset("x")) {
+            cli.x = x;
+        }
+        if let (Some(y), true) = (self.y, unset("y")) {
+            cli.y = y;
+        }
+        if let (Some(zoom), true) = (self.zoom, unset("zoom")) {
+            cli.zoom = zoom;
+        }
+        if let (Some(iterations), true) = (self.iterations, unset("iterations")) {
+            cli.iterations = iterations;
+        }
+        if let (Some(palette), true) = (&self.palette, unset("palette")) {
+            cli.palette = palette.clone();
+        }
+        if let (Some(threads), true) = (self.threads, unset("threads")) {
+            cli.threads = threads;
+        }
+    }
+
+    //Moves params to whatever was edited since old, leaving the rest as the
+    //viewer has it. Nothing is changed if the palette isn't loaded
+    pub fn update(&self, old : &Config, params : &mut MandleParams, palettes : &[Palette]) -> Result<(), ViewError> {
+        let palette = match &self.palette {
+            Some(name) if self.palette != old.palette => Some(
+                crate::find_palette(palettes, name).ok_or_else(|| ViewError::Invalid(format!("no palette named {}", name)))?
+            ),
+            _ => None,
+        };
+        fn edited<T : Copy + PartialEq>(new : Option<T>, old : Option<T>) -> Option<T> {
+            new.filter(|_| new != old)
+        }
+        if let Some(x) = edited(self.x, old.x) {
+            params.x = x;
+        }
+        if let Some(y) = edited(self.y, old.y) {
+            params.y = y;
+        }
+        if let Some(zoom) = edited(self.zoom, old.zoom) {
+            params.zoom = zoom;
+        }
+        if let Some(iterations) = edited(self.iterations, old.iterations) {
+            params.iterations = iterations;
+            params.auto_iterations = false;
+        }
+        if let Some(threads) = edited(self.threads, old.threads) {
+            params.threads = threads;
+        }
+        if let Some(palette) = palette {
+            params.palette = palette;
+        }
+        Ok(())
+    }
+}
+
+//Which key each action is on. Actions are named by their default key, so
+//[keys] C = "Z" does on Z what C does otherwise, and C is left with nothing.
+//Keys not moved keep their own action
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Keys {
+    moved : HashMap<VirtualKeyCode, Option<VirtualKeyCode>>,
+}
+
+impl Keys {
+
+    fn from_names(names : &HashMap<String, String>) -> Result<Keys, ViewError> {
+        let mut moved = HashMap::new();
+        for (action, key) in names {
+            let action = key_named(action).ok_or_else(|| ViewError::Invalid(format!("no key named {}", action)))?;
+            let key = key_named(key).ok_or_else(|| ViewError::Invalid(format!("no key named {}", key)))?;
+            moved.insert(action, Some(key));
+            //Whatever was on the new key goes, unless it was moved somewhere too
+            moved.entry(key).or_insert(None);
+        }
+        Ok(Keys { moved })
+    }
+
+    //The key the action of default is on, None when another action took it
+    pub fn key(&self, default : VirtualKeyCode) -> Option<VirtualKeyCode> {
+        self.moved.get(&default).copied().unwrap_or(Some(default))
+    }
+
+    pub fn pressed(&self, input : &WinitInputHelper, default : VirtualKeyCode) -> bool {
+        self.key(default).is_some_and(|key| input.key_pressed(key))
+    }
+
+    pub fn held(&self, input : &WinitInputHelper, default : VirtualKeyCode) -> bool {
+        self.key(default).is_some_and(|key| input.key_held(key))
+    }
+}
+
+//Keys that can be named in [keys], by their winit names ignoring case
+const KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Escape, Back, Tab, Return, Space, Home, End, Insert, Delete, PageUp, PageDown,
+        Left, Right, Up, Down, LBracket, RBracket, Minus, Equals, Plus, Comma, Period,
+        Slash, Backslash, Semicolon, Apostrophe, Grave, LAlt, RAlt,
+        NumpadAdd, NumpadSubtract, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4,
+        Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ]
+};
+
+fn key_named(name : &str) -> Option<VirtualKeyCode> {
+    KEYS.iter().copied().find(|key| format!("{:?}", key).eq_ignore_ascii_case(name.trim()))
+}
+
+//Notices when a file is written, waking the event loop through proxy.
+//The directory is watched rather than the file since editors tend to save
+//by replacing it, and a file that appears counts too
+pub struct Watcher {
+    path : PathBuf,
+    changed : Arc<AtomicBool>,
+    _watcher : RecommendedWatcher,
+}
+
+impl Watcher {
+
+    pub fn new(path : &Path, proxy : EventLoopProxy<()>) -> notify::Result<Watcher> {
+        let changed = Arc::new(AtomicBool::new(false));
+        let name = path.file_name().map(|name| name.to_os_string());
+        let flag = Arc::clone(&changed);
+        let mut watcher = notify::recommended_watcher(move |event : notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            let ours = event.paths.iter().any(|changed| changed.file_name() == name.as_deref());
+            if ours && !matches!(event.kind, EventKind::Access(_)) {
+                flag.store(true, Ordering::Relaxed);
+                let _ = proxy.send_event(());
+            }
+        })?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Watcher {
+            path: path.to_path_buf(),
+            changed,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    //Whether the file changed since the last time this said so
+    pub fn changed(&mut self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,473 @@
+//Renders split across machines. Workers listen for coordinators, a render
+//or animate run given --workers, and compute the tiles those send them.
+//The coordinator colours and writes the image as if it had computed every
+//tile itself, only the samples cross the network:
+//
+//  MANDTILES 2\n                      both ways once connected
+//  tile <length>\n<TOML of the view>  coordinator to worker
+//  busy\n                             worker to coordinator every few seconds while it computes
+//  ok <length>\n<samples>             then that, see tile_cache::encode
+//  error <message>\n                  or that when the view can't be computed
+//
+//Each worker has one tile at a time. A worker that fails, drops out or
+//says nothing for SILENCE_TIMEOUT has its tile handed to the others, and
+//whatever is left when none remain is computed on the coordinator
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use mandelbrot_core::offline::{self, Local, TileSource};
+use mandelbrot_core::palette::{self, Palette};
+use mandelbrot_core::script::Script;
+use mandelbrot_core::{CancelToken, Grid, MandleParams, Sample};
+
+use crate::tile_cache;
+use crate::view::ViewState;
+
+const HANDSHAKE: &str = "MANDTILES 2";
+
+//How long to try each worker before going on without it
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+//How long a worker may go without a word before it counts as gone, and
+//how often it says it's busy so a long tile isn't taken for that. Short
+//in tests so they can wait one out
+const SILENCE_TIMEOUT: Duration = Duration::from_secs(if cfg!(test) { 2 } else { 30 });
+const HEARTBEAT: Duration = Duration::from_millis(SILENCE_TIMEOUT.as_millis() as u64 / 6);
+
+//Compiled scripts a worker keeps for the next tiles, any more and the
+//longest unused goes
+const MAX_SCRIPTS: usize = 16;
+
+//Largest tile a worker takes on and the longest view it reads, well past
+//what coordinators send so a stray connection can't have it allocate much
+const MAX_TILE_PIXELS: usize = 4096 * 4096;
+const MAX_JOB: usize = 1 << 20;
+
+//A tile as sent to a worker. The palette is left to the coordinator, the
+//view names the first built in one so any worker has it
+#[derive(Serialize, Deserialize)]
+struct Job {
+    width : usize,
+    height : usize,
+    subdivide : bool,
+    double_double : bool,
+    //Text of the formula script, see script
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    script : Option<String>,
+    #[serde(flatten)]
+    view : ViewState,
+}
+
+//Compiled formula scripts by their text, the most recently used last
+#[derive(Default)]
+struct Scripts {
+    compiled : VecDeque<Arc<Script>>,
+}
+
+impl Scripts {
+
+    //The script compiled from source, compiling it if it isn't kept
+    fn get(&mut self, source : &str) -> Result<Arc<Script>, String> {
+        let script = match self.compiled.iter().position(|script| script.source() == source) {
+            Some(index) => self.compiled.remove(index).unwrap(),
+            None => Arc::new(Script::parse(source).map_err(|err| format!("formula script {}", err))?),
+        };
+        self.compiled.push_back(Arc::clone(&script));
+        if self.compiled.len() > MAX_SCRIPTS {
+            self.compiled.pop_front();
+        }
+        Ok(script)
+    }
+}
+
+//Serves coordinators on address until killed, a thread each
+pub fn work(address : &str) -> Result<(), Error> {
+    let listener = TcpListener::bind(address)?;
+    println!("Waiting for coordinators on {}", listener.local_addr()?);
+    serve(listener)
+}
+
+fn serve(listener : TcpListener) -> Result<(), Error> {
+    let scripts : Arc<Mutex<Scripts>> = Arc::default();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                println!("Error accepting a connection {}", err);
+                continue;
+            }
+        };
+        let scripts = scripts.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().map_or_else(|_| "a coordinator".to_string(), |peer| peer.to_string());
+            match serve_coordinator(stream, &scripts) {
+                Ok(tiles) => println!("Computed {} tiles for {}", tiles, peer),
+                Err(err) => println!("Error serving {} {}", peer, err),
+            }
+        });
+    }
+    Ok(())
+}
+
+//Computes tiles for one coordinator until it hangs up, counting them
+fn serve_coordinator(stream : TcpStream, scripts : &Mutex<Scripts>) -> Result<usize, Error> {
+    //A coordinator that stops reading can't hold the thread forever
+    stream.set_write_timeout(Some(SILENCE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    if read_line(&mut reader)?.as_deref() != Some(HANDSHAKE) {
+        writeln!(writer, "error expected {}", HANDSHAKE)?;
+        return Err(Error::new(ErrorKind::InvalidData, "not a coordinator"));
+    }
+    writeln!(writer, "{}", HANDSHAKE)?;
+
+    let palettes = palette::builtin_palettes();
+    let mut tiles = 0;
+    while let Some(line) = read_line(&mut reader)? {
+        let length = sized(&line, "tile", MAX_JOB)?;
+        let mut job = vec![0; length];
+        reader.read_exact(&mut job)?;
+        match tile_params(&job, &palettes, scripts) {
+            Ok(params) => {
+                //Computed on a thread of its own so the coordinator hears
+                //from this one while it takes
+                let grid = std::thread::scope(|scope| -> Result<Grid<Sample>, Error> {
+                    let (done, computed) = mpsc::channel();
+                    scope.spawn(move || done.send(compute_tile(&params)));
+                    loop {
+                        match computed.recv_timeout(HEARTBEAT) {
+                            Ok(grid) => return Ok(grid),
+                            Err(mpsc::RecvTimeoutError::Timeout) => {
+                                writeln!(writer, "busy")?;
+                                writer.flush()?;
+                            }
+                            Err(mpsc::RecvTimeoutError::Disconnected) => return Err(Error::other("computing the tile panicked")),
+                        }
+                    }
+                })?;
+                let mut samples = Vec::new();
+                tile_cache::encode(&grid, &mut samples);
+                writeln!(writer, "ok {}", samples.len())?;
+                writer.write_all(&samples)?;
+                tiles += 1;
+            }
+            Err(err) => writeln!(writer, "error {}", err.replace('\n', " "))?,
+        }
+        writer.flush()?;
+    }
+    Ok(tiles)
+}
+
+//Computes the samples of a tile on this machine
+fn compute_tile(params : &MandleParams) -> Grid<Sample> {
+    let mut grid = Grid::new(params.width, params.height, Sample::INTERIOR);
+    offline::compute(&mut grid, params, &CancelToken::never());
+    grid
+}
+
+//The view a job describes, with its script from those kept
+fn tile_params(job : &[u8], palettes : &[Palette], scripts : &Mutex<Scripts>) -> Result<MandleParams, String> {
+    let job : Job = std::str::from_utf8(job)
+        .map_err(|err| err.to_string())
+        .and_then(|job| toml::from_str(job).map_err(|err| err.to_string()))?;
+    if job.width == 0 || job.height == 0 || job.width.saturating_mul(job.height) > MAX_TILE_PIXELS {
+        return Err(format!("{}x{} isn't a tile size", job.width, job.height));
+    }
+    let mut params = MandleParams::new(job.width, job.height);
+    job.view.apply(&mut params, palettes).map_err(|err| err.to_string())?;
+    params.subdivide = job.subdivide;
+    params.double_double = job.double_double;
+    params.script = job.script.map(|source| scripts.lock().unwrap().get(&source)).transpose()?;
+    Ok(params)
+}
+
+//Tiles computed by workers on other machines
+pub struct Farm {
+    workers : Vec<Worker>,
+    //What jobs name their palette from
+    palettes : Vec<Palette>,
+}
+
+struct Worker {
+    address : String,
+    //None once it has failed, it isn't sent anything more
+    connection : Mutex<Option<Connection>>,
+}
+
+struct Connection {
+    reader : BufReader<TcpStream>,
+    writer : TcpStream,
+}
+
+impl Farm {
+
+    //Connects to each of addresses, going on without those that can't be
+    //reached. Fails if none can
+    pub fn connect(addresses : &[String]) -> Result<Farm, Error> {
+        let mut workers = Vec::new();
+        for address in addresses {
+            match Connection::open(address) {
+                Ok(connection) => {
+                    println!("Connected to worker {}", address);
+                    workers.push(Worker { address: address.clone(), connection: Mutex::new(Some(connection)) });
+                }
+                Err(err) => println!("Error connecting to worker {} {}", address, err),
+            }
+        }
+        if workers.is_empty() {
+            return Err(Error::new(ErrorKind::NotConnected, "none of the workers could be reached"));
+        }
+        Ok(Farm { workers, palettes: palette::builtin_palettes() })
+    }
+
+    fn job(&self, tile : &MandleParams) -> Result<String, Error> {
+        let job = Job {
+            width: tile.width,
+            height: tile.height,
+            subdivide: tile.subdivide,
+            double_double: tile.double_double,
+            script: tile.script.as_ref().map(|script| script.source().to_string()),
+            view: ViewState::from_params(&MandleParams { palette: 0, ..tile.clone() }, &self.palettes),
+        };
+        toml::to_string(&job).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+}
+
+impl TileSource for Farm {
+    fn compute_tiles(&self, tiles : &[MandleParams], cancel : &CancelToken, done : &mut dyn FnMut(usize, Grid<Sample>)) {
+        let queue = Mutex::new((0..tiles.len()).collect::<VecDeque<_>>());
+        std::thread::scope(|scope| {
+            let (finished, arrived) = mpsc::channel();
+            for worker in &self.workers {
+                let (queue, finished) = (&queue, finished.clone());
+                scope.spawn(move || {
+                    let mut connection = worker.connection.lock().unwrap();
+                    while let Some(live) = connection.as_mut() {
+                        if cancel.is_cancelled() {
+                            return;
+                        }
+                        let Some(index) = queue.lock().unwrap().pop_front() else {
+                            return;
+                        };
+                        match self.job(&tiles[index]).and_then(|job| live.compute(&job, &tiles[index])) {
+                            Ok(grid) => {
+                                let _ = finished.send((index, grid));
+                            }
+                            Err(err) => {
+                                println!("\nError from worker {} {}, its tiles go to the others", worker.address, err);
+                                queue.lock().unwrap().push_back(index);
+                                *connection = None;
+                            }
+                        }
+                    }
+                });
+            }
+            drop(finished);
+            for (index, grid) in arrived {
+                done(index, grid);
+            }
+        });
+
+        let left : Vec<usize> = queue.into_inner().unwrap().into();
+        if !left.is_empty() && !cancel.is_cancelled() {
+            println!("\nWarning no workers left, computing {} tiles here", left.len());
+            let views : Vec<MandleParams> = left.iter().map(|&index| tiles[index].clone()).collect();
+            Local.compute_tiles(&views, cancel, &mut |at, grid| done(left[at], grid));
+        }
+    }
+}
+
+impl Connection {
+
+    fn open(address : &str) -> Result<Connection, Error> {
+        let mut last = Error::new(ErrorKind::NotFound, "no addresses");
+        for socket in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    //A worker that goes quiet gives its tile up rather than
+                    //holding the render forever
+                    stream.set_read_timeout(Some(SILENCE_TIMEOUT))?;
+                    stream.set_write_timeout(Some(SILENCE_TIMEOUT))?;
+                    let mut connection = Connection { reader: BufReader::new(stream.try_clone()?), writer: stream };
+                    writeln!(connection.writer, "{}", HANDSHAKE)?;
+                    return match connection.answer()? {
+                        line if line == HANDSHAKE => Ok(connection),
+                        line => Err(Error::new(ErrorKind::InvalidData, format!("answered {}", line))),
+                    };
+                }
+                Err(err) => last = err,
+            }
+        }
+        Err(last)
+    }
+
+    //The samples of the tile job describes, checked to be tile's size
+    fn compute(&mut self, job : &str, tile : &MandleParams) -> Result<Grid<Sample>, Error> {
+        write!(self.writer, "tile {}\n{}", job.len(), job)?;
+        self.writer.flush()?;
+        let mut line = self.answer()?;
+        while line == "busy" {
+            line = self.answer()?;
+        }
+        if let Some(message) = line.strip_prefix("error ") {
+            return Err(Error::other(message.to_string()));
+        }
+        let length = sized(&line, "ok", 8 + tile.width * tile.height * 16)?;
+        let mut samples = vec![0; length];
+        self.reader.read_exact(&mut samples)?;
+        tile_cache::decode(&samples)
+            .filter(|grid| grid.cols() == tile.width && grid.rows() == tile.height)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "sent samples of the wrong size"))
+    }
+
+    //The next line from the worker, timing out if it has gone quiet
+    fn answer(&mut self) -> Result<String, Error> {
+        match read_line(&mut self.reader) {
+            Ok(Some(line)) => Ok(line),
+            Ok(None) => Err(Error::new(ErrorKind::UnexpectedEof, "hung up")),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Err(Error::new(ErrorKind::TimedOut, format!("said nothing for {} seconds", SILENCE_TIMEOUT.as_secs())))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+//A line without its ending, None at the end of the stream
+fn read_line(reader : &mut impl BufRead) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_JOB as u64).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+//The length in a line like "<word> <length>", at most limit
+fn sized(line : &str, word : &str, limit : usize) -> Result<usize, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("expected {} <length>, got {}", word, line));
+    let length = line.strip_prefix(word)
+        .and_then(|rest| rest.strip_prefix(' '))
+        .and_then(|length| length.parse::<usize>().ok())
+        .ok_or_else(invalid)?;
+    if length > limit {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} bytes is more than {}", length, limit)));
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    use mandelbrot_core::formula;
+
+    //A worker on a port of its own, serving until the tests finish
+    fn worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || serve(listener));
+        address
+    }
+
+    //The encoded samples of each tile, as computed by source
+    fn computed(source : &dyn TileSource, tiles : &[MandleParams]) -> Vec<Option<Vec<u8>>> {
+        let mut computed = vec![None; tiles.len()];
+        source.compute_tiles(tiles, &CancelToken::never(), &mut |index, grid| {
+            let mut samples = Vec::new();
+            tile_cache::encode(&grid, &mut samples);
+            computed[index] = Some(samples);
+        });
+        computed
+    }
+
+    fn tiles() -> Vec<MandleParams> {
+        let seahorse = MandleParams {
+            x: "-0.7436".parse().unwrap(),
+            y: "0.1318".parse().unwrap(),
+            zoom: 1e-5,
+            ..MandleParams::new(24, 16)
+        };
+        vec![MandleParams::new(32, 24), seahorse, MandleParams { iterations: 50, ..MandleParams::new(8, 40) }]
+    }
+
+    fn connected(farm : &Farm) -> bool {
+        farm.workers[0].connection.lock().unwrap().is_some()
+    }
+
+    #[test]
+    fn workers_compute_what_this_machine_would() {
+        let farm = Farm::connect(&[worker()]).unwrap();
+        let tiles = tiles();
+        assert_eq!(computed(&farm, &tiles), computed(&Local, &tiles));
+        assert!(connected(&farm));
+    }
+
+    #[test]
+    fn long_tiles_keep_their_worker() {
+        //A script tile inside the set, slow enough to need a few heartbeats
+        let script = Arc::new(Script::parse("fn step(z, c) { z * z + c }").unwrap());
+        let tile = MandleParams {
+            zoom: 1e-3,
+            //Rhai runs many times faster optimised
+            iterations: if cfg!(debug_assertions) { 6000 } else { 100000 },
+            formula: formula::find("Script").unwrap(),
+            script: Some(script),
+            ..MandleParams::new(8, 8)
+        };
+        let farm = Farm::connect(&[worker()]).unwrap();
+        let started = Instant::now();
+        let tiles = computed(&farm, &[tile]);
+        assert!(started.elapsed() > SILENCE_TIMEOUT, "took {:?}, too quick to test the heartbeat", started.elapsed());
+        assert!(connected(&farm));
+        assert!(tiles[0].is_some());
+    }
+
+    #[test]
+    fn silent_workers_tiles_are_computed_here() {
+        //Answers the handshake and then nothing more
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            read_line(&mut reader).unwrap();
+            writeln!(stream, "{}", HANDSHAKE).unwrap();
+            std::thread::sleep(SILENCE_TIMEOUT * 5);
+        });
+        let farm = Farm::connect(&[address]).unwrap();
+        let started = Instant::now();
+        let tiles = tiles();
+        assert_eq!(computed(&farm, &tiles), computed(&Local, &tiles));
+        assert!(started.elapsed() < SILENCE_TIMEOUT * 3);
+        assert!(!connected(&farm));
+    }
+
+    #[test]
+    fn kept_scripts_are_bounded() {
+        let source = |n : usize| format!("fn step(z, c) {{ z * z + c + {} }}", n);
+        let mut scripts = Scripts::default();
+        let first = scripts.get(&source(0)).unwrap();
+        assert!(Arc::ptr_eq(&first, &scripts.get(&source(0)).unwrap()));
+        for n in 1..=MAX_SCRIPTS {
+            scripts.get(&source(n)).unwrap();
+        }
+        assert_eq!(scripts.compiled.len(), MAX_SCRIPTS);
+        assert!(!scripts.compiled.iter().any(|script| Arc::ptr_eq(script, &first)));
+        //Tiles still holding one that went can go on with it, and it's
+        //freed with the last of them
+        assert_eq!(first.step((1.0, 0.0), (0.0, 0.0), 2.0, 4.0), (1.0, 0.0));
+        let freed = Arc::downgrade(&first);
+        drop(first);
+        assert!(freed.upgrade().is_none());
+        assert!(scripts.get("fn step(z) { z").is_err());
+        assert_eq!(scripts.compiled.len(), MAX_SCRIPTS);
+    }
+}
@@ -0,0 +1,65 @@
+//Short animated GIFs of the view for sharing, F11 cycles the palette
+//through one repeat so the loop has no seam, Shift+F11 zooms into the
+//centre. They are written next to the F12 posters
+
+use std::io::Write;
+use std::path::Path;
+
+use mandelbrot_core::gif::{self, GifDither};
+use mandelbrot_core::offline::{self, OfflineError};
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{MandleParams, MIN_ZOOM};
+
+pub enum Motion {
+    Cycle,
+    //Zooms in by this much from the first frame to the last
+    Zoom(f64),
+}
+
+pub struct Options {
+    pub size : (usize, usize),
+    pub frames : usize,
+    pub delay_ms : u32,
+    pub dither : GifDither,
+}
+
+//Renders the frames and saves them, printing progress to stdout
+pub fn capture(
+    params : &MandleParams,
+    palette : &Palette,
+    motion : Motion,
+    options : &Options,
+    path : &Path
+) -> Result<(), OfflineError> {
+    let (width, height) = options.size;
+    println!("Rendering {} {}x{} GIF frames to {}", options.frames, width, height, path.display());
+    let progress = |done : f64| {
+        print!("\rRendering {:.0}%", done * 100.0);
+        let _ = std::io::stdout().flush();
+    };
+    let frames = match motion {
+        //The set doesn't move, it's computed once and coloured for each frame
+        Motion::Cycle => {
+            let offsets : Vec<f64> = (0..options.frames)
+                .map(|frame| (params.palette_offset + frame as f64 / options.frames as f64).rem_euclid(1.0))
+                .collect();
+            offline::render_recolored(&offline::Local, params, palette, width, height, &offsets, progress)
+        }
+        Motion::Zoom(factor) => {
+            let last = options.frames.saturating_sub(1).max(1) as f64;
+            (0..options.frames).map(|frame| {
+                let view = MandleParams {
+                    zoom: (params.zoom / factor.powf(frame as f64 / last)).max(MIN_ZOOM),
+                    ..params.clone()
+                };
+                let image = offline::render_image(&view, palette, width, height, |_| {});
+                progress((frame + 1) as f64 / options.frames as f64);
+                image
+            }).collect()
+        }
+    };
+    println!();
+    gif::write_gif(path, &frames, width, height, options.delay_ms, options.dither)?;
+    println!("Saved {}", path.display());
+    Ok(())
+}
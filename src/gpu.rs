@@ -0,0 +1,211 @@
+use pixels::wgpu;
+
+use mandelbrot_core::{CancelToken, Fractal, Grid, MandleParams, Sample};
+
+//Size of the Params struct in mandlebrot.wgsl, padded to 16 bytes for the uniform buffer
+const PARAMS_SIZE: u64 = 48;
+
+//Bytes per pixel of the shader's output, three f32
+const OUTPUT_SIZE: u64 = 12;
+
+pub struct GpuRenderer {
+    device : wgpu::Device,
+    queue : wgpu::Queue,
+    pipeline : wgpu::ComputePipeline,
+    buffers : Buffers,
+}
+
+//Everything sized to the grid, rebuilt when it changes size
+struct Buffers {
+    bind_group : wgpu::BindGroup,
+    params : wgpu::Buffer,
+    output : wgpu::Buffer,
+    readback : wgpu::Buffer,
+    width : u32,
+    height : u32,
+}
+
+impl GpuRenderer {
+
+    //Builds the compute pipeline on a device of its own, so the render thread
+    //doesn't need the window's. Picks the adapter the way pixels does.
+    //Returns None without one that can run compute shaders (eg. downlevel webgl)
+    pub fn new(width : u32, height : u32) -> Option<GpuRenderer> {
+        let backends = wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all);
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter = wgpu::util::initialize_adapter_from_env(&instance, backends).or_else(|| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: None,
+                force_fallback_adapter: false,
+                power_preference: wgpu::util::power_preference_from_env().unwrap_or_default(),
+            }))
+        })?;
+        let capabilities = adapter.get_downlevel_capabilities();
+        if !capabilities.flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS) {
+            return None;
+        }
+        let (device, queue) = match pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("mandlebrot_device"),
+            limits: adapter.limits(),
+            ..wgpu::DeviceDescriptor::default()
+        }, None)) {
+            Ok(device) => device,
+            Err(err) => {
+                println!("Error requesting gpu device {}", err);
+                return None;
+            }
+        };
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("mandlebrot_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("mandlebrot.wgsl").into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("mandlebrot_pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let buffers = Buffers::new(&device, &pipeline, width, height);
+        Some(GpuRenderer {
+            device,
+            queue,
+            pipeline,
+            buffers,
+        })
+    }
+
+    pub fn resize(&mut self, width : u32, height : u32) {
+        self.buffers = Buffers::new(&self.device, &self.pipeline, width, height);
+    }
+
+    //Same contract as calc_mandlebrot_set, each pixel is iterated on the
+    //gpu and read back into the grid for colouring.
+    //A dispatch can't be interrupted, so cancellation is only checked once it finishes
+    pub fn calc_mandlebrot_set(
+        &self,
+        grid : &mut Grid<Sample>,
+        params : &MandleParams,
+        cancel : &CancelToken
+    ) -> bool {
+        let device = &self.device;
+        let queue = &self.queue;
+        let buffers = &self.buffers;
+
+        let mut uniform = [0u8; PARAMS_SIZE as usize];
+        uniform[0..4].copy_from_slice(&(params.x.to_f64() as f32).to_le_bytes());
+        uniform[4..8].copy_from_slice(&(params.y.to_f64() as f32).to_le_bytes());
+        uniform[8..12].copy_from_slice(&(params.zoom as f32).to_le_bytes());
+        uniform[12..16].copy_from_slice(&params.iterations.to_le_bytes());
+        uniform[16..20].copy_from_slice(&buffers.width.to_le_bytes());
+        uniform[20..24].copy_from_slice(&buffers.height.to_le_bytes());
+        let bailout2 = (params.bailout * params.bailout) as f32;
+        uniform[24..28].copy_from_slice(&bailout2.to_le_bytes());
+        if let Fractal::Julia { c_a, c_b } = params.fractal {
+            uniform[28..32].copy_from_slice(&1u32.to_le_bytes());
+            uniform[32..36].copy_from_slice(&c_a.to_num::<f32>().to_le_bytes());
+            uniform[36..40].copy_from_slice(&c_b.to_num::<f32>().to_le_bytes());
+        }
+        queue.write_buffer(&buffers.params, 0, &uniform);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mandlebrot_encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("mandlebrot_pass"),
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &buffers.bind_group, &[]);
+            pass.dispatch_workgroups(
+                buffers.width.div_ceil(8),
+                buffers.height.div_ceil(8),
+                1
+            );
+        }
+        encoder.copy_buffer_to_buffer(&buffers.output, 0, &buffers.readback, 0, buffers.readback.size());
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffers.readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            if let Err(err) = result {
+                println!("Error reading gpu output {}", err);
+            }
+        });
+        device.poll(wgpu::Maintain::Wait);
+        if cancel.is_cancelled() {
+            buffers.readback.unmap();
+            return false;
+        }
+        {
+            let data = slice.get_mapped_range();
+            let float = |bytes : &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            for ((_, cell), bytes) in grid.iter_mut().zip(data.chunks_exact(OUTPUT_SIZE as usize)) {
+                let stopped = float(&bytes[0..4]);
+                *cell = Sample {
+                    //Negative for points that never escaped
+                    stopped: (stopped >= 0.0).then_some(stopped as u32),
+                    z: (float(&bytes[4..8]), float(&bytes[8..12])),
+                    distance: f32::NAN,
+                };
+            }
+        }
+        buffers.readback.unmap();
+        true
+    }
+}
+
+impl Buffers {
+
+    fn new(device : &wgpu::Device, pipeline : &wgpu::ComputePipeline, width : u32, height : u32) -> Buffers {
+        //Stopping iteration and final z per pixel, see mandlebrot.wgsl
+        let output_size = width as u64 * height as u64 * OUTPUT_SIZE;
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mandlebrot_params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mandlebrot_output"),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mandlebrot_readback"),
+            size: output_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mandlebrot_bind_group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        Buffers {
+            bind_group,
+            params,
+            output,
+            readback,
+            width,
+            height,
+        }
+    }
+}
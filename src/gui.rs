@@ -0,0 +1,265 @@
+//egui side panel for typing in exact values. The panel is laid out on the
+//event loop, the resulting paint jobs are drawn over the frame when it is
+//next presented
+
+use egui::{ClippedPrimitive, Context, TexturesDelta};
+use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+use pixels::{Pixels, PixelsContext, wgpu};
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::Window;
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{ColorMode, MandleParams, formula};
+
+use crate::cli;
+
+//Layout and input side, lives on the event loop
+pub struct Gui {
+    ctx : Context,
+    state : egui_winit::State,
+    pub visible : bool,
+    //Corners of the zoom box being dragged out, in physical window pixels
+    pub zoom_box : Option<((f32, f32), (f32, f32))>,
+    //Points of the orbit shown while O is held, in physical window pixels
+    pub orbit : Vec<(f32, f32)>,
+    //Text of the coordinate fields while they are being edited
+    x_text : String,
+    y_text : String,
+    zoom_text : String,
+}
+
+//What presenting needs to draw the last laid out panel
+pub struct Overlay {
+    renderer : Renderer,
+    paint_jobs : Vec<ClippedPrimitive>,
+    textures : TexturesDelta,
+    screen : ScreenDescriptor,
+}
+
+impl Gui {
+
+    pub fn new<T>(event_loop : &EventLoopWindowTarget<T>, window : &Window, pixels : &Pixels) -> Gui {
+        let mut state = egui_winit::State::new(event_loop);
+        state.set_max_texture_side(pixels.device().limits().max_texture_dimension_2d as usize);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+        Gui {
+            ctx: Context::default(),
+            state,
+            visible: false,
+            zoom_box: None,
+            orbit: Vec::new(),
+            x_text: String::new(),
+            y_text: String::new(),
+            zoom_text: String::new(),
+        }
+    }
+
+    //Starts egui over with a new context, which sends all of its textures
+    //again for an overlay that was created afresh
+    pub fn reset(&mut self) {
+        self.ctx = Context::default();
+    }
+
+    //Returns whether the panel needs laying out again
+    pub fn on_event(&mut self, event : &WindowEvent) -> bool {
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            self.state.set_pixels_per_point(*scale_factor as f32);
+        }
+        self.visible && self.state.on_event(&self.ctx, event).repaint
+    }
+
+    //Input over the panel shouldn't also pan or zoom the view
+    pub fn wants_pointer(&self) -> bool {
+        self.visible && self.ctx.wants_pointer_input()
+    }
+
+    //Keys typed into a field shouldn't trigger shortcuts
+    pub fn wants_keyboard(&self) -> bool {
+        self.visible && self.ctx.wants_keyboard_input()
+    }
+
+    //Lays out the panel for params, editing them in place
+    pub fn prepare(&mut self, window : &Window, params : &mut MandleParams, palettes : &[Palette], overlay : &mut Overlay) {
+        let raw_input = self.state.take_egui_input(window);
+        //Context is reference counted, the clone lets the closure borrow self
+        let ctx = self.ctx.clone();
+        let output = ctx.run(raw_input, |ctx| {
+            if self.visible {
+                self.panel(ctx, params, palettes);
+            }
+            if let Some((start, end)) = self.zoom_box {
+                let pixels_per_point = ctx.pixels_per_point();
+                let corner = |(x, y) : (f32, f32)| egui::pos2(x / pixels_per_point, y / pixels_per_point);
+                let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("zoom box"));
+                ctx.layer_painter(layer).rect(
+                    egui::Rect::from_two_pos(corner(start), corner(end)),
+                    0.0,
+                    egui::Color32::from_white_alpha(24),
+                    egui::Stroke::new(1.0, egui::Color32::WHITE)
+                );
+            }
+            if let Some(&start) = self.orbit.first() {
+                let pixels_per_point = ctx.pixels_per_point();
+                let point = |&(x, y) : &(f32, f32)| egui::pos2(x / pixels_per_point, y / pixels_per_point);
+                let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("orbit"));
+                let painter = ctx.layer_painter(layer);
+                painter.add(egui::Shape::line(
+                    self.orbit.iter().map(point).collect(),
+                    egui::Stroke::new(1.0, egui::Color32::WHITE)
+                ));
+                //The point the orbit starts from, under the cursor
+                painter.circle_filled(point(&start), 3.0, egui::Color32::WHITE);
+            }
+        });
+        self.state.handle_platform_output(window, &self.ctx, output.platform_output);
+        overlay.textures.append(output.textures_delta);
+        overlay.paint_jobs = self.ctx.tessellate(output.shapes);
+        overlay.screen.pixels_per_point = self.state.pixels_per_point();
+    }
+
+    fn panel(&mut self, ctx : &Context, params : &mut MandleParams, palettes : &[Palette]) {
+        egui::SidePanel::left("controls").show(ctx, |ui| {
+            ui.heading("View");
+            if let Some(x) = real_field(ui, "X", &mut self.x_text, &params.x.to_string(), cli::parse_real) {
+                params.x = x;
+            }
+            if let Some(y) = real_field(ui, "Y", &mut self.y_text, &params.y.to_string(), cli::parse_real) {
+                params.y = y;
+            }
+            if let Some(zoom) = real_field(ui, "Zoom", &mut self.zoom_text, &format!("{:e}", params.zoom), cli::parse_zoom) {
+                params.zoom = zoom;
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Iterations");
+                ui.add_enabled(
+                    !params.auto_iterations,
+                    egui::DragValue::new(&mut params.iterations).clamp_range(1..=u32::MAX)
+                );
+                ui.checkbox(&mut params.auto_iterations, "Auto");
+            });
+
+            egui::ComboBox::from_label("Fractal")
+                .selected_text(formula::get(params.formula).name())
+                .show_ui(ui, |ui| {
+                    for (index, formula) in formula::FORMULAS.iter().enumerate() {
+                        ui.selectable_value(&mut params.formula, index, formula.name());
+                    }
+                });
+
+            egui::ComboBox::from_label("Palette")
+                .selected_text(palettes[params.palette].name.as_str())
+                .show_ui(ui, |ui| {
+                    for (index, palette) in palettes.iter().enumerate() {
+                        ui.selectable_value(&mut params.palette, index, palette.name.as_str());
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut params.cycling, "Cycle palette");
+                ui.add(egui::DragValue::new(&mut params.cycle_speed).speed(0.01).suffix("/s"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Exposure");
+                ui.add(egui::DragValue::new(&mut params.exposure).speed(0.05).clamp_range(-8.0..=8.0));
+                ui.label("Gamma");
+                ui.add(egui::DragValue::new(&mut params.gamma).speed(0.01).clamp_range(0.1..=10.0));
+                ui.checkbox(&mut params.dither, "Dither");
+            });
+            if params.color_mode == ColorMode::Trap {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:?} trap", params.trap));
+                    ui.add(egui::DragValue::new(&mut params.trap_x).speed(0.01));
+                    ui.add(egui::DragValue::new(&mut params.trap_y).speed(0.01));
+                    ui.label("r");
+                    ui.add(egui::DragValue::new(&mut params.trap_radius).speed(0.01).clamp_range(0.001..=4.0));
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("Threads");
+                ui.add(egui::DragValue::new(&mut params.threads).clamp_range(0..=256));
+                ui.label("(0 for one per core)");
+            });
+            ui.checkbox(&mut params.subdivide, "Subdivide flat rectangles");
+            ui.checkbox(&mut params.double_double, "Double-double past f64");
+            ui.checkbox(&mut params.sketch, "Gpu sketch while computing");
+        });
+    }
+}
+
+//Text field for a coordinate or zoom shown as current, applied once editing finishes
+//(enter or clicking away). Invalid text is thrown away and the current value shown again
+fn real_field<T>(
+    ui : &mut egui::Ui,
+    label : &str,
+    text : &mut String,
+    current : &str,
+    parse : fn(&str) -> Result<T, String>
+) -> Option<T> {
+    ui.label(label);
+    let response = ui.text_edit_singleline(text);
+    if response.lost_focus() {
+        let parsed = parse(text.trim()).ok();
+        *text = current.to_string();
+        return parsed;
+    }
+    if !response.has_focus() {
+        *text = current.to_string();
+    }
+    None
+}
+
+impl Overlay {
+
+    pub fn new(pixels : &Pixels, width : u32, height : u32, pixels_per_point : f32) -> Overlay {
+        Overlay {
+            renderer: Renderer::new(pixels.device(), pixels.render_texture_format(), None, 1),
+            paint_jobs: Vec::new(),
+            textures: TexturesDelta::default(),
+            screen: ScreenDescriptor {
+                size_in_pixels: [width, height],
+                pixels_per_point,
+            },
+        }
+    }
+
+    //Has to follow the surface size, clip rects are scaled against it
+    pub fn resize(&mut self, width : u32, height : u32) {
+        self.screen.size_in_pixels = [width, height];
+    }
+
+    //Draws the panel on top of whatever is already in render_target
+    pub fn render(
+        &mut self,
+        encoder : &mut wgpu::CommandEncoder,
+        render_target : &wgpu::TextureView,
+        context : &PixelsContext
+    ) {
+        for (id, image_delta) in &self.textures.set {
+            self.renderer.update_texture(&context.device, &context.queue, *id, image_delta);
+        }
+        self.renderer.update_buffers(&context.device, &context.queue, encoder, &self.paint_jobs, &self.screen);
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: render_target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer.render(&mut pass, &self.paint_jobs, &self.screen);
+        }
+        //Textures are only uploaded once, freed ones after the frame that last used them
+        let textures = std::mem::take(&mut self.textures);
+        for id in &textures.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
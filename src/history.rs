@@ -0,0 +1,110 @@
+//Views visited so far, Backspace steps back through them and Shift+Backspace
+//forward again. Only the position is tracked, undoing doesn't undo palette
+//or render setting changes made since
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use mandelbrot_core::MandleParams;
+
+//A drag, a flick of the mouse wheel or a held pan key is one step. Changes
+//closer together than this belong to the same step
+const SETTLE: Duration = Duration::from_millis(500);
+
+//Oldest views are forgotten past this many
+const MAX_STEPS: usize = 100;
+
+pub struct History {
+    back : VecDeque<MandleParams>,
+    forward : Vec<MandleParams>,
+    //The view last seen and when it changed, None once stepped to
+    current : MandleParams,
+    changed : Option<Instant>,
+}
+
+impl History {
+
+    pub fn new(params : &MandleParams) -> History {
+        History {
+            back: VecDeque::new(),
+            forward: Vec::new(),
+            current: params.clone(),
+            changed: None,
+        }
+    }
+
+    //Picks up where a saved session left off, back oldest first and forward
+    //in the order redo goes through it backwards, like back() and forward()
+    pub fn restore(params : &MandleParams, back : Vec<MandleParams>, forward : Vec<MandleParams>) -> History {
+        let mut history = History::new(params);
+        for view in back {
+            history.remember(view);
+        }
+        history.forward = forward;
+        history
+    }
+
+    pub fn back(&self) -> impl Iterator<Item = &MandleParams> {
+        self.back.iter()
+    }
+
+    pub fn forward(&self) -> &[MandleParams] {
+        &self.forward
+    }
+
+    //Called with the params whenever they may have changed. The view before a
+    //new step is remembered, and anything undone can't be redone anymore
+    pub fn record(&mut self, params : &MandleParams) {
+        if same_view(params, &self.current) {
+            return;
+        }
+        let now = Instant::now();
+        if self.changed.is_none_or(|changed| now - changed >= SETTLE) {
+            self.remember(self.current.clone());
+            self.forward.clear();
+        }
+        self.current = params.clone();
+        self.changed = Some(now);
+    }
+
+    //Moves params back to the view before, false if there is none
+    pub fn undo(&mut self, params : &mut MandleParams) -> bool {
+        let Some(view) = self.back.pop_back() else {
+            return false;
+        };
+        self.forward.push(params.clone());
+        self.step_to(params, &view);
+        true
+    }
+
+    //Moves params forward to the view last undone, false if there is none
+    pub fn redo(&mut self, params : &mut MandleParams) -> bool {
+        let Some(view) = self.forward.pop() else {
+            return false;
+        };
+        self.remember(params.clone());
+        self.step_to(params, &view);
+        true
+    }
+
+    fn remember(&mut self, view : MandleParams) {
+        if self.back.len() == MAX_STEPS {
+            self.back.pop_front();
+        }
+        self.back.push_back(view);
+    }
+
+    fn step_to(&mut self, params : &mut MandleParams, view : &MandleParams) {
+        params.x = view.x;
+        params.y = view.y;
+        params.zoom = view.zoom;
+        params.fractal = view.fractal;
+        self.current = params.clone();
+        //The next change starts a step of its own however soon it comes
+        self.changed = None;
+    }
+}
+
+fn same_view(a : &MandleParams, b : &MandleParams) -> bool {
+    a.x == b.x && a.y == b.y && a.zoom == b.zoom && a.fractal == b.fractal
+}
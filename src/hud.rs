@@ -0,0 +1,127 @@
+//Text overlay with the view and the last frame's render time, drawn
+//straight into the rgba frame with an 8x8 bitmap font
+
+use std::time::Duration;
+
+use font8x8::{BASIC_FONTS, UnicodeFonts};
+
+use mandelbrot_core::{Backend, GPU_MIN_ZOOM, MandleParams, Precision};
+
+//Each font pixel is drawn as a SCALE x SCALE block
+const SCALE: usize = 2;
+const MARGIN: usize = 8;
+const LINE_HEIGHT: usize = 10 * SCALE;
+
+//Digits shown past the first one that changes between neighbouring pixels
+const EXTRA_DIGITS: usize = 2;
+
+//Draws the hud onto a frame rendered for params, if it is switched on.
+//samples_per_pixel counts the antialiasing samples taken so far
+//Running out of precision is shown with the hud off as well, the image
+//turns to noise without explaining itself otherwise
+pub fn overlay(frame : &mut [u8], params : &MandleParams, render_time : Duration, samples_per_pixel : f64) {
+    if params.hud {
+        draw(frame, params.width, params.height, &lines(params, render_time, samples_per_pixel));
+    } else if let Some(warning) = warning(params) {
+        draw(frame, params.width, params.height, &[warning]);
+    }
+}
+
+fn warning(params : &MandleParams) -> Option<String> {
+    params
+        .exhausted_precision()
+        .map(|name| format!("WARN {} has run out of precision", name))
+}
+
+pub fn lines(params : &MandleParams, render_time : Duration, samples_per_pixel : f64) -> Vec<String> {
+    let zoom = params.zoom;
+    //Enough decimals to tell neighbouring pixels apart, MReal prints all 35 otherwise
+    let digits = (-zoom.log10()).ceil().max(0.0) as usize + EXTRA_DIGITS;
+    let mut lines = vec![
+        format!("X    {:.*}", digits, params.x),
+        format!("Y    {:.*}", digits, params.y),
+        format!("ZOOM {:.3e}x", 1.0 / zoom),
+        format!(
+            "ITER {}{}",
+            params.max_iterations(),
+            if params.auto_iterations { " auto" } else { "" }
+        ),
+        format!("TIME {} ms", render_time.as_millis()),
+        format!("CALC {}", calculation(params)),
+    ];
+    if params.supersample > 1 || params.temporal {
+        let mut modes = Vec::new();
+        if params.supersample > 1 {
+            let adaptive = if params.supersample_all { "" } else { " adaptive" };
+            modes.push(format!("{0}x{0}{1}", params.supersample, adaptive));
+        }
+        if params.temporal {
+            modes.push("temporal".to_string());
+        }
+        lines.push(format!("AA   {} {:.2} spp", modes.join(" + "), samples_per_pixel));
+    }
+    lines.extend(warning(params));
+    lines
+}
+
+//Backend and number type the escape time grid is computed with
+pub fn calculation(params : &MandleParams) -> String {
+    match (params.render_backend(), params.precision()) {
+        (Backend::Gpu, _) if params.zoom >= GPU_MIN_ZOOM => "gpu f32".to_string(),
+        #[cfg(feature = "rug")]
+        (Backend::Perturbation, Precision::Arbitrary) => {
+            format!("perturbation mpfr {}", mandelbrot_core::deep::precision(params.zoom))
+        }
+        (Backend::Perturbation, _) => "perturbation f64".to_string(),
+        (_, Precision::Double) if mandelbrot_core::simd::applies(params) => "cpu f64 simd".to_string(),
+        (_, Precision::Double) => "cpu f64".to_string(),
+        (_, Precision::DoubleDouble) => "cpu double-double".to_string(),
+        _ => "cpu fixed 128".to_string(),
+    }
+}
+
+//Top left of the frame, on a darkened box so it stays readable over bright areas.
+//Frames left transparent for the sketch are taken as premultiplied over it,
+//so the box darkens that too
+pub fn draw(frame : &mut [u8], width : usize, height : usize, lines : &[String]) {
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    let box_width = (columns * 8 * SCALE + 2 * MARGIN).min(width);
+    let box_height = (lines.len() * LINE_HEIGHT + 2 * MARGIN).min(height);
+    for y in 0..box_height {
+        for x in 0..box_width {
+            let idx = (y * width + x) * 4;
+            for channel in &mut frame[idx..idx + 3] {
+                *channel /= 3;
+            }
+            frame[idx + 3] = 0xff - (0xff - frame[idx + 3]) / 3;
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        for (column, glyph) in line.chars().enumerate() {
+            let Some(bitmap) = BASIC_FONTS.get(glyph) else {
+                continue;
+            };
+            let left = MARGIN + column * 8 * SCALE;
+            let top = MARGIN + row * LINE_HEIGHT;
+            for (gy, bits) in bitmap.iter().enumerate() {
+                for gx in 0..8 {
+                    //Bit 0 is the leftmost pixel
+                    if bits & (1 << gx) == 0 {
+                        continue;
+                    }
+                    for sy in 0..SCALE {
+                        for sx in 0..SCALE {
+                            let x = left + gx * SCALE + sx;
+                            let y = top + gy * SCALE + sy;
+                            if x < width && y < height {
+                                let idx = (y * width + x) * 4;
+                                frame[idx..idx + 4].copy_from_slice(&[0xff; 4]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//Keyframes for zoom videos, captured in the window with Insert and rendered
+//along a path between them by the animate subcommand. Kept in order in a
+//TOML file between sessions
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{MandleParams, MIN_ZOOM};
+
+use crate::view::{ViewError, parse_real};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keyframe {
+    pub x : String,
+    pub y : String,
+    //Height of the view in the complex plane rather than the pixel spacing,
+    //so the keyframe frames the same area at any resolution
+    pub span : String,
+    pub palette : String,
+    #[serde(default)]
+    pub palette_offset : f64,
+}
+
+impl Keyframe {
+
+    pub fn from_params(params : &MandleParams, palettes : &[Palette]) -> Keyframe {
+        Keyframe {
+            x: params.x.to_string(),
+            y: params.y.to_string(),
+            span: format!("{:e}", params.zoom * params.height as f64),
+            palette: palettes[params.palette].name.clone(),
+            palette_offset: params.palette_offset,
+        }
+    }
+
+    //Moves params to the keyframe, fitted to their height.
+    //Nothing is changed if any field is invalid
+    pub fn apply(&self, params : &mut MandleParams, palettes : &[Palette]) -> Result<(), ViewError> {
+        let x = parse_real("x", &self.x)?;
+        let y = parse_real("y", &self.y)?;
+        let span : f64 = parse_real("span", &self.span)?;
+        if !(span.is_finite() && span > 0.0) {
+            return Err(ViewError::Invalid(format!("span {} is not positive", self.span)));
+        }
+        let palette = palettes.iter()
+            .position(|palette| palette.name == self.palette)
+            .ok_or_else(|| ViewError::Invalid(format!("no palette named {}", self.palette)))?;
+        if !self.palette_offset.is_finite() {
+            return Err(ViewError::Invalid(format!("palette offset {} is not a number", self.palette_offset)));
+        }
+        params.x = x;
+        params.y = y;
+        params.zoom = (span / params.height as f64).max(MIN_ZOOM);
+        params.palette = palette;
+        params.palette_offset = self.palette_offset.rem_euclid(1.0);
+        Ok(())
+    }
+}
+
+//An array of tables, [[keyframe]], in the order they were captured
+#[derive(Serialize, Deserialize, Default)]
+struct KeyframeFile {
+    #[serde(default)]
+    keyframe : Vec<Keyframe>,
+}
+
+pub struct Keyframes {
+    path : PathBuf,
+    file : KeyframeFile,
+}
+
+impl Keyframes {
+
+    //Starts empty when the file doesn't exist yet
+    pub fn load(path : &Path) -> Result<Keyframes, ViewError> {
+        let file = if path.exists() {
+            toml::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            KeyframeFile::default()
+        };
+        Ok(Keyframes {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn empty(path : &Path) -> Keyframes {
+        Keyframes {
+            path: path.to_path_buf(),
+            file: KeyframeFile::default(),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.file.keyframe
+    }
+
+    //Adds the keyframe after the others and writes them all back to the file
+    pub fn push(&mut self, keyframe : Keyframe) -> Result<(), ViewError> {
+        self.file.keyframe.push(keyframe);
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> Result<(), ViewError> {
+        self.file.keyframe.clear();
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), ViewError> {
+        std::fs::write(&self.path, toml::to_string(&self.file)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//Opening Kalles Fraktaler .kfr location files, so deep zooms shared in that
+//format can be looked at here. A .kfr is "Key: value" lines, of which only
+//the location is read:
+//
+//  Re: -1.7490441304234213361800664105378
+//  Im: 0.0000000000011664302202482098561
+//  Zoom: 4.2E20
+//  Iterations: 12000
+//
+//Re and Im are decimal strings as long as the zoom needs, parsed into the
+//view coordinates at whatever precision this build has
+
+use std::path::Path;
+
+use mandelbrot_core::{formula, Fractal, MandleParams, MIN_ZOOM};
+
+use crate::view::{ViewError, parse_real};
+
+//Height of the default view Kalles Fraktaler's zoom is relative to, it
+//shows -2..2 at zoom 1
+const KFR_HEIGHT: f64 = 4.0;
+
+pub struct Location {
+    pub x : String,
+    pub y : String,
+    pub zoom : String,
+    pub iterations : u32,
+}
+
+impl Location {
+
+    pub fn load(path : &Path) -> Result<Location, ViewError> {
+        Location::parse(&path.display().to_string(), &std::fs::read_to_string(path)?)
+    }
+
+    //The location in the text of a .kfr, errors naming it source
+    fn parse(source : &str, text : &str) -> Result<Location, ViewError> {
+        let field = |key : &str| {
+            text.lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.trim().to_string())
+        };
+        let required = |key : &str| field(key).ok_or_else(|| ViewError::Invalid(format!("no {} in {}", key, source)));
+        //Files often leave these out, those are the mandlebrot set
+        if let Some(kind) = field("FractalType").filter(|kind| kind != "0") {
+            return Err(ViewError::Invalid(format!("fractal type {} isn't supported, only the mandlebrot set (0)", kind)));
+        }
+        if let Some(power) = field("Power").filter(|power| power != "2") {
+            return Err(ViewError::Invalid(format!("power {} isn't supported, only 2", power)));
+        }
+        let iterations = required("Iterations")?;
+        Ok(Location {
+            x: required("Re")?,
+            y: required("Im")?,
+            zoom: required("Zoom")?,
+            iterations: iterations.parse()
+                .map_err(|_| ViewError::Invalid(format!("iterations {} is not a number", iterations)))?,
+        })
+    }
+
+    //Shows the location at the height of params, on the mandlebrot set.
+    //Nothing is changed if any field is invalid
+    pub fn apply(&self, params : &mut MandleParams) -> Result<(), ViewError> {
+        let x = parse_real("x", &self.x)?;
+        let y = parse_real("y", &self.y)?;
+        let zoom = self.zoom.parse::<f64>()
+            .map_err(|err| ViewError::Invalid(format!("zoom {} {}", self.zoom, err)))?;
+        if zoom.is_nan() || zoom <= 0.0 {
+            return Err(ViewError::Invalid(format!("zoom {} is not positive", self.zoom)));
+        }
+        let spacing = KFR_HEIGHT / (zoom * params.height as f64);
+        if spacing < MIN_ZOOM {
+            return Err(ViewError::Invalid(format!("zoom {} is deeper than this build zooms to", self.zoom)));
+        }
+        if self.iterations == 0 {
+            return Err(ViewError::Invalid("iterations must be positive".to_string()));
+        }
+        params.x = x;
+        params.y = y;
+        params.zoom = spacing;
+        params.iterations = self.iterations;
+        params.auto_iterations = false;
+        params.fractal = Fractal::Mandlebrot;
+        params.formula = formula::find("Mandlebrot").expect("the mandlebrot formula exists");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mandelbrot_core::Coord;
+
+    //The start of a file Kalles Fraktaler saved, with its Windows line ends
+    const SAVED: &str = "Re: -1.7490441304234213361800664105378123456789012345678901\r\n\
+        Im: 0.0000000000011664302202482098561987654321098765432109\r\n\
+        Zoom: 4.2E20\r\n\
+        Iterations: 12000\r\n\
+        IterDiv: 0.010000\r\n\
+        SmoothMethod: 0\r\n\
+        ColorMethod: 7\r\n\
+        Colors: 255,255,255,128,0,64,\r\n\
+        InteriorColor: 0,0,0,\r\n\
+        Power: 2\r\n\
+        FractalType: 0\r\n\
+        Slopes: 1\r\n";
+
+    fn with(field : &str) -> String {
+        format!("{}{}\r\n", SAVED, field).replace("Power: 2\r\nFractalType: 0\r\n", "")
+    }
+
+    #[test]
+    fn saved_files_load() {
+        let location = Location::parse("saved.kfr", SAVED).unwrap();
+        assert_eq!(location.x, "-1.7490441304234213361800664105378123456789012345678901");
+        assert_eq!(location.y, "0.0000000000011664302202482098561987654321098765432109");
+        assert_eq!((location.zoom.as_str(), location.iterations), ("4.2E20", 12000));
+    }
+
+    #[test]
+    fn magnification_becomes_pixel_spacing() {
+        let location = Location::parse("saved.kfr", SAVED).unwrap();
+        let mut params = MandleParams::new(800, 600);
+        params.auto_iterations = true;
+        location.apply(&mut params).unwrap();
+        //The view is 4 / 4.2e20 high whatever its size
+        assert!((params.zoom * 600.0 / (KFR_HEIGHT / 4.2e20) - 1.0).abs() < 1e-12);
+        assert_eq!(params.iterations, 12000);
+        assert!(!params.auto_iterations);
+        assert!(params.fractal == Fractal::Mandlebrot);
+        //The digits past f64 are kept
+        let rounded : Coord = "-1.7490441304234213361800664105378".parse().unwrap();
+        let rest = params.x.minus(rounded);
+        assert!((rest / -1.234_567_89e-32 - 1.0).abs() < 1e-2, "{}", rest);
+    }
+
+    #[test]
+    fn bad_locations_are_refused() {
+        let mut params = MandleParams::new(800, 600);
+        for zoom in ["0", "-1", "NaN", "1E400", "lots"] {
+            let location = Location::parse("saved.kfr", &SAVED.replace("4.2E20", zoom)).unwrap();
+            assert!(location.apply(&mut params).is_err(), "zoom {}", zoom);
+        }
+        let location = Location::parse("saved.kfr", &SAVED.replace("Iterations: 12000", "Iterations: 0")).unwrap();
+        assert!(location.apply(&mut params).is_err());
+        assert!(params == MandleParams::new(800, 600));
+    }
+
+    #[test]
+    fn other_fractals_are_refused() {
+        assert!(Location::parse("saved.kfr", &with("FractalType: 1")).is_err());
+        assert!(Location::parse("saved.kfr", &with("Power: 3")).is_err());
+        //Left out they're the mandlebrot set
+        assert!(Location::parse("saved.kfr", &with("Slopes: 1")).is_ok());
+        for key in ["Re", "Im", "Zoom", "Iterations"] {
+            let text : String = SAVED.lines().filter(|line| !line.starts_with(key)).collect::<Vec<_>>().join("\n");
+            let err = Location::parse("saved.kfr", &text).err().map(|err| err.to_string());
+            assert_eq!(err, Some(format!("no {} in saved.kfr", key)));
+        }
+        assert!(Location::parse("saved.kfr", &SAVED.replace("12000", "many")).is_err());
+    }
+}
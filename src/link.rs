@@ -0,0 +1,230 @@
+//Locations as one line links to share, the bookmark fields and the palette:
+//
+//  mandel://x=-0.743643887&y=0.131825904&z=1e-7&i=1000&palette=Ultra%20Fractal
+//
+//The palette is optional. Values are percent encoded like a URL query, the
+//coordinates keep every digit
+
+use std::fmt;
+use std::str::FromStr;
+
+use mandelbrot_core::MandleParams;
+use mandelbrot_core::palette::Palette;
+
+use crate::bookmarks::Bookmark;
+use crate::view::ViewError;
+
+pub const SCHEME: &str = "mandel://";
+
+#[derive(Clone, Debug)]
+pub struct Link {
+    pub location : Bookmark,
+    pub palette : Option<String>,
+}
+
+impl Link {
+
+    //With the iterations params renders with, see Bookmark::from_params
+    pub fn from_params(params : &MandleParams, palettes : &[Palette]) -> Link {
+        Link {
+            location: Bookmark::from_params(params),
+            palette: Some(palettes[params.palette].name.clone()),
+        }
+    }
+
+    //Nothing is changed if any field is invalid or the palette isn't loaded
+    pub fn apply(&self, params : &mut MandleParams, palettes : &[Palette]) -> Result<(), ViewError> {
+        let palette = match &self.palette {
+            Some(name) => palettes.iter()
+                .position(|palette| palette.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| ViewError::Invalid(format!("no palette named {}", name)))?,
+            None => params.palette,
+        };
+        self.location.apply(params)?;
+        params.palette = palette;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, fmt : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = &self.location;
+        write!(
+            fmt, "{}x={}&y={}&z={}&i={}",
+            SCHEME, encode(&location.x), encode(&location.y), encode(&location.zoom), location.iterations
+        )?;
+        if let Some(palette) = &self.palette {
+            write!(fmt, "&palette={}", encode(palette))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Link {
+    type Err = ViewError;
+
+    fn from_str(text : &str) -> Result<Link, ViewError> {
+        let query = text.trim().strip_prefix(SCHEME)
+            .ok_or_else(|| ViewError::Invalid(format!("{} doesn't start with {}", text.trim(), SCHEME)))?;
+        let fields = query.split('&')
+            .filter_map(|field| field.split_once('='))
+            .map(|(key, value)| Ok((key, decode(value)?)))
+            .collect::<Result<Vec<(&str, String)>, ViewError>>()?;
+        let field = |key : &str| fields.iter().find(|(name, _)| *name == key).map(|(_, value)| value.clone());
+        let required = |key : &str| field(key).ok_or_else(|| ViewError::Invalid(format!("no {}= in {}", key, text.trim())));
+        let iterations = required("i")?;
+        Ok(Link {
+            location: Bookmark {
+                x: required("x")?,
+                y: required("y")?,
+                zoom: required("z")?,
+                iterations: iterations.parse()
+                    .map_err(|_| ViewError::Invalid(format!("iterations {} is not a number", iterations)))?,
+            },
+            palette: field("palette"),
+        })
+    }
+}
+
+//Everything but letters, digits and -._~ as %XX
+fn encode(value : &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+//%XX back to bytes and + to a space, like a browser's query string
+fn decode(value : &str) -> Result<String, ViewError> {
+    let invalid = || ViewError::Invalid(format!("{} isn't percent encoded", value));
+    let mut bytes = Vec::new();
+    let mut rest = value.bytes();
+    while let Some(byte) = rest.next() {
+        bytes.push(match byte {
+            b'%' => {
+                let hex = [rest.next().ok_or_else(invalid)?, rest.next().ok_or_else(invalid)?];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                u8::from_str_radix(hex, 16).map_err(|_| invalid())?
+            }
+            b'+' => b' ',
+            byte => byte,
+        });
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mandelbrot_core::palette::builtin_palettes;
+
+    fn deep_params() -> MandleParams {
+        let mut params = MandleParams::new(64, 64);
+        params.x = "-1.74995768370609350360221450607069970727110579726252077930242837820286008082972804887218672784431700831100544507655659531379747541999999995".parse().unwrap();
+        params.y = "0.00000000000000000278793706563379402178294753790944364927085054500163081379043930650189386849765202169477470552201325772332454726999999995".parse().unwrap();
+        params.zoom = 3.5e-31;
+        params.iterations = 40000;
+        params.palette = 1;
+        params
+    }
+
+    #[test]
+    fn round_trips() {
+        let palettes = builtin_palettes();
+        let params = deep_params();
+        let text = Link::from_params(&params, &palettes).to_string();
+        assert!(text.starts_with(SCHEME));
+
+        let mut opened = MandleParams::new(64, 64);
+        text.parse::<Link>().unwrap().apply(&mut opened, &palettes).unwrap();
+        assert_eq!(opened.x, params.x);
+        assert_eq!(opened.y, params.y);
+        assert_eq!(opened.zoom, params.zoom);
+        assert_eq!(opened.iterations, params.iterations);
+        assert_eq!(opened.palette, params.palette);
+    }
+
+    #[test]
+    fn keeps_auto_iterations_as_rendered() {
+        let palettes = builtin_palettes();
+        let mut params = deep_params();
+        params.auto_iterations = true;
+        let text = Link::from_params(&params, &palettes).to_string();
+        assert!(text.contains(&format!("&i={}&", params.max_iterations())), "{}", text);
+        assert_ne!(params.max_iterations(), params.iterations);
+
+        //Opened anywhere it renders with those, not its own auto limit
+        let mut opened = MandleParams::new(64, 64);
+        opened.auto_iterations = true;
+        opened.iteration_scale = params.iteration_scale * 2.0;
+        text.parse::<Link>().unwrap().apply(&mut opened, &palettes).unwrap();
+        assert!(!opened.auto_iterations);
+        assert_eq!(opened.max_iterations(), params.max_iterations());
+        assert_eq!(opened.zoom, params.zoom);
+    }
+
+    #[test]
+    fn encodes_palette_names() {
+        let name = "Sunset & sea+100% ü";
+        let link = Link {
+            location: Bookmark::from_params(&deep_params()),
+            palette: Some(name.to_string()),
+        };
+        let text = link.to_string();
+        assert!(!text.contains(' ') && !text.contains("&sea") && !text.contains('ü'));
+        assert_eq!(text.parse::<Link>().unwrap().palette.as_deref(), Some(name));
+        assert_eq!(decode("Ultra+Fractal").unwrap(), "Ultra Fractal");
+    }
+
+    #[test]
+    fn palette_is_optional() {
+        let link : Link = "mandel://i=500&z=0.01&y=0.1&x=-0.75".parse().unwrap();
+        assert_eq!(link.palette, None);
+        let mut params = MandleParams::new(64, 64);
+        params.palette = 2;
+        link.apply(&mut params, &builtin_palettes()).unwrap();
+        assert_eq!(params.palette, 2);
+        assert_eq!(params.iterations, 500);
+    }
+
+    #[test]
+    fn rejects_malformed_links() {
+        for text in [
+            "",
+            "x=-0.75&y=0.1&z=0.01&i=500",
+            "http://x=-0.75&y=0.1&z=0.01&i=500",
+            "mandel://y=0.1&z=0.01&i=500",
+            "mandel://x=-0.75&y=0.1&i=500",
+            "mandel://x=-0.75&y=0.1&z=0.01",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=lots",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=-5",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=500&palette=Ultra%2",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=500&palette=Ultra%G0",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=500&palette=%FF",
+        ] {
+            assert!(text.parse::<Link>().is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn invalid_fields_change_nothing() {
+        let palettes = builtin_palettes();
+        for text in [
+            "mandel://x=left&y=0.1&z=0.01&i=500",
+            "mandel://x=-0.75&y=0.1&z=-1&i=500",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=0",
+            "mandel://x=-0.75&y=0.1&z=0.01&i=500&palette=No%20such%20palette",
+        ] {
+            let mut params = deep_params();
+            let link : Link = text.parse().unwrap();
+            assert!(link.apply(&mut params, &palettes).is_err(), "{:?} applied", text);
+            assert!(params == deep_params(), "{:?} changed the view", text);
+        }
+    }
+}
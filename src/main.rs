@@ -1,279 +1,1753 @@
-use pixels::{Error, Pixels, SurfaceTexture};
+use pixels::{Error, Pixels, SurfaceTexture, wgpu::SurfaceError};
 use winit::{
-    dpi::LogicalSize,
-    event::{Event, VirtualKeyCode},
+    dpi::{LogicalSize, PhysicalSize},
+    event::{Event, VirtualKeyCode, WindowEvent},
     event_loop::{EventLoop, ControlFlow},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
+use clap::{CommandFactory, FromArgMatches};
+use clap::parser::ValueSource;
 use std::clone::Clone;
+use std::path::Path;
 use std::thread;
-use std::sync::RwLock;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use fixed::FixedI128;
-use fixed::types::extra::U117;
+mod animation;
+mod autopilot;
+mod batch;
+mod bookmarks;
+mod cli;
+mod clipboard;
+mod config;
+mod distribute;
+mod gif_loop;
+mod gpu;
+mod gui;
+mod history;
+mod hud;
+mod keyframes;
+mod kfr;
+mod link;
+mod pipe;
+mod preview;
+mod recovery;
+mod remote;
+mod requests;
+mod serve;
+mod session;
+mod sketch;
+mod terminal;
+mod tile_cache;
+mod transition;
+mod view;
 
-type MReal = FixedI128<U117>;
-//This type allows a max of 1024/-1024.
-//Width or heigh will be the value that decdes this range
+use mandelbrot_core::palette::{self, Palette};
+use mandelbrot_core::script::Script;
+use mandelbrot_core::{
+    buddhabrot, colorizer, formula, mesh, offline, perturbation, raw, shading, simd, supersample, temporal, tiles,
+    Backend, CancelToken, ColorMode, Coord, Fractal, Grid, MandleParams, MIN_BAILOUT, MIN_ZOOM,
+    RefinePass, RenderMode, Sample, calc_mandlebrot_set, orbit, render_mandlebrot, render_mandlebrot_region,
+};
+
+use requests::{Finished, Frame, Requests, Settings};
+use tile_cache::TileCache;
+use tiles::Tile;
+use transition::Transition;
 
+const TITLE: &str = "Mandlebrot set";
+
+//Initial grid size, the grid follows the physical window size after that
 const WIDTH: usize = 640;
 const HEIGHT: usize = 360;
 
-struct MandleParams {
-    x : MReal,
-    y : MReal,
-    zoom : MReal,
-    iterations : u32,
+//Image size for the render subcommand when --width/--height aren't given
+const RENDER_WIDTH: usize = 1920;
+const RENDER_HEIGHT: usize = 1080;
+
+//Grid pixels moved per tick while a pan key is held, and the tick length
+const PAN_STEP: f64 = 4.0;
+const PAN_INTERVAL: Duration = Duration::from_millis(16);
+
+//Tiles finished within this long of the last frame wait to be shown with
+//the next ones, rather than every tile holding up the render to present
+const TILE_PRESENT_INTERVAL: Duration = Duration::from_millis(16);
+
+//Zoom boxes narrower and shorter than this many grid pixels are taken as a
+//stray click rather than a box
+const MIN_ZOOM_BOX: f64 = 4.0;
+
+//Most points of an orbit drawn while O is held, past a few hundred they
+//only pile up on whatever the orbit settled into
+const ORBIT_POINTS: usize = 1000;
+
+//Iterations added or removed by +/-, ten times as many with shift
+const ITERATION_STEP: u32 = 50;
+
+//Pixels scales the buffer by the largest integer that fits and centers it.
+//Normally 1 since the grid follows the window, but the grid may lag a resize
+fn window_scale(params : &MandleParams, window_size : PhysicalSize<u32>) -> f64 {
+    (window_size.width as f64 / params.width as f64)
+        .min(window_size.height as f64 / params.height as f64)
+        .floor()
+        .max(1.0)
 }
 
-impl std::fmt::Display for MandleParams{
-    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>{
-        write!(
-            fmt,"MandleParams[X: {}, Y: {}, Zoom:{}, Iterations:{}]",
-            self.x,
-            self.y,
-            self.zoom,
-            self.iterations
-        )
-    }
+//Maps a physical window position onto (fractional) grid coordinates
+fn window_pos_to_grid(params : &MandleParams, window_size : PhysicalSize<u32>, pos : (f32, f32)) -> (f64, f64) {
+    let scale = window_scale(params, window_size);
+    let offset_x = (window_size.width as f64 - params.width as f64 * scale) / 2.0;
+    let offset_y = (window_size.height as f64 - params.height as f64 * scale) / 2.0;
+    (
+        (pos.0 as f64 - offset_x) / scale,
+        (pos.1 as f64 - offset_y) / scale,
+    )
 }
 
-struct Grid<T: Clone> {
-    rows : usize,
-    cols : usize,
-    contents: Box<[T]>,
+//The other way, from grid coordinates to a physical window position
+fn grid_to_window_pos(params : &MandleParams, window_size : PhysicalSize<u32>, (px, py) : (f64, f64)) -> (f32, f32) {
+    let scale = window_scale(params, window_size);
+    let offset_x = (window_size.width as f64 - params.width as f64 * scale) / 2.0;
+    let offset_y = (window_size.height as f64 - params.height as f64 * scale) / 2.0;
+    ((px * scale + offset_x) as f32, (py * scale + offset_y) as f32)
 }
 
-impl<T : Clone> Grid<T> {
+//The orbit of the point under the cursor at pos, in physical window pixels
+fn orbit_overlay(params : &MandleParams, window_size : PhysicalSize<u32>, pos : (f32, f32)) -> Vec<(f32, f32)> {
+    let (px, py) = window_pos_to_grid(params, window_size, pos);
+    let (half_width, half_height) = (params.width as f64 / 2.0, params.height as f64 / 2.0);
+    orbit(params, px, py, ORBIT_POINTS)
+        .into_iter()
+        .map(|(dx, dy)| grid_to_window_pos(params, window_size, (half_width + dx, half_height + dy)))
+        .collect()
+}
 
-    fn new(rows: usize, cols: usize, default: T) -> Grid<T> {
-        Grid::<T>{
-            rows: rows,
-            cols: cols,
-            contents: vec![default; rows * cols]
-                .into_boxed_slice(),     
+//Window title while inspecting, the point under the cursor at pos in the
+//grid and what iterating its pixel found once the view is finished
+fn inspector_title(params : &MandleParams, finished : Option<&Finished>, pos : (f64, f64)) -> String {
+    let (x, y) = params.pixel_to_complex(pos.0, pos.1);
+    let point = format!("x {} y {}", x, y);
+    if pos.0 < 0.0 || pos.1 < 0.0 || pos.0 >= params.width as f64 || pos.1 >= params.height as f64 {
+        return point;
+    }
+    let Some(finished) = finished.filter(|finished| params.resolved().recolors(&finished.params)) else {
+        return format!("{}  rendering", point);
+    };
+    let sample = finished.grid[(pos.0 as usize, pos.1 as usize)];
+    let Some(stopped) = sample.stopped else {
+        return format!("{}  interior", point);
+    };
+    let smooth = sample.value(&MandleParams { color_mode: ColorMode::Smooth, ..finished.params.clone() });
+    match finished.params.color_mode {
+        ColorMode::Distance if !sample.distance.is_nan() => {
+            format!("{}  iteration {}  smooth {:.6}  distance {:.3} px", point, stopped, smooth, sample.distance)
         }
+        _ => format!("{}  iteration {}  smooth {:.6}", point, stopped, smooth),
     }
+}
 
-    fn get(&mut self, x : usize, y : usize) -> &mut T{
-        if x >= self.rows || y >= self.cols {
-            panic!(
-                "x:{} y:{} out of bounds for grid[{},{}]",
-                x,
-                y,
-                self.rows,
-                self.cols
-            );
-        } else {
-            return &mut self.contents[y * self.rows + x];
+//Redraws the frame rendered for last as it would look centred and zoomed
+//like params. Each pixel takes the old one over the same point, so panning
+//shifts the frame and zooming scales it about the centre of the zoom.
+//Pixels the old frame didn't cover are cleared to black
+fn reproject_frame(frame : &mut [u8], width : usize, height : usize, last : &MandleParams, params : &MandleParams){
+    let old = frame.to_vec();
+    let scale = params.zoom / last.zoom;
+    //Old pixels from the old centre to the new one
+    let moved_x = params.x.minus(last.x) / last.zoom;
+    let moved_y = params.y.minus(last.y) / last.zoom;
+    let source = |pos : usize, size : usize, moved : f64| {
+        let half = size as f64 / 2.0;
+        let src = ((pos as f64 + 0.5 - half) * scale + moved + half).floor();
+        (src >= 0.0 && src < size as f64).then_some(src as usize)
+    };
+    for y in 0..height{
+        let src_y = source(y, height, moved_y);
+        for x in 0..width{
+            let dst = (x + y * width) * 4;
+            match (source(x, width, moved_x), src_y) {
+                (Some(src_x), Some(src_y)) => {
+                    let src = (src_x + src_y * width) * 4;
+                    frame[dst..dst + 4].copy_from_slice(&old[src..src + 4]);
+                }
+                _ => frame[dst..dst + 4].copy_from_slice(&[0, 0, 0, 0xff]),
+            }
         }
     }
+}
 
-    fn get_val(&self, x : usize, y : usize) -> T {
-        if x >= self.rows || y >= self.cols {
-            panic!(
-                "x:{} y:{} out of bounds for grid[{},{}]",
-                x,
-                y,
-                self.rows,
-                self.cols
-            );
-        } else {
-            return (self.contents[y * self.rows + x]).clone();
-        }
+//The parts of a grid shifted by (dx, dy) left without values. Rows across
+//the whole width, then columns down the rest
+fn uncovered(width : usize, height : usize, dx : isize, dy : isize) -> Vec<Tile> {
+    let (rows, cols) = (dy.unsigned_abs(), dx.unsigned_abs());
+    let top = if dy > 0 { height - rows } else { 0 };
+    let left = if dx > 0 { width - cols } else { 0 };
+    //Rows the strip of rows leaves for the strip of columns
+    let rest = if dy > 0 { 0 } else { rows };
+    [
+        Tile { left: 0, top, width, height: rows },
+        Tile { left, top: rest, width: cols, height: height - rows },
+    ]
+        .into_iter()
+        .filter(|tile| tile.width > 0 && tile.height > 0)
+        .collect()
+}
+
+//Computes pass of the part of params' grid under tile as a view of its own,
+//starting from the samples already there, and puts the samples and any
+//orbits kept back in their place. The tile's samples, None if cancelled
+fn compute_tile(
+    grid : &mut Grid<Sample>,
+    kept : &mut Option<simd::Orbits>,
+    params : &MandleParams,
+    tile : Tile,
+    pass : RefinePass,
+    cancel : &CancelToken,
+    install : impl Fn(&mut (dyn FnMut() -> bool + Send)) -> bool
+) -> Option<Grid<Sample>> {
+    let view = tile.view(params);
+    let mut cells = Grid::new(tile.width, tile.height, Sample::INTERIOR);
+    for ((x, y), cell) in cells.iter_mut() {
+        *cell = grid[(tile.left + x, tile.top + y)];
     }
+    let mut tile_orbits = kept.as_ref().map(|_| simd::Orbits::new(params));
+    let completed = match params.render_backend() {
+        Backend::Perturbation => install(&mut || perturbation::calc_mandlebrot_set(&mut cells, &view, pass, cancel)),
+        _ => install(&mut || match &mut tile_orbits {
+            Some(tile_orbits) => simd::calc_mandlebrot_set_keeping(&mut cells, &view, pass, cancel, tile_orbits),
+            None => calc_mandlebrot_set(&mut cells, &view, pass, cancel),
+        }),
+    };
+    if !completed {
+        return None;
+    }
+    paste_tile(grid, &cells, tile);
+    if let (Some(kept), Some(tile_orbits)) = (kept, tile_orbits) {
+        kept.insert(tile_orbits, tile.left, tile.top);
+    }
+    Some(cells)
 }
 
+fn paste_tile(grid : &mut Grid<Sample>, cells : &Grid<Sample>, tile : Tile) {
+    for ((x, y), &sample) in cells.iter() {
+        grid[(tile.left + x, tile.top + y)] = sample;
+    }
+}
+
+//The window surface and the egui panel drawn over it, owned by the event loop.
+//size is what the surface and buffer were last sized to, shown the params of
+//the frame in the buffer and sketched the view last sketched in the window
+struct Screen {
+    pixels : Pixels,
+    overlay : gui::Overlay,
+    sketch : sketch::Sketch,
+    size : (u32, u32),
+    shown : Option<MandleParams>,
+    sketched : Option<MandleParams>,
+}
 
-fn calc_mandle_divergence(
-    mut a : MReal, 
-    mut b : MReal, 
-    max_iter : u32
-) -> f64 {
+impl Screen {
 
-    let z0_a : MReal = a;
-    let z0_b : MReal = b;
-    for i in 0..max_iter{
-        if a.abs() + b.abs() > 4.0 {
-           return i as f64 / max_iter as f64;
+    //A surface over the whole window showing a width x height buffer
+    fn new(window : &Window, width : u32, height : u32) -> Result<Screen, Error> {
+        let window_size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
+        let pixels = Pixels::new(width, height, surface_texture)?;
+        Ok(Screen {
+            overlay: gui::Overlay::new(
+                &pixels,
+                window_size.width,
+                window_size.height,
+                window.scale_factor() as f32
+            ),
+            sketch: sketch::Sketch::new(&pixels),
+            pixels,
+            size: (width, height),
+            shown: None,
+            sketched: None,
+        })
+    }
+
+    //Shows the frame loaded last in the window now showing view, sketched
+    //under it or in place of it where the sketch covers view
+    fn present(&mut self, view : &MandleParams, palettes : &[Palette]) -> Result<(), Error> {
+        let plan = sketch::plan(view, self.shown.as_ref());
+        if let Some((params, over)) = &plan {
+            self.sketch.prepare(&self.pixels, params, *over, &palettes[params.palette], self.size);
         }
-        //square Z[I]
-        let a_new : MReal = a * a - b * b;
-        let b_new : MReal = MReal::from_num(2.0) * a * b;
+        let sketching = plan.is_some();
+        self.sketched = plan.map(|(params, _)| params);
+        let (overlay, sketch) = (&mut self.overlay, &self.sketch);
+        self.pixels.render_with(|encoder, target, context| {
+            if sketching {
+                sketch.render(encoder, target, context.scaling_renderer.clip_rect());
+            } else {
+                context.scaling_renderer.render(encoder, target);
+            }
+            overlay.render(encoder, target, context);
+            Ok(())
+        })
+    }
 
-        //Z[I] + Z[0]
-        a = a_new + z0_a;
-        b = b_new + z0_b;
+    //Whether the window is behind on sketching view, which then needs a
+    //redraw rather than waiting on the render thread for one
+    fn sketch_behind(&self, view : &MandleParams) -> bool {
+        let view = view.resolved();
+        sketch::covers(&view) && self.sketched != Some(view)
+    }
 
+    //Copies in a frame from the render thread for the next present. The grid
+    //follows the window, a frame of a new size means it was resized
+    fn load(&mut self, frame : &Frame) {
+        let size = (frame.params.width as u32, frame.params.height as u32);
+        if self.size != size {
+            if let Err(err) = self.pixels.resize_surface(size.0, size.1) {
+                println!("Error resizing surface {}", err);
+            }
+            if let Err(err) = self.pixels.resize_buffer(size.0, size.1) {
+                println!("Error resizing buffer {}", err);
+            }
+            self.overlay.resize(size.0, size.1);
+            self.sketch.resize(&self.pixels);
+            self.size = size;
+        }
+        self.pixels.frame_mut().copy_from_slice(&frame.pixels);
+        self.shown = Some(frame.params.clone());
+    }
 
+    //Presents, building the surface again if it was lost. pixels reconfigures
+    //and retries once itself, a surface still lost after that is gone for good.
+    //The new overlay starts without egui's textures, so the panel is reset
+    fn present_or_recover(&mut self, window : &Window, gui : &mut gui::Gui, view : &MandleParams, palettes : &[Palette]) -> Result<(), Error> {
+        match self.present(view, palettes) {
+            Err(err) if surface_lost(&err) => {
+                //Minimised windows have nothing to present to until restored
+                let window_size = window.inner_size();
+                if window_size.width == 0 || window_size.height == 0 {
+                    return Ok(());
+                }
+                println!("Surface lost, creating it again");
+                let mut screen = Screen::new(window, self.size.0, self.size.1)?;
+                screen.pixels.frame_mut().copy_from_slice(self.pixels.frame());
+                screen.shown = self.shown.take();
+                *self = screen;
+                gui.reset();
+                window.request_redraw();
+                self.present(view, palettes)
+            }
+            result => result,
+        }
     }
-    return 0.0;
 }
 
+//Lost and outdated surfaces can be created again from the window, a timeout
+//only drops the frame. Anything else leaves nothing to present with
+fn surface_lost(err : &Error) -> bool {
+    matches!(err, Error::Surface(SurfaceError::Lost | SurfaceError::Outdated))
+}
+
+fn fatal(err : &Error) -> bool {
+    !surface_lost(err) && !matches!(err, Error::Surface(SurfaceError::Timeout))
+}
+
+//Render loop for the render thread, drawing frames for the requests from
+//the event loop until it goes away
+fn update(requests : &Requests, palettes : &[Palette], cache : &mut TileCache, rendered : &remote::Subscribers){
+    let mut grid = Grid::new(0, 0, Sample::INTERIOR);
+    //The frame last handed to the event loop, drawn over in place
+    let mut frame = Vec::new();
+    let mut gpu : Option<gpu::GpuRenderer> = None;
+    //Only tried once, there is no point looking for an adapter every resize
+    let mut gpu_checked = false;
+
+    //Params the frame currently on screen was computed with
+    let mut shown : Option<MandleParams> = None;
+    //Whether every pixel of the grid is computed for the shown params
+    let mut complete = false;
+    //Params every cell of the grid was computed for at full resolution, which
+    //a pan by whole pixels can keep most of
+    let mut computed : Option<MandleParams> = None;
+    //Orbits of the pixels still going at computed's iteration limit, when
+    //the simd kernel computed the grid
+    let mut orbits : Option<simd::Orbits> = None;
+    //How long the last complete frame took, shown in the hud
+    let mut render_time = Duration::ZERO;
+    //Antialiasing samples for the complete grid, if any were taken
+    let mut supersamples : Option<supersample::Supersamples> = None;
+    //Pool the cpu backends run on and its thread count, rebuilt when that changes
+    let mut pool : Option<(usize, rayon::ThreadPool)> = None;
+    //Params remote subscribers were last told about
+    let mut notified : Option<MandleParams> = None;
+
+    //Nothing to do until the event loop changes something.
+    //A cancelled render has already been superseded so this returns straight away
+    'render: while let Some((params, generation)) = requests.next() {
+        let cancel = requests.cancel_token(generation);
+        let params = params.resolved();
+
+        if pool.as_ref().map(|(threads, _)| *threads) != Some(params.threads) {
+            match rayon::ThreadPoolBuilder::new().num_threads(params.threads).build() {
+                Ok(new_pool) => pool = Some((params.threads, new_pool)),
+                Err(err) => println!("Error configuring thread pool {}", err),
+            }
+        }
+        let install = |op : &mut (dyn FnMut() -> bool + Send)| match &pool {
+            Some((_, pool)) => pool.install(op),
+            None => op(),
+        };
+
+        //The window was resized, everything sized to the grid is rebuilt
+        if grid.cols() != params.width || grid.rows() != params.height {
+            grid = Grid::new(params.width, params.height, Sample::INTERIOR);
+            frame = vec![0u8; params.width * params.height * 4];
+            computed = None;
+            orbits = None;
+            let width = params.width as u32;
+            let height = params.height as u32;
+            match &mut gpu {
+                Some(gpu) => gpu.resize(width, height),
+                None if !gpu_checked => {
+                    gpu = gpu::GpuRenderer::new(width, height);
+                    gpu_checked = true;
+                    if gpu.is_none() {
+                        println!("Gpu backend unavailable, no adapter supports compute shaders");
+                    }
+                }
+                None => {}
+            }
+            shown = None;
+        }
+        let present = |frame : &[u8]| requests.present(frame, &params);
+
+        //Sampled batch by batch until there are enough samples or the params change,
+        //the escape time grid and frame aren't used
+        if params.mode != RenderMode::EscapeTime {
+            shown = None;
+            complete = false;
+            let started = Instant::now();
+            let mut buddhabrot = buddhabrot::Buddhabrot::new(&params);
+            while !buddhabrot.done() {
+                //Requests that only recolour don't cancel, the hud still needs redrawing
+                if !install(&mut || buddhabrot.sample(&params, &cancel)) || requests.pending() {
+                    continue 'render;
+                }
+                buddhabrot.render(&mut frame);
+                hud::overlay(&mut frame, &params, started.elapsed(), 1.0);
+                if !present(&frame) {
+                    break 'render;
+                }
+            }
+            continue;
+        }
+
+        //When panning or zooming, move and scale what is already on screen
+        //to the new view while the new frame is computed, so dragging and
+        //zooming don't wait on the render. The sketch does better where it
+        //covers the view
+        if let Some(last) = &shown {
+            let moved = (last.x, last.y, last.zoom) != (params.x, params.y, params.zoom);
+            if moved && last.fractal == params.fractal && last.formula == params.formula {
+                if sketch::covers(&params) {
+                    sketch::clear(&mut frame);
+                } else {
+                    reproject_frame(&mut frame, params.width, params.height, last, &params);
+                }
+                hud::overlay(&mut frame, &params, render_time, 1.0);
+                if !present(&frame) {
+                    break;
+                }
+                shown = Some(params.clone());
+                complete = false;
+            }
+        }
+
+        'compute: {
+            if complete && shown.as_ref().is_some_and(|last| params.recolors(last)) {
+                let coloring = colorizer::new(&grid, 1, &params, palettes);
+                render_mandlebrot(&grid, &mut frame, 1, &*coloring);
+                if let Some(samples) = &supersamples {
+                    samples.render(&mut frame, &*coloring);
+                }
+                shading::apply(&grid, &mut frame, 1, &params);
+                hud::overlay(
+                    &mut frame,
+                    &params,
+                    render_time,
+                    supersamples.as_ref().map_or(1.0, supersample::Supersamples::per_pixel)
+                );
+                if !present(&frame) {
+                    break 'render;
+                }
+                shown = Some(params.clone());
+                requests.finished(&params, &grid);
+                break 'compute;
+            }
+
+            let backend = params.render_backend();
+            let use_gpu = backend == Backend::Gpu
+                && gpu.is_some()
+                && params.zoom >= mandelbrot_core::GPU_MIN_ZOOM;
+
+            //Panning by whole pixels keeps the grid shifted over, only the strips
+            //uncovered along the edges are computed. The gpu is sized to the
+            //whole grid and quick enough redoing it
+            let last = computed.take();
+            let last_orbits = orbits.take();
+            let pan = last.as_ref()
+                .filter(|_| !use_gpu)
+                .and_then(|last| params.pan_from(last));
+            //Raising the iteration limit only carries on the orbits that ran out
+            let resume = last_orbits.is_some() && last.as_ref().is_some_and(|last| params.raises_iterations(last));
+
+            //The cpu fills everything in after its coarse first pass tile by
+            //tile, from the centre out. Perturbation iterates a reference orbit
+            //for each view, so it refines the whole grid pass by pass
+            let from_scratch = pan.is_none() && !resume;
+            let tiled = from_scratch && !use_gpu && backend != Backend::Perturbation;
+            let tiles = if tiled { tiles::spiral(params.width, params.height) } else { Vec::new() };
+            //A view seen again comes straight out of the tile cache, without
+            //even the coarse pass
+            let cached = tiled && tiles.iter().all(|tile| cache.contains(&tile.view(&params)));
+
+            //The gpu finishes a whole frame quicker than a coarse cpu pass
+            let passes = if !from_scratch {
+                Vec::new()
+            } else if use_gpu {
+                vec![RefinePass::FULL]
+            } else if cached {
+                Vec::new()
+            } else if tiled {
+                RefinePass::progressive()[..1].to_vec()
+            } else {
+                RefinePass::progressive().to_vec()
+            };
+
+            //Kept on from the last grid when it is reused, kept afresh when the
+            //simd kernel computes it from scratch
+            let mut kept = if passes.is_empty() {
+                last_orbits
+            } else {
+                (!use_gpu && backend != Backend::Perturbation && simd::applies(&params)).then(|| simd::Orbits::new(&params))
+            };
+
+            complete = false;
+            supersamples = None;
+            let started = Instant::now();
+            if let Some(resumed) = kept.as_mut().filter(|_| resume) {
+                //Left as they were when cancelled, so the next raise can still carry on
+                if !install(&mut || resumed.resume(&mut grid, &params, &cancel)) {
+                    computed = last;
+                    orbits = kept;
+                    continue 'render;
+                }
+                let coloring = colorizer::new(&grid, 1, &params, palettes);
+                render_mandlebrot(&grid, &mut frame, 1, &*coloring);
+                shading::apply(&grid, &mut frame, 1, &params);
+                hud::overlay(&mut frame, &params, started.elapsed(), 1.0);
+                if !present(&frame) {
+                    break 'render;
+                }
+                shown = Some(params.clone());
+            }
+            if let Some((dx, dy)) = pan {
+                grid.shift(dx, dy, Sample::INTERIOR);
+                if let Some(kept) = &mut kept {
+                    kept.shift(dx, dy, params.width, params.height);
+                }
+                for strip in uncovered(params.width, params.height, dx, dy) {
+                    if compute_tile(&mut grid, &mut kept, &params, strip, RefinePass::FULL, &cancel, install).is_none() {
+                        continue 'render;
+                    }
+                }
+                let coloring = colorizer::new(&grid, 1, &params, palettes);
+                render_mandlebrot(&grid, &mut frame, 1, &*coloring);
+                shading::apply(&grid, &mut frame, 1, &params);
+                hud::overlay(&mut frame, &params, started.elapsed(), 1.0);
+                if !present(&frame) {
+                    break 'render;
+                }
+                shown = Some(params.clone());
+            }
+            for pass in passes {
+                let completed = match (backend, &gpu) {
+                    (Backend::Gpu, Some(gpu)) if use_gpu => gpu.calc_mandlebrot_set(&mut grid, &params, &cancel),
+                    (Backend::Perturbation, _) => {
+                        install(&mut || perturbation::calc_mandlebrot_set(&mut grid, &params, pass, &cancel))
+                    }
+                    _ => install(&mut || match &mut kept {
+                        Some(kept) => simd::calc_mandlebrot_set_keeping(&mut grid, &params, pass, &cancel, kept),
+                        None => calc_mandlebrot_set(&mut grid, &params, pass, &cancel),
+                    }),
+                };
+                if !completed {
+                    continue 'render;
+                }
+
+                //Coarse passes are blockier than the sketch, which shows
+                //until the pixels are done in full
+                if pass.step > 1 && sketch::covers(&params) {
+                    sketch::clear(&mut frame);
+                } else {
+                    let coloring = colorizer::new(&grid, pass.step, &params, palettes);
+                    render_mandlebrot(&grid, &mut frame, pass.step, &*coloring);
+                    shading::apply(&grid, &mut frame, pass.step, &params);
+                }
+                hud::overlay(&mut frame, &params, started.elapsed(), 1.0);
+                if !present(&frame) {
+                    break 'render;
+                }
+                shown = Some(params.clone());
+            }
+            if tiled {
+                let coarse = RefinePass::progressive()[0];
+                //Coloured with the coarse pass's histogram until every tile is in
+                let coloring = colorizer::new(&grid, coarse.step, &params, palettes);
+                let mut presented = Instant::now();
+                for tile in tiles {
+                    let view = tile.view(&params);
+                    if let Some(cells) = cache.get(&view) {
+                        paste_tile(&mut grid, &cells, tile);
+                        //The cache has no orbits to carry on for raising the iterations
+                        kept = None;
+                    } else {
+                        let rest = RefinePass::rest_after(coarse);
+                        let Some(cells) = compute_tile(&mut grid, &mut kept, &params, tile, rest, &cancel, install) else {
+                            continue 'render;
+                        };
+                        cache.insert(&view, cells);
+                    }
+                    render_mandlebrot_region(&grid, &mut frame, 1, &*coloring, tile.columns(), tile.rows());
+                    shading::apply_region(&grid, &mut frame, 1, &params, tile.columns(), tile.rows());
+                    if presented.elapsed() >= TILE_PRESENT_INTERVAL {
+                        hud::overlay(&mut frame, &params, started.elapsed(), 1.0);
+                        if !present(&frame) {
+                            break 'render;
+                        }
+                        presented = Instant::now();
+                    }
+                }
+                let coloring = colorizer::new(&grid, 1, &params, palettes);
+                render_mandlebrot(&grid, &mut frame, 1, &*coloring);
+                shading::apply(&grid, &mut frame, 1, &params);
+                hud::overlay(&mut frame, &params, started.elapsed(), 1.0);
+                if !present(&frame) {
+                    break 'render;
+                }
+            }
+            computed = Some(params.clone());
+            orbits = kept;
+            requests.finished(&params, &grid);
+
+            //Antialiasing goes over the finished grid, the frame above stays up meanwhile
+            if params.supersample > 1 {
+                let mut samples = None;
+                install(&mut || {
+                    samples = supersample::Supersamples::compute(&grid, &params, &cancel);
+                    samples.is_some()
+                });
+                let Some(samples) = samples else {
+                    continue 'render;
+                };
+                let coloring = colorizer::new(&grid, 1, &params, palettes);
+                render_mandlebrot(&grid, &mut frame, 1, &*coloring);
+                samples.render(&mut frame, &*coloring);
+                shading::apply(&grid, &mut frame, 1, &params);
+                hud::overlay(&mut frame, &params, started.elapsed(), samples.per_pixel());
+                if !present(&frame) {
+                    break 'render;
+                }
+                supersamples = Some(samples);
+            }
+            complete = true;
+            render_time = started.elapsed();
+        }
+
+        //Remote clients hear about each view that finishes, not every step
+        //of the palette cycling through one
+        if complete && !rendered.is_empty() && !(params.cycling && notified.as_ref().is_some_and(|last| params.recolors(last))) {
+            rendered.notify(&serde_json::json!({
+                "event": "rendered",
+                "params": remote::params_json(&params, palettes),
+                "milliseconds": render_time.as_millis() as u64,
+            }));
+            notified = Some(params.clone());
+        }
 
-fn calc_mandlebrot_set(
-    grid : &mut Grid<f64>,
-    a : MReal, 
-    b: MReal, 
-    zoom_level: MReal, 
-    max_iter: u32
-    ){
-    //A is the real part of the complex number
-    //B is the coefficent to I
-
-    for x in 0..WIDTH{
-        for y in 0..HEIGHT{
-            *grid.get(x as usize,y as usize) = calc_mandle_divergence(
-                a + (MReal::from_num(x) - MReal::from_num(WIDTH as f64 / 2.0)) * zoom_level,
-                b + (MReal::from_num(y) - MReal::from_num(HEIGHT as f64 / 2.0)) * zoom_level,
-                max_iter
-            );
+        //Nothing changed since the frame was finished, keep adding a sample per pixel until MAX_FRAMES
+        if params.temporal && params.width * params.height > 0 {
+            let coloring = colorizer::new(&grid, 1, &params, palettes);
+            let mut base = vec![0u8; params.width * params.height * 4];
+            render_mandlebrot(&grid, &mut base, 1, &*coloring);
+            if let Some(samples) = &supersamples {
+                samples.render(&mut base, &*coloring);
+            }
+            let mut accumulator = temporal::Accumulator::new(&base, params.width);
+            let spp = supersamples.as_ref().map_or(1.0, supersample::Supersamples::per_pixel);
+            while accumulator.frames() < temporal::MAX_FRAMES {
+                //The palette may have moved on without cancelling, the samples so far are in the old one
+                if !install(&mut || accumulator.add_frame(&params, &*coloring, &cancel)) || requests.pending() {
+                    continue 'render;
+                }
+                accumulator.render(&mut frame, params.dither);
+                shading::apply(&grid, &mut frame, 1, &params);
+                hud::overlay(&mut frame, &params, render_time, spp + (accumulator.frames() - 1) as f64);
+                if !present(&frame) {
+                    break 'render;
+                }
+            }
         }
     }
 }
 
-//map divergence value (x) to a set of r/g/b
-fn map_color(x : f64) -> [u8; 3]{
-    //Constant is max of u24 (3 u8s)
-    let num = (x * 4294967296.0) as u32;
-    let mut arr : [u8; 3] = [0; 3];
-    
-    arr[0] = num as u8;
-    arr[1] = (num >> 8) as u8;
-    arr[2] = (num >> 16) as u8;
-    
-    return arr;
+//Sets off towards target, or goes straight there when there's nothing to
+//animate. Everything but the centre and zoom changes straight away
+fn fly_to(settings : &mut Settings, target : &MandleParams, duration : Duration) -> Option<Transition> {
+    let from = settings.snapshot();
+    let transition = Transition::new(&from, target, duration);
+    *settings.write() = match transition {
+        Some(_) => MandleParams { x: from.x, y: from.y, zoom: from.zoom, ..target.clone() },
+        None => target.clone(),
+    };
+    transition
 }
 
-fn render_mandlebrot(
-    grid : & Grid<f64>,
-    frame : & mut [u8]
-    ){
-    
-    for x in 0..WIDTH{
-        for y in 0..HEIGHT{
-            let col = map_color(grid.get_val(x,y)); 
-            // r/g/b/a
-            frame[((x + (y * WIDTH)) * 4    ) as usize] = col[0];
-            frame[((x + (y * WIDTH)) * 4 + 1) as usize] = col[1];
-            frame[((x + (y * WIDTH)) * 4 + 2) as usize] = col[2];
-            frame[((x + (y * WIDTH)) * 4 + 3) as usize] = 0xff;
-        }
+//Direction to pan from the held arrow/WASD keys, each axis is -1, 0 or 1
+fn held_pan_direction(input : &WinitInputHelper, bindings : &config::Keys) -> (f64, f64) {
+    let held = |keys : [VirtualKeyCode; 2]| keys.iter().any(|&key| bindings.held(input, key));
+    let mut x = 0.0;
+    let mut y = 0.0;
+    if held([VirtualKeyCode::Left, VirtualKeyCode::A]) { x -= 1.0; }
+    if held([VirtualKeyCode::Right, VirtualKeyCode::D]) { x += 1.0; }
+    if held([VirtualKeyCode::Up, VirtualKeyCode::W]) { y -= 1.0; }
+    if held([VirtualKeyCode::Down, VirtualKeyCode::S]) { y += 1.0; }
+    (x, y)
+}
+
+//Number of worker threads used for the mandlebrot calculation, 0 lets rayon pick one per core
+fn configure_thread_pool(threads : usize){
+    if let Err(err) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global() {
+        println!("Error configuring thread pool {}", err);
     }
-    
 }
 
-fn update(
-    settings : &RwLock<MandleParams>,
-    grid : &mut Grid<f64>,
-    pixels : &mut Pixels
-){
-    loop {
-        calc_mandlebrot_set(
-            grid,
-            settings.read().unwrap().x,
-            settings.read().unwrap().y,
-            settings.read().unwrap().zoom,
-            settings.read().unwrap().iterations
-        );
-        render_mandlebrot(&grid, pixels.frame_mut()); 
-        match pixels.render() {
-            Ok(_) => {}
-            Err(err) => {println!("Error {}", err); break;}
+//Palette by name (ignoring case) or by index
+fn find_palette(palettes : &[Palette], name : &str) -> Option<usize> {
+    if let Ok(index) = name.parse::<usize>() {
+        return (index < palettes.len()).then_some(index);
+    }
+    palettes.iter().position(|palette| palette.name.eq_ignore_ascii_case(name))
+}
+
+//Where render and animate compute their tiles, the workers when there are
+//any. Exits if none of them can be reached
+fn tile_source(workers : &[String]) -> Box<dyn offline::TileSource> {
+    if workers.is_empty() {
+        return Box::new(offline::Local);
+    }
+    match distribute::Farm::connect(workers) {
+        Ok(farm) => Box::new(farm),
+        Err(err) => {
+            println!("Error connecting to workers {}", err);
+            std::process::exit(1);
         }
     }
 }
 
 fn main() -> Result<(), Error> {
-    let settings = Arc::new(RwLock::new(MandleParams{
-        x: MReal::from_num(-0.20710786709396773),
-        y: MReal::from_num(1.12275706363259748),
-        zoom: MReal::from_num(0.01),
-        iterations: 300 
-    }));
+    //The config file fills in what isn't on the command line
+    let matches = cli::Cli::command().get_matches();
+    let mut cli = cli::Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let config_path = cli.config.clone().or_else(config::default_path);
+    let mut config = match &config_path {
+        Some(path) => config::Config::load(path).unwrap_or_else(|err| {
+            println!("Error loading config from {} {}", path.display(), err);
+            config::Config::default()
+        }),
+        None => config::Config::default(),
+    };
+    config.apply(&mut cli, &matches);
+    configure_thread_pool(cli.threads);
+    let palettes = Arc::new(palette::all_palettes(&cli.palettes, &cli.palette_dir));
 
-    
-    let mut grid: Grid<f64> 
-        = Grid::new(WIDTH, HEIGHT, 0.0);
-
-    calc_mandlebrot_set(
-        &mut grid,
-        settings.read().unwrap().x,
-        settings.read().unwrap().y,
-        settings.read().unwrap().zoom,
-        settings.read().unwrap().iterations
-    );
+    let palette = match find_palette(&palettes, &cli.palette) {
+        Some(palette) => palette,
+        None => cli::Cli::command()
+            .error(clap::error::ErrorKind::InvalidValue, format!("no palette named {}", cli.palette))
+            .exit(),
+    };
+    let default_size = match cli.command {
+        Some(cli::Command::Render { .. } | cli::Command::Batch { .. } | cli::Command::Pipe | cli::Command::Animate { .. }) => {
+            (RENDER_WIDTH, RENDER_HEIGHT)
+        }
+        Some(cli::Command::Terminal { .. } | cli::Command::Serve { .. } | cli::Command::Worker { .. }) | None => (WIDTH, HEIGHT),
+    };
+    let (width, height) = match (cli.width, cli.height) {
+        (Some(width), Some(height)) => (width as usize, height as usize),
+        _ => default_size,
+    };
+
+    let mut params = MandleParams{
+        x: cli.x,
+        y: cli.y,
+        zoom: cli.zoom,
+        iterations: cli.iterations,
+        auto_iterations: cli.auto_iterations,
+        iteration_scale: cli.iteration_scale,
+        formula: cli.formula,
+        exponent: cli.exponent,
+        script: None,
+        fractal: Fractal::Mandlebrot,
+        mode: RenderMode::EscapeTime,
+        nebula_iterations: cli.nebula_iterations,
+        backend: cli.backend,
+        color_mode: ColorMode::Discrete,
+        palette,
+        palette_offset: 0.0,
+        cycling: cli.cycle,
+        cycle_speed: cli.cycle_speed,
+        exposure: cli.exposure,
+        gamma: cli.gamma,
+        dither: !cli.no_dither,
+        bailout: MIN_BAILOUT,
+        width,
+        height,
+        hud: false,
+        threads: cli.threads,
+        subdivide: cli.subdivide,
+        double_double: cli.double_double,
+        supersample: cli.supersample,
+        supersample_all: cli.supersample_all,
+        temporal: cli.temporal,
+        sketch: cli.sketch,
+        shading: cli.shading,
+        light_angle: cli.light_angle,
+        light_elevation: cli.light_elevation,
+        histogram: cli.histogram,
+        trap: cli.trap,
+        trap_x: cli.trap_x,
+        trap_y: cli.trap_y,
+        trap_radius: cli.trap_radius,
+    };
+    if let Some(path) = &cli.formula_script {
+        match Script::load(path) {
+            Ok(script) => {
+                params.script = Some(Arc::new(script));
+                params.formula = formula::find("Script").unwrap_or(params.formula);
+            }
+            Err(err) => {
+                println!("Error loading formula script from {} {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = &cli.kfr {
+        if let Err(err) = kfr::Location::load(path).and_then(|location| location.apply(&mut params)) {
+            println!("Error loading location from {} {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(link) = &cli.link {
+        if let Err(err) = link.apply(&mut params, &palettes) {
+            println!("Error opening {} {}", link, err);
+            std::process::exit(1);
+        }
+    }
+    recovery::install(&cli.recovery_file, Arc::clone(&palettes));
+    recovery::record(&params);
+
+    //Headless, computed straight into an image with no window or pixels surface
+    if let Some(cli::Command::Render { output }) = &cli.command {
+        let rendered = if raw::is_data_path(output) {
+            raw::render_to_file(&params, width, height, output)
+        } else if mesh::is_mesh_path(output) {
+            let relief = mesh::Relief { height: cli.mesh_height, log: cli.mesh_log };
+            mesh::render_to_file(&params, width, height, relief, output)
+        } else {
+            let tiles = tile_source(&cli.workers);
+            offline::render_to_file_from(tiles.as_ref(), &params, &palettes[params.palette], width, height, output, cli.bit_depth)
+        };
+        if let Err(err) = rendered {
+            println!("Error rendering {} {}", output.display(), err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Batch { file, output, jobs }) = &cli.command {
+        let failed = match batch::load(file) {
+            Ok(locations) => batch::render(&locations, &params, &palettes, output, *jobs, cli.bit_depth),
+            Err(err) => {
+                println!("Error loading locations from {} {}", file.display(), err);
+                1
+            }
+        };
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Pipe) = &cli.command {
+        let output = pipe::Output {
+            depth: cli.bit_depth,
+            relief: mesh::Relief { height: cli.mesh_height, log: cli.mesh_log },
+        };
+        if let Err(err) = pipe::run(params, &palettes, &output, std::io::stdin().lock(), std::io::stdout().lock()) {
+            println!("Error reading commands {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Animate { output, ffmpeg, fps, seconds, expmap }) = &cli.command {
+        let views = keyframes::Keyframes::load(&cli.keyframes_file).and_then(|keyframes| {
+            keyframes.keyframes().iter().map(|keyframe| {
+                let mut view = params.clone();
+                keyframe.apply(&mut view, &palettes)?;
+                Ok(view)
+            }).collect::<Result<Vec<_>, _>>()
+        });
+        let views = match views {
+            Ok(views) if views.is_empty() => {
+                println!("No keyframes in {}, capture some with Insert", cli.keyframes_file.display());
+                std::process::exit(1);
+            }
+            Ok(views) => views,
+            Err(err) => {
+                println!("Error loading keyframes from {} {}", cli.keyframes_file.display(), err);
+                std::process::exit(1);
+            }
+        };
+        let frames = animation::frames(&views, (*fps as f64 * seconds).round().max(1.0) as usize);
+        let output = match ffmpeg {
+            Some(video) => animation::Output::Ffmpeg(video.clone()),
+            None => animation::Output::Frames(output.clone()),
+        };
+        let tiles = tile_source(&cli.workers);
+        if let Err(err) = animation::render(tiles.as_ref(), &frames, &palettes, &output, *fps, *expmap) {
+            println!("Error rendering animation {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let location_given = ["x", "y", "zoom", "kfr", "link"].iter()
+        .any(|id| matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable)));
+
+    if let Some(cli::Command::Serve { address, cache }) = &cli.command {
+        //The whole set at the top level unless told where, the default
+        //starting view is a close up
+        let home = MandleParams::new(width, height);
+        let origin = if location_given { params.clone() } else { MandleParams { x: home.x, y: home.y, zoom: home.zoom, ..params } };
+        if let Err(err) = serve::run(&origin, &palettes[params.palette], address, *cache) {
+            println!("Error serving tiles on {} {}", address, err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Worker { address }) = &cli.command {
+        if let Err(err) = distribute::work(address) {
+            println!("Error computing tiles on {} {}", address, err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Terminal { sixel }) = &cli.command {
+        if let Err(err) = terminal::run(params, Arc::clone(&palettes), *sixel) {
+            println!("Error in the terminal {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    //The viewer carries on from the last session unless told where to start
+    let mut restored = None;
+    if !cli.fresh && !location_given && cli.session_file.exists() {
+        match session::Session::load(&cli.session_file).and_then(|session| session.restore(&mut params, &palettes)) {
+            Ok(history) => restored = Some(history),
+            Err(err) => println!("Error restoring session from {} {}", cli.session_file.display(), err),
+        }
+    }
 
     let event_loop = EventLoop::new();
 
-    let window = { 
-        let scaled_size = LogicalSize::new(
-            WIDTH as f64 * 3.0, 
-            HEIGHT as f64 * 3.0
-        ); 
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
-        WindowBuilder::new()
-            .with_title("Mandlebrot set")
-            .with_inner_size(scaled_size)
-            .with_min_inner_size(size)
+    //Shared with the render threads, which ask it for redraws
+    let window = Arc::new({
+        let builder = WindowBuilder::new().with_title(TITLE);
+        //An explicit size is physical pixels, one per grid cell
+        let builder = if cli.width.is_some() {
+            builder.with_inner_size(PhysicalSize::new(width as u32, height as u32))
+        } else {
+            let scaled_size = LogicalSize::new(
+                WIDTH as f64 * 3.0, 
+                HEIGHT as f64 * 3.0
+            ); 
+            let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+            builder
+                .with_inner_size(scaled_size)
+                .with_min_inner_size(size)
+        };
+        builder
             .build(&event_loop)
             .unwrap()
+    });
+
+    let mut screen = Screen::new(&window, width as u32, height as u32)?;
+    let mut gui = gui::Gui::new(&event_loop, &window, &screen.pixels);
+    screen.present(&params, &palettes)?;
+
+    if cli.width.is_none() {
+        window.set_maximized(true);
+    }
+
+    let preview_window = Arc::new(preview::create_window(&event_loop));
+    let mut preview_pixels = preview::create_pixels(&preview_window)?;
+    let (mut settings, render_requests) = requests::channel(params.clone(), Arc::clone(&window));
+    let (mut preview_settings, preview_requests) = requests::channel(
+        preview::preview_params(&params, params.x.to_fixed(), params.y.to_fixed()),
+        Arc::clone(&preview_window)
+    );
+    let mut preview_visible = false;
+    //Whether the title shows the pixel under the cursor, and the title shown
+    let mut inspecting = false;
+    let mut title = TITLE.to_string();
+    let mut autopilot : Option<autopilot::Autopilot> = None;
+    //Renders are paused while nobody can see the window
+    let mut focused = true;
+    let mut occluded = false;
+    let mut minimized = false;
+    let render_unfocused = cli.render_unfocused;
+    //When the palette was last moved on while colour cycling
+    let mut cycle_tick : Option<Instant> = None;
+    
+    let mut bookmarks = match bookmarks::Bookmarks::load(&cli.bookmarks_file) {
+        Ok(bookmarks) => bookmarks,
+        Err(err) => {
+            println!("Error loading bookmarks from {} {}", cli.bookmarks_file.display(), err);
+            bookmarks::Bookmarks::empty(&cli.bookmarks_file)
+        }
     };
 
-    let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(
-            window_size.width, 
-            window_size.height, 
-            &window
-        );
-        Pixels::new(
-            WIDTH as u32,
-            HEIGHT as u32,
-            surface_texture
-        )?
+    recovery::record(&params);
+    let mut history = restored.unwrap_or_else(|| history::History::new(&params));
+    let mut autosave = session::Autosave::new();
+
+    let transition_time = Duration::from_secs_f64(cli.transition);
+    let mut transition : Option<Transition> = None;
+    //Whether a frame came in since the transition's last step
+    let mut frame_arrived = false;
+
+    let mut keyframes = match keyframes::Keyframes::load(&cli.keyframes_file) {
+        Ok(keyframes) => keyframes,
+        Err(err) => {
+            println!("Error loading keyframes from {} {}", cli.keyframes_file.display(), err);
+            keyframes::Keyframes::empty(&cli.keyframes_file)
+        }
     };
-    
-    render_mandlebrot(&grid,pixels.frame_mut());
-    pixels.render()?;
 
-    window.set_maximized(true);
-    
     let mut input = WinitInputHelper::new(); 
+    //Nothing to watch when there is no config directory, eg. the default
+    //path on a machine that never had a config
+    let watch = |path : &Path| match config::Watcher::new(path, event_loop.create_proxy()) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            println!("Error watching {} {}", path.display(), err);
+            None
+        }
+    };
+    let mut watcher = config_path.as_deref()
+        .filter(|path| cli.config.is_some() || path.parent().is_some_and(Path::exists))
+        .and_then(watch);
+    let mut script_watcher = cli.formula_script.as_deref().and_then(watch);
+
+    //Mandlebrot view from before switching to a julia set, restored on M
+    let mut mandlebrot_view : Option<(Coord, Coord, f64)> = None;
     
+    let remote = cli.remote.as_deref().and_then(|address| {
+        let access = remote::Access { origins: cli.remote_origin.clone(), dir: cli.remote_dir.clone() };
+        let started = remote::Remote::start(address, event_loop.create_proxy(), Arc::clone(&palettes), cli.poster_size, cli.bit_depth, access);
+        started.map_err(|err| println!("Error starting the remote control on {} {}", address, err)).ok()
+    });
+
+    let mut cache = tile_cache::TileCache::new(cli.tile_cache, cli.tile_cache_dir.clone());
     thread::spawn({
-        let read_settings = Arc::clone(&settings);
+        let palettes = Arc::clone(&palettes);
+        let rendered = remote.as_ref().map(|remote| remote.subscribers.clone()).unwrap_or_default();
 
-        move || update(
-            &read_settings,
-            &mut grid,
-            &mut pixels
-        )
+        move || update(&render_requests, &palettes, &mut cache, &rendered)
+    });
+
+    thread::spawn({
+        let palettes = Arc::clone(&palettes);
+
+        move || preview::update(&preview_requests, &palettes)
     });
 
     
     event_loop.run(move | event, _, control_flow | {
-        *control_flow = ControlFlow::Wait;
+        //Whatever changed handling the last event, the panel included
+        history.record(&settings.snapshot());
+        recovery::record(&settings.snapshot());
+        //The sketch follows every change to the view straight away
+        if screen.sketch_behind(&settings.snapshot()) {
+            window.request_redraw();
+        }
 
+        //Saved on the way out as well as every so often, in case it never gets there
+        if matches!(event, Event::LoopDestroyed) || autosave.due() {
+            autosave.save(&session::Session::new(&settings.snapshot(), &history, &palettes), &cli.session_file);
+        }
 
         //settings.write().unwrap().zoom = settings.read().unwrap().zoom * MReal::from_num(0.95f64);
 
-        if let Event::RedrawRequested(_) = event {
-        
+        //Everything is presented from here. The render threads ask for a redraw
+        //once a frame is waiting, the panel whenever it needs updating
+        if let Event::RedrawRequested(window_id) = event {
+            if window_id == preview_window.id() {
+                if let Some(frame) = preview_settings.latest_frame() {
+                    preview_pixels.frame_mut().copy_from_slice(&frame.pixels);
+                }
+                let result = match preview_pixels.render() {
+                    //Hidden, there is nothing to present to until it is shown
+                    Err(err) if surface_lost(&err) && !preview_visible => Ok(()),
+                    Err(err) if surface_lost(&err) => {
+                        println!("Preview surface lost, creating it again");
+                        preview::create_pixels(&preview_window).and_then(|mut pixels| {
+                            pixels.frame_mut().copy_from_slice(preview_pixels.frame());
+                            preview_pixels = pixels;
+                            preview_pixels.render()
+                        })
+                    }
+                    result => result,
+                };
+                if let Err(err) = result {
+                    println!("Error rendering preview {}", err);
+                    if fatal(&err) {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+            }
+            if window_id == window.id() {
+                if let Some(frame) = settings.latest_frame() {
+                    screen.load(&frame);
+                    frame_arrived = true;
+                }
+                let params = settings.snapshot();
+                let mut edited = params.clone();
+                gui.prepare(&window, &mut edited, &palettes, &mut screen.overlay);
+                if edited != params {
+                    *settings.write() = edited;
+                }
+                if let Err(err) = screen.present_or_recover(&window, &mut gui, &settings.snapshot(), &palettes) {
+                    println!("Error presenting {}", err);
+                    if fatal(&err) {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+            }
+        }
+
+        //The input helper doesn't tell windows apart, so it only sees the main one
+        if let Event::WindowEvent { window_id, event } = &event {
+            if *window_id == preview_window.id() {
+                if let WindowEvent::CloseRequested = event {
+                    preview_window.set_visible(false);
+                    preview_visible = false;
+                }
+                return;
+            }
+            if *window_id == window.id() {
+                match event {
+                    WindowEvent::Focused(now_focused) => focused = *now_focused,
+                    WindowEvent::Occluded(now_occluded) => occluded = *now_occluded,
+                    //Windows reports minimizing as a resize to 0x0 instead of occlusion
+                    WindowEvent::Resized(size) => minimized = size.width == 0 || size.height == 0,
+                    _ => {}
+                }
+                settings.pause(occluded || minimized || (!focused && !render_unfocused));
+                if gui.on_event(event) {
+                    window.request_redraw();
+                }
+            }
         }
 
         if input.update(&event) {
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            *control_flow = ControlFlow::Wait;
+
+            //Edits to the config apply straight away, only what was edited
+            //so the view isn't thrown back to the start each time
+            if let Some(watcher) = &mut watcher {
+                if watcher.changed() {
+                    let mut target = settings.snapshot();
+                    let loaded = config::Config::load(watcher.path())
+                        .and_then(|loaded| loaded.update(&config, &mut target, &palettes).map(|()| loaded));
+                    match loaded {
+                        Ok(loaded) => {
+                            if let Some((width, height)) = loaded.size.filter(|_| loaded.size != config.size) {
+                                window.set_maximized(false);
+                                window.set_inner_size(PhysicalSize::new(width, height));
+                            }
+                            if target != settings.snapshot() {
+                                transition = fly_to(&mut settings, &target, transition_time);
+                                frame_arrived = true;
+                            }
+                            config = loaded;
+                            println!("Reloaded config from {}", watcher.path().display());
+                        }
+                        Err(err) => println!("Error loading config from {} {}", watcher.path().display(), err),
+                    }
+                }
+            }
+
+            //A saved script is drawn straight away, one that doesn't parse
+            //leaves the last one that did in place
+            if let Some(watcher) = &mut script_watcher {
+                if watcher.changed() {
+                    match Script::load(watcher.path()) {
+                        Ok(script) => {
+                            let mut settings = settings.write();
+                            settings.script = Some(Arc::new(script));
+                            println!("Reloaded formula script from {}", watcher.path().display());
+                        }
+                        Err(err) => println!("Error loading formula script from {} {}", watcher.path().display(), err),
+                    }
+                }
+            }
+
+            //Remote clients wake the loop up when they send something
+            if let Some(target) = remote.as_ref().and_then(|remote| remote.handle(&settings.snapshot())) {
+                transition = fly_to(&mut settings, &target, transition_time);
+                frame_arrived = true;
+            }
+
+            //Minimizing reports a 0x0 size, keep the old grid until the window comes back
+            if let Some(size) = input.window_resized() {
+                if size.width > 0 && size.height > 0 {
+                    let mut settings = settings.write();
+                    settings.width = size.width as usize;
+                    settings.height = size.height as usize;
+                }
+            }
+
+            if input.close_requested() || input.destroyed() {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            //Keep the panel's fields in step with changes made from the keyboard
+            if gui.visible {
+                window.request_redraw();
+            }
+            if input.mouse_held(0) && !gui.wants_pointer() {
+                let (dx, dy) = input.mouse_diff();
+                if dx != 0.0 || dy != 0.0 {
+                    let mut settings = settings.write();
+                    let scale = window_scale(&settings, window.inner_size());
+                    let zoom = settings.zoom;
+                    settings.x = settings.x.offset(-dx as f64 / scale * zoom);
+                    settings.y = settings.y.offset(-dy as f64 / scale * zoom);
+                }
+            }
+            //Holding O draws the orbit of the point under the cursor over the frame
+            let orbit = match input.mouse() {
+                Some(mouse) if config.keys.held(&input, VirtualKeyCode::O) && !gui.wants_keyboard() => {
+                    orbit_overlay(&settings.snapshot().resolved(), window.inner_size(), mouse)
+                }
+                _ => Vec::new(),
+            };
+            if orbit != gui.orbit {
+                gui.orbit = orbit;
+                window.request_redraw();
+            }
+            //Right dragging draws a box, letting go zooms in on it
+            if input.mouse_pressed(1) && !gui.wants_pointer() {
+                gui.zoom_box = input.mouse().map(|pos| (pos, pos));
+            }
+            if let Some((start, end)) = gui.zoom_box {
+                let end = input.mouse().unwrap_or(end);
+                if input.mouse_held(1) {
+                    gui.zoom_box = Some((start, end));
+                } else {
+                    gui.zoom_box = None;
+                    let params = settings.snapshot();
+                    let start = window_pos_to_grid(&params, window.inner_size(), start);
+                    let end = window_pos_to_grid(&params, window.inner_size(), end);
+                    if (end.0 - start.0).abs() >= MIN_ZOOM_BOX || (end.1 - start.1).abs() >= MIN_ZOOM_BOX {
+                        settings.write().zoom_to_box(start, end);
+                    }
+                }
+                window.request_redraw();
+            }
+            let scroll = input.scroll_diff();
+            if scroll != 0.0 && !gui.wants_pointer() {
+                if let Some(mouse) = input.mouse() {
+                    let mut settings = settings.write();
+                    let (px, py) = window_pos_to_grid(&settings, window.inner_size(), mouse);
+                    settings.zoom_about(px, py, 0.9f64.powf(scroll as f64));
+                }
+            }
+            //Colour cycling moves the palette on every tick, only the grid is recoloured
+            if settings.snapshot().cycling && !settings.paused() {
+                let now = Instant::now();
+                if let Some(last) = cycle_tick {
+                    let mut settings = settings.write();
+                    let step = settings.cycle_speed * (now - last).as_secs_f64();
+                    settings.palette_offset = (settings.palette_offset + step).rem_euclid(1.0);
+                }
+                cycle_tick = Some(now);
+                *control_flow = ControlFlow::WaitUntil(now + PAN_INTERVAL);
+            } else {
+                cycle_tick = None;
+            }
+            //Each step waits for the frame of the one before, so every view
+            //on the way is drawn however long its first pass takes
+            if let Some(moving) = &mut transition {
+                if frame_arrived {
+                    frame_arrived = false;
+                    let before = settings.snapshot();
+                    let mut params = before.clone();
+                    let moving_on = moving.step(&mut params);
+                    if params != before {
+                        *settings.write() = params;
+                    }
+                    if !moving_on {
+                        transition = None;
+                    }
+                }
+                *control_flow = ControlFlow::WaitUntil(Instant::now() + PAN_INTERVAL);
+            }
+            //Waits on the render like a transition, and lets go as soon as
+            //anything else moves the view
+            if let Some(pilot) = &mut autopilot {
+                let before = settings.snapshot();
+                let mut params = before.clone();
+                let flying = pilot.step(&mut params, settings.finished().as_deref(), frame_arrived, transition_time);
+                frame_arrived = false;
+                if params != before {
+                    *settings.write() = params;
+                }
+                if !flying {
+                    autopilot = None;
+                    settings.inspect(inspecting);
+                    println!("Autopilot off");
+                }
+                *control_flow = ControlFlow::WaitUntil(Instant::now() + PAN_INTERVAL);
+            }
+            //Typing in a panel field shouldn't also trigger shortcuts
+            if gui.wants_keyboard() {
+                return;
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Escape) {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
-            if input.key_pressed(VirtualKeyCode::Space){
-                settings.write().unwrap().zoom *= MReal::from_num(0.95f64);
+            if config.keys.pressed(&input, VirtualKeyCode::Back) {
+                let mut params = settings.snapshot();
+                let moved = if input.held_shift() {
+                    history.redo(&mut params)
+                } else {
+                    history.undo(&mut params)
+                };
+                if moved {
+                    *settings.write() = params;
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Space){
+                let mut settings = settings.write();
+                settings.zoom = (settings.zoom * 0.95).max(MIN_ZOOM);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::RAlt){
+                settings.write().zoom *= 1.05;
+            }
+            for slot in 1..=bookmarks::SLOTS {
+                if !config.keys.pressed(&input, bookmarks::slot_key(slot)) {
+                    continue;
+                }
+                if input.held_control() {
+                    let bookmark = bookmarks::Bookmark::from_params(&settings.snapshot());
+                    match bookmarks.set(slot, bookmark) {
+                        Ok(()) => println!("Stored bookmark {}", slot),
+                        Err(err) => println!("Error storing bookmark {} {}", slot, err),
+                    }
+                } else if let Some(bookmark) = bookmarks.get(slot) {
+                    let mut target = settings.snapshot();
+                    match bookmark.apply(&mut target) {
+                        Ok(()) => {
+                            transition = fly_to(&mut settings, &target, transition_time);
+                            frame_arrived = true;
+                        }
+                        Err(err) => println!("Error jumping to bookmark {} {}", slot, err),
+                    }
+                }
+            }
+
+            if config.keys.pressed(&input, VirtualKeyCode::Insert) {
+                let keyframe = keyframes::Keyframe::from_params(&settings.snapshot(), &palettes);
+                match keyframes.push(keyframe) {
+                    Ok(()) => println!("Stored keyframe {}", keyframes.keyframes().len()),
+                    Err(err) => println!("Error storing keyframe {}", err),
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Delete) {
+                match keyframes.clear() {
+                    Ok(()) => println!("Cleared keyframes"),
+                    Err(err) => println!("Error clearing keyframes {}", err),
+                }
+            }
+
+            let (pan_x, pan_y) = held_pan_direction(&input, &config.keys);
+            if pan_x != 0.0 || pan_y != 0.0 {
+                let mut settings = settings.write();
+                let zoom = settings.zoom;
+                settings.x = settings.x.offset(pan_x * PAN_STEP * zoom);
+                settings.y = settings.y.offset(pan_y * PAN_STEP * zoom);
+                //Keep ticking while the key is held, not just on os key repeat
+                *control_flow = ControlFlow::WaitUntil(Instant::now() + PAN_INTERVAL);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::C){
+                if input.held_control() {
+                    //With shift as a link that keeps the palette too
+                    let params = settings.snapshot();
+                    let text = if input.held_shift() {
+                        link::Link::from_params(&params, &palettes).to_string()
+                    } else {
+                        bookmarks::Bookmark::from_params(&params).to_string()
+                    };
+                    match clipboard::copy(&text) {
+                        Ok(()) => println!("Copied {}", text),
+                        Err(err) => println!("Error copying the view {}", err),
+                    }
+                } else {
+                    let mut settings = settings.write();
+                    settings.color_mode = settings.color_mode.next();
+                    println!("{}", *settings);
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::B){
+                let factor = if input.held_shift() { 0.5 } else { 2.0 };
+                let mut settings = settings.write();
+                settings.scale_bailout(factor);
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::P){
+                let mut settings = settings.write();
+                settings.palette = (settings.palette + 1) % palettes.len();
+                println!("Palette: {}", palettes[settings.palette].name);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::F12){
+                //Rendered off the event loop so the window stays responsive
+                let params = settings.snapshot();
+                let palettes = Arc::clone(&palettes);
+                thread::spawn(move || {
+                    let (width, height) = cli.poster_size;
+                    let secs = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|time| time.as_secs())
+                        .unwrap_or(0);
+                    let path = format!("mandlebrot_{}.png", secs);
+                    let palette = &palettes[params.palette];
+                    if let Err(err) = offline::render_to_file(&params, palette, width, height, path.as_ref(), cli.bit_depth) {
+                        println!("Error rendering {} {}", path, err);
+                    }
+                });
             }
-            if input.key_pressed(VirtualKeyCode::RAlt){
-                settings.write().unwrap().zoom *= MReal::from_num(1.05f64);
+            if config.keys.pressed(&input, VirtualKeyCode::F11){
+                let params = settings.snapshot();
+                let palettes = Arc::clone(&palettes);
+                let motion = if input.held_shift() {
+                    gif_loop::Motion::Zoom(cli.gif_zoom)
+                } else {
+                    gif_loop::Motion::Cycle
+                };
+                let options = gif_loop::Options {
+                    size: cli.gif_size,
+                    frames: cli.gif_frames,
+                    delay_ms: cli.gif_delay,
+                    dither: cli.gif_dither,
+                };
+                thread::spawn(move || {
+                    let secs = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|time| time.as_secs())
+                        .unwrap_or(0);
+                    let path = format!("mandlebrot_{}.gif", secs);
+                    let palette = &palettes[params.palette];
+                    if let Err(err) = gif_loop::capture(&params, palette, motion, &options, path.as_ref()) {
+                        println!("Error rendering {} {}", path, err);
+                    }
+                });
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::F5){
+                let state = view::ViewState::from_params(&settings.snapshot(), &palettes);
+                match state.save(&cli.view_file) {
+                    Ok(()) => println!("Saved view to {}", cli.view_file.display()),
+                    Err(err) => println!("Error saving view to {} {}", cli.view_file.display(), err),
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::F9){
+                let mut target = settings.snapshot();
+                let loaded = view::ViewState::load(&cli.view_file)
+                    .and_then(|state| state.apply(&mut target, &palettes));
+                match loaded {
+                    Ok(()) => {
+                        transition = fly_to(&mut settings, &target, transition_time);
+                        frame_arrived = true;
+                        println!("Loaded view from {}", cli.view_file.display());
+                    }
+                    Err(err) => println!("Error loading view from {} {}", cli.view_file.display(), err),
+                }
+            }
+            if let Some(path) = input.dropped_file().filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("kfr"))) {
+                let mut target = settings.snapshot();
+                match kfr::Location::load(&path).and_then(|location| location.apply(&mut target)) {
+                    Ok(()) => {
+                        transition = fly_to(&mut settings, &target, transition_time);
+                        frame_arrived = true;
+                        println!("Loaded location from {}", path.display());
+                    }
+                    Err(err) => println!("Error loading location from {} {}", path.display(), err),
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::N){
+                //Searched for up to the iterations the view is drawn with
+                let params = settings.snapshot();
+                match formula::get(params.formula).nucleus(&params.resolved()) {
+                    Ok(nucleus) => {
+                        println!("Minibrot of period {} at {} {}", nucleus.period, nucleus.x, nucleus.y);
+                        transition = fly_to(&mut settings, &nucleus.view(&params), transition_time);
+                        frame_arrived = true;
+                    }
+                    Err(err) => println!("Error finding a minibrot {}", err),
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::I){
+                let mut settings = settings.write();
+                settings.auto_iterations = !settings.auto_iterations;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::J){
+                let mut settings = settings.write();
+                //The point under the cursor becomes c, the view centre without a cursor
+                let (c_a, c_b) = match input.mouse() {
+                    Some(mouse) => {
+                        let (px, py) = window_pos_to_grid(&settings, window.inner_size(), mouse);
+                        let (c_a, c_b) = settings.pixel_to_complex(px, py);
+                        (c_a.to_fixed(), c_b.to_fixed())
+                    }
+                    None => (settings.x.to_fixed(), settings.y.to_fixed()),
+                };
+                if settings.fractal == Fractal::Mandlebrot {
+                    mandlebrot_view = Some((settings.x, settings.y, settings.zoom));
+                }
+                settings.fractal = Fractal::Julia { c_a, c_b };
+                settings.x = Coord::ZERO;
+                settings.y = Coord::ZERO;
+                settings.zoom = 4.0 / settings.height as f64;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Home) {
+                let mut settings = settings.write();
+                settings.reset_view();
+                mandlebrot_view = None;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::M){
+                let mut settings = settings.write();
+                if settings.fractal != Fractal::Mandlebrot {
+                    settings.fractal = Fractal::Mandlebrot;
+                    if let Some((x, y, zoom)) = mandlebrot_view.take() {
+                        settings.x = x;
+                        settings.y = y;
+                        settings.zoom = zoom;
+                    }
+                    println!("{}", *settings);
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::G){
+                let mut settings = settings.write();
+                settings.backend = settings.backend.next();
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::R){
+                let mut settings = settings.write();
+                settings.subdivide = !settings.subdivide;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Q){
+                let mut settings = settings.write();
+                if input.held_shift() {
+                    settings.supersample_all = !settings.supersample_all;
+                } else {
+                    let factors = supersample::FACTORS;
+                    let next = factors.iter().position(|&n| n == settings.supersample).map_or(0, |i| i + 1);
+                    settings.supersample = factors[next % factors.len()];
+                }
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::T){
+                let mut settings = settings.write();
+                settings.temporal = !settings.temporal;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Z){
+                let mut settings = settings.write();
+                settings.sketch = !settings.sketch;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::K){
+                let mut settings = settings.write();
+                settings.trap = settings.trap.next();
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Y){
+                let mut settings = settings.write();
+                settings.cycling = !settings.cycling;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::H){
+                let mut settings = settings.write();
+                settings.histogram = !settings.histogram;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::L){
+                let mut settings = settings.write();
+                settings.shading = !settings.shading;
+                println!("{}", *settings);
+            }
+            let turn = if config.keys.pressed(&input, VirtualKeyCode::LBracket) {
+                -shading::LIGHT_STEP
+            } else if config.keys.pressed(&input, VirtualKeyCode::RBracket) {
+                shading::LIGHT_STEP
+            } else {
+                0.0
+            };
+            if turn != 0.0 {
+                let mut settings = settings.write();
+                settings.light_angle = (settings.light_angle + turn).rem_euclid(360.0);
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::F){
+                let mut settings = settings.write();
+                settings.formula = (settings.formula + 1) % formula::FORMULAS.len();
+                //Without a script it would only be the mandlebrot set again
+                if settings.script.is_none() && Some(settings.formula) == formula::find("Script") {
+                    settings.formula = (settings.formula + 1) % formula::FORMULAS.len();
+                }
+                println!("{}", *settings);
+            }
+            //Each key with its direction and whether shift is free to mean
+            //ten steps on it. Where + takes shift to type it's = for one step
+            //and shift = for ten, where it has a key of its own that key is
+            //always one step
+            let steps = [
+                (VirtualKeyCode::Equals, 1, true),
+                (VirtualKeyCode::NumpadAdd, 1, true),
+                (VirtualKeyCode::Plus, 1, false),
+                (VirtualKeyCode::Minus, -1, true),
+                (VirtualKeyCode::NumpadSubtract, -1, true),
+            ];
+            let (direction, shifted) = steps.iter()
+                .find(|(key, ..)| config.keys.pressed(&input, *key))
+                .map_or((0, false), |&(_, direction, shiftable)| (direction, shiftable && input.held_shift()));
+            if direction != 0 && input.held_control() {
+                let mut settings = settings.write();
+                settings.exponent = (settings.exponent + direction as f64)
+                    .clamp(formula::MIN_EXPONENT, formula::MAX_EXPONENT);
+                println!("{}", *settings);
+            } else if direction != 0 {
+                let step = if shifted { ITERATION_STEP * 10 } else { ITERATION_STEP };
+                let mut settings = settings.write();
+                //Stepping from auto continues from the limit it was using
+                let iterations = settings.max_iterations() as i64 + direction * step as i64;
+                settings.iterations = iterations.clamp(1, u32::MAX as i64) as u32;
+                settings.auto_iterations = false;
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::Tab){
+                let mut settings = settings.write();
+                settings.hud = !settings.hud;
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::U){
+                let mut settings = settings.write();
+                settings.mode = settings.mode.next();
+                println!("{}", *settings);
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::F1){
+                gui.visible = !gui.visible;
+                window.request_redraw();
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::V){
+                if input.held_control() {
+                    let mut target = settings.snapshot();
+                    let pasted = clipboard::paste()
+                        .map_err(view::ViewError::Io)
+                        .and_then(|text| {
+                            if text.trim().starts_with(link::SCHEME) {
+                                text.parse::<link::Link>()?.apply(&mut target, &palettes)
+                            } else {
+                                text.parse::<bookmarks::Bookmark>()?.apply(&mut target)
+                            }
+                        });
+                    match pasted {
+                        Ok(()) => {
+                            transition = fly_to(&mut settings, &target, transition_time);
+                            frame_arrived = true;
+                        }
+                        Err(err) => println!("Error pasting a view {}", err),
+                    }
+                } else {
+                    preview_visible = !preview_visible;
+                    preview_window.set_visible(preview_visible);
+                }
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::X){
+                inspecting = !inspecting;
+                settings.inspect(inspecting || autopilot.is_some());
+            }
+            if config.keys.pressed(&input, VirtualKeyCode::E){
+                if autopilot.take().is_some() {
+                    settings.inspect(inspecting);
+                    println!("Autopilot off");
+                } else {
+                    //Dives only stay sharp if the iterations keep up
+                    settings.write().auto_iterations = true;
+                    autopilot = Some(autopilot::Autopilot::new(&settings.snapshot()));
+                    transition = None;
+                    settings.inspect(true);
+                    println!("Autopilot on, any move of the view takes over");
+                }
+            }
+            //Only set when it changes, the title is redrawn every time
+            let shown_title = match (inspecting, input.mouse()) {
+                (true, Some(mouse)) => {
+                    let params = settings.snapshot();
+                    let pos = window_pos_to_grid(&params, window.inner_size(), mouse);
+                    inspector_title(&params, settings.finished().as_deref(), pos)
+                }
+                _ => TITLE.to_string(),
+            };
+            if shown_title != title {
+                window.set_title(&shown_title);
+                title = shown_title;
+            }
+
+            //Only written when it changes, every write restarts the preview render
+            if preview_visible {
+                let params = settings.snapshot();
+                if let (Fractal::Mandlebrot, Some(mouse)) = (params.fractal, input.mouse()) {
+                    let (px, py) = window_pos_to_grid(&params, window.inner_size(), mouse);
+                    let (c_a, c_b) = params.pixel_to_complex(px, py);
+                    let preview = preview::preview_params(&params, c_a.to_fixed(), c_b.to_fixed());
+                    if preview_settings.snapshot() != preview {
+                        *preview_settings.write() = preview;
+                    }
+                }
             }
         }
         
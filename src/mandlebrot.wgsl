@@ -0,0 +1,72 @@
+// Escape time iteration for the gpu backend.
+// Mirrors calc_mandle_divergence but in f32, so it is only used for shallow zooms.
+
+struct Params {
+    x : f32,
+    y : f32,
+    zoom : f32,
+    max_iter : u32,
+    width : u32,
+    height : u32,
+    // Escape radius squared
+    bailout2 : f32,
+    // 1 for Fractal::Julia, c is then fixed at (c_a, c_b)
+    julia : u32,
+    c_a : f32,
+    c_b : f32,
+    _padding : u32,
+    _padding2 : u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params : Params;
+
+// Three per pixel, the iteration it escaped on (-1 if it never did) and the final z
+@group(0) @binding(1)
+var<storage, read_write> output : array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id : vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+
+    let z0_a = params.x + (f32(id.x) - f32(params.width) / 2.0) * params.zoom;
+    let z0_b = params.y + (f32(id.y) - f32(params.height) / 2.0) * params.zoom;
+    var c_a = z0_a;
+    var c_b = z0_b;
+    if (params.julia == 1u) {
+        c_a = params.c_a;
+        c_b = params.c_b;
+    }
+    var a = z0_a;
+    var b = z0_b;
+    let index = (id.y * params.width + id.x) * 3u;
+    output[index] = -1.0;
+    output[index + 1u] = 0.0;
+    output[index + 2u] = 0.0;
+
+    // Main cardioid and period 2 bulb never escape, same test as formula::in_main_bulbs
+    if (params.julia == 0u) {
+        let q = (c_a - 0.25) * (c_a - 0.25) + c_b * c_b;
+        let cardioid = q * (q + (c_a - 0.25)) <= 0.25 * c_b * c_b;
+        let bulb = (c_a + 1.0) * (c_a + 1.0) + c_b * c_b <= 0.0625;
+        if (cardioid || bulb) {
+            return;
+        }
+    }
+
+    for (var i = 0u; i < params.max_iter; i = i + 1u) {
+        if (a * a + b * b > params.bailout2) {
+            output[index] = f32(i);
+            output[index + 1u] = a;
+            output[index + 2u] = b;
+            return;
+        }
+        //square Z[I] + C
+        let a_new = a * a - b * b;
+        let b_new = 2.0 * a * b;
+        a = a_new + c_a;
+        b = b_new + c_b;
+    }
+}
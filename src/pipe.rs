@@ -0,0 +1,164 @@
+//Commands on stdin for shell scripts and other programs to drive renders
+//without linking against the crate. Each line is a JSON message like the
+//remote control's, each answered with one line of JSON on stdout:
+//
+//  {"command": "set", "params": {"x": "-0.743643887", "zoom": "1e-7"}}
+//  {"command": "get"}
+//  {"command": "render", "path": "seahorse.png", "width": 1920, "height": 1080}
+//  {"command": "quit"}
+//
+//get and set answer with a params event, render with a saved event once
+//the file is written, any of the outputs of the render subcommand. Errors
+//are error events and the next line is read as if nothing happened. The
+//end of stdin quits too
+
+use std::io::{BufRead, Error, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use mandelbrot_core::mesh::{self, Relief};
+use mandelbrot_core::offline::{self, ImageDepth, OfflineError};
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{raw, MandleParams};
+
+use crate::remote::{self, error_event, params_event};
+
+//Renders as the render subcommand would, see there
+pub struct Output {
+    pub depth : ImageDepth,
+    pub relief : Relief,
+}
+
+//Answers commands read from input on stdout until quit or the end of
+//input, starting from params. Renders are params' size unless a command
+//gives its own
+pub fn run(
+    mut params : MandleParams,
+    palettes : &[Palette],
+    output : &Output,
+    input : impl BufRead,
+    mut stdout : impl Write
+) -> Result<(), Error> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                writeln!(stdout, "{}", error_event(format!("not JSON, {}", err)))?;
+                stdout.flush()?;
+                continue;
+            }
+        };
+        let reply = match message.get("command").and_then(Value::as_str) {
+            Some("get") => params_event("params", &params, palettes),
+            Some("set") => {
+                let mut view = params.clone();
+                let applied = match message.get("params") {
+                    Some(fields) => remote::apply_json(fields, &mut view, palettes).map_err(|err| err.to_string()),
+                    None => Err("set needs params".to_string()),
+                };
+                match applied {
+                    Ok(()) => {
+                        params = view;
+                        params_event("params", &params, palettes)
+                    }
+                    Err(err) => error_event(err),
+                }
+            }
+            Some("render") => render(&message, &params, palettes, output),
+            Some("quit") => return Ok(()),
+            Some(other) => error_event(format!("no command named {}", other)),
+            None => error_event("messages need a command"),
+        };
+        writeln!(stdout, "{}", reply)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn render(message : &Value, params : &MandleParams, palettes : &[Palette], output : &Output) -> Value {
+    let (path, (width, height)) = match remote::render_target(message, (params.width, params.height)) {
+        Ok(target) => target,
+        Err(err) => return error_event(err),
+    };
+    let started = Instant::now();
+    match save(params, &palettes[params.palette], (width, height), &path, output) {
+        Ok(()) => json!({
+            "event": "saved",
+            "path": path.display().to_string(),
+            "milliseconds": started.elapsed().as_millis() as u64,
+        }),
+        Err(err) => error_event(format!("rendering {} {}", path.display(), err)),
+    }
+}
+
+//Whatever the path's extension asks for, without printing the progress
+fn save(
+    params : &MandleParams,
+    palette : &Palette,
+    (width, height) : (usize, usize),
+    path : &Path,
+    output : &Output
+) -> Result<(), OfflineError> {
+    if raw::is_data_path(path) {
+        raw::save_data(params, width, height, path, |_| {})
+    } else if mesh::is_mesh_path(path) {
+        mesh::save_mesh(params, width, height, output.relief, path, |_| {}).map(|_| ())
+    } else {
+        offline::save_image(params, palette, (width, height), path, output.depth, |_| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mandelbrot_core::palette::builtin_palettes;
+
+    //The events run answers lines with
+    fn events(lines : &str) -> Vec<Value> {
+        let output = Output { depth: ImageDepth::Eight, relief: Relief { height: 1.0, log: false } };
+        let mut written = Vec::new();
+        run(MandleParams::new(64, 48), &builtin_palettes(), &output, lines.as_bytes(), &mut written).unwrap();
+        String::from_utf8(written).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn commands_are_answered_in_order() {
+        let events = events(concat!(
+            r#"{"command": "set", "params": {"x": "-0.743643887", "zoom": "1e-7"}}"#, "\n",
+            "\n",
+            r#"{"command": "get"}"#, "\n",
+            "set x=1\n",
+            r#"{"command": "set", "params": {"zoom": "big"}}"#, "\n",
+            r#"{"command": "set"}"#, "\n",
+            r#"{"command": "spin"}"#, "\n",
+            r#"{"params": {}}"#, "\n",
+            r#"{"command": "get"}"#, "\n",
+            r#"{"command": "quit"}"#, "\n",
+            r#"{"command": "get"}"#, "\n",
+        ));
+        let kinds : Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["params", "params", "error", "error", "error", "error", "error", "params"]);
+        for event in [&events[0], &events[1], &events[7]] {
+            assert_eq!(event["params"]["x"], "-0.743643887");
+            assert_eq!(event["params"]["zoom"].as_str().and_then(|zoom| zoom.parse::<f64>().ok()), Some(1e-7));
+        }
+        let message = |at : usize| events[at]["message"].as_str().unwrap().to_string();
+        assert!(message(2).starts_with("not JSON, "), "{}", message(2));
+        assert_eq!(message(4), "set needs params");
+        assert_eq!(message(5), "no command named spin");
+        assert_eq!(message(6), "messages need a command");
+    }
+
+    #[test]
+    fn input_ending_quits() {
+        assert!(events("").is_empty());
+        assert_eq!(events(r#"{"command": "get"}"#).len(), 1);
+    }
+}
@@ -0,0 +1,78 @@
+//Small second window showing the julia set for the point under the cursor
+
+use pixels::{Error, Pixels, SurfaceTexture};
+use winit::{
+    dpi::LogicalSize,
+    event_loop::EventLoop,
+    window::{Window, WindowBuilder},
+};
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{
+    colorizer, Backend, Coord, Fractal, Grid, MandleParams, MReal, RefinePass, Sample,
+    calc_mandlebrot_set, render_mandlebrot,
+};
+
+use crate::requests::Requests;
+
+//Grid size of the preview, kept small so it keeps up with the cursor
+const PREVIEW_WIDTH: usize = 160;
+const PREVIEW_HEIGHT: usize = 90;
+
+//Deep mandlebrot views use far more iterations than a julia set at this
+//size needs, so the preview caps them
+const PREVIEW_MAX_ITER: u32 = 256;
+
+//Starts hidden, V toggles it. Not resizable so the surface never changes size
+pub fn create_window<T>(event_loop : &EventLoop<T>) -> Window {
+    WindowBuilder::new()
+        .with_title("Julia preview")
+        .with_inner_size(LogicalSize::new(
+            PREVIEW_WIDTH as f64 * 2.0,
+            PREVIEW_HEIGHT as f64 * 2.0
+        ))
+        .with_resizable(false)
+        .with_visible(false)
+        .build(event_loop)
+        .unwrap()
+}
+
+pub fn create_pixels(window : &Window) -> Result<Pixels, Error> {
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
+    Pixels::new(PREVIEW_WIDTH as u32, PREVIEW_HEIGHT as u32, surface_texture)
+}
+
+//The whole julia set for c, coloured the same way as the main view
+pub fn preview_params(main : &MandleParams, c_a : MReal, c_b : MReal) -> MandleParams {
+    MandleParams {
+        x: Coord::ZERO,
+        y: Coord::ZERO,
+        zoom: 4.0 / PREVIEW_HEIGHT as f64,
+        iterations: main.max_iterations().min(PREVIEW_MAX_ITER),
+        auto_iterations: false,
+        fractal: Fractal::Julia { c_a, c_b },
+        backend: Backend::Cpu,
+        width: PREVIEW_WIDTH,
+        height: PREVIEW_HEIGHT,
+        ..main.clone()
+    }
+}
+
+//Render loop for the preview thread. Each frame is a single full pass,
+//small enough that progressive refinement isn't worth it
+pub fn update(requests : &Requests, palettes : &[Palette]) {
+    let mut grid = Grid::new(PREVIEW_WIDTH, PREVIEW_HEIGHT, Sample::INTERIOR);
+    let mut frame = vec![0u8; PREVIEW_WIDTH * PREVIEW_HEIGHT * 4];
+    //The first request is the starting view, the window is hidden until V
+    requests.next();
+    while let Some((params, generation)) = requests.next() {
+        if !calc_mandlebrot_set(&mut grid, &params, RefinePass::FULL, &requests.cancel_token(generation)) {
+            continue;
+        }
+        render_mandlebrot(&grid, &mut frame, 1, &*colorizer::new(&grid, 1, &params, palettes));
+        if !requests.present(&frame, &params) {
+            break;
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//Keeps the view from being lost when something panics. The event loop
+//records the params it has every time round, and a panic hook writes the
+//last of them out as a view file before the usual panic message
+//
+//  # Panicked at mandelbrot-core/src/lib.rs:812:9: ...
+//  # Computed with cpu fixed 128
+//  x = "-1.7490441304234213361800664105378"
+//  ...
+//
+//which F9 loads back with --view-file pointing at it
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use mandelbrot_core::MandleParams;
+use mandelbrot_core::palette::Palette;
+
+use crate::hud;
+use crate::view::{ViewError, ViewState};
+
+static LATEST: Mutex<Option<MandleParams>> = Mutex::new(None);
+
+pub fn record(params : &MandleParams) {
+    if let Ok(mut latest) = LATEST.lock() {
+        *latest = Some(params.clone());
+    }
+}
+
+//Writes to path on any panic, on whichever thread
+pub fn install(path : &Path, palettes : Arc<Vec<Palette>>) {
+    let path : PathBuf = path.to_path_buf();
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        //Not waited on, the panic may have come from inside record
+        let latest = LATEST.try_lock().ok().and_then(|latest| latest.clone());
+        if let Some(params) = latest {
+            match write(&path, &params, &palettes, &info.to_string()) {
+                Ok(()) => println!("Saved the view to {} before crashing, load it with --view-file {} and F9", path.display(), path.display()),
+                Err(err) => println!("Error saving the view to {} {}", path.display(), err),
+            }
+        }
+        default(info);
+    }));
+}
+
+fn write(path : &Path, params : &MandleParams, palettes : &[Palette], panic : &str) -> Result<(), ViewError> {
+    let build = format!("{} {}{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), if cfg!(feature = "rug") { " with rug" } else { "" });
+    let about = format!("{}\nComputed with {}\n{}\n{}", panic, hud::calculation(params), build, params);
+    let mut text = String::new();
+    for line in about.lines() {
+        let _ = writeln!(text, "# {}", line);
+    }
+    text.push_str(&toml::to_string(&ViewState::from_params(params, palettes))?);
+    std::fs::write(path, text)?;
+    Ok(())
+}
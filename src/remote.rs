@@ -0,0 +1,644 @@
+//Remote control over a WebSocket, for external UIs, installations or OBS
+//overlays driving the viewer. Clients send JSON messages naming a command:
+//
+//  {"command": "get"}
+//  {"command": "set", "params": {"x": "-0.743643887", "zoom": "1e-7"}}
+//  {"command": "render", "path": "poster.png", "width": 3840, "height": 2160}
+//  {"command": "subscribe"} and {"command": "unsubscribe"}
+//
+//and get JSON events back. get and set answer with a params event holding
+//the view, set flying there first. params have the fields of a saved view,
+//set only needs the ones it changes. render saves the view like F12 and
+//sends a saved event once it's written. Subscribed clients get a rendered
+//event with the params every time a view finishes. Anything wrong is an
+//error event with a message
+//
+//Web pages can reach a socket on localhost too, so a handshake from a
+//browser is refused unless its Origin was allowed, and renders only write
+//below one directory. Each client has a thread, commands go to the event
+//loop, which is woken up for them
+
+use std::io::{Error, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, Sender};
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{ErrorResponse, Request as Handshake, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tungstenite::{Message, WebSocket};
+use winit::event_loop::EventLoopProxy;
+
+use mandelbrot_core::{check_size, MandleParams};
+use mandelbrot_core::offline::{self, ImageDepth};
+use mandelbrot_core::palette::Palette;
+
+use crate::view::{ViewError, ViewState};
+
+//Longest message a client may send, views are a few hundred bytes
+const MAX_MESSAGE: usize = 1 << 20;
+
+//Clients connected at once, the ones after are turned away
+const MAX_CLIENTS: usize = 16;
+
+//How long a client has to finish the handshake, and to take what is sent
+//to it before it counts as gone
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+//How often a client's thread stops waiting for its messages to send events
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+//Where events for one client go, to its thread
+#[derive(Clone)]
+pub struct Client {
+    id : u64,
+    outgoing : Sender<String>,
+}
+
+impl Client {
+
+    //False once the client has gone
+    pub fn send(&self, event : &Value) -> bool {
+        self.outgoing.send(event.to_string()).is_ok()
+    }
+
+    fn error(&self, message : impl std::fmt::Display) {
+        self.send(&error_event(message));
+    }
+}
+
+//Clients that asked for rendered events, shared with the render thread.
+//Empty without a remote, so there is nothing to send
+#[derive(Clone, Default)]
+pub struct Subscribers(Arc<Mutex<Vec<Client>>>);
+
+impl Subscribers {
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    //Sends event to everyone subscribed, forgetting clients that have gone
+    pub fn notify(&self, event : &Value) {
+        self.0.lock().unwrap().retain(|client| client.send(event));
+    }
+
+    fn add(&self, client : &Client) {
+        let mut clients = self.0.lock().unwrap();
+        if !clients.iter().any(|other| other.id == client.id) {
+            clients.push(client.clone());
+        }
+    }
+
+    fn remove(&self, client : &Client) {
+        self.0.lock().unwrap().retain(|other| other.id != client.id);
+    }
+}
+
+//A message from a client for the event loop
+pub struct Request {
+    message : Value,
+    client : Client,
+}
+
+//A render command waiting for the render thread
+struct Render {
+    params : MandleParams,
+    path : PathBuf,
+    size : (usize, usize),
+    client : Client,
+}
+
+//Who may connect and where renders go
+pub struct Access {
+    //Origins of the web pages allowed to connect, programs send none
+    pub origins : Vec<String>,
+    //Directory render paths are taken relative to
+    pub dir : PathBuf,
+}
+
+//The event loop's end, commands waiting and who to tell about renders
+pub struct Remote {
+    requests : Receiver<Request>,
+    renders : Sender<Render>,
+    pub subscribers : Subscribers,
+    palettes : Arc<Vec<Palette>>,
+    dir : PathBuf,
+    //Size of renders that don't give their own, F12's
+    poster_size : (usize, usize),
+}
+
+impl Remote {
+
+    //Listens on address, waking the event loop through proxy whenever a
+    //command comes in
+    pub fn start(
+        address : &str,
+        proxy : EventLoopProxy<()>,
+        palettes : Arc<Vec<Palette>>,
+        poster_size : (usize, usize),
+        depth : ImageDepth,
+        access : Access
+    ) -> Result<Remote, Error> {
+        let listener = TcpListener::bind(address)?;
+        println!("Remote control listening on ws://{}/", listener.local_addr()?);
+        let (sender, requests) = crossbeam_channel::unbounded();
+        let origins = Arc::new(access.origins);
+        thread::spawn(move || {
+            let ids = AtomicU64::new(0);
+            let connected = Arc::new(AtomicUsize::new(0));
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        println!("Error accepting a remote connection {}", err);
+                        continue;
+                    }
+                };
+                let peer = stream.peer_addr().map_or_else(|_| "a client".to_string(), |addr| addr.to_string());
+                if connected.fetch_add(1, Ordering::Relaxed) >= MAX_CLIENTS {
+                    connected.fetch_sub(1, Ordering::Relaxed);
+                    println!("Remote connection from {} refused, {} clients are connected already", peer, MAX_CLIENTS);
+                    continue;
+                }
+                let sender = sender.clone();
+                let proxy = proxy.clone();
+                let origins = Arc::clone(&origins);
+                let connected = Arc::clone(&connected);
+                let id = ids.fetch_add(1, Ordering::Relaxed);
+                thread::spawn(move || {
+                    //Gone with the event loop, the next request sent finds out
+                    let wake = || {
+                        let _ = proxy.send_event(());
+                    };
+                    if let Err(err) = serve_client(stream, id, &origins, &sender, &wake) {
+                        println!("Remote connection from {} closed {}", peer, err);
+                    }
+                    connected.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+
+        //One render at a time, later ones wait their turn
+        let (renders, queue) = crossbeam_channel::unbounded::<Render>();
+        let render_palettes = Arc::clone(&palettes);
+        thread::spawn(move || {
+            for Render { params, path, size: (width, height), client } in queue {
+                let palette = &render_palettes[params.palette];
+                match offline::render_to_file(&params, palette, width, height, &path, depth) {
+                    Ok(()) => client.send(&json!({"event": "saved", "path": path.display().to_string()})),
+                    Err(err) => {
+                        println!("Error rendering {} {}", path.display(), err);
+                        client.error(format!("rendering {} {}", path.display(), err));
+                        true
+                    }
+                };
+            }
+        });
+
+        Ok(Remote {
+            requests,
+            renders,
+            subscribers: Subscribers::default(),
+            palettes,
+            dir: access.dir,
+            poster_size,
+        })
+    }
+
+    //Carries out the commands that came in since the last call. The view
+    //to fly to if a client set one
+    pub fn handle(&self, params : &MandleParams) -> Option<MandleParams> {
+        let mut target = None;
+        for Request { message, client } in self.requests.try_iter() {
+            let current = target.as_ref().unwrap_or(params).clone();
+            match message.get("command").and_then(Value::as_str) {
+                Some("get") => {
+                    client.send(&params_event("params", &current, &self.palettes));
+                }
+                Some("set") => {
+                    let mut view = current;
+                    let applied = match message.get("params") {
+                        Some(fields) => apply_json(fields, &mut view, &self.palettes),
+                        None => Err(ViewError::Invalid("set needs params".to_string())),
+                    };
+                    match applied {
+                        Ok(()) => {
+                            client.send(&params_event("params", &view, &self.palettes));
+                            target = Some(view);
+                        }
+                        Err(err) => client.error(err),
+                    }
+                }
+                Some("render") => self.render(&message, &current, client),
+                Some("subscribe") => self.subscribers.add(&client),
+                Some("unsubscribe") => self.subscribers.remove(&client),
+                Some(other) => client.error(format!("no command named {}", other)),
+                None => client.error("messages need a command"),
+            }
+        }
+        target
+    }
+
+    //Queues params to be saved to the message's path, below the directory
+    fn render(&self, message : &Value, params : &MandleParams, client : Client) {
+        let target = render_target(message, self.poster_size)
+            .and_then(|(path, size)| Ok((within(&self.dir, &path)?, size)));
+        match target {
+            Ok((path, size)) => {
+                let _ = self.renders.send(Render { params: params.clone(), path, size, client });
+            }
+            Err(err) => client.error(err),
+        }
+    }
+}
+
+//The path and size a render message asks for, default_size unless it
+//gives a width or height
+pub fn render_target(message : &Value, default_size : (usize, usize)) -> Result<(PathBuf, (usize, usize)), String> {
+    let path = message.get("path").and_then(Value::as_str).map(PathBuf::from).ok_or("render needs a path")?;
+    let size = |name : &str, default : usize| match message.get(name) {
+        Some(value) => text(value)
+            .and_then(|text| text.parse::<usize>().ok())
+            .filter(|&size| size > 0)
+            .ok_or_else(|| format!("{} {} is not a positive size", name, value)),
+        None => Ok(default),
+    };
+    let size = (size("width", default_size.0)?, size("height", default_size.1)?);
+    check_size(size)?;
+    Ok((path, size))
+}
+
+//path inside dir, refusing anything that could lead out of it
+fn within(dir : &Path, path : &Path) -> Result<PathBuf, String> {
+    let inside = path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside || path.file_name().is_none() {
+        return Err(format!("{} isn't a file name below the render directory", path.display()));
+    }
+    Ok(dir.join(path))
+}
+
+//Whether a handshake may go ahead. Browsers say which page is connecting,
+//programs don't send an Origin at all
+fn allow_origin(origin : Option<&str>, origins : &[String]) -> bool {
+    match origin {
+        Some(origin) => origins.iter().any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin)),
+        None => true,
+    }
+}
+
+pub fn error_event(message : impl std::fmt::Display) -> Value {
+    json!({"event": "error", "message": message.to_string()})
+}
+
+//An event carrying the view, the fields of a saved view
+pub fn params_event(event : &str, params : &MandleParams, palettes : &[Palette]) -> Value {
+    json!({"event": event, "params": params_json(params, palettes)})
+}
+
+//The view as the fields of a saved view
+pub fn params_json(params : &MandleParams, palettes : &[Palette]) -> Value {
+    match toml::Value::try_from(ViewState::from_params(params, palettes)) {
+        Ok(value) => from_toml(&value),
+        Err(_) => Value::Null,
+    }
+}
+
+//Changes the fields of params given in fields, which are those of a saved
+//view. Nothing is changed if any of them is invalid. Strings like the
+//coordinates may be given as numbers too, with every digit they were
+//written with
+pub fn apply_json(fields : &Value, params : &mut MandleParams, palettes : &[Palette]) -> Result<(), ViewError> {
+    let Value::Object(fields) = fields else {
+        return Err(ViewError::Invalid("params must be an object".to_string()));
+    };
+    let mut view = toml::Value::try_from(ViewState::from_params(params, palettes))?;
+    let toml::Value::Table(table) = &mut view else {
+        return Err(ViewError::Invalid("the view isn't a table".to_string()));
+    };
+    for (name, value) in fields {
+        let value = match (table.get(name), value) {
+            (Some(toml::Value::String(_)), Value::Number(number)) => toml::Value::String(number.to_string()),
+            (Some(_), value) => to_toml(value)?,
+            //The julia constant is left out of the mandlebrot set's view
+            (None, value) if name == "julia" => to_toml(value)?,
+            (None, _) => return Err(ViewError::Invalid(format!("no field named {}", name))),
+        };
+        table.insert(name.clone(), value);
+    }
+    let state : ViewState = view.try_into()?;
+    state.apply(params, palettes)
+}
+
+//A string, or a number as it was written
+fn text(value : &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+fn from_toml(value : &toml::Value) -> Value {
+    match value {
+        toml::Value::String(text) => Value::String(text.clone()),
+        toml::Value::Integer(value) => json!(value),
+        toml::Value::Float(value) => json!(value),
+        toml::Value::Boolean(value) => Value::Bool(*value),
+        toml::Value::Datetime(value) => Value::String(value.to_string()),
+        toml::Value::Array(values) => Value::Array(values.iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Object(table.iter().map(|(name, value)| (name.clone(), from_toml(value))).collect()),
+    }
+}
+
+fn to_toml(value : &Value) -> Result<toml::Value, ViewError> {
+    Ok(match value {
+        Value::Null => return Err(ViewError::Invalid("null can't be a field".to_string())),
+        Value::Bool(value) => toml::Value::Boolean(*value),
+        Value::Number(number) => {
+            let text = number.to_string();
+            match text.parse::<i64>() {
+                Ok(integer) => toml::Value::Integer(integer),
+                Err(_) => toml::Value::Float(text.parse().map_err(|_| ViewError::Invalid(format!("{} is not a number", text)))?),
+            }
+        }
+        Value::String(text) => toml::Value::String(text.clone()),
+        Value::Array(values) => toml::Value::Array(values.iter().map(to_toml).collect::<Result<_, _>>()?),
+        Value::Object(fields) => toml::Value::Table(fields.iter()
+            .map(|(name, value)| Ok((name.clone(), to_toml(value)?)))
+            .collect::<Result<_, ViewError>>()?),
+    })
+}
+
+//Upgrades the connection, then passes the client's messages on, calling
+//wake for each, and sends it its events until it closes
+fn serve_client(
+    stream : TcpStream,
+    id : u64,
+    origins : &[String],
+    requests : &Sender<Request>,
+    wake : &dyn Fn()
+) -> Result<(), Error> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE))
+        .max_frame_size(Some(MAX_MESSAGE));
+    //The error is the response tungstenite sends, its type isn't ours to shrink
+    #[allow(clippy::result_large_err)]
+    let check = |request : &Handshake, response : Response| -> Result<Response, ErrorResponse> {
+        let origin = request.headers().get("origin").map(|origin| origin.to_str().unwrap_or("?"));
+        if allow_origin(origin, origins) {
+            return Ok(response);
+        }
+        let mut refused = ErrorResponse::new(Some("Origin not allowed, see --remote-origin\n".to_string()));
+        *refused.status_mut() = StatusCode::FORBIDDEN;
+        Err(refused)
+    };
+    let mut socket = tungstenite::accept_hdr_with_config(stream, check, Some(config))
+        .map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let (outgoing, queue) = crossbeam_channel::unbounded();
+    let client = Client { id, outgoing };
+    loop {
+        match socket.read() {
+            //Binary messages are taken as text too
+            Ok(Message::Text(text)) => pass_on(text.as_bytes(), &client, requests, wake)?,
+            Ok(Message::Binary(bytes)) => pass_on(&bytes, &client, requests, wake)?,
+            //Pings are answered and closes echoed by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err)) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => return Ok(()),
+            //Frames breaking the protocol, like unmasked ones, close the
+            //connection with a status saying why
+            Err(err) => {
+                let (code, reason) = match err {
+                    tungstenite::Error::Capacity(_) => (CloseCode::Size, "message too long"),
+                    tungstenite::Error::Utf8(_) => (CloseCode::Invalid, "text isn't UTF-8"),
+                    _ => (CloseCode::Protocol, "protocol error"),
+                };
+                let _ = socket.close(Some(CloseFrame { code, reason: reason.into() }));
+                let _ = socket.flush();
+                return Err(Error::new(ErrorKind::InvalidData, err.to_string()));
+            }
+        }
+        send_queued(&mut socket, &queue)?;
+    }
+}
+
+//Hands a message to the event loop, an error back to the client if it isn't JSON
+fn pass_on(message : &[u8], client : &Client, requests : &Sender<Request>, wake : &dyn Fn()) -> Result<(), Error> {
+    match serde_json::from_slice::<Value>(message) {
+        Ok(message) => {
+            if requests.send(Request { message, client: client.clone() }).is_err() {
+                return Err(Error::new(ErrorKind::BrokenPipe, "the viewer closed"));
+            }
+            wake();
+        }
+        Err(err) => client.error(format!("not JSON, {}", err)),
+    }
+    Ok(())
+}
+
+//Writes the events waiting for the client, and the pongs and closes
+//tungstenite has queued up
+fn send_queued(socket : &mut WebSocket<TcpStream>, queue : &Receiver<String>) -> Result<(), Error> {
+    let closed = |err : tungstenite::Error| Error::new(ErrorKind::BrokenPipe, err.to_string());
+    for text in queue.try_iter() {
+        socket.write(Message::text(text)).map_err(closed)?;
+    }
+    match socket.flush() {
+        Ok(()) | Err(tungstenite::Error::ConnectionClosed) => Ok(()),
+        Err(err) => Err(closed(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+
+    use tungstenite::HandshakeError;
+    use tungstenite::client::IntoClientRequest;
+
+    //One client served on a local socket, its requests on the receiver
+    fn serve(origins : &[&str]) -> (SocketAddr, Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let origins : Vec<String> = origins.iter().map(|origin| origin.to_string()).collect();
+        let (sender, requests) = crossbeam_channel::unbounded();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = serve_client(stream, 0, &origins, &sender, &|| {});
+        });
+        (address, requests)
+    }
+
+    fn connect(address : SocketAddr, origin : Option<&str>) -> Result<WebSocket<TcpStream>, tungstenite::Error> {
+        let mut request = format!("ws://{}/", address).into_client_request().unwrap();
+        if let Some(origin) = origin {
+            request.headers_mut().insert("origin", origin.parse().unwrap());
+        }
+        match tungstenite::client(request, TcpStream::connect(address).unwrap()) {
+            Ok((socket, _)) => Ok(socket),
+            Err(HandshakeError::Failure(err)) => Err(err),
+            Err(HandshakeError::Interrupted(_)) => panic!("handshake interrupted"),
+        }
+    }
+
+    //A raw connection that has sent request and read the server's answer
+    fn raw_handshake(address : SocketAddr, request : &str) -> (TcpStream, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        let mut byte = [0u8];
+        while !response.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+            response.push(byte[0]);
+        }
+        (stream, String::from_utf8_lossy(&response).into_owned())
+    }
+
+    fn upgrade_request(version : u32) -> String {
+        format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: {}\r\n\r\n",
+            version
+        )
+    }
+
+    //A client frame, masked unless mask is all zeroes
+    fn frame(first : u8, payload : &[u8], mask : [u8; 4]) -> Vec<u8> {
+        let masked = mask != [0; 4];
+        let mut frame = vec![first, payload.len() as u8 | if masked { 0x80 } else { 0 }];
+        if masked {
+            frame.extend_from_slice(&mask);
+        }
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        frame
+    }
+
+    //The status of the close frame the server answers with
+    fn close_status(stream : &mut TcpStream) -> u16 {
+        let mut head = [0u8; 4];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head[0], 0x88, "not a close frame");
+        u16::from_be_bytes([head[2], head[3]])
+    }
+
+    #[test]
+    fn commands_reach_the_viewer() {
+        let (address, requests) = serve(&[]);
+        let mut socket = connect(address, None).unwrap();
+        socket.send(Message::text(r#"{"command": "get"}"#)).unwrap();
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.message["command"], "get");
+
+        request.client.send(&json!({"event": "params"}));
+        let reply : Value = serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap();
+        assert_eq!(reply["event"], "params");
+
+        socket.send(Message::text("{not json")).unwrap();
+        let reply : Value = serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap();
+        assert_eq!(reply["event"], "error");
+    }
+
+    #[test]
+    fn refuses_other_origins() {
+        let (address, _requests) = serve(&["http://localhost:8080"]);
+        match connect(address, Some("https://example.com")) {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), StatusCode::FORBIDDEN),
+            other => panic!("connected from another origin {:?}", other.map(|_| ())),
+        }
+        let (address, _requests) = serve(&["http://localhost:8080"]);
+        assert!(connect(address, Some("http://localhost:8080")).is_ok());
+    }
+
+    #[test]
+    fn handshakes_need_websocket_13() {
+        let (address, _requests) = serve(&[]);
+        let (_, response) = raw_handshake(address, &upgrade_request(8));
+        assert!(!response.starts_with("HTTP/1.1 101"), "{}", response);
+
+        let (address, _requests) = serve(&[]);
+        let request = upgrade_request(13).replace("Upgrade: websocket\r\n", "");
+        let (_, response) = raw_handshake(address, &request);
+        assert!(!response.starts_with("HTTP/1.1 101"), "{}", response);
+    }
+
+    #[test]
+    fn unmasked_frames_close_the_connection() {
+        let (address, _requests) = serve(&[]);
+        let (mut stream, response) = raw_handshake(address, &upgrade_request(13));
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+        stream.write_all(&frame(0x81, b"{}", [0; 4])).unwrap();
+        assert_eq!(close_status(&mut stream), 1002);
+    }
+
+    #[test]
+    fn text_inside_a_fragmented_message_closes_the_connection() {
+        let (address, _requests) = serve(&[]);
+        let (mut stream, _) = raw_handshake(address, &upgrade_request(13));
+        stream.write_all(&frame(0x01, br#"{"command""#, [1, 2, 3, 4])).unwrap();
+        stream.write_all(&frame(0x81, br#"{"command": "get"}"#, [5, 6, 7, 8])).unwrap();
+        assert_eq!(close_status(&mut stream), 1002);
+    }
+
+    #[test]
+    fn renders_stay_in_the_directory() {
+        let dir = Path::new("renders");
+        assert_eq!(within(dir, Path::new("poster.png")), Ok(dir.join("poster.png")));
+        assert_eq!(within(dir, Path::new("./zooms/poster.png")), Ok(dir.join("./zooms/poster.png")));
+        for path in ["../poster.png", "zooms/../../poster.png", "/etc/poster.png", "", ".", "zooms/.."] {
+            assert!(within(dir, Path::new(path)).is_err(), "{} allowed", path);
+        }
+    }
+
+    #[test]
+    fn browsers_need_an_allowed_origin() {
+        let origins = vec!["http://localhost:8080/".to_string()];
+        assert!(allow_origin(None, &origins));
+        assert!(allow_origin(Some("http://localhost:8080"), &origins));
+        assert!(allow_origin(Some("HTTP://LOCALHOST:8080"), &origins));
+        assert!(!allow_origin(Some("http://localhost:8081"), &origins));
+        assert!(!allow_origin(Some("https://example.com"), &origins));
+        assert!(!allow_origin(Some("null"), &origins));
+        assert!(!allow_origin(Some("http://localhost:8080"), &[]));
+    }
+
+    #[test]
+    fn render_sizes_are_limited() {
+        let message = |text : &str| serde_json::from_str::<Value>(text).unwrap();
+        let default = (800, 600);
+        assert_eq!(render_target(&message(r#"{"path": "a.png"}"#), default), Ok((PathBuf::from("a.png"), default)));
+        assert_eq!(render_target(&message(r#"{"path": "a.png", "width": "1920"}"#), default).map(|(_, size)| size), Ok((1920, 600)));
+        for text in [
+            r#"{"width": 100}"#,
+            r#"{"path": "a.png", "width": 0}"#,
+            r#"{"path": "a.png", "width": -5}"#,
+            r#"{"path": "a.png", "width": 1.5}"#,
+            r#"{"path": "a.png", "width": 1000000, "height": 1000000}"#,
+            r#"{"path": "a.png", "width": 18446744073709551616}"#,
+        ] {
+            assert!(render_target(&message(text), default).is_err(), "{} allowed", text);
+        }
+    }
+
+    #[test]
+    fn numbers_keep_their_digits() {
+        let mut params = MandleParams::new(64, 64);
+        let fields = serde_json::from_str::<Value>(r#"{"x": -0.743643887037158704752191506114774, "iterations": 500}"#).unwrap();
+        apply_json(&fields, &mut params, &mandelbrot_core::palette::builtin_palettes()).unwrap();
+        assert_eq!(params.x, "-0.743643887037158704752191506114774".parse().unwrap());
+        assert_eq!(params.iterations, 500);
+    }
+}
@@ -0,0 +1,272 @@
+//Message passing between the event loop and a thread rendering for it.
+//The event loop owns the params and sends a full copy every time it changes
+//them, the render thread works through the newest one and leaves finished
+//frames for the event loop, asking the window for a redraw to present them
+
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crossbeam_channel::{Receiver, Select, Sender, TryRecvError};
+use winit::window::Window;
+
+use mandelbrot_core::{CancelToken, Grid, MandleParams, Sample};
+
+//Params to render, numbered so the render thread can tell newer ones apart
+struct RenderRequest {
+    params : MandleParams,
+    generation : u64,
+}
+
+//A rendered rgba image ready to be presented, and the params it was rendered for
+pub struct Frame {
+    pub pixels : Vec<u8>,
+    pub params : MandleParams,
+}
+
+//The samples of the last view computed in full, for looking up the pixel
+//under the cursor
+pub struct Finished {
+    pub params : MandleParams,
+    pub grid : Grid<Sample>,
+}
+
+//Both ends for a render thread starting on params, drawing into window
+pub fn channel(params : MandleParams, window : Arc<Window>) -> (Settings, Requests) {
+    let (request_sender, request_receiver) = crossbeam_channel::unbounded();
+    //Only one frame waits at a time, the render thread doesn't get ahead of the window
+    let (frame_sender, frame_receiver) = crossbeam_channel::bounded(1);
+    let (pause_sender, pause_receiver) = crossbeam_channel::unbounded();
+    let latest = Arc::new(AtomicU64::new(0));
+    let inspecting = Arc::new(AtomicBool::new(false));
+    let finished = Arc::new(Mutex::new(None));
+    let settings = Settings {
+        params,
+        generation: 0,
+        requests: request_sender,
+        latest: Arc::clone(&latest),
+        frames: frame_receiver,
+        paused: false,
+        pause: pause_sender,
+        inspecting: Arc::clone(&inspecting),
+        finished: Arc::clone(&finished),
+    };
+    settings.send();
+    let requests = Requests {
+        requests: request_receiver,
+        latest,
+        frames: frame_sender,
+        window,
+        pause: pause_receiver,
+        paused: Cell::new(false),
+        presented: Cell::new(false),
+        inspecting,
+        finished,
+    };
+    (settings, requests)
+}
+
+//The event loop's end. Every write is sent on as a new request
+pub struct Settings {
+    params : MandleParams,
+    generation : u64,
+    requests : Sender<RenderRequest>,
+    //Generation of the last request that needs the grid recomputed, the
+    //render thread's cancel tokens poll it per pixel
+    latest : Arc<AtomicU64>,
+    frames : Receiver<Frame>,
+    paused : bool,
+    pause : Sender<bool>,
+    //Whether the render thread should share the grids it finishes, they are
+    //only copied while something looks at them
+    inspecting : Arc<AtomicBool>,
+    finished : Arc<Mutex<Option<Arc<Finished>>>>,
+}
+
+impl Settings {
+
+    pub fn write(&mut self) -> ParamsWriteGuard<'_> {
+        ParamsWriteGuard {
+            before: self.params.clone(),
+            settings: self,
+        }
+    }
+
+    pub fn snapshot(&self) -> MandleParams {
+        self.params.clone()
+    }
+
+    //The newest frame waiting, if any
+    pub fn latest_frame(&self) -> Option<Frame> {
+        self.frames.try_iter().last()
+    }
+
+    //Holds the render thread at its next frame until unpaused, for windows
+    //nobody is looking at. New requests still get their first frame drawn
+    pub fn pause(&mut self, paused : bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            let _ = self.pause.send(paused);
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    //Starts or stops sharing finished grids. Starting asks for the current
+    //view again, which only recolours if it is done, so its grid comes over
+    //without waiting for the next change
+    pub fn inspect(&mut self, inspecting : bool) {
+        self.inspecting.store(inspecting, Ordering::Relaxed);
+        if inspecting {
+            self.generation += 1;
+            self.send();
+        } else {
+            *self.finished.lock().unwrap() = None;
+        }
+    }
+
+    //The grid of the last view the render thread finished while inspecting
+    pub fn finished(&self) -> Option<Arc<Finished>> {
+        self.finished.lock().unwrap().clone()
+    }
+
+    //The render thread is gone once the event loop is, nothing to do about it
+    fn send(&self) {
+        let _ = self.requests.send(RenderRequest {
+            params: self.params.clone(),
+            generation: self.generation,
+        });
+    }
+}
+
+pub struct ParamsWriteGuard<'a> {
+    settings : &'a mut Settings,
+    //Params when the write started, to tell if it only recolours
+    before : MandleParams,
+}
+
+impl Deref for ParamsWriteGuard<'_> {
+    type Target = MandleParams;
+
+    fn deref(&self) -> &MandleParams {
+        &self.settings.params
+    }
+}
+
+impl DerefMut for ParamsWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut MandleParams {
+        &mut self.settings.params
+    }
+}
+
+impl Drop for ParamsWriteGuard<'_> {
+    fn drop(&mut self) {
+        let settings = &mut *self.settings;
+        settings.generation += 1;
+        //A render in progress can carry on and be recoloured once it's done,
+        //so palette cycling doesn't keep restarting it
+        if !settings.params.recolors(&self.before) {
+            settings.latest.store(settings.generation, Ordering::Relaxed);
+        }
+        settings.send();
+    }
+}
+
+//The render thread's end
+pub struct Requests {
+    requests : Receiver<RenderRequest>,
+    latest : Arc<AtomicU64>,
+    frames : Sender<Frame>,
+    window : Arc<Window>,
+    pause : Receiver<bool>,
+    paused : Cell<bool>,
+    //Whether a frame went out since the last call to next
+    presented : Cell<bool>,
+    inspecting : Arc<AtomicBool>,
+    finished : Arc<Mutex<Option<Arc<Finished>>>>,
+}
+
+impl Requests {
+
+    //Blocks for the next params and their generation, skipping to the newest
+    //when several queued up. None once the event loop has gone
+    pub fn next(&self) -> Option<(MandleParams, u64)> {
+        let request = self.requests.recv().ok()?;
+        let request = self.requests.try_iter().last().unwrap_or(request);
+        self.presented.set(false);
+        Some((request.params, request.generation))
+    }
+
+    //Whether anything was requested since the last call to next, including
+    //changes that only recolour and so don't cancel
+    pub fn pending(&self) -> bool {
+        !self.requests.is_empty()
+    }
+
+    //Token for a render of the given generation, cancelled by the next
+    //request that needs the grid recomputed
+    pub fn cancel_token(&self, generation : u64) -> CancelToken<'_> {
+        CancelToken::new(&self.latest, generation)
+    }
+
+    //Leaves a copy of the frame for the event loop to present on its next
+    //redraw, waiting for it to take the one before. False once it has gone.
+    //While paused only the first frame of a request goes out, later passes
+    //wait for the pause to end or something newer to be requested
+    pub fn present(&self, pixels : &[u8], params : &MandleParams) -> bool {
+        if self.presented.get() {
+            self.wait_while_paused();
+        }
+        self.presented.set(true);
+        let frame = Frame {
+            pixels: pixels.to_vec(),
+            params: params.clone(),
+        };
+        if self.frames.send(frame).is_err() {
+            return false;
+        }
+        self.window.request_redraw();
+        true
+    }
+
+    //Shares the grid computed in full for params, when the event loop is
+    //inspecting and doesn't already have one for the same view
+    pub fn finished(&self, params : &MandleParams, grid : &Grid<Sample>) {
+        if !self.inspecting.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut finished = self.finished.lock().unwrap();
+        if finished.as_ref().is_some_and(|finished| params.recolors(&finished.params)) {
+            return;
+        }
+        *finished = Some(Arc::new(Finished {
+            params: params.clone(),
+            grid: grid.clone(),
+        }));
+        //The title is only looked at on the next pass of the event loop
+        self.window.request_redraw();
+    }
+
+    fn wait_while_paused(&self) {
+        loop {
+            loop {
+                match self.pause.try_recv() {
+                    Ok(paused) => self.paused.set(paused),
+                    Err(TryRecvError::Empty) => break,
+                    //The event loop has gone, present finds out
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            if !self.paused.get() || self.pending() {
+                return;
+            }
+            let mut select = Select::new();
+            select.recv(&self.pause);
+            select.recv(&self.requests);
+            select.ready();
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//Slippy map tiles of the set over HTTP, /z/x/y.png as Leaflet and
+//OpenLayers ask for them. Tile 0/0/0 is a square around the starting view
+//as wide as its longer side, each level splits every tile of the one above
+//in four. Tiles are computed when first asked for and the encoded PNGs kept
+//in memory, the least recently asked for go first. / serves a Leaflet page
+//browsing them
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{offline, MandleParams, MIN_ZOOM};
+
+//Edge length of a tile in pixels, what map libraries expect
+const TILE_PIXELS: usize = 256;
+
+//Deepest level. Past it the tile offsets from the centre lose precision in
+//f64 before the pixels do
+const MAX_LEVEL: u32 = 44;
+
+//How long a connection is kept open between requests
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+//Connections served at once, the ones after are turned away. Browsers
+//open a handful each
+const MAX_CONNECTIONS: usize = 64;
+
+//Longest request or header line read, far past any tile's
+const MAX_LINE: usize = 8192;
+
+const INDEX: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Mandlebrot set</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>html, body, #map { height: 100%; margin: 0; background: #000; }</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+const map = L.map("map", { crs: L.CRS.Simple, maxZoom: MAX_LEVEL }).setView([-128, 128], 1);
+L.tileLayer("/{z}/{x}/{y}.png", { maxZoom: MAX_LEVEL, noWrap: true, bounds: [[0, 0], [-256, 256]] }).addTo(map);
+</script>
+</body>
+</html>
+"#;
+
+//Level, column and row
+type TileId = (u32, u64, u64);
+
+struct PngCache {
+    capacity : usize,
+    //Each tile with when it was last asked for
+    tiles : HashMap<TileId, (Arc<Vec<u8>>, u64)>,
+    clock : u64,
+}
+
+impl PngCache {
+
+    fn get(&mut self, id : TileId) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        let (png, used) = self.tiles.get_mut(&id)?;
+        *used = self.clock;
+        Some(png.clone())
+    }
+
+    fn insert(&mut self, id : TileId, png : Arc<Vec<u8>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.tiles.len() >= self.capacity && !self.tiles.contains_key(&id) {
+            let oldest = self.tiles.iter().min_by_key(|(_, (_, used))| *used).map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                self.tiles.remove(&oldest);
+            }
+        }
+        self.tiles.insert(id, (png, self.clock));
+    }
+}
+
+struct Server {
+    origin : MandleParams,
+    //Edge length of tile 0/0/0 in the complex plane
+    side : f64,
+    palette : Palette,
+    cache : Mutex<PngCache>,
+}
+
+impl Server {
+
+    fn new(params : &MandleParams, palette : &Palette, capacity : usize) -> Server {
+        Server {
+            origin: MandleParams {
+                //Each tile is equalized on its own, which would show at the seams
+                histogram: false,
+                ..params.clone()
+            },
+            side: params.zoom * params.width.max(params.height) as f64,
+            palette: palette.clone(),
+            cache: Mutex::new(PngCache { capacity, tiles: HashMap::new(), clock: 0 }),
+        }
+    }
+}
+
+struct Response {
+    status : &'static str,
+    content_type : &'static str,
+    body : Arc<Vec<u8>>,
+}
+
+impl Response {
+
+    fn text(status : &'static str, text : &str) -> Response {
+        Response { status, content_type: "text/plain; charset=utf-8", body: Arc::new(text.as_bytes().to_vec()) }
+    }
+}
+
+//Serves tiles of params drawn with palette on address until killed,
+//keeping up to capacity of them. Each connection gets a thread
+pub fn run(params : &MandleParams, palette : &Palette, address : &str, capacity : usize) -> Result<(), Error> {
+    let listener = TcpListener::bind(address)?;
+    println!("Serving tiles on http://{}/, z/x/y.png down to level {}", listener.local_addr()?, MAX_LEVEL);
+    let server = Arc::new(Server::new(params, palette, capacity));
+    let connected = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                println!("Error accepting a connection {}", err);
+                continue;
+            }
+        };
+        if connected.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+            connected.fetch_sub(1, Ordering::Relaxed);
+            //Small enough to fit the socket's buffer, so this never waits
+            let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            continue;
+        }
+        let server = server.clone();
+        let connected = Arc::clone(&connected);
+        std::thread::spawn(move || {
+            //A client going away mid request is nothing to report
+            let _ = stream.set_read_timeout(Some(IDLE_TIMEOUT))
+                .and_then(|()| stream.set_write_timeout(Some(IDLE_TIMEOUT)))
+                .and_then(|()| stream.try_clone())
+                .and_then(|reader| serve_connection(BufReader::new(reader), stream, &server));
+            connected.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+    Ok(())
+}
+
+//Answers requests read from reader on writer until the client closes the
+//connection or goes quiet
+fn serve_connection(mut reader : impl BufRead, mut writer : impl Write, server : &Server) -> Result<(), Error> {
+    while let Some(request) = read_line(&mut reader)? {
+        let mut close = false;
+        loop {
+            let Some(header) = read_line(&mut reader)? else {
+                return Ok(());
+            };
+            if header.is_empty() {
+                break;
+            }
+            close |= header.eq_ignore_ascii_case("connection: close");
+        }
+
+        let mut parts = request.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let response = match method {
+            "GET" | "HEAD" => route(server, target),
+            _ => Response::text("405 Method Not Allowed", "Only GET and HEAD are served\n"),
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\n{}\r\n",
+            response.status,
+            response.content_type,
+            response.body.len(),
+            if close { "Connection: close\r\n" } else { "" },
+        )?;
+        if method != "HEAD" {
+            writer.write_all(&response.body)?;
+        }
+        writer.flush()?;
+        if close {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+//A line without its ending, None at the end of the stream. A longer one
+//than MAX_LINE ends the connection
+fn read_line(reader : &mut impl BufRead) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_LINE as u64).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && line.len() == MAX_LINE {
+        return Err(Error::new(ErrorKind::InvalidData, format!("a line longer than {} bytes", MAX_LINE)));
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn route(server : &Server, target : &str) -> Response {
+    let path = target.split(['?', '#']).next().unwrap_or("");
+    if path == "/" || path == "/index.html" {
+        let page = INDEX.replace("MAX_LEVEL", &MAX_LEVEL.to_string());
+        return Response { status: "200 OK", content_type: "text/html; charset=utf-8", body: Arc::new(page.into_bytes()) };
+    }
+    let Some(id) = parse_tile(path) else {
+        return Response::text("404 Not Found", "Tiles are at /z/x/y.png\n");
+    };
+    match tile(server, id) {
+        Ok(png) => Response { status: "200 OK", content_type: "image/png", body: png },
+        Err(err) => {
+            println!("Error rendering tile {}/{}/{} {}", id.0, id.1, id.2, err);
+            Response::text("500 Internal Server Error", &format!("{}\n", err))
+        }
+    }
+}
+
+//The tile a path like /3/5/2.png names, if it is one inside the map
+fn parse_tile(path : &str) -> Option<TileId> {
+    let mut parts = path.strip_prefix('/')?.strip_suffix(".png")?.split('/');
+    let level = parts.next()?.parse::<u32>().ok()?;
+    let col = parts.next()?.parse::<u64>().ok()?;
+    let row = parts.next()?.parse::<u64>().ok()?;
+    let tiles = 1u64 << level.min(MAX_LEVEL);
+    (parts.next().is_none() && level <= MAX_LEVEL && col < tiles && row < tiles).then_some((level, col, row))
+}
+
+fn tile(server : &Server, id : TileId) -> Result<Arc<Vec<u8>>, offline::OfflineError> {
+    if let Some(png) = server.cache.lock().unwrap().get(id) {
+        return Ok(png);
+    }
+    let params = tile_params(server, id);
+    let image = offline::render_image(&params, &server.palette, TILE_PIXELS, TILE_PIXELS, |_| {});
+    let mut png = Vec::new();
+    offline::encode_png(&mut png, &image, TILE_PIXELS, TILE_PIXELS)?;
+    let png = Arc::new(png);
+    server.cache.lock().unwrap().insert(id, png.clone());
+    Ok(png)
+}
+
+//The view of a tile, rows running down the image like the plane's y does
+fn tile_params(server : &Server, (level, col, row) : TileId) -> MandleParams {
+    let tiles = (1u64 << level) as f64;
+    let origin = &server.origin;
+    MandleParams {
+        x: origin.x.offset(((col as f64 + 0.5) / tiles - 0.5) * server.side),
+        y: origin.y.offset(((row as f64 + 0.5) / tiles - 0.5) * server.side),
+        zoom: (server.side / tiles / TILE_PIXELS as f64).max(MIN_ZOOM),
+        width: TILE_PIXELS,
+        height: TILE_PIXELS,
+        ..origin.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mandelbrot_core::palette;
+
+    fn server() -> Server {
+        Server::new(&MandleParams::new(64, 48), &palette::builtin_palettes()[0], 4)
+    }
+
+    //What the server writes back to requests sent on one connection
+    fn exchange(server : &Server, requests : &str) -> String {
+        let mut written = Vec::new();
+        serve_connection(requests.as_bytes(), &mut written, server).unwrap();
+        String::from_utf8_lossy(&written).into_owned()
+    }
+
+    #[test]
+    fn tiles_in_the_map_parse() {
+        assert_eq!(parse_tile("/0/0/0.png"), Some((0, 0, 0)));
+        assert_eq!(parse_tile("/3/7/5.png"), Some((3, 7, 5)));
+        let last = (1u64 << MAX_LEVEL) - 1;
+        assert_eq!(parse_tile(&format!("/{}/{}/{}.png", MAX_LEVEL, last, last)), Some((MAX_LEVEL, last, last)));
+    }
+
+    #[test]
+    fn paths_outside_the_map_are_not_tiles() {
+        for path in [
+            "/-1/0/0.png", "/0/-1/0.png", "/0/0/-1.png",
+            //Past the level's edge or the deepest level
+            "/1/2/0.png", "/1/0/2.png", &format!("/{}/0/0.png", MAX_LEVEL + 1), "/4294967296/0/0.png",
+            "/0/0/0", "/0/0/0.jpg", "0/0/0.png",
+            "/0/0/0/0.png", "/0/0.png", "/a/0/0/0.png", "//0/0/0.png",
+        ] {
+            assert_eq!(parse_tile(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn other_paths_and_methods_are_refused() {
+        let response = exchange(&server(), "GET /nothing HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", response);
+        let response = exchange(&server(), "POST /0/0/0.png HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"), "{}", response);
+    }
+
+    #[test]
+    fn connections_are_kept_alive() {
+        let server = server();
+        let response = exchange(&server, "GET /0/0/0.png HTTP/1.1\r\nHost: x\r\n\r\nHEAD /0/0/0.png HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n");
+        let answers : Vec<&str> = response.matches("HTTP/1.1 200 OK\r\n").collect();
+        //The one after close isn't answered
+        assert_eq!(answers.len(), 3, "{}", response);
+        assert!(response.contains("Content-Type: image/png\r\n"));
+        //The signature after its first byte, which isn't UTF-8
+        assert!(response.contains("PNG\r\n\u{1a}\n"));
+        assert_eq!(response.matches("Connection: close\r\n").count(), 1);
+        assert!(response.trim_end().ends_with("</html>"));
+        assert_eq!(server.cache.lock().unwrap().tiles.len(), 1);
+    }
+
+    #[test]
+    fn long_lines_end_the_connection() {
+        let request = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        let mut written = Vec::new();
+        assert!(serve_connection(request.as_bytes(), &mut written, &server()).is_err());
+        assert!(written.is_empty());
+    }
+}
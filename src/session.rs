@@ -0,0 +1,143 @@
+//The viewer's state kept between runs. Saved every so often while it is
+//open and on the way out, and picked up again on the next launch. The view
+//is saved like F5 saves it, with the history of positions Backspace goes
+//through. Bookmarks and keyframes have files of their own already
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{Fractal, MandleParams};
+
+use crate::history::History;
+use crate::view::{JuliaConstant, ViewError, ViewState, parse_real, parse_zoom};
+
+//How often the session is saved while it's changing
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub view : ViewState,
+    #[serde(default)]
+    pub back : Vec<Position>,
+    #[serde(default)]
+    pub forward : Vec<Position>,
+}
+
+//One step of the history, as much of the params as it keeps
+#[derive(Serialize, Deserialize)]
+pub struct Position {
+    pub x : String,
+    pub y : String,
+    pub zoom : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub julia : Option<JuliaConstant>,
+}
+
+impl Position {
+
+    fn from_params(params : &MandleParams) -> Position {
+        Position {
+            x: params.x.to_string(),
+            y: params.y.to_string(),
+            zoom: format!("{:e}", params.zoom),
+            julia: match params.fractal {
+                Fractal::Mandlebrot => None,
+                Fractal::Julia { c_a, c_b } => Some(JuliaConstant {
+                    x: c_a.to_string(),
+                    y: c_b.to_string(),
+                }),
+            },
+        }
+    }
+
+    //params at this position
+    fn apply(&self, params : &MandleParams) -> Result<MandleParams, ViewError> {
+        Ok(MandleParams {
+            x: parse_real("x", &self.x)?,
+            y: parse_real("y", &self.y)?,
+            zoom: parse_zoom(&self.zoom)?,
+            fractal: match &self.julia {
+                None => Fractal::Mandlebrot,
+                Some(julia) => Fractal::Julia {
+                    c_a: parse_real("julia x", &julia.x)?,
+                    c_b: parse_real("julia y", &julia.y)?,
+                },
+            },
+            ..params.clone()
+        })
+    }
+}
+
+impl Session {
+
+    pub fn new(params : &MandleParams, history : &History, palettes : &[Palette]) -> Session {
+        Session {
+            view: ViewState::from_params(params, palettes),
+            back: history.back().map(Position::from_params).collect(),
+            forward: history.forward().iter().map(Position::from_params).collect(),
+        }
+    }
+
+    //Moves params to the saved view, with the history that led there.
+    //Nothing is changed if anything saved is invalid
+    pub fn restore(&self, params : &mut MandleParams, palettes : &[Palette]) -> Result<History, ViewError> {
+        let mut view = params.clone();
+        self.view.apply(&mut view, palettes)?;
+        let steps = |positions : &[Position]| {
+            positions.iter().map(|position| position.apply(&view)).collect::<Result<Vec<_>, _>>()
+        };
+        let history = History::restore(&view, steps(&self.back)?, steps(&self.forward)?);
+        *params = view;
+        Ok(history)
+    }
+
+    pub fn load(path : &Path) -> Result<Session, ViewError> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+//Written next to the file and moved over it, so a crash halfway through
+//leaves the last session there
+fn write(path : &Path, text : &str) -> Result<(), ViewError> {
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, text)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+//Saves the session every AUTOSAVE_INTERVAL, when it has changed
+pub struct Autosave {
+    saved : Option<String>,
+    last : Instant,
+}
+
+impl Autosave {
+
+    pub fn new() -> Autosave {
+        Autosave {
+            saved: None,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.elapsed() >= AUTOSAVE_INTERVAL
+    }
+
+    pub fn save(&mut self, session : &Session, path : &Path) {
+        self.last = Instant::now();
+        let saved = toml::to_string(session).map_err(ViewError::from).and_then(|text| {
+            if self.saved.as_ref() != Some(&text) {
+                write(path, &text)?;
+                self.saved = Some(text);
+            }
+            Ok(())
+        });
+        if let Err(err) = saved {
+            println!("Error saving session to {} {}", path.display(), err);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//A quick f32 picture of the view drawn by a fragment shader straight onto
+//the window, for while it moves. The render thread leaves the pixels it
+//hasn't computed in full transparent and the sketch shows through them, so
+//the cpu's pixels replace it tile by tile once the view stops. Past f32 or
+//for anything the shader doesn't draw the frame is shown as before
+
+use std::num::NonZeroU32;
+
+use pixels::{wgpu, Pixels};
+
+use mandelbrot_core::palette::{self, Palette};
+use mandelbrot_core::{formula, Backend, ColorMode, Fractal, MandleParams, RenderMode, GPU_MIN_ZOOM};
+
+//Size of the Params struct in sketch.wgsl, padded to 16 bytes for the uniform buffer
+const PARAMS_SIZE: u64 = 64;
+
+//Texels the palette is sampled into, between them it is interpolated
+const PALETTE_TEXELS: u32 = 512;
+
+//Iterations the shader goes up to at most, deeper orbits are left black
+//until the cpu gets to them so the sketch stays quick
+const MAX_ITERATIONS: u32 = 2048;
+
+//Whether the sketch is drawn for params, resolved as the render thread has them.
+//Only the palette colouring, histograms and the other modes look too different
+pub fn covers(params : &MandleParams) -> bool {
+    params.sketch
+        && params.mode == RenderMode::EscapeTime
+        && formula::get(params.formula).supports(Backend::Gpu)
+        && params.zoom >= GPU_MIN_ZOOM
+        && matches!(params.color_mode, ColorMode::Discrete | ColorMode::Smooth)
+        && !params.histogram
+}
+
+//Leaves the whole frame for the sketch, for frames not yet computed in full
+pub fn clear(frame : &mut [u8]) {
+    frame.fill(0);
+}
+
+//What to sketch in the window showing view and a frame rendered for shown,
+//and whether the frame goes over it. None shows the frame on its own
+pub fn plan(view : &MandleParams, shown : Option<&MandleParams>) -> Option<(MandleParams, bool)> {
+    let view = view.resolved();
+    if covers(&view) {
+        let recolors = shown.is_some_and(|shown| view.recolors(shown));
+        return Some((view, recolors));
+    }
+    //A frame left for a sketch that no longer applies, eg. zoomed past f32
+    //before the render thread caught up
+    shown.filter(|shown| covers(shown)).map(|shown| (shown.clone(), true))
+}
+
+pub struct Sketch {
+    pipeline : wgpu::RenderPipeline,
+    params : wgpu::Buffer,
+    palette : wgpu::Texture,
+    sampler : wgpu::Sampler,
+    bind_group : wgpu::BindGroup,
+}
+
+impl Sketch {
+
+    //A pipeline drawing onto pixels' surface, reading its buffer
+    pub fn new(pixels : &Pixels) -> Sketch {
+        let device = &pixels.context().device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sketch_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("sketch.wgsl").into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sketch_pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: pixels.render_texture_format(),
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sketch_params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        //sRGB so the shader reads linear light, like the frame
+        let palette = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("sketch_palette"),
+            size: wgpu::Extent3d { width: PALETTE_TEXELS, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("sketch_palette_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..wgpu::SamplerDescriptor::default()
+        });
+        let bind_group = bind_group(pixels, &pipeline, &params, &palette, &sampler);
+        Sketch {
+            pipeline,
+            params,
+            palette,
+            sampler,
+            bind_group,
+        }
+    }
+
+    //pixels makes a new buffer texture when it is resized, the sketch has
+    //to read that one instead
+    pub fn resize(&mut self, pixels : &Pixels) {
+        self.bind_group = bind_group(pixels, &self.pipeline, &self.params, &self.palette, &self.sampler);
+    }
+
+    //Sets up the next render to sketch params on a width x height frame,
+    //coloured with palette, with the frame over it if over
+    pub fn prepare(&self, pixels : &Pixels, params : &MandleParams, over : bool, palette : &Palette, (width, height) : (u32, u32)) {
+        let queue = &pixels.context().queue;
+
+        let mut uniform = [0u8; PARAMS_SIZE as usize];
+        uniform[0..4].copy_from_slice(&(params.x.to_f64() as f32).to_le_bytes());
+        uniform[4..8].copy_from_slice(&(params.y.to_f64() as f32).to_le_bytes());
+        uniform[8..12].copy_from_slice(&(params.zoom as f32).to_le_bytes());
+        uniform[12..16].copy_from_slice(&(width as f32).to_le_bytes());
+        uniform[16..20].copy_from_slice(&(height as f32).to_le_bytes());
+        uniform[20..24].copy_from_slice(&params.iterations.min(MAX_ITERATIONS).to_le_bytes());
+        uniform[24..28].copy_from_slice(&(params.iterations as f32).to_le_bytes());
+        uniform[28..32].copy_from_slice(&((params.bailout * params.bailout) as f32).to_le_bytes());
+        if let Fractal::Julia { c_a, c_b } = params.fractal {
+            uniform[32..36].copy_from_slice(&1u32.to_le_bytes());
+            uniform[36..40].copy_from_slice(&c_a.to_num::<f32>().to_le_bytes());
+            uniform[40..44].copy_from_slice(&c_b.to_num::<f32>().to_le_bytes());
+        }
+        uniform[44..48].copy_from_slice(&u32::from(params.color_mode == ColorMode::Smooth).to_le_bytes());
+        uniform[48..52].copy_from_slice(&(params.palette_offset.rem_euclid(1.0) as f32).to_le_bytes());
+        uniform[52..56].copy_from_slice(&u32::from(over).to_le_bytes());
+        queue.write_buffer(&self.params, 0, &uniform);
+
+        //A few hundred samples, cheaper than keeping track of what changed
+        let mut texels = Vec::with_capacity(PALETTE_TEXELS as usize * 4);
+        for texel in 0..PALETTE_TEXELS {
+            let t = (texel as f64 + 0.5) / PALETTE_TEXELS as f64;
+            let light = palette::tone(palette.sample(t), params.exposure, params.gamma);
+            texels.extend(light.map(|channel| palette::to_srgb(channel, 0.0)));
+            texels.push(0xff);
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.palette,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(PALETTE_TEXELS * 4),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d { width: PALETTE_TEXELS, height: 1, depth_or_array_layers: 1 },
+        );
+    }
+
+    //Draws the sketch into clip_rect of target, where pixels draws the frame,
+    //clearing around it the same way
+    pub fn render(&self, encoder : &mut wgpu::CommandEncoder, target : &wgpu::TextureView, clip_rect : (u32, u32, u32, u32)) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("sketch_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let (x, y, width, height) = clip_rect;
+        if width == 0 || height == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        pass.set_scissor_rect(x, y, width, height);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn bind_group(
+    pixels : &Pixels,
+    pipeline : &wgpu::RenderPipeline,
+    params : &wgpu::Buffer,
+    palette : &wgpu::Texture,
+    sampler : &wgpu::Sampler
+) -> wgpu::BindGroup {
+    let context = pixels.context();
+    let frame = context.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let palette = palette.create_view(&wgpu::TextureViewDescriptor::default());
+    context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("sketch_bind_group"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&frame),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&palette),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
@@ -0,0 +1,114 @@
+// Quick f32 picture of the view drawn straight onto the window while it
+// moves, with the frame from the render thread over it where that is done.
+// Palette colouring of the mandlebrot set only, see sketch.rs.
+
+struct Params {
+    x : f32,
+    y : f32,
+    zoom : f32,
+    // Size of the frame in pixels
+    width : f32,
+    height : f32,
+    iterations : u32,
+    // The view's iteration limit, which values are a fraction of
+    max_iter : f32,
+    bailout2 : f32,
+    // 1 for Fractal::Julia, c is then fixed at (c_a, c_b)
+    julia : u32,
+    c_a : f32,
+    c_b : f32,
+    // 1 for ColorMode::Smooth, discrete otherwise
+    smoothed : u32,
+    palette_offset : f32,
+    // 1 to draw the frame over the sketch
+    over : u32,
+    _padding : u32,
+    _padding2 : u32,
+};
+
+// Palette::color repeats the palette this many times over the values
+const PALETTE_REPEATS : f32 = 8.0;
+
+@group(0) @binding(0)
+var<uniform> params : Params;
+
+// The render thread's frame, rgb premultiplied by how much of each pixel is done
+@group(0) @binding(1)
+var frame : texture_2d<f32>;
+
+// Palette::sample across the texture, toned already
+@group(0) @binding(2)
+var palette : texture_2d<f32>;
+
+@group(0) @binding(3)
+var palette_sampler : sampler;
+
+struct Sketched {
+    @builtin(position) position : vec4<f32>,
+    // 0..1 across the frame from the top left
+    @location(0) uv : vec2<f32>,
+};
+
+// One triangle over the whole viewport, which is set to where the frame goes
+@vertex
+fn vs_main(@builtin(vertex_index) index : u32) -> Sketched {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out : Sketched;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in : Sketched) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(params.width, params.height);
+    // Whole frame pixels, so the sketch lines up with what replaces it
+    let pixel = min(floor(in.uv * size), size - 1.0);
+    let sketch = escape(pixel);
+    if (params.over == 1u) {
+        let shown = textureLoad(frame, vec2<i32>(pixel), 0);
+        return vec4<f32>(shown.rgb + sketch * (1.0 - shown.a), 1.0);
+    }
+    return vec4<f32>(sketch, 1.0);
+}
+
+// Linear colour of the pixel, same as calc_mandle_divergence then the palette colouring
+fn escape(pixel : vec2<f32>) -> vec3<f32> {
+    let z0 = vec2<f32>(params.x, params.y) + (pixel - vec2<f32>(params.width, params.height) / 2.0) * params.zoom;
+    var c = z0;
+    if (params.julia == 1u) {
+        c = vec2<f32>(params.c_a, params.c_b);
+    } else {
+        // Main cardioid and period 2 bulb never escape, same test as formula::in_main_bulbs
+        let q = (c.x - 0.25) * (c.x - 0.25) + c.y * c.y;
+        let cardioid = q * (q + (c.x - 0.25)) <= 0.25 * c.y * c.y;
+        let bulb = (c.x + 1.0) * (c.x + 1.0) + c.y * c.y <= 0.0625;
+        if (cardioid || bulb) {
+            return vec3<f32>(0.0);
+        }
+    }
+
+    var z = z0;
+    for (var i = 0u; i < params.iterations; i = i + 1u) {
+        let mod2 = dot(z, z);
+        if (mod2 > params.bailout2) {
+            return color(i, mod2);
+        }
+        z = vec2<f32>(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+    }
+    return vec3<f32>(0.0);
+}
+
+// ColorMode::divergence for degree 2, then Palette::color
+fn color(i : u32, mod2 : f32) -> vec3<f32> {
+    var value = f32(i) / params.max_iter;
+    if (params.smoothed == 1u) {
+        let nu = f32(i) + 1.0 - log2(0.5 * log(mod2));
+        value = clamp(nu / params.max_iter, 0.0, 1.0);
+    }
+    if (value <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    let t = fract(value * PALETTE_REPEATS + params.palette_offset);
+    return textureSampleLevel(palette, palette_sampler, vec2<f32>(t, 0.5), 0.0).rgb;
+}
@@ -0,0 +1,226 @@
+//The explorer in a terminal, for SSH sessions without a display. Each
+//character cell is two pixels, the upper half block in the colour of the
+//top one over a background of the bottom one, in 24 bit ANSI colour. In
+//terminals that draw Sixel images the view is one at the window's full
+//resolution instead. The last line shows the view and the keys. Raw mode,
+//the size and the keys go through crossterm, and views are drawn on a
+//thread of their own so keys and resizes are taken while one renders
+
+use std::fmt::Write as _;
+use std::io::{Error, ErrorKind, Write};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, Sender};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, style, terminal};
+
+use mandelbrot_core::gif::GifDither;
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::{offline, sixel, MandleParams};
+
+//Fraction of the view an arrow key pans by
+const PAN_FRACTION: f64 = 0.125;
+
+//Zoom factor of Space and Z
+const ZOOM_STEP: f64 = 0.5;
+
+//Iterations added or removed by +/-
+const ITERATION_STEP: u32 = 50;
+
+//Puts the terminal in raw mode on the alternate screen, and back the way
+//it was when dropped
+struct RawMode;
+
+impl RawMode {
+
+    fn enter() -> Result<RawMode, Error> {
+        terminal::enable_raw_mode()?;
+        let raw = RawMode;
+        execute!(std::io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(raw)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), style::ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+//A view to draw in a terminal of cells columns and rows
+struct View {
+    params : MandleParams,
+    cells : (usize, usize),
+}
+
+//Width and height of the terminal's text area in pixels, as the terminal
+//reports them with its size
+fn pixel_size() -> Result<(usize, usize), Error> {
+    let size = terminal::window_size()?;
+    match (size.width as usize, size.height as usize) {
+        (width, height) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(Error::new(ErrorKind::Unsupported, "the terminal didn't say its size in pixels, it may not draw Sixel images")),
+    }
+}
+
+//Explores from params until Escape, drawing with palettes in half blocks
+//or as Sixel images. A view still being drawn when it quits is left to
+//its thread
+pub fn run(mut params : MandleParams, palettes : Arc<Vec<Palette>>, use_sixel : bool) -> Result<(), Error> {
+    let _raw = RawMode::enter()?;
+    let (cols, rows) = terminal::size()?;
+    let mut cells = (cols as usize, rows as usize);
+    let mut pixels = if use_sixel { pixel_size()? } else { (0, 0) };
+
+    let (requests, queue) = crossbeam_channel::unbounded();
+    let (finished, drawn) = crossbeam_channel::unbounded();
+    let drawing_palettes = Arc::clone(&palettes);
+    thread::spawn(move || draw_views(&queue, &finished, &drawing_palettes, use_sixel));
+
+    let mut shown = None;
+    let mut drawing = false;
+    loop {
+        let (cols, rows) = cells;
+        let (width, height) = if use_sixel {
+            //The image stops above the status line, on a whole band of six
+            //rows so the one after it doesn't scroll the screen
+            let (pixel_width, pixel_height) = pixels;
+            (pixel_width, (pixel_height * rows.saturating_sub(1) / rows.max(1) / 6 * 6).max(6))
+        } else {
+            //Pixels are half a cell tall, the bottom line is the status
+            (cols.max(1), (rows.saturating_sub(1) * 2).max(2))
+        };
+        if (params.width, params.height) != (width, height) {
+            //The same height of view whatever the terminal's size
+            params.zoom *= params.height as f64 / height as f64;
+            params.width = width;
+            params.height = height;
+        }
+        if shown.as_ref() != Some(&(params.clone(), cells)) {
+            let _ = requests.send(View { params: params.clone(), cells });
+            shown = Some((params.clone(), cells));
+            drawing = true;
+        }
+
+        //Waits on the keys alone when nothing is being drawn, otherwise
+        //looks for the finished view between them
+        if drawing && !event::poll(Duration::from_millis(20))? {
+            for (view, text) in drawn.try_iter() {
+                //Only the latest view, earlier ones were moved away from
+                if Some((view.params, view.cells)) == shown {
+                    let mut stdout = std::io::stdout().lock();
+                    stdout.write_all(text.as_bytes())?;
+                    stdout.flush()?;
+                    drawing = false;
+                }
+            }
+            continue;
+        }
+
+        match event::read()? {
+            Event::Resize(cols, rows) => {
+                if use_sixel {
+                    pixels = pixel_size()?;
+                }
+                cells = (cols as usize, rows as usize);
+            }
+            Event::Key(key) if key.kind != KeyEventKind::Release && !apply_key(key, &mut params, &palettes) => {
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+}
+
+//Moves params for key, false for the keys that quit
+fn apply_key(key : KeyEvent, params : &mut MandleParams, palettes : &[Palette]) -> bool {
+    let pan = |params : &mut MandleParams, dx : f64, dy : f64| {
+        let (x, y) = params.pixel_to_complex(
+            params.width as f64 * (0.5 + dx * PAN_FRACTION),
+            params.height as f64 * (0.5 + dy * PAN_FRACTION)
+        );
+        params.x = x;
+        params.y = y;
+    };
+    match key.code {
+        KeyCode::Esc => return false,
+        //Ctrl+C, raw mode doesn't turn it into a signal
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Up | KeyCode::Char('w') => pan(params, 0.0, -1.0),
+        KeyCode::Down | KeyCode::Char('s') => pan(params, 0.0, 1.0),
+        KeyCode::Left | KeyCode::Char('a') => pan(params, -1.0, 0.0),
+        KeyCode::Right | KeyCode::Char('d') => pan(params, 1.0, 0.0),
+        KeyCode::Char(' ') => params.zoom_about(params.width as f64 / 2.0, params.height as f64 / 2.0, ZOOM_STEP),
+        KeyCode::Char('z') => params.zoom_about(params.width as f64 / 2.0, params.height as f64 / 2.0, 1.0 / ZOOM_STEP),
+        KeyCode::Char('+' | '=') => {
+            params.iterations = params.max_iterations() + ITERATION_STEP;
+            params.auto_iterations = false;
+        }
+        KeyCode::Char('-') => {
+            params.iterations = params.max_iterations().saturating_sub(ITERATION_STEP).max(1);
+            params.auto_iterations = false;
+        }
+        KeyCode::Char('i') => params.auto_iterations = !params.auto_iterations,
+        KeyCode::Char('h') => params.histogram = !params.histogram,
+        KeyCode::Char('p') => params.palette = (params.palette + 1) % palettes.len(),
+        KeyCode::Home => params.reset_view(),
+        _ => {}
+    }
+    true
+}
+
+//Draws the latest view queued each time, skipping any queued before it,
+//until the explorer stops
+fn draw_views(queue : &Receiver<View>, finished : &Sender<(View, String)>, palettes : &[Palette], use_sixel : bool) {
+    while let Ok(view) = queue.recv() {
+        let view = queue.try_iter().last().unwrap_or(view);
+        let text = draw(&view.params, &palettes[view.params.palette], view.cells, use_sixel);
+        if finished.send((view, text)).is_err() {
+            return;
+        }
+    }
+}
+
+//What puts the view in the cells above the status line
+fn draw(params : &MandleParams, palette : &Palette, (cols, rows) : (usize, usize), use_sixel : bool) -> String {
+    let (width, height) = (params.width, params.height);
+    let image = offline::render_image(params, palette, width, height, |_| {});
+
+    let mut text = String::from("\x1b[H");
+    if use_sixel {
+        //Ordered so the dots stay put as the view moves
+        text.push_str(&sixel::encode(&image, width, height, GifDither::Ordered));
+    } else {
+        let pixel = |x : usize, y : usize| {
+            let idx = (y * width + x) * 3;
+            [image[idx], image[idx + 1], image[idx + 2]]
+        };
+        for row in 0..height / 2 {
+            //Colours are only sent when they change
+            let mut last = None;
+            for col in 0..width {
+                let colors = (pixel(col, row * 2), pixel(col, row * 2 + 1));
+                if last != Some(colors) {
+                    let ([r, g, b], [br, bg, bb]) = colors;
+                    let _ = write!(text, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", r, g, b, br, bg, bb);
+                    last = Some(colors);
+                }
+                text.push('▀');
+            }
+            text.push_str("\x1b[0m\r\n");
+        }
+    }
+    let status = format!(
+        "x {} y {} zoom {:e} iterations {}{}  {}  arrows pan, space/z zoom, +/- iterations, p palette, esc quits",
+        params.x, params.y, params.zoom, params.max_iterations(),
+        if params.auto_iterations { " (auto)" } else { "" },
+        palette.name,
+    );
+    let _ = write!(text, "\x1b[{};1H", rows);
+    text.extend(status.chars().take(cols));
+    text.push_str("\x1b[K");
+    text
+}
@@ -0,0 +1,276 @@
+//Finished tiles of recently seen views, so going back to one (undoing a
+//zoom, jumping to a bookmark, panning back) draws it without computing
+//anything. Kept in memory up to a number of tiles, the least recently used
+//go first, and optionally in a directory as well so they outlast the session
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use mandelbrot_core::{Grid, MandleParams, Sample};
+
+//Bytes of a sample on disk, stopped, z and distance
+const SAMPLE_BYTES: usize = 16;
+
+pub struct TileCache {
+    capacity : usize,
+    dir : Option<PathBuf>,
+    //Each tile with when it was last used
+    tiles : HashMap<String, (Grid<Sample>, u64)>,
+    clock : u64,
+}
+
+impl TileCache {
+
+    //Up to capacity tiles in memory, none with 0, and every tile computed in
+    //dir too when there is one
+    pub fn new(capacity : usize, dir : Option<PathBuf>) -> TileCache {
+        if let Some(dir) = &dir {
+            if let Err(err) = std::fs::create_dir_all(dir) {
+                println!("Error creating tile cache {} {}", dir.display(), err);
+            }
+        }
+        TileCache {
+            capacity,
+            dir,
+            tiles: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    //Whether get would find the tile of view, without loading it
+    pub fn contains(&self, view : &MandleParams) -> bool {
+        let key = key(view);
+        self.tiles.contains_key(&key) || self.dir.as_ref().is_some_and(|dir| file(dir, &key).exists())
+    }
+
+    //The samples of the tile computed for view, if it is kept anywhere
+    pub fn get(&mut self, view : &MandleParams) -> Option<Grid<Sample>> {
+        let key = key(view);
+        self.clock += 1;
+        if let Some((cells, used)) = self.tiles.get_mut(&key) {
+            *used = self.clock;
+            return Some(cells.clone());
+        }
+        //Unreadable files are left for insert to write over
+        let cells = read(&file(self.dir.as_ref()?, &key), &key).ok()?;
+        self.remember(key, cells.clone());
+        Some(cells)
+    }
+
+    pub fn insert(&mut self, view : &MandleParams, cells : Grid<Sample>) {
+        let key = key(view);
+        self.clock += 1;
+        if let Some(dir) = &self.dir {
+            if let Err(err) = write(&file(dir, &key), &key, &cells) {
+                println!("Error writing tile cache {} {}, only keeping tiles in memory", dir.display(), err);
+                self.dir = None;
+            }
+        }
+        self.remember(key, cells);
+    }
+
+    fn remember(&mut self, key : String, cells : Grid<Sample>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.tiles.len() >= self.capacity && !self.tiles.contains_key(&key) {
+            let oldest = self.tiles.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.tiles.remove(&oldest);
+            }
+        }
+        self.tiles.insert(key, (cells, self.clock));
+    }
+}
+
+//Everything about a tile's view that goes into its samples. Colouring that
+//only reads them (the palette, shading, histogram) doesn't count
+fn key(view : &MandleParams) -> String {
+    format!(
+        "{} {} {:e} {} {} {} {:x} {:?} {:?} {} {}x{} {} {} {:?} {} {} {}",
+        view.x,
+        view.y,
+        view.zoom,
+        view.iterations,
+        view.formula,
+        view.exponent,
+        view.script.as_ref().map_or(0, |script| script.id()),
+        view.fractal,
+        view.color_mode,
+        view.bailout,
+        view.width,
+        view.height,
+        view.subdivide,
+        view.double_double,
+        view.trap,
+        view.trap_x,
+        view.trap_y,
+        view.trap_radius,
+    )
+}
+
+fn file(dir : &Path, key : &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    dir.join(format!("{:016x}.tile", hasher.finish()))
+}
+
+//The key on a line of its own, in case another tile's hash matched, then
+//the samples
+fn write(path : &Path, key : &str, cells : &Grid<Sample>) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(key.len() + 9 + cells.cols() * cells.rows() * SAMPLE_BYTES);
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.push(b'\n');
+    encode(cells, &mut bytes);
+    std::fs::write(path, bytes)
+}
+
+fn read(path : &Path, key : &str) -> io::Result<Grid<Sample>> {
+    let bytes = std::fs::read(path)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a tile of this view");
+    let rest = bytes.strip_prefix(key.as_bytes()).and_then(|rest| rest.strip_prefix(b"\n")).ok_or_else(invalid)?;
+    decode(rest).ok_or_else(invalid)
+}
+
+//The size as two u32 then each sample, all little endian. Workers send
+//their tiles back like this too, see distribute
+pub fn encode(cells : &Grid<Sample>, bytes : &mut Vec<u8>) {
+    bytes.extend_from_slice(&(cells.cols() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(cells.rows() as u32).to_le_bytes());
+    for (_, sample) in cells.iter() {
+        bytes.extend_from_slice(&sample.stopped.unwrap_or(u32::MAX).to_le_bytes());
+        bytes.extend_from_slice(&sample.z.0.to_le_bytes());
+        bytes.extend_from_slice(&sample.z.1.to_le_bytes());
+        bytes.extend_from_slice(&sample.distance.to_le_bytes());
+    }
+}
+
+//Samples written by encode, None unless bytes are exactly that
+pub fn decode(bytes : &[u8]) -> Option<Grid<Sample>> {
+    let word = |at : usize| bytes.get(at..at + 4).map(|word| [word[0], word[1], word[2], word[3]]);
+    let cols = u32::from_le_bytes(word(0)?) as usize;
+    let rows = u32::from_le_bytes(word(4)?) as usize;
+    //A size that doesn't fit in memory can't match however long bytes is
+    let length = cols.checked_mul(rows)?.checked_mul(SAMPLE_BYTES)?.checked_add(8)?;
+    if bytes.len() != length {
+        return None;
+    }
+    let mut cells = Grid::new(cols, rows, Sample::INTERIOR);
+    for (index, (_, cell)) in cells.iter_mut().enumerate() {
+        let at = 8 + index * SAMPLE_BYTES;
+        let stopped = u32::from_le_bytes(word(at)?);
+        *cell = Sample {
+            stopped: (stopped != u32::MAX).then_some(stopped),
+            z: (f32::from_le_bytes(word(at + 4)?), f32::from_le_bytes(word(at + 8)?)),
+            distance: f32::from_le_bytes(word(at + 12)?),
+        };
+    }
+    Some(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Samples stopping at their index, some never, with NaN distances
+    fn cells(cols : usize, rows : usize) -> Grid<Sample> {
+        let mut cells = Grid::new(cols, rows, Sample::INTERIOR);
+        for (index, (_, cell)) in cells.iter_mut().enumerate() {
+            *cell = Sample {
+                stopped: (index % 3 != 0).then_some(index as u32),
+                z: (index as f32 * 0.5, -1.25),
+                distance: if index % 2 == 0 { f32::NAN } else { index as f32 },
+            };
+        }
+        cells
+    }
+
+    //Each field of each sample as bits, so NaNs compare equal
+    fn bits(cells : &Grid<Sample>) -> Vec<(Option<u32>, u32, u32, u32)> {
+        cells.iter().map(|(_, sample)| (sample.stopped, sample.z.0.to_bits(), sample.z.1.to_bits(), sample.distance.to_bits())).collect()
+    }
+
+    fn view(x : f64) -> MandleParams {
+        let mut view = MandleParams::new(8, 8);
+        view.x = view.x.offset(x);
+        view
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let cells = cells(5, 3);
+        let mut bytes = Vec::new();
+        encode(&cells, &mut bytes);
+        assert_eq!(bytes.len(), 8 + 15 * SAMPLE_BYTES);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!((decoded.cols(), decoded.rows()), (5, 3));
+        assert_eq!(bits(&decoded), bits(&cells));
+        assert_eq!(decoded.iter().filter(|(_, sample)| sample.stopped.is_none()).count(), 5);
+        assert!(decoded.iter().any(|(_, sample)| sample.distance.is_nan()));
+    }
+
+    #[test]
+    fn wrong_lengths_are_rejected() {
+        let mut bytes = Vec::new();
+        encode(&cells(4, 4), &mut bytes);
+        for length in [0, 3, 7, 8, bytes.len() - 1] {
+            assert!(decode(&bytes[..length]).is_none(), "{} bytes", length);
+        }
+        bytes.push(0);
+        assert!(decode(&bytes).is_none());
+        //Sizes whose samples wouldn't fit in memory
+        let mut huge = Vec::new();
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode(&huge).is_none());
+        //An empty grid is just its size
+        let mut empty = Vec::new();
+        encode(&Grid::new(0, 0, Sample::INTERIOR), &mut empty);
+        assert!(decode(&empty).is_some_and(|cells| cells.cols() == 0));
+    }
+
+    #[test]
+    fn least_recently_used_go_first() {
+        let mut cache = TileCache::new(2, None);
+        cache.insert(&view(0.0), cells(1, 1));
+        cache.insert(&view(1.0), cells(2, 1));
+        assert!(cache.get(&view(0.0)).is_some());
+        cache.insert(&view(2.0), cells(3, 1));
+        assert!(cache.contains(&view(0.0)) && cache.contains(&view(2.0)));
+        assert!(!cache.contains(&view(1.0)));
+        //Putting back one that's kept makes room for nothing
+        cache.insert(&view(2.0), cells(3, 2));
+        assert_eq!(cache.tiles.len(), 2);
+        assert!(cache.get(&view(0.0)).is_some());
+        assert_eq!(cache.get(&view(2.0)).map(|cells| cells.rows()), Some(2));
+        cache.insert(&view(3.0), cells(1, 1));
+        assert!(!cache.contains(&view(0.0)));
+        //Without room nothing is kept
+        let mut none = TileCache::new(0, None);
+        none.insert(&view(0.0), cells(1, 1));
+        assert!(none.get(&view(0.0)).is_none());
+    }
+
+    #[test]
+    fn files_of_other_views_are_not_read() {
+        let dir = std::env::temp_dir().join(format!("mandlebrot-tile-cache-{}", std::process::id()));
+        let mut cache = TileCache::new(0, Some(dir.clone()));
+        let (view, other) = (view(0.0), view(1.0));
+        //Another view's tile where this one's hash points
+        let path = file(&dir, &key(&view));
+        write(&path, &key(&other), &cells(2, 2)).unwrap();
+        assert!(read(&path, &key(&view)).is_err());
+        assert!(read(&path, &key(&other)).is_ok());
+        //Nor one whose key only starts the same
+        write(&path, &format!("{} more", key(&view)), &cells(2, 2)).unwrap();
+        assert!(read(&path, &key(&view)).is_err());
+        assert!(cache.get(&view).is_none());
+
+        cache.insert(&view, cells(2, 2));
+        assert_eq!(TileCache::new(0, Some(dir.clone())).get(&view).map(|cells| bits(&cells)), Some(bits(&cells(2, 2))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//Animated moves to bookmarks and loaded views, and the path zoom videos take
+//between keyframes. The zoom changes by the same factor every frame, and the
+//pan is measured in pixels of the current zoom so the destination drifts
+//into the centre rather than racing past while zoomed in deep
+
+use std::time::{Duration, Instant};
+
+use mandelbrot_core::{Coord, MandleParams};
+
+//Centre and zoom, all a transition moves
+#[derive(Clone, Copy, PartialEq)]
+struct Camera {
+    x : Coord,
+    y : Coord,
+    zoom : f64,
+}
+
+impl Camera {
+    fn of(params : &MandleParams) -> Camera {
+        Camera { x: params.x, y: params.y, zoom: params.zoom }
+    }
+
+    //t of the way to the other camera
+    fn towards(&self, to : &Camera, t : f64) -> Camera {
+        if t >= 1.0 {
+            return *to;
+        }
+        let zoom = self.zoom * (to.zoom / self.zoom).powf(t);
+        let dx = to.x.minus(self.x);
+        let dy = to.y.minus(self.y);
+        //Offsets are taken from whichever end is zoomed in deeper, its
+        //coordinates have precision to spare for the other end
+        let (x, y) = if to.zoom <= self.zoom {
+            let remaining = (1.0 - t) * zoom / self.zoom;
+            (to.x.offset(-dx * remaining), to.y.offset(-dy * remaining))
+        } else {
+            let travelled = t * zoom / to.zoom;
+            (self.x.offset(dx * travelled), self.y.offset(dy * travelled))
+        };
+        Camera { x, y, zoom }
+    }
+}
+
+//from with its centre and zoom moved t of the way to those of to, at a
+//steady zoom rate
+pub fn between(from : &MandleParams, to : &MandleParams, t : f64) -> MandleParams {
+    let camera = Camera::of(from).towards(&Camera::of(to), t);
+    MandleParams { x: camera.x, y: camera.y, zoom: camera.zoom, ..from.clone() }
+}
+
+pub struct Transition {
+    from : Camera,
+    to : Camera,
+    started : Instant,
+    duration : Duration,
+    //Where the last step went, anywhere else means the view was moved meanwhile
+    last : Camera,
+}
+
+impl Transition {
+
+    //Moves the centre and zoom of from to those of to, the other settings
+    //are for the caller to change. None when there is nothing to animate,
+    //views of another fractal aren't on the way anywhere
+    pub fn new(from : &MandleParams, to : &MandleParams, duration : Duration) -> Option<Transition> {
+        if duration.is_zero() || from.fractal != to.fractal || from.formula != to.formula {
+            return None;
+        }
+        Some(Transition {
+            from: Camera::of(from),
+            to: Camera::of(to),
+            started: Instant::now(),
+            duration,
+            last: Camera::of(from),
+        })
+    }
+
+    //Moves params on to where the transition is now. False once it has
+    //arrived, or given up because params were changed from elsewhere
+    pub fn step(&mut self, params : &mut MandleParams) -> bool {
+        if Camera::of(params) != self.last {
+            return false;
+        }
+        let t = (self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64()).min(1.0);
+        //Eased in and out
+        let camera = self.from.towards(&self.to, t * t * (3.0 - 2.0 * t));
+        params.x = camera.x;
+        params.y = camera.y;
+        params.zoom = camera.zoom;
+        self.last = camera;
+        t < 1.0
+    }
+}
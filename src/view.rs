@@ -0,0 +1,236 @@
+//Saving and loading the current view to a TOML file
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use mandelbrot_core::formula;
+use mandelbrot_core::palette::Palette;
+use mandelbrot_core::trap::Trap;
+use mandelbrot_core::{Backend, ColorMode, Fractal, MandleParams, RenderMode, MAX_BAILOUT, MIN_BAILOUT};
+
+use crate::cli;
+
+//Coordinates are stored as decimal strings, they have more precision than
+//a TOML float so they round trip exactly. The palette is stored by name
+//since indices depend on which palette file was loaded
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ViewState {
+    pub x : String,
+    pub y : String,
+    pub zoom : String,
+    pub iterations : u32,
+    #[serde(default)]
+    pub auto_iterations : bool,
+    #[serde(default = "default_iteration_scale")]
+    pub iteration_scale : f64,
+    pub bailout : f64,
+    pub color_mode : ColorMode,
+    pub backend : Backend,
+    pub palette : String,
+    //By name like the palette, views saved before formulas existed are mandlebrot
+    #[serde(default = "default_formula")]
+    pub formula : String,
+    #[serde(default = "default_exponent")]
+    pub exponent : f64,
+    #[serde(default)]
+    pub mode : RenderMode,
+    #[serde(default = "default_nebula_iterations")]
+    pub nebula_iterations : [u32; 3],
+    //Constant of the julia set being shown, missing for the mandlebrot set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub julia : Option<JuliaConstant>,
+    #[serde(default = "default_trap")]
+    pub trap : Trap,
+    #[serde(default)]
+    pub trap_x : f64,
+    #[serde(default)]
+    pub trap_y : f64,
+    #[serde(default = "default_trap_radius")]
+    pub trap_radius : f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JuliaConstant {
+    pub x : String,
+    pub y : String,
+}
+
+#[derive(Debug)]
+pub enum ViewError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ViewError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewError::Io(err) => write!(fmt, "{}", err),
+            ViewError::Parse(err) => write!(fmt, "{}", err),
+            ViewError::Serialize(err) => write!(fmt, "{}", err),
+            ViewError::Invalid(msg) => write!(fmt, "{}", msg),
+        }
+    }
+}
+
+impl From<std::io::Error> for ViewError {
+    fn from(err : std::io::Error) -> ViewError {
+        ViewError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ViewError {
+    fn from(err : toml::de::Error) -> ViewError {
+        ViewError::Parse(err)
+    }
+}
+
+impl From<toml::ser::Error> for ViewError {
+    fn from(err : toml::ser::Error) -> ViewError {
+        ViewError::Serialize(err)
+    }
+}
+
+fn default_formula() -> String {
+    formula::get(0).name().to_string()
+}
+
+fn default_iteration_scale() -> f64 {
+    100.0
+}
+
+fn default_exponent() -> f64 {
+    3.0
+}
+
+fn default_trap() -> Trap {
+    Trap::Point
+}
+
+fn default_trap_radius() -> f64 {
+    1.0
+}
+
+fn default_nebula_iterations() -> [u32; 3] {
+    mandelbrot_core::buddhabrot::NEBULA_ITERATIONS
+}
+
+pub fn parse_real<T : FromStr>(name : &str, val : &str) -> Result<T, ViewError>
+where
+    T::Err : fmt::Display
+{
+    val.parse::<T>()
+        .map_err(|err| ViewError::Invalid(format!("{} {} {}", name, val, err)))
+}
+
+pub fn parse_zoom(val : &str) -> Result<f64, ViewError> {
+    cli::parse_zoom(val).map_err(|err| ViewError::Invalid(format!("zoom {}", err)))
+}
+
+impl ViewState {
+
+    pub fn from_params(params : &MandleParams, palettes : &[Palette]) -> ViewState {
+        ViewState {
+            x: params.x.to_string(),
+            y: params.y.to_string(),
+            zoom: format!("{:e}", params.zoom),
+            iterations: params.iterations,
+            auto_iterations: params.auto_iterations,
+            iteration_scale: params.iteration_scale,
+            bailout: params.bailout,
+            color_mode: params.color_mode,
+            backend: params.backend,
+            palette: palettes[params.palette].name.clone(),
+            formula: formula::get(params.formula).name().to_string(),
+            exponent: params.exponent,
+            mode: params.mode,
+            nebula_iterations: params.nebula_iterations,
+            julia: match params.fractal {
+                Fractal::Mandlebrot => None,
+                Fractal::Julia { c_a, c_b } => Some(JuliaConstant {
+                    x: c_a.to_string(),
+                    y: c_b.to_string(),
+                }),
+            },
+            trap: params.trap,
+            trap_x: params.trap_x,
+            trap_y: params.trap_y,
+            trap_radius: params.trap_radius,
+        }
+    }
+
+    //Overwrites the view in params, the window size is left alone.
+    //Nothing is changed if any field is invalid
+    pub fn apply(&self, params : &mut MandleParams, palettes : &[Palette]) -> Result<(), ViewError> {
+        let x = parse_real("x", &self.x)?;
+        let y = parse_real("y", &self.y)?;
+        let zoom = parse_zoom(&self.zoom)?;
+        if !self.bailout.is_finite() {
+            return Err(ViewError::Invalid(format!("bailout {} is not a number", self.bailout)));
+        }
+        if !(self.iteration_scale.is_finite() && self.iteration_scale > 0.0) {
+            return Err(ViewError::Invalid(format!("iteration scale {} is not positive", self.iteration_scale)));
+        }
+        if self.iterations == 0 || self.nebula_iterations.contains(&0) {
+            return Err(ViewError::Invalid("iterations must be at least 1".to_string()));
+        }
+        let palette = palettes.iter()
+            .position(|palette| palette.name == self.palette)
+            .ok_or_else(|| ViewError::Invalid(format!("no palette named {}", self.palette)))?;
+        if !(formula::MIN_EXPONENT..=formula::MAX_EXPONENT).contains(&self.exponent) {
+            return Err(ViewError::Invalid(format!(
+                "exponent {} is not between {} and {}",
+                self.exponent, formula::MIN_EXPONENT, formula::MAX_EXPONENT
+            )));
+        }
+        if !(self.trap_x.is_finite() && self.trap_y.is_finite()) {
+            return Err(ViewError::Invalid(format!("trap centre {}, {} is not a number", self.trap_x, self.trap_y)));
+        }
+        if !(self.trap_radius.is_finite() && self.trap_radius > 0.0) {
+            return Err(ViewError::Invalid(format!("trap radius {} is not positive", self.trap_radius)));
+        }
+        let formula = formula::find(&self.formula)
+            .ok_or_else(|| ViewError::Invalid(format!("no formula named {}", self.formula)))?;
+        let fractal = match &self.julia {
+            None => Fractal::Mandlebrot,
+            Some(julia) => Fractal::Julia {
+                c_a: parse_real("julia x", &julia.x)?,
+                c_b: parse_real("julia y", &julia.y)?,
+            },
+        };
+
+        params.x = x;
+        params.y = y;
+        params.zoom = zoom;
+        params.iterations = self.iterations;
+        params.auto_iterations = self.auto_iterations;
+        params.iteration_scale = self.iteration_scale;
+        params.formula = formula;
+        params.exponent = self.exponent;
+        params.mode = self.mode;
+        params.nebula_iterations = self.nebula_iterations;
+        params.fractal = fractal;
+        params.bailout = self.bailout.clamp(MIN_BAILOUT, MAX_BAILOUT);
+        params.color_mode = self.color_mode;
+        params.backend = self.backend;
+        params.palette = palette;
+        params.trap = self.trap;
+        params.trap_x = self.trap_x;
+        params.trap_y = self.trap_y;
+        params.trap_radius = self.trap_radius;
+        Ok(())
+    }
+
+    pub fn save(&self, path : &Path) -> Result<(), ViewError> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path : &Path) -> Result<ViewState, ViewError> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}